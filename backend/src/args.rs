@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 /// The arguments to this program
 #[derive(Debug, Parser)]
@@ -22,7 +22,109 @@ pub struct ProgramArgs {
     #[arg(long)]
     pub create_super_user: Option<String>,
 
+    /// How often to delete expired sessions, in seconds
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub session_cleanup_interval: Option<u64>,
+
+    /// How long a login lasts when the user asks to be remembered, in days
+    #[arg(long)]
+    pub remember_me_days: Option<u32>,
+
+    /// The public url of the site, used for links in emails
+    #[arg(long)]
+    pub public_url: Option<String>,
+
+    /// The email domains allowed to register, separated by commas
+    #[arg(long, value_delimiter = ',')]
+    pub allowed_email_domains: Vec<String>,
+
+    /// The url of the SMTP server to send emails through
+    #[arg(long)]
+    pub smtp_url: Option<String>,
+
+    /// The directory to write emails to instead of sending them
+    #[arg(long)]
+    pub mail_dir: Option<PathBuf>,
+
+    /// The address emails are sent from
+    #[arg(long)]
+    pub mail_from: Option<String>,
+
+    /// The issuer url of the OpenID Connect provider to allow single sign-on through
+    #[arg(long)]
+    pub oidc_issuer_url: Option<String>,
+
+    /// The client id registered with the OpenID Connect provider
+    #[arg(long)]
+    pub oidc_client_id: Option<String>,
+
+    /// The client secret registered with the OpenID Connect provider
+    #[arg(long)]
+    pub oidc_client_secret: Option<String>,
+
+    /// The name of the OpenID Connect provider shown on the login page
+    #[arg(long)]
+    pub oidc_provider_name: Option<String>,
+
+    /// The markdown file with the terms of use, instead of the ones built in
+    #[arg(long)]
+    pub terms_file: Option<PathBuf>,
+
+    /// How many failed logins a username may have before it is locked out
+    #[arg(long)]
+    pub login_max_failures: Option<u32>,
+
+    /// How many failed logins an IP address may have before it is locked out
+    #[arg(long)]
+    pub login_max_failures_per_ip: Option<u32>,
+
+    /// How long a lockout lasts and failed logins are remembered, in seconds
+    #[arg(long)]
+    pub login_lockout_duration: Option<u64>,
+
+    /// Take client addresses from the X-Forwarded-For header set by a reverse proxy
+    #[arg(long)]
+    pub trust_proxy_headers: bool,
+
+    /// The Argon2 algorithm to hash passwords with: argon2d, argon2i or argon2id
+    #[arg(long)]
+    pub argon2_algorithm: Option<String>,
+
+    /// The Argon2 version to hash passwords with: 16 or 19
+    #[arg(long)]
+    pub argon2_version: Option<u32>,
+
+    /// The memory Argon2 uses to hash a password, in KiB
+    #[arg(long)]
+    pub argon2_memory_cost: Option<u32>,
+
+    /// The number of passes Argon2 makes over its memory
+    #[arg(long)]
+    pub argon2_time_cost: Option<u32>,
+
+    /// The number of lanes Argon2 hashes with
+    #[arg(long)]
+    pub argon2_parallelism: Option<u32>,
+
     /// The logging verbosity
     #[arg(short, long)]
     pub verbosity: Option<String>,
+
+    /// Run a command instead of serving
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// The commands this program can run instead of serving
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Import a CSV roster with username, email, grade, role and student_id columns
+    ImportRoster {
+        /// The CSV file to import
+        file: PathBuf,
+
+        /// Import the roster instead of only showing what would change
+        #[arg(long)]
+        apply: bool,
+    },
 }
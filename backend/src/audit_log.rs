@@ -0,0 +1,345 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{self, request::Parts},
+};
+use sea_orm::{
+    ActiveModelTrait as _, ActiveValue::Set, ColumnTrait as _, DatabaseConnection, DbErr,
+    EntityTrait as _, PaginatorTrait as _, QueryFilter as _, QueryOrder as _, QuerySelect as _,
+    Select,
+};
+use time::{Date, Duration, Month, OffsetDateTime, Time};
+use tracing::{Level, event};
+
+use crate::{auth, client_ip::ClientIp, db, states::BackendState};
+
+/// The most events a single export holds
+const MAX_EXPORTED_EVENTS: u64 = 100_000;
+
+/// The longest name kept for an actor nobody is known to be, since anyone can type one
+const MAX_ACTOR_NAME_LENGTH: usize = 64;
+
+/// The longest user agent kept for a request
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
+/// The names of the actions the audit log records
+pub mod actions {
+    pub const LOGIN: &str = "login";
+    pub const LOGIN_FAILED: &str = "login.failed";
+    pub const LOGOUT: &str = "logout";
+    pub const USER_CREATE: &str = "user.create";
+    pub const USER_REGISTER: &str = "user.register";
+    pub const USER_UPDATE: &str = "user.update";
+    pub const USER_DELETE: &str = "user.delete";
+    pub const USER_RESET_PASSWORD: &str = "user.reset-password";
+    pub const USER_IMPORT: &str = "user.import";
+    pub const PASSWORD_CHANGE: &str = "password.change";
+    pub const PASSWORD_RESET: &str = "password.reset";
+    pub const TWO_FACTOR_ENABLE: &str = "two-factor.enable";
+    pub const TWO_FACTOR_DISABLE: &str = "two-factor.disable";
+    pub const PASSKEY_ADD: &str = "passkey.add";
+    pub const PASSKEY_REMOVE: &str = "passkey.remove";
+    pub const PROFILE_UPDATE: &str = "profile.update";
+    pub const API_TOKEN_CREATE: &str = "api-token.create";
+    pub const API_TOKEN_REVOKE: &str = "api-token.revoke";
+    pub const LOGIN_UNLOCK: &str = "login.unlock";
+    pub const SUSPENSION_CREATE: &str = "suspension.create";
+    pub const SUSPENSION_LIFT: &str = "suspension.lift";
+    pub const IMPERSONATION_START: &str = "impersonation.start";
+    pub const IMPERSONATION_STOP: &str = "impersonation.stop";
+    pub const TERMS_ACCEPT: &str = "terms.accept";
+    pub const SESSION_REVOKE: &str = "session.revoke";
+
+    /// Every action, in the order the admin page offers them
+    pub const ALL: &[&str] = &[
+        LOGIN,
+        LOGIN_FAILED,
+        LOGOUT,
+        USER_CREATE,
+        USER_REGISTER,
+        USER_UPDATE,
+        USER_DELETE,
+        USER_RESET_PASSWORD,
+        USER_IMPORT,
+        PASSWORD_CHANGE,
+        PASSWORD_RESET,
+        TWO_FACTOR_ENABLE,
+        TWO_FACTOR_DISABLE,
+        PASSKEY_ADD,
+        PASSKEY_REMOVE,
+        PROFILE_UPDATE,
+        API_TOKEN_CREATE,
+        API_TOKEN_REVOKE,
+        LOGIN_UNLOCK,
+        SUSPENSION_CREATE,
+        SUSPENSION_LIFT,
+        IMPERSONATION_START,
+        IMPERSONATION_STOP,
+        TERMS_ACCEPT,
+        SESSION_REVOKE,
+    ];
+}
+
+/// Where a request came from, as the audit log records it
+#[derive(Debug, Clone)]
+pub struct RequestOrigin {
+    pub ip: String,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequestParts<BackendState> for RequestOrigin {
+    type Rejection = (http::StatusCode, &'static str);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &BackendState,
+    ) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip) = ClientIp::from_request_parts(parts, state).await?;
+        let user_agent = parts
+            .headers
+            .get(http::header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|user_agent| truncate(user_agent, MAX_USER_AGENT_LENGTH));
+        Ok(RequestOrigin {
+            ip: ip.to_string(),
+            user_agent,
+        })
+    }
+}
+
+/// Something that happened, waiting to be recorded
+#[derive(Debug, Clone)]
+pub struct Event {
+    action: &'static str,
+    actor_id: Option<i64>,
+    actor_name: Option<String>,
+    target_id: Option<i64>,
+    target_name: Option<String>,
+    details: Option<String>,
+}
+
+impl Event {
+    pub fn new(action: &'static str) -> Self {
+        Self {
+            action,
+            actor_id: None,
+            actor_name: None,
+            target_id: None,
+            target_name: None,
+            details: None,
+        }
+    }
+
+    /// Set the user who did it
+    pub fn actor(mut self, user: &auth::User) -> Self {
+        self.actor_id = Some(user.id);
+        self.actor_name = Some(user.username.clone());
+        self
+    }
+
+    /// Set the name someone gave when nobody is known to have done it, like for failed logins
+    pub fn actor_name(mut self, name: impl AsRef<str>) -> Self {
+        self.actor_name = Some(truncate(name.as_ref(), MAX_ACTOR_NAME_LENGTH));
+        self
+    }
+
+    /// Set the user it was done to
+    pub fn target(mut self, id: i64, name: impl Into<String>) -> Self {
+        self.target_id = Some(id);
+        self.target_name = Some(name.into());
+        self
+    }
+
+    /// Describe it further
+    pub fn details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+}
+
+/// Which events to read from the audit log
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub action: Option<String>,
+    /// Only events whose actor's name contains this
+    pub actor: Option<String>,
+    /// Only events whose target's name contains this
+    pub target: Option<String>,
+    /// Only events on or after this day, in UTC
+    pub from: Option<Date>,
+    /// Only events on or before this day, in UTC
+    pub to: Option<Date>,
+}
+
+/// Keeps a persistent record of security-sensitive actions
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    db: DatabaseConnection,
+}
+
+impl AuditLog {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Record an event, logging instead of failing when it can't be written
+    ///
+    /// The action it records already happened, so a broken audit log shouldn't fail the request.
+    pub async fn record(&self, origin: &RequestOrigin, event: Event) {
+        let result = db::audit_events::ActiveModel {
+            action: Set(event.action.to_string()),
+            actor_id: Set(event.actor_id),
+            actor_name: Set(event.actor_name),
+            target_id: Set(event.target_id),
+            target_name: Set(event.target_name),
+            ip: Set(Some(origin.ip.clone())),
+            user_agent: Set(origin.user_agent.clone()),
+            details: Set(event.details),
+            created_at: Set(OffsetDateTime::now_utc()),
+            ..Default::default()
+        }
+        .insert(&self.db)
+        .await;
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to record {} in the audit log: {}", event.action, err);
+        }
+    }
+
+    /// Get a page of events, newest first, along with how many events match
+    pub async fn search(
+        &self,
+        filter: &Filter,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<db::audit_events::Model>, u64), DbErr> {
+        let paginator = Self::select(filter).paginate(&self.db, per_page);
+        let total = paginator.num_items().await?;
+        let events = paginator.fetch_page(page).await?;
+        Ok((events, total))
+    }
+
+    /// Get every event to export, newest first
+    pub async fn export(&self, filter: &Filter) -> Result<Vec<db::audit_events::Model>, DbErr> {
+        Self::select(filter)
+            .limit(MAX_EXPORTED_EVENTS)
+            .all(&self.db)
+            .await
+    }
+
+    fn select(filter: &Filter) -> Select<db::audit_events::Entity> {
+        let mut select = db::audit_events::Entity::find()
+            .order_by_desc(db::audit_events::Column::CreatedAt)
+            .order_by_desc(db::audit_events::Column::Id);
+        if let Some(action) = &filter.action {
+            select = select.filter(db::audit_events::Column::Action.eq(action.as_str()));
+        }
+        if let Some(actor) = &filter.actor {
+            select = select.filter(db::audit_events::Column::ActorName.contains(actor));
+        }
+        if let Some(target) = &filter.target {
+            select = select.filter(db::audit_events::Column::TargetName.contains(target));
+        }
+        if let Some(from) = filter.from {
+            select = select.filter(
+                db::audit_events::Column::CreatedAt.gte(from.with_time(Time::MIDNIGHT).assume_utc()),
+            );
+        }
+        if let Some(to) = filter.to {
+            let before = (to + Duration::days(1)).with_time(Time::MIDNIGHT).assume_utc();
+            select = select.filter(db::audit_events::Column::CreatedAt.lt(before));
+        }
+        select
+    }
+}
+
+/// Cut text down to at most a number of characters
+fn truncate(text: &str, max_length: usize) -> String {
+    text.chars().take(max_length).collect()
+}
+
+/// Parse a day written as `YYYY-MM-DD`, the way date inputs send it
+pub fn parse_date(date: &str) -> Option<Date> {
+    let mut parts = date.trim().splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = Month::try_from(parts.next()?.parse::<u8>().ok()?).ok()?;
+    let day = parts.next()?.parse().ok()?;
+    Date::from_calendar_date(year, month, day).ok()
+}
+
+/// Write events as CSV with a header, with times in UTC
+pub fn write_csv(events: &[db::audit_events::Model]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "id",
+        "time",
+        "action",
+        "actor",
+        "target",
+        "ip",
+        "user_agent",
+        "details",
+    ])?;
+    for event in events {
+        let created_at = event.created_at.to_offset(time::UtcOffset::UTC);
+        writer.write_record([
+            event.id.to_string().as_str(),
+            &format!(
+                "{}T{:02}:{:02}:{:02}Z",
+                created_at.date(),
+                created_at.hour(),
+                created_at.minute(),
+                created_at.second()
+            ),
+            &event.action,
+            &csv_cell(event.actor_name.as_deref()),
+            &csv_cell(event.target_name.as_deref()),
+            &csv_cell(event.ip.as_deref()),
+            &csv_cell(event.user_agent.as_deref()),
+            &csv_cell(event.details.as_deref()),
+        ])?;
+    }
+    writer.into_inner().map_err(|err| err.into_error().into())
+}
+
+/// Quote text that a spreadsheet would otherwise run as a formula
+fn csv_cell(text: Option<&str>) -> String {
+    let text = text.unwrap_or_default();
+    if text.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_formulas_as_text() {
+        let event = db::audit_events::Model {
+            id: 1,
+            action: actions::LOGIN_FAILED.to_string(),
+            actor_id: None,
+            actor_name: Some("=HYPERLINK(\"https://evil.example\")".to_string()),
+            target_id: None,
+            target_name: None,
+            ip: Some("127.0.0.1".to_string()),
+            user_agent: Some("@SUM(1)".to_string()),
+            details: Some("Wrong username or password".to_string()),
+            created_at: OffsetDateTime::UNIX_EPOCH,
+        };
+
+        let csv = String::from_utf8(write_csv(&[event]).unwrap()).unwrap();
+        let row = csv.lines().nth(1).unwrap();
+        assert_eq!(
+            row,
+            "1,1970-01-01T00:00:00Z,login.failed,\"'=HYPERLINK(\"\"https://evil.example\"\")\",,127.0.0.1,'@SUM(1),Wrong username or password"
+        );
+    }
+
+    #[test]
+    fn caps_typed_actor_names() {
+        let event = Event::new(actions::LOGIN_FAILED).actor_name("a".repeat(1000));
+        assert_eq!(event.actor_name.unwrap().len(), MAX_ACTOR_NAME_LENGTH);
+    }
+}
@@ -1,20 +1,157 @@
+use std::collections::{HashMap, HashSet};
+
 use argon2::{
-    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version,
     password_hash::{SaltString, rand_core::OsRng},
 };
 use async_trait::async_trait;
-use axum_login::{AuthUser, AuthnBackend, UserId};
-use sea_orm::{ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use axum::{
+    extract::{Request, State},
+    http,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_login::{AuthSession, AuthUser, AuthnBackend, AuthzBackend, UserId};
+use sea_orm::{
+    ActiveModelTrait as _,
+    ActiveValue::{Set, Unchanged}, ColumnTrait, Condition, ConnectionTrait,
+    DatabaseConnection,
+    EntityTrait, Order, PaginatorTrait as _, QueryFilter, QueryOrder, QuerySelect as _, SqlErr,
+    TransactionTrait as _,
+    sea_query::{Expr, LikeExpr},
+};
 use secrecy::{ExposeSecret as _, SecretString};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use time::{Duration, OffsetDateTime};
+use tower_sessions::{Expiry, Session};
+
+use crate::{db, duplicates, roster, tokens, totp, webauthn};
+
+/// How long an email verification link stays valid
+const EMAIL_VERIFICATION_LIFETIME: Duration = Duration::hours(24);
+
+/// How long a password reset link stays valid
+const PASSWORD_RESET_LIFETIME: Duration = Duration::hours(1);
+
+/// How long an invite link for a user imported from a roster stays valid
+const ROSTER_INVITE_LIFETIME: Duration = Duration::days(7);
+
+/// How long a login waits for its second factor after the password was checked
+const PENDING_TWO_FACTOR_LIFETIME: Duration = Duration::minutes(5);
+
+/// How many wrong second factor codes a pending login may send before it is dropped
+pub const PENDING_TWO_FACTOR_ATTEMPTS: u32 = 5;
+
+/// The session key holding a login that still needs its second factor
+pub const PENDING_TWO_FACTOR_KEY: &str = "auth.pending_two_factor";
+
+/// Why a user whose role requires a second factor is refused until they set one up
+const SECOND_FACTOR_REQUIRED: &str =
+    "Your role requires two-factor authentication or a passkey, set one up in your settings";
+
+/// The session key holding the admin who is viewing the site as the logged in user
+pub const IMPERSONATION_KEY: &str = "auth.impersonation";
+
+/// The session key holding when a login the user asked to remember ends, as a unix timestamp
+pub const REMEMBER_UNTIL_KEY: &str = "auth.remember_until";
+
+/// The longest a personal API token can stay valid, in days
+pub const API_TOKEN_MAX_LIFETIME_DAYS: u32 = 365;
+
+/// The longest a username can be, in characters
+pub const USERNAME_MAX_LENGTH: usize = 64;
+
+/// The longest a student ID can be, in characters
+pub const STUDENT_ID_MAX_LENGTH: usize = 32;
+
+/// The longest a profile's display name can be, in characters
+pub const DISPLAY_NAME_MAX_LENGTH: usize = 100;
+
+/// The longest a profile's pronouns can be, in characters
+pub const PRONOUNS_MAX_LENGTH: usize = 40;
+
+/// The longest a profile's bio can be, in characters
+pub const BIO_MAX_LENGTH: usize = 1000;
+
+/// The most links a profile can have
+pub const PROFILE_MAX_LINKS: usize = 5;
+
+/// The longest a profile link can be, in characters
+pub const PROFILE_LINK_MAX_LENGTH: usize = 200;
+
+/// The names of the roles seeded by the migrations
+pub mod roles {
+    pub const STUDENT: &str = "student";
+    pub const ADMIN: &str = "admin";
+}
+
+/// The names of the permissions seeded by the migrations
+pub mod permissions {
+    /// Access to the admin panel
+    pub const ADMIN_PANEL: &str = "admin.panel";
+
+    /// Creating new users
+    pub const USERS_CREATE: &str = "users.create";
+
+    /// Managing existing users
+    pub const USERS_MANAGE: &str = "users.manage";
+
+    /// Suspending and banning users
+    pub const USERS_MODERATE: &str = "users.moderate";
+
+    /// Viewing the site as another user
+    pub const USERS_IMPERSONATE: &str = "users.impersonate";
+
+    /// Reading and exporting the audit log
+    pub const AUDIT_READ: &str = "audit.read";
+}
+
+/// The kinds of suspension a moderator can give
+pub mod suspension_kinds {
+    /// A temporary or open-ended suspension
+    pub const SUSPENDED: &str = "suspended";
+
+    /// A ban, which is only expected to be lifted by hand
+    pub const BANNED: &str = "banned";
 
-use crate::db;
+    /// Every kind of suspension
+    pub const ALL: &[&str] = &[SUSPENDED, BANNED];
+}
+
+/// The scopes a personal API token can be limited to
+pub mod scopes {
+    /// Reading the owner's own account
+    pub const READ_PROFILE: &str = "read-profile";
+
+    /// Everything the owner's role allows, including changes
+    pub const ADMIN: &str = "admin";
+
+    /// Every scope a token can be given
+    pub const ALL: &[&str] = &[READ_PROFILE, ADMIN];
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: i64,
     pub username: String,
-    pub admin: bool,
+    pub role_id: i64,
+    pub role: String,
+    pub require_two_factor: bool,
+    /// What sessions are checked against, derived so the session secret never ends up in them
+    #[serde(skip)]
+    session_auth_hash: Vec<u8>,
+    /// The scopes of the API token the request was made with, or `None` for a session
+    pub token_scopes: Option<Vec<String>>,
+}
+
+impl User {
+    /// Check whether the user's session or API token allows a scope
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.token_scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.iter().any(|token_scope| token_scope == scope))
+    }
 }
 
 impl AuthUser for User {
@@ -25,19 +162,203 @@ impl AuthUser for User {
     }
 
     fn session_auth_hash(&self) -> &[u8] {
-        &[]
+        // Changing the password rotates the secret, which invalidates every other session
+        &self.session_auth_hash
+    }
+}
+
+impl From<(db::users::Model, db::roles::Model)> for User {
+    fn from((entity, role): (db::users::Model, db::roles::Model)) -> Self {
+        Self {
+            id: entity.id,
+            username: entity.username,
+            role_id: entity.role_id,
+            role: role.name,
+            require_two_factor: role.require_two_factor,
+            session_auth_hash: Sha256::digest(entity.session_secret.as_bytes()).to_vec(),
+            token_scopes: None,
+        }
+    }
+}
+
+/// The columns the admin user list can be sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserSort {
+    Id,
+    Username,
+    Email,
+    Role,
+}
+
+/// Which page of users the admin user list shows, how they're narrowed down and in what order
+#[derive(Debug, Clone)]
+pub struct UserListing {
+    /// Only users whose username or email contains this
+    pub search: Option<String>,
+    /// Only users with this role
+    pub role: Option<String>,
+    /// Only users that are or aren't active
+    pub active: Option<bool>,
+    pub sort: UserSort,
+    pub descending: bool,
+    /// The page to show, counting from zero
+    pub page: u64,
+    pub per_page: u64,
+}
+
+/// Changes an admin makes to a user, leaving fields that are `None` as they are
+#[derive(Debug, Clone, Default)]
+pub struct UserChanges {
+    pub username: Option<String>,
+    /// A new email, or `Some(None)` to remove it
+    pub email: Option<Option<String>>,
+    pub role: Option<String>,
+    pub active: Option<bool>,
+    /// A new student ID, or `Some(None)` to remove it
+    pub student_id: Option<Option<String>>,
+}
+
+/// Changes a user makes to their profile, leaving fields that are `None` as they are
+#[derive(Debug, Clone, Default)]
+pub struct ProfileChanges {
+    /// A new display name, or `Some(None)` to remove it
+    pub display_name: Option<Option<String>>,
+    /// New pronouns, or `Some(None)` to remove them
+    pub pronouns: Option<Option<String>>,
+    /// A new graduation year, or `Some(None)` to remove it
+    pub graduation_year: Option<Option<i32>>,
+    /// A new bio, or `Some(None)` to remove it
+    pub bio: Option<Option<String>>,
+    pub links: Option<Vec<String>>,
+}
+
+/// The usernames, emails and student IDs of the roster rows checked so far
+#[derive(Debug, Default)]
+struct RosterSeen {
+    usernames: HashSet<String>,
+    emails: HashSet<String>,
+    student_ids: HashSet<String>,
+}
+
+/// A login whose password was correct but that still needs its second factor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTwoFactor {
+    pub user_id: i64,
+    pub expires_at: i64,
+    pub attempts: u32,
+    /// Whether the user asked to stay logged in on this device
+    #[serde(default)]
+    pub remember: bool,
+}
+
+impl PendingTwoFactor {
+    pub fn new(user_id: i64, remember: bool) -> Self {
+        Self {
+            user_id,
+            expires_at: (OffsetDateTime::now_utc() + PENDING_TWO_FACTOR_LIFETIME).unix_timestamp(),
+            attempts: 0,
+            remember,
+        }
+    }
+
+    /// Check whether the login waited too long for its second factor
+    pub fn is_expired(&self) -> bool {
+        OffsetDateTime::now_utc().unix_timestamp() >= self.expires_at
+    }
+}
+
+/// An admin viewing the site as another user, kept in the session so they can go back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Impersonation {
+    /// The impersonation's row in the audit log
+    pub id: i64,
+    pub admin_id: i64,
+    pub admin_username: String,
+}
+
+/// A named permission granted through a role
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Permission(pub String);
+
+impl From<&str> for Permission {
+    fn from(name: &str) -> Self {
+        Self(name.to_string())
+    }
+}
+
+/// A role along with the permissions it grants
+#[derive(Debug, Clone)]
+pub struct Role {
+    pub name: String,
+    pub permissions: Vec<String>,
+}
+
+/// The Argon2 settings new password hashes are made with
+#[derive(Debug, Clone, Default)]
+pub struct PasswordSettings {
+    pub algorithm: Algorithm,
+    pub version: Version,
+    pub params: Params,
+}
+
+impl PasswordSettings {
+    /// Get a hasher using these settings
+    fn hasher(&self) -> Argon2<'static> {
+        Argon2::new(self.algorithm, self.version, self.params.clone())
+    }
+
+    /// Check whether a stored hash was made with a different algorithm or version, or lower costs
+    fn is_weaker(&self, password_hash: &str) -> bool {
+        let Ok(password_hash) = PasswordHash::new(password_hash) else {
+            return true;
+        };
+        let (Ok(algorithm), Ok(params)) = (
+            Algorithm::try_from(password_hash.algorithm),
+            Params::try_from(&password_hash),
+        ) else {
+            return true;
+        };
+        // Hashes without a version were made with the first one
+        let version = password_hash
+            .version
+            .map_or(Ok(Version::V0x10), Version::try_from);
+
+        algorithm != self.algorithm
+            || version != Ok(self.version)
+            || params.m_cost() < self.params.m_cost()
+            || params.t_cost() < self.params.t_cost()
+            || params.p_cost() < self.params.p_cost()
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Backend {
     db: DatabaseConnection,
+    password_settings: PasswordSettings,
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum Error {
     DatabaseError(sea_orm::DbErr),
     Argon2Error(argon2::password_hash::Error),
+    TotpError(totp_rs::TotpError),
+    UsernameTaken,
+    EmailAlreadyExists,
+    StudentIdTaken,
+    UserNotFound,
+    RoleNotFound,
+    IncorrectPassword,
+    InvalidToken,
+    EmailDomainNotAllowed,
+    TwoFactorAlreadyEnabled,
+    TwoFactorNotSetUp,
+    InvalidTwoFactorCode,
+    InvalidScope,
+    Suspended(Box<db::suspensions::Model>),
+    InvalidRoster,
+    Passkey(webauthn::Error),
+    PasskeyAlreadyAdded,
 }
 
 impl From<sea_orm::DbErr> for Error {
@@ -52,11 +373,40 @@ impl From<argon2::password_hash::Error> for Error {
     }
 }
 
+impl From<webauthn::Error> for Error {
+    fn from(err: webauthn::Error) -> Self {
+        Error::Passkey(err)
+    }
+}
+
+impl From<totp_rs::TotpError> for Error {
+    fn from(err: totp_rs::TotpError) -> Self {
+        Error::TotpError(err)
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::DatabaseError(err) => write!(f, "Database Error: {}", err),
             Error::Argon2Error(err) => write!(f, "Argon2 Error: {}", err),
+            Error::TotpError(err) => write!(f, "TOTP Error: {}", err),
+            Error::UsernameTaken => write!(f, "Username is already taken"),
+            Error::EmailAlreadyExists => write!(f, "Email already in use"),
+            Error::StudentIdTaken => write!(f, "Student ID already belongs to another user"),
+            Error::UserNotFound => write!(f, "User not found"),
+            Error::RoleNotFound => write!(f, "Role not found"),
+            Error::IncorrectPassword => write!(f, "Incorrect password"),
+            Error::InvalidToken => write!(f, "Invalid or expired token"),
+            Error::EmailDomainNotAllowed => write!(f, "Email domain is not allowed"),
+            Error::TwoFactorAlreadyEnabled => write!(f, "Two-factor authentication is already enabled"),
+            Error::TwoFactorNotSetUp => write!(f, "Two-factor authentication is not set up"),
+            Error::InvalidTwoFactorCode => write!(f, "Invalid two-factor code"),
+            Error::InvalidScope => write!(f, "Invalid token scope"),
+            Error::Suspended(_) => write!(f, "User is suspended"),
+            Error::InvalidRoster => write!(f, "Roster has invalid rows"),
+            Error::Passkey(err) => write!(f, "{}", err),
+            Error::PasskeyAlreadyAdded => write!(f, "Passkey is already added"),
         }
     }
 }
@@ -66,54 +416,1585 @@ impl std::error::Error for Error {
         match self {
             Error::DatabaseError(err) => Some(err),
             Error::Argon2Error(err) => Some(err),
+            Error::TotpError(err) => Some(err),
+            Error::UsernameTaken => None,
+            Error::EmailAlreadyExists => None,
+            Error::StudentIdTaken => None,
+            Error::UserNotFound => None,
+            Error::RoleNotFound => None,
+            Error::IncorrectPassword => None,
+            Error::InvalidToken => None,
+            Error::EmailDomainNotAllowed => None,
+            Error::TwoFactorAlreadyEnabled => None,
+            Error::TwoFactorNotSetUp => None,
+            Error::InvalidTwoFactorCode => None,
+            Error::InvalidScope => None,
+            Error::Suspended(_) => None,
+            Error::InvalidRoster => None,
+            Error::Passkey(err) => Some(err),
+            Error::PasskeyAlreadyAdded => None,
         }
     }
 }
 
 impl Backend {
-    pub fn new(db: DatabaseConnection) -> Self {
-        Self { db }
+    pub fn new(db: DatabaseConnection, password_settings: PasswordSettings) -> Self {
+        Self {
+            db,
+            password_settings,
+        }
     }
 
-    /// Create a user with a password
-    pub async fn create_user(&self, username: impl AsRef<str>, password: impl AsRef<str>, admin: bool) -> Result<(), Error> {
+    /// Create a user with a password, a role and optionally the student ID they're verified to have
+    pub async fn create_user(&self, username: impl AsRef<str>, password: impl AsRef<str>, role: impl AsRef<str>, student_id: Option<&str>) -> Result<User, Error> {
         // Convert args to &str
-        let username = username.as_ref();
+        let username = username.as_ref().trim();
         let password = password.as_ref();
+        let role = role.as_ref();
+
+        // Find the role
+        let role_entity = db::roles::Entity::find()
+            .filter(db::roles::Column::Name.eq(role))
+            .one(&self.db)
+            .await?
+            .ok_or(Error::RoleNotFound)?;
 
         // Hash the password
-        let password_hash = Argon2::default()
-            .hash_password(
-                password.as_bytes(),
-                &SaltString::generate(&mut OsRng),
-            )?
-            .to_string();
+        let password_hash = self.hash_password(password)?;
+
+        // Add the user, letting the unique indexes refuse taken usernames and student IDs
+        let user_entity = db::users::Entity::insert(db::users::ActiveModel {
+            username: Set(username.to_string()),
+            normalized_username: Set(normalize_username(username)),
+            password_hash: Set(password_hash),
+            session_secret: Set(tokens::generate().0),
+            role_id: Set(role_entity.id),
+            active: Set(true),
+            student_id: Set(student_id.map(normalize_student_id)),
+            ..Default::default()
+        })
+        .exec_with_returning(&self.db)
+        .await
+        .map_err(user_conflict)?;
+
+        Ok((user_entity, role_entity).into())
+    }
+
+    /// Register an inactive student account, returning it with the token that activates it
+    pub async fn register_user(&self, username: impl AsRef<str>, email: impl AsRef<str>, password: impl AsRef<str>) -> Result<(User, String), Error> {
+        // Convert args to &str
+        let username = username.as_ref().trim();
+        let email = email.as_ref();
+        let password = password.as_ref();
+
+        // Find the student role
+        let role_entity = db::roles::Entity::find()
+            .filter(db::roles::Column::Name.eq(roles::STUDENT))
+            .one(&self.db)
+            .await?
+            .ok_or(Error::RoleNotFound)?;
+
+        // Add the inactive user along with their verification token, letting the unique
+        // indexes refuse taken usernames and emails
+        let password_hash = self.hash_password(password)?;
+        let (token, token_hash) = tokens::generate();
+        let transaction = self.db.begin().await?;
+        let user_entity = db::users::Entity::insert(db::users::ActiveModel {
+            username: Set(username.to_string()),
+            normalized_username: Set(normalize_username(username)),
+            password_hash: Set(password_hash),
+            session_secret: Set(tokens::generate().0),
+            role_id: Set(role_entity.id),
+            email: Set(Some(email.to_string())),
+            active: Set(false),
+            ..Default::default()
+        })
+        .exec_with_returning(&transaction)
+        .await
+        .map_err(user_conflict)?;
+        db::email_verifications::Entity::insert(db::email_verifications::ActiveModel {
+            user_id: Set(user_entity.id),
+            token_hash: Set(token_hash),
+            expires_at: Set(OffsetDateTime::now_utc() + EMAIL_VERIFICATION_LIFETIME),
+            ..Default::default()
+        })
+        .exec(&transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(((user_entity, role_entity).into(), token))
+    }
+
+    /// Activate the account an email verification token was issued for
+    pub async fn verify_email(&self, token: impl AsRef<str>) -> Result<User, Error> {
+        // Find the unexpired verification
+        let verification_entity = db::email_verifications::Entity::find()
+            .filter(db::email_verifications::Column::TokenHash.eq(tokens::hash(token.as_ref())))
+            .filter(db::email_verifications::Column::ExpiresAt.gt(OffsetDateTime::now_utc()))
+            .one(&self.db)
+            .await?
+            .ok_or(Error::InvalidToken)?;
+
+        // Activate the user and use up the token
+        let (user_entity, role_entity) = db::users::Entity::find_by_id(verification_entity.user_id)
+            .find_also_related(db::roles::Entity)
+            .one(&self.db)
+            .await?
+            .and_then(|(entity, role)| Some((entity, role?)))
+            .ok_or(Error::UserNotFound)?;
+        let transaction = self.db.begin().await?;
+        let mut user_entity: db::users::ActiveModel = user_entity.into();
+        user_entity.active = Set(true);
+        let user_entity = user_entity.update(&transaction).await?;
+        db::email_verifications::Entity::delete_many()
+            .filter(db::email_verifications::Column::UserId.eq(user_entity.id))
+            .exec(&transaction)
+            .await?;
+        transaction.commit().await?;
+
+        Ok((user_entity, role_entity).into())
+    }
+
+    /// Delete registrations that weren't verified in time, so their usernames and emails are
+    /// free to register again
+    pub async fn delete_expired_registrations(&self) -> Result<(), Error> {
+        let user_ids: Vec<i64> = db::email_verifications::Entity::find()
+            .select_only()
+            .column(db::email_verifications::Column::UserId)
+            .filter(db::email_verifications::Column::ExpiresAt.lte(OffsetDateTime::now_utc()))
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+        db::users::Entity::delete_many()
+            .filter(db::users::Column::Id.is_in(user_ids))
+            .filter(db::users::Column::Active.eq(false))
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Log in through a single sign-on provider, linking or creating an account for emails in an allowed domain
+    pub async fn external_login(&self, issuer: impl AsRef<str>, subject: impl AsRef<str>, email: Option<&str>, allowed_email_domains: &[String]) -> Result<User, Error> {
+        // Convert args to &str
+        let issuer = issuer.as_ref();
+        let subject = subject.as_ref();
+
+        // Use the account already linked to the identity
+        if let Some(identity_entity) = db::external_identities::Entity::find()
+            .filter(db::external_identities::Column::Issuer.eq(issuer))
+            .filter(db::external_identities::Column::Subject.eq(subject))
+            .one(&self.db)
+            .await?
+        {
+            let user: User = db::users::Entity::find_by_id(identity_entity.user_id)
+                .filter(db::users::Column::Active.eq(true))
+                .find_also_related(db::roles::Entity)
+                .one(&self.db)
+                .await?
+                .and_then(|(entity, role)| Some((entity, role?).into()))
+                .ok_or(Error::UserNotFound)?;
+            self.ensure_not_suspended(user.id).await?;
+            return Ok(user);
+        }
+
+        // Otherwise the email has to belong to an allowed domain
+        let email = email
+            .map(str::to_lowercase)
+            .filter(|email| {
+                email.rsplit_once('@').is_some_and(|(_, domain)| {
+                    allowed_email_domains.iter().any(|allowed| allowed == domain)
+                })
+            })
+            .ok_or(Error::EmailDomainNotAllowed)?;
 
-        // Try to get an existing user
+        // Link the account already using the email, or create a student for it
+        let transaction = self.db.begin().await?;
+        let user_entity = match db::users::Entity::find()
+            .filter(db::users::Column::Email.eq(&email))
+            .one(&transaction)
+            .await?
+        {
+            // The provider verified the email so an unfinished registration can be activated, but
+            // an account an admin deactivated has to stay that way
+            Some(user_entity) if !user_entity.active => {
+                let registration_pending = db::email_verifications::Entity::find()
+                    .filter(db::email_verifications::Column::UserId.eq(user_entity.id))
+                    .one(&transaction)
+                    .await?
+                    .is_some();
+                if !registration_pending {
+                    return Err(Error::UserNotFound);
+                }
+
+                // Whoever registered may not own the email, so neither their password nor their
+                // verification link may get into the account
+                let (password, _) = tokens::generate();
+                let mut user_entity: db::users::ActiveModel = user_entity.into();
+                user_entity.password_hash = Set(self.hash_password(password)?);
+                user_entity.session_secret = Set(tokens::generate().0);
+                user_entity.active = Set(true);
+                let user_entity = user_entity.update(&transaction).await?;
+                db::email_verifications::Entity::delete_many()
+                    .filter(db::email_verifications::Column::UserId.eq(user_entity.id))
+                    .exec(&transaction)
+                    .await?;
+                user_entity
+            }
+            Some(user_entity) => user_entity,
+            None => {
+                let role_entity = db::roles::Entity::find()
+                    .filter(db::roles::Column::Name.eq(roles::STUDENT))
+                    .one(&transaction)
+                    .await?
+                    .ok_or(Error::RoleNotFound)?;
+                let username = available_username(&transaction, &email).await?;
+
+                // Nobody knows the password, but a reset can set one later
+                let (password, _) = tokens::generate();
+                db::users::Entity::insert(db::users::ActiveModel {
+                    normalized_username: Set(normalize_username(&username)),
+                    username: Set(username),
+                    password_hash: Set(self.hash_password(password)?),
+                    session_secret: Set(tokens::generate().0),
+                    role_id: Set(role_entity.id),
+                    email: Set(Some(email.clone())),
+                    active: Set(true),
+                    ..Default::default()
+                })
+                .exec_with_returning(&transaction)
+                .await?
+            }
+        };
+        db::external_identities::Entity::insert(db::external_identities::ActiveModel {
+            user_id: Set(user_entity.id),
+            issuer: Set(issuer.to_string()),
+            subject: Set(subject.to_string()),
+            ..Default::default()
+        })
+        .exec(&transaction)
+        .await?;
+        transaction.commit().await?;
+        self.ensure_not_suspended(user_entity.id).await?;
+
+        // Get the user's role
+        let role_entity = db::roles::Entity::find_by_id(user_entity.role_id)
+            .one(&self.db)
+            .await?
+            .ok_or(Error::RoleNotFound)?;
+
+        Ok((user_entity, role_entity).into())
+    }
+
+    /// Find an active user by their username, ignoring case
+    pub async fn find_user(&self, username: impl AsRef<str>) -> Result<Option<User>, Error> {
+        let username = username.as_ref().trim();
         let user_entity = db::users::Entity::find()
-            .filter(db::users::Column::Username.eq(username))
+            .filter(username_condition(username))
+            .filter(db::users::Column::Active.eq(true))
+            .find_also_related(db::roles::Entity)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .max_by_key(|(entity, _)| entity.username == username);
+        Ok(user_entity.and_then(|(entity, role)| Some((entity, role?).into())))
+    }
+
+    /// Get a page of users along with their roles and how many users match in total
+    pub async fn list_users(&self, listing: &UserListing) -> Result<(Vec<(db::users::Model, db::roles::Model)>, u64), Error> {
+        let mut select = db::users::Entity::find().find_also_related(db::roles::Entity);
+
+        // Narrow the users down
+        if let Some(search) = listing.search.as_deref().map(str::trim).filter(|search| !search.is_empty()) {
+            select = select.filter(
+                Condition::any()
+                    .add(db::users::Column::NormalizedUsername.like(contains_pattern(&normalize_username(search))))
+                    .add(db::users::Column::Username.like(contains_pattern(search)))
+                    .add(db::users::Column::Email.like(contains_pattern(&search.to_lowercase()))),
+            );
+        }
+        if let Some(role) = &listing.role {
+            select = select.filter(db::roles::Column::Name.eq(role));
+        }
+        if let Some(active) = listing.active {
+            select = select.filter(db::users::Column::Active.eq(active));
+        }
+
+        // Sort them, falling back to the id so pages stay stable
+        let order = if listing.descending {
+            Order::Desc
+        } else {
+            Order::Asc
+        };
+        select = match listing.sort {
+            UserSort::Id => select,
+            UserSort::Username => select.order_by(db::users::Column::NormalizedUsername, order.clone()),
+            UserSort::Email => select.order_by(db::users::Column::Email, order.clone()),
+            UserSort::Role => select.order_by(db::roles::Column::Name, order.clone()),
+        };
+        select = select.order_by(db::users::Column::Id, order);
+
+        let paginator = select.paginate(&self.db, listing.per_page);
+        let total = paginator.num_items().await?;
+        let users = paginator
+            .fetch_page(listing.page)
+            .await?
+            .into_iter()
+            .filter_map(|(entity, role)| Some((entity, role?)))
+            .collect();
+        Ok((users, total))
+    }
+
+    /// Get any user, active or not, along with their role
+    pub async fn user_by_id(&self, user_id: i64) -> Result<Option<(db::users::Model, db::roles::Model)>, Error> {
+        Ok(db::users::Entity::find_by_id(user_id)
+            .find_also_related(db::roles::Entity)
+            .one(&self.db)
+            .await?
+            .and_then(|(entity, role)| Some((entity, role?))))
+    }
+
+    /// Get an active user by their username along with their profile, if they ever edited it
+    pub async fn profile(&self, username: &str) -> Result<Option<(db::users::Model, Option<db::profiles::Model>)>, Error> {
+        let Some(user_entity) = db::users::Entity::find()
+            .filter(username_condition(username))
+            .filter(db::users::Column::Active.eq(true))
+            .one(&self.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let profile_entity = db::profiles::Entity::find()
+            .filter(db::profiles::Column::UserId.eq(user_entity.id))
             .one(&self.db)
             .await?;
+        Ok(Some((user_entity, profile_entity)))
+    }
 
-        // If the user does not exist then add them
-        if user_entity.is_none() {
-            db::users::Entity::insert(db::users::ActiveModel {
-                username: Set(username.to_string()),
-                password_hash: Set(password_hash),
-                admin: Set(admin),
+    /// Change a user's profile, making it the first time they edit it
+    pub async fn update_profile(&self, user_id: i64, changes: ProfileChanges) -> Result<db::profiles::Model, Error> {
+        let existing = db::profiles::Entity::find()
+            .filter(db::profiles::Column::UserId.eq(user_id))
+            .one(&self.db)
+            .await?;
+        let mut profile_entity = match &existing {
+            Some(profile_entity) => db::profiles::ActiveModel::from(profile_entity.clone()),
+            None => db::profiles::ActiveModel {
+                user_id: Set(user_id),
+                display_name: Set(None),
+                pronouns: Set(None),
+                graduation_year: Set(None),
+                bio: Set(None),
+                links: Set(String::new()),
                 ..Default::default()
-            })
+            },
+        };
+
+        if let Some(display_name) = changes.display_name {
+            profile_entity.display_name = Set(display_name);
+        }
+        if let Some(pronouns) = changes.pronouns {
+            profile_entity.pronouns = Set(pronouns);
+        }
+        if let Some(graduation_year) = changes.graduation_year {
+            profile_entity.graduation_year = Set(graduation_year);
+        }
+        if let Some(bio) = changes.bio {
+            profile_entity.bio = Set(bio);
+        }
+        if let Some(links) = changes.links {
+            profile_entity.links = Set(links.join("\n"));
+        }
+        profile_entity.updated_at = Set(OffsetDateTime::now_utc());
+
+        Ok(match existing {
+            Some(_) => profile_entity.update(&self.db).await?,
+            None => profile_entity.insert(&self.db).await?,
+        })
+    }
+
+    /// Get which of some users have a suspension in force
+    pub async fn suspended_user_ids(&self, user_ids: &[i64]) -> Result<HashSet<i64>, Error> {
+        Ok(db::suspensions::Entity::find()
+            .filter(db::suspensions::Column::UserId.is_in(user_ids.iter().copied()))
+            .filter(active_suspension_condition())
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|suspension| suspension.user_id)
+            .collect())
+    }
+
+    /// Find accounts that might belong to the same person
+    pub async fn duplicate_candidates(&self) -> Result<Vec<duplicates::Candidate>, Error> {
+        let user_entities = db::users::Entity::find()
+            .order_by_asc(db::users::Column::Id)
+            .all(&self.db)
+            .await?;
+        Ok(duplicates::find(&user_entities))
+    }
+
+    /// Change a user's name, email, role, student ID or whether they're active
+    ///
+    /// Deactivated users are signed out of every session on their next request.
+    pub async fn update_user(&self, user_id: i64, changes: UserChanges) -> Result<(db::users::Model, db::roles::Model), Error> {
+        let transaction = self.db.begin().await?;
+        let (user_entity, role_entity) = db::users::Entity::find_by_id(user_id)
+            .find_also_related(db::roles::Entity)
+            .one(&transaction)
+            .await?
+            .and_then(|(entity, role)| Some((entity, role?)))
+            .ok_or(Error::UserNotFound)?;
+
+        // Find the new role
+        let role_entity = match &changes.role {
+            Some(role) => db::roles::Entity::find()
+                .filter(db::roles::Column::Name.eq(role))
+                .one(&transaction)
+                .await?
+                .ok_or(Error::RoleNotFound)?,
+            None => role_entity,
+        };
+
+        let mut user_entity: db::users::ActiveModel = user_entity.into();
+        if let Some(username) = &changes.username {
+            user_entity.username = Set(username.trim().to_string());
+            user_entity.normalized_username = Set(normalize_username(username));
+        }
+        if let Some(email) = changes.email {
+            user_entity.email = Set(email.map(|email| email.trim().to_lowercase()));
+        }
+        if let Some(active) = changes.active {
+            user_entity.active = Set(active);
+        }
+        if let Some(student_id) = changes.student_id {
+            user_entity.student_id = Set(student_id.as_deref().map(normalize_student_id));
+        }
+        user_entity.role_id = Set(role_entity.id);
+        let user_entity = user_entity.update(&transaction).await.map_err(user_conflict)?;
+        transaction.commit().await?;
+
+        Ok((user_entity, role_entity))
+    }
+
+    /// Replace a user's password with a random temporary one, returning the only copy of it
+    ///
+    /// This signs the user out of every session.
+    pub async fn set_temporary_password(&self, user_id: i64) -> Result<String, Error> {
+        let user_entity = db::users::Entity::find_by_id(user_id)
+            .one(&self.db)
+            .await?
+            .ok_or(Error::UserNotFound)?;
+        let password = tokens::generate_password();
+        self.set_password(&self.db, user_entity, &password).await?;
+        Ok(password)
+    }
+
+    /// Work out what importing a roster would do, checking every row
+    ///
+    /// With an issuer, rows can only give roles and change users the issuer could grant.
+    pub async fn plan_roster(&self, issuer: Option<&User>, rows: Vec<roster::Row>) -> Result<Vec<roster::PlannedRow>, Error> {
+        // Find the roles and whether they can be given
+        let mut roles = HashMap::new();
+        for role_entity in db::roles::Entity::find().all(&self.db).await? {
+            let grantable = match issuer {
+                Some(issuer) => self.can_grant_role(issuer, &role_entity.name).await?,
+                None => true,
+            };
+            roles.insert(role_entity.name, (role_entity.id, grantable));
+        }
+
+        let mut seen = RosterSeen::default();
+        let mut planned = Vec::with_capacity(rows.len());
+        for row in rows {
+            let action = self.plan_roster_row(issuer, &row, &roles, &mut seen).await?;
+            planned.push(roster::PlannedRow { row, action });
+        }
+        Ok(planned)
+    }
+
+    /// Work out what importing a roster row would do, remembering what it used
+    async fn plan_roster_row(
+        &self,
+        issuer: Option<&User>,
+        row: &roster::Row,
+        roles: &HashMap<String, (i64, bool)>,
+        seen: &mut RosterSeen,
+    ) -> Result<roster::Action, Error> {
+        // Check the cells
+        if row.username.is_empty() {
+            return Ok(roster::Action::Invalid("Missing username".to_string()));
+        }
+        if row.username.chars().count() > USERNAME_MAX_LENGTH {
+            return Ok(roster::Action::Invalid(format!(
+                "Username is longer than {} characters",
+                USERNAME_MAX_LENGTH
+            )));
+        }
+        if !seen.usernames.insert(normalize_username(&row.username)) {
+            return Ok(roster::Action::Invalid(
+                "Username appears more than once".to_string(),
+            ));
+        }
+        if let Some(email) = &row.email {
+            if !email.contains('@') {
+                return Ok(roster::Action::Invalid("Invalid email".to_string()));
+            }
+            if !seen.emails.insert(email.clone()) {
+                return Ok(roster::Action::Invalid(
+                    "Email appears more than once".to_string(),
+                ));
+            }
+        }
+        if row.grade.as_ref().is_some_and(|grade| grade.chars().count() > 16) {
+            return Ok(roster::Action::Invalid(
+                "Grade is longer than 16 characters".to_string(),
+            ));
+        }
+        if let Some(student_id) = &row.student_id {
+            if student_id.chars().count() > STUDENT_ID_MAX_LENGTH {
+                return Ok(roster::Action::Invalid(format!(
+                    "Student ID is longer than {} characters",
+                    STUDENT_ID_MAX_LENGTH
+                )));
+            }
+            if !seen.student_ids.insert(student_id.clone()) {
+                return Ok(roster::Action::Invalid(
+                    "Student ID appears more than once".to_string(),
+                ));
+            }
+        }
+        let role_id = match &row.role {
+            Some(role) => match roles.get(role) {
+                Some((role_id, true)) => Some(*role_id),
+                Some((_, false)) => {
+                    return Ok(roster::Action::Invalid(format!("You can't give the role {}", role)));
+                }
+                None => return Ok(roster::Action::Invalid(format!("Unknown role {}", role))),
+            },
+            None => None,
+        };
+
+        // Find the user the row is about, and make sure nobody else has the email
+        let user_entity = db::users::Entity::find()
+            .filter(username_condition(&row.username))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .max_by_key(|entity| entity.username == row.username);
+        if let Some(email) = &row.email
+            && let Some(owner) = db::users::Entity::find()
+                .filter(db::users::Column::Email.eq(email))
+                .one(&self.db)
+                .await?
+            && user_entity.as_ref().is_none_or(|entity| entity.id != owner.id)
+        {
+            return Ok(roster::Action::Invalid(format!(
+                "Email already belongs to {}",
+                owner.username
+            )));
+        }
+        if let Some(student_id) = &row.student_id
+            && let Some(owner) = db::users::Entity::find()
+                .filter(db::users::Column::StudentId.eq(student_id))
+                .one(&self.db)
+                .await?
+            && user_entity.as_ref().is_none_or(|entity| entity.id != owner.id)
+        {
+            return Ok(roster::Action::Invalid(format!(
+                "Student ID already belongs to {}",
+                owner.username
+            )));
+        }
+        let Some(user_entity) = user_entity else {
+            return Ok(roster::Action::Create);
+        };
+
+        // Describe what changes for existing users, leaving blank cells as they are
+        let role_name = |role_id: i64| {
+            roles
+                .iter()
+                .find(|(_, (id, _))| *id == role_id)
+                .map_or("unknown", |(name, _)| name.as_str())
+        };
+        let mut changes = Vec::new();
+        if row.email.is_some() && row.email != user_entity.email {
+            changes.push(format!(
+                "email: {} → {}",
+                user_entity.email.as_deref().unwrap_or("none"),
+                row.email.as_deref().unwrap_or("none")
+            ));
+        }
+        if row.grade.is_some() && row.grade != user_entity.grade {
+            changes.push(format!(
+                "grade: {} → {}",
+                user_entity.grade.as_deref().unwrap_or("none"),
+                row.grade.as_deref().unwrap_or("none")
+            ));
+        }
+        if row.student_id.is_some() && row.student_id != user_entity.student_id {
+            changes.push(format!(
+                "student ID: {} → {}",
+                user_entity.student_id.as_deref().unwrap_or("none"),
+                row.student_id.as_deref().unwrap_or("none")
+            ));
+        }
+        if let Some(role_id) = role_id
+            && role_id != user_entity.role_id
+        {
+            if issuer.is_some_and(|issuer| issuer.id == user_entity.id) {
+                return Ok(roster::Action::Invalid(
+                    "You can't change your own role".to_string(),
+                ));
+            }
+            changes.push(format!(
+                "role: {} → {}",
+                role_name(user_entity.role_id),
+                role_name(role_id)
+            ));
+        }
+        if changes.is_empty() {
+            return Ok(roster::Action::Skip);
+        }
+
+        // Only users ranking no higher than the issuer can be changed
+        if !roles
+            .get(role_name(user_entity.role_id))
+            .is_some_and(|(_, grantable)| *grantable)
+        {
+            return Ok(roster::Action::Invalid(format!(
+                "You can't change {}",
+                user_entity.username
+            )));
+        }
+        Ok(roster::Action::Update {
+            user_id: user_entity.id,
+            changes,
+        })
+    }
+
+    /// Import a planned roster in a single transaction
+    ///
+    /// New users get a temporary password, or an invite token for setting their own password when
+    /// they have an email.
+    pub async fn apply_roster(&self, planned: Vec<roster::PlannedRow>) -> Result<Vec<roster::Outcome>, Error> {
+        if planned
+            .iter()
+            .any(|planned_row| matches!(planned_row.action, roster::Action::Invalid(_)))
+        {
+            return Err(Error::InvalidRoster);
+        }
+
+        let transaction = self.db.begin().await?;
+        let roles = db::roles::Entity::find()
+            .all(&transaction)
+            .await?
+            .into_iter()
+            .map(|role_entity| (role_entity.name, role_entity.id))
+            .collect::<HashMap<_, _>>();
+        let role_id = |role: Option<&String>| {
+            roles
+                .get(role.map_or(roles::STUDENT, String::as_str))
+                .copied()
+                .ok_or(Error::RoleNotFound)
+        };
+
+        let mut outcomes = Vec::with_capacity(planned.len());
+        for planned_row in planned {
+            let row = &planned_row.row;
+            let mut outcome = roster::Outcome {
+                planned: planned_row.clone(),
+                temporary_password: None,
+                invite_token: None,
+            };
+            match &planned_row.action {
+                roster::Action::Create => {
+                    let password = tokens::generate_password();
+                    let user_entity = db::users::Entity::insert(db::users::ActiveModel {
+                        username: Set(row.username.clone()),
+                        normalized_username: Set(normalize_username(&row.username)),
+                        password_hash: Set(self.hash_password(&password)?),
+                        session_secret: Set(tokens::generate().0),
+                        role_id: Set(role_id(row.role.as_ref())?),
+                        email: Set(row.email.clone()),
+                        active: Set(true),
+                        grade: Set(row.grade.clone()),
+                        student_id: Set(row.student_id.clone()),
+                        ..Default::default()
+                    })
+                    .exec_with_returning(&transaction)
+                    .await
+                    .map_err(user_conflict)?;
+
+                    // Users with an email choose their own password through a longer lived reset link
+                    if row.email.is_some() {
+                        let (token, token_hash) = tokens::generate();
+                        db::password_reset_tokens::Entity::insert(
+                            db::password_reset_tokens::ActiveModel {
+                                user_id: Set(user_entity.id),
+                                token_hash: Set(token_hash),
+                                expires_at: Set(OffsetDateTime::now_utc() + ROSTER_INVITE_LIFETIME),
+                                used_at: Set(None),
+                                ..Default::default()
+                            },
+                        )
+                        .exec(&transaction)
+                        .await?;
+                        outcome.invite_token = Some(token);
+                    } else {
+                        outcome.temporary_password = Some(password);
+                    }
+                }
+                roster::Action::Update { user_id, .. } => {
+                    let mut user_entity = db::users::ActiveModel {
+                        id: Unchanged(*user_id),
+                        ..Default::default()
+                    };
+                    if let Some(email) = &row.email {
+                        user_entity.email = Set(Some(email.clone()));
+                    }
+                    if let Some(grade) = &row.grade {
+                        user_entity.grade = Set(Some(grade.clone()));
+                    }
+                    if let Some(student_id) = &row.student_id {
+                        user_entity.student_id = Set(Some(student_id.clone()));
+                    }
+                    if row.role.is_some() {
+                        user_entity.role_id = Set(role_id(row.role.as_ref())?);
+                    }
+                    user_entity
+                        .update(&transaction)
+                        .await
+                        .map_err(user_conflict)?;
+                }
+                roster::Action::Skip | roster::Action::Invalid(_) => {}
+            }
+            outcomes.push(outcome);
+        }
+        transaction.commit().await?;
+
+        Ok(outcomes)
+    }
+
+    /// Delete a user
+    pub async fn delete_user(&self, user_id: i64) -> Result<(), Error> {
+        db::users::Entity::delete_by_id(user_id).exec(&self.db).await?;
+        Ok(())
+    }
+
+    /// Change a user's password after checking their current one
+    pub async fn change_password(&self, user_id: i64, current_password: impl AsRef<str>, new_password: impl AsRef<str>) -> Result<User, Error> {
+        // Get the user entity in the database
+        let (user_entity, role_entity) = db::users::Entity::find_by_id(user_id)
+            .find_also_related(db::roles::Entity)
+            .one(&self.db)
+            .await?
+            .and_then(|(entity, role)| Some((entity, role?)))
+            .ok_or(Error::UserNotFound)?;
+
+        // Make sure the current password is correct
+        if !verify_password(current_password, &user_entity.password_hash)? {
+            return Err(Error::IncorrectPassword);
+        }
+
+        // Store the new password
+        let user_entity = self.set_password(&self.db, user_entity, new_password).await?;
+
+        Ok((user_entity, role_entity).into())
+    }
+
+    /// Issue a password reset token for the active user with an email, if there is one
+    pub async fn request_password_reset(&self, email: impl AsRef<str>) -> Result<Option<(User, String)>, Error> {
+        // Find the user
+        let Some((user_entity, Some(role_entity))) = db::users::Entity::find()
+            .filter(db::users::Column::Email.eq(email.as_ref()))
+            .filter(db::users::Column::Active.eq(true))
+            .find_also_related(db::roles::Entity)
+            .one(&self.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        // Store the hashed token
+        let (token, token_hash) = tokens::generate();
+        db::password_reset_tokens::Entity::insert(db::password_reset_tokens::ActiveModel {
+            user_id: Set(user_entity.id),
+            token_hash: Set(token_hash),
+            expires_at: Set(OffsetDateTime::now_utc() + PASSWORD_RESET_LIFETIME),
+            used_at: Set(None),
+            ..Default::default()
+        })
+        .exec(&self.db)
+        .await?;
+
+        Ok(Some(((user_entity, role_entity).into(), token)))
+    }
+
+    /// Set a new password using a password reset token, using up every outstanding token
+    pub async fn reset_password(&self, token: impl AsRef<str>, new_password: impl AsRef<str>) -> Result<User, Error> {
+        // Use up the token first so that only one request can ever redeem it
+        let now = OffsetDateTime::now_utc();
+        let token_hash = tokens::hash(token.as_ref());
+        let transaction = self.db.begin().await?;
+        let result = db::password_reset_tokens::Entity::update_many()
+            .col_expr(db::password_reset_tokens::Column::UsedAt, Expr::value(now))
+            .filter(db::password_reset_tokens::Column::TokenHash.eq(token_hash.clone()))
+            .filter(db::password_reset_tokens::Column::UsedAt.is_null())
+            .filter(db::password_reset_tokens::Column::ExpiresAt.gt(now))
+            .exec(&transaction)
+            .await?;
+        if result.rows_affected != 1 {
+            return Err(Error::InvalidToken);
+        }
+        let token_entity = db::password_reset_tokens::Entity::find()
+            .filter(db::password_reset_tokens::Column::TokenHash.eq(token_hash))
+            .one(&transaction)
+            .await?
+            .ok_or(Error::InvalidToken)?;
+
+        // Find the user the token belongs to
+        let (user_entity, role_entity) = db::users::Entity::find_by_id(token_entity.user_id)
+            .find_also_related(db::roles::Entity)
+            .one(&transaction)
+            .await?
+            .and_then(|(entity, role)| Some((entity, role?)))
+            .ok_or(Error::UserNotFound)?;
+
+        // Set the password and use up the user's other tokens together
+        let user_entity = self.set_password(&transaction, user_entity, new_password).await?;
+        db::password_reset_tokens::Entity::update_many()
+            .col_expr(db::password_reset_tokens::Column::UsedAt, Expr::value(now))
+            .filter(db::password_reset_tokens::Column::UserId.eq(user_entity.id))
+            .filter(db::password_reset_tokens::Column::UsedAt.is_null())
+            .exec(&transaction)
+            .await?;
+        transaction.commit().await?;
+
+        Ok((user_entity, role_entity).into())
+    }
+
+    /// Hash and store a new password for a user, signing out every session they have
+    async fn set_password(&self, db: &impl ConnectionTrait, user_entity: db::users::Model, password: impl AsRef<str>) -> Result<db::users::Model, Error> {
+        let mut user_entity: db::users::ActiveModel = user_entity.into();
+        user_entity.password_hash = Set(self.hash_password(password)?);
+        user_entity.session_secret = Set(tokens::generate().0);
+        Ok(user_entity.update(db).await?)
+    }
+
+    /// Hash the same password again with the current settings, keeping the user's sessions
+    async fn rehash_password(&self, user_entity: db::users::Model, password: impl AsRef<str>) -> Result<db::users::Model, Error> {
+        let mut user_entity: db::users::ActiveModel = user_entity.into();
+        user_entity.password_hash = Set(self.hash_password(password)?);
+        Ok(user_entity.update(&self.db).await?)
+    }
+
+    /// Check whether a user has finished enrolling in two-factor authentication
+    pub async fn two_factor_enabled(&self, user_id: i64) -> Result<bool, Error> {
+        Ok(self.enabled_totp_credential(user_id).await?.is_some())
+    }
+
+    /// Start enrolling a user in two-factor authentication, returning the new secret and its provisioning URI
+    pub async fn begin_two_factor_setup(&self, user: &User) -> Result<(String, String), Error> {
+        if self.two_factor_enabled(user.id).await? {
+            return Err(Error::TwoFactorAlreadyEnabled);
+        }
+
+        // Generate the secret
+        let secret = totp::generate_secret();
+        let provisioning_uri = totp::provisioning_uri(&secret, &user.username)?;
+
+        // Replace any setup that was never finished
+        let transaction = self.db.begin().await?;
+        db::totp_credentials::Entity::delete_many()
+            .filter(db::totp_credentials::Column::UserId.eq(user.id))
+            .exec(&transaction)
+            .await?;
+        db::totp_credentials::Entity::insert(db::totp_credentials::ActiveModel {
+            user_id: Set(user.id),
+            secret: Set(secret.clone()),
+            enabled_at: Set(None),
+            last_used_step: Set(None),
+            ..Default::default()
+        })
+        .exec(&transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok((secret, provisioning_uri))
+    }
+
+    /// Finish enrolling in two-factor authentication with a code from the authenticator, returning new recovery codes
+    pub async fn enable_two_factor(&self, user_id: i64, code: impl AsRef<str>) -> Result<Vec<String>, Error> {
+        // Find the unfinished setup
+        let credential_entity = db::totp_credentials::Entity::find()
+            .filter(db::totp_credentials::Column::UserId.eq(user_id))
+            .filter(db::totp_credentials::Column::EnabledAt.is_null())
+            .one(&self.db)
+            .await?
+            .ok_or(Error::TwoFactorNotSetUp)?;
+
+        // Make sure the authenticator was set up correctly
+        let step = totp::check(&credential_entity.secret, code.as_ref())?
+            .ok_or(Error::InvalidTwoFactorCode)?;
+
+        // Enable it and issue the recovery codes together
+        let recovery_codes = totp::generate_recovery_codes();
+        let transaction = self.db.begin().await?;
+        let mut credential_entity: db::totp_credentials::ActiveModel = credential_entity.into();
+        credential_entity.enabled_at = Set(Some(OffsetDateTime::now_utc()));
+        credential_entity.last_used_step = Set(Some(step as i64));
+        credential_entity.update(&transaction).await?;
+        self.replace_recovery_codes(&transaction, user_id, &recovery_codes)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(recovery_codes)
+    }
+
+    /// Turn off two-factor authentication after checking the user's password
+    pub async fn disable_two_factor(&self, user_id: i64, password: impl AsRef<str>) -> Result<(), Error> {
+        // Make sure the password is correct
+        let user_entity = db::users::Entity::find_by_id(user_id)
+            .one(&self.db)
+            .await?
+            .ok_or(Error::UserNotFound)?;
+        if !verify_password(password, &user_entity.password_hash)? {
+            return Err(Error::IncorrectPassword);
+        }
+
+        // Remove the secret along with the recovery codes
+        let transaction = self.db.begin().await?;
+        db::totp_credentials::Entity::delete_many()
+            .filter(db::totp_credentials::Column::UserId.eq(user_id))
+            .exec(&transaction)
+            .await?;
+        db::recovery_codes::Entity::delete_many()
+            .filter(db::recovery_codes::Column::UserId.eq(user_id))
+            .exec(&transaction)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    /// Replace a user's recovery codes after checking a second factor
+    pub async fn regenerate_recovery_codes(&self, user_id: i64, code: impl AsRef<str>) -> Result<Vec<String>, Error> {
+        if !self.two_factor_enabled(user_id).await? {
+            return Err(Error::TwoFactorNotSetUp);
+        }
+        if !self.verify_two_factor(user_id, code).await? {
+            return Err(Error::InvalidTwoFactorCode);
+        }
+
+        let recovery_codes = totp::generate_recovery_codes();
+        let transaction = self.db.begin().await?;
+        self.replace_recovery_codes(&transaction, user_id, &recovery_codes)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(recovery_codes)
+    }
+
+    /// Check a second factor, accepting either a code from the authenticator or an unused recovery code
+    pub async fn verify_two_factor(&self, user_id: i64, code: impl AsRef<str>) -> Result<bool, Error> {
+        let code = code.as_ref().trim();
+        let Some(credential_entity) = self.enabled_totp_credential(user_id).await? else {
+            return Ok(false);
+        };
+
+        // Authenticator codes are six digits, anything else is a recovery code
+        if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) {
+            let Some(step) = totp::check(&credential_entity.secret, code)? else {
+                return Ok(false);
+            };
+
+            // Only accept each time step once so an observed code can't be replayed
+            let result = db::totp_credentials::Entity::update_many()
+                .col_expr(
+                    db::totp_credentials::Column::LastUsedStep,
+                    Expr::value(step as i64),
+                )
+                .filter(db::totp_credentials::Column::Id.eq(credential_entity.id))
+                .filter(
+                    Condition::any()
+                        .add(db::totp_credentials::Column::LastUsedStep.is_null())
+                        .add(db::totp_credentials::Column::LastUsedStep.lt(step as i64)),
+                )
+                .exec(&self.db)
+                .await?;
+            Ok(result.rows_affected == 1)
+        } else {
+            // Use up the recovery code
+            let result = db::recovery_codes::Entity::update_many()
+                .col_expr(
+                    db::recovery_codes::Column::UsedAt,
+                    Expr::value(OffsetDateTime::now_utc()),
+                )
+                .filter(db::recovery_codes::Column::UserId.eq(user_id))
+                .filter(
+                    db::recovery_codes::Column::CodeHash
+                        .eq(tokens::hash(&totp::normalize_recovery_code(code))),
+                )
+                .filter(db::recovery_codes::Column::UsedAt.is_null())
+                .exec(&self.db)
+                .await?;
+            Ok(result.rows_affected == 1)
+        }
+    }
+
+    /// Get a user's TOTP credential if they finished enrolling
+    async fn enabled_totp_credential(&self, user_id: i64) -> Result<Option<db::totp_credentials::Model>, Error> {
+        Ok(db::totp_credentials::Entity::find()
+            .filter(db::totp_credentials::Column::UserId.eq(user_id))
+            .filter(db::totp_credentials::Column::EnabledAt.is_not_null())
+            .one(&self.db)
+            .await?)
+    }
+
+    /// Check whether a user has a second factor, either an authenticator app or a passkey
+    pub async fn has_second_factor(&self, user_id: i64) -> Result<bool, Error> {
+        Ok(self.two_factor_enabled(user_id).await? || !self.passkeys(user_id).await?.is_empty())
+    }
+
+    /// Get a user's passkeys, oldest first
+    pub async fn passkeys(&self, user_id: i64) -> Result<Vec<db::passkeys::Model>, Error> {
+        Ok(db::passkeys::Entity::find()
+            .filter(db::passkeys::Column::UserId.eq(user_id))
+            .order_by_asc(db::passkeys::Column::CreatedAt)
+            .all(&self.db)
+            .await?)
+    }
+
+    /// Store a passkey a user just registered
+    pub async fn add_passkey(
+        &self,
+        user_id: i64,
+        name: impl AsRef<str>,
+        credential: webauthn::NewCredential,
+    ) -> Result<db::passkeys::Model, Error> {
+        db::passkeys::ActiveModel {
+            user_id: Set(user_id),
+            name: Set(name.as_ref().to_string()),
+            credential_id: Set(credential.id),
+            public_key: Set(credential.public_key),
+            sign_count: Set(credential.sign_count.into()),
+            created_at: Set(OffsetDateTime::now_utc()),
+            last_used_at: Set(None),
+            ..Default::default()
+        }
+        .insert(&self.db)
+        .await
+        .map_err(|err| match err.sql_err() {
+            Some(SqlErr::UniqueConstraintViolation(_)) => Error::PasskeyAlreadyAdded,
+            _ => Error::DatabaseError(err),
+        })
+    }
+
+    /// Remove one of a user's passkeys after checking their password, returning it if it existed
+    pub async fn remove_passkey(&self, user_id: i64, passkey_id: i64, password: impl AsRef<str>) -> Result<Option<db::passkeys::Model>, Error> {
+        // Make sure the password is correct
+        let user_entity = db::users::Entity::find_by_id(user_id)
+            .one(&self.db)
+            .await?
+            .ok_or(Error::UserNotFound)?;
+        if !verify_password(password, &user_entity.password_hash)? {
+            return Err(Error::IncorrectPassword);
+        }
+
+        let Some(passkey_entity) = db::passkeys::Entity::find_by_id(passkey_id)
+            .filter(db::passkeys::Column::UserId.eq(user_id))
+            .one(&self.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        db::passkeys::Entity::delete_by_id(passkey_entity.id)
             .exec(&self.db)
             .await?;
+        Ok(Some(passkey_entity))
+    }
+
+    /// Check a passkey's answer to a login challenge, returning the active user it belongs to
+    ///
+    /// Unknown passkeys and ones of users who are gone give `None`, while answers that don't hold
+    /// up give `Error::Passkey`.
+    pub async fn authenticate_passkey(
+        &self,
+        relying_party: &webauthn::RelyingParty,
+        pending: &webauthn::PendingCeremony,
+        assertion: webauthn::Assertion<'_>,
+    ) -> Result<Option<User>, Error> {
+        // Find the passkey, which has to be the expected user's when there is one
+        let Some(passkey_entity) = db::passkeys::Entity::find()
+            .filter(db::passkeys::Column::CredentialId.eq(assertion.credential_id))
+            .one(&self.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        if pending
+            .user_id
+            .is_some_and(|user_id| user_id != passkey_entity.user_id)
+            || assertion
+                .user_handle
+                .is_some_and(|handle| handle != webauthn::user_handle(passkey_entity.user_id))
+        {
+            return Ok(None);
         }
+
+        // Check the signature, then remember the new count so a copy of the passkey stands out
+        let sign_count = relying_party.finish_authentication(
+            pending,
+            assertion,
+            &passkey_entity.public_key,
+            u32::try_from(passkey_entity.sign_count).unwrap_or(u32::MAX),
+        )?;
+        db::passkeys::Entity::update_many()
+            .col_expr(db::passkeys::Column::SignCount, Expr::value(i64::from(sign_count)))
+            .col_expr(
+                db::passkeys::Column::LastUsedAt,
+                Expr::value(OffsetDateTime::now_utc()),
+            )
+            .filter(db::passkeys::Column::Id.eq(passkey_entity.id))
+            .exec(&self.db)
+            .await?;
+
+        // Only tell suspended users why once they proved who they are
+        self.ensure_not_suspended(passkey_entity.user_id).await?;
+        self.get_user(&passkey_entity.user_id).await
+    }
+
+    /// Store the hashes of a new set of recovery codes, dropping the old ones
+    async fn replace_recovery_codes(&self, db: &impl ConnectionTrait, user_id: i64, recovery_codes: &[String]) -> Result<(), Error> {
+        db::recovery_codes::Entity::delete_many()
+            .filter(db::recovery_codes::Column::UserId.eq(user_id))
+            .exec(db)
+            .await?;
+        db::recovery_codes::Entity::insert_many(recovery_codes.iter().map(|code| {
+            db::recovery_codes::ActiveModel {
+                user_id: Set(user_id),
+                code_hash: Set(tokens::hash(code)),
+                used_at: Set(None),
+                ..Default::default()
+            }
+        }))
+        .exec(db)
+        .await?;
         Ok(())
     }
+
+    /// Create a personal API token, returning it along with the only copy of its secret
+    pub async fn create_api_token(
+        &self,
+        user: &User,
+        name: impl AsRef<str>,
+        scopes: &[String],
+        lifetime: Duration,
+    ) -> Result<(db::api_tokens::Model, String), Error> {
+        // Only allow known scopes, and the admin scope only for admins
+        if scopes.is_empty()
+            || scopes
+                .iter()
+                .any(|scope| !scopes::ALL.contains(&scope.as_str()))
+        {
+            return Err(Error::InvalidScope);
+        }
+        if scopes.iter().any(|scope| scope == scopes::ADMIN)
+            && !self.has_perm(user, permissions::ADMIN_PANEL.into()).await?
+        {
+            return Err(Error::InvalidScope);
+        }
+
+        // Store the token
+        let (token, token_hash) = tokens::generate();
+        let now = OffsetDateTime::now_utc();
+        let token_entity = db::api_tokens::ActiveModel {
+            user_id: Set(user.id),
+            name: Set(name.as_ref().to_string()),
+            token_hash: Set(token_hash),
+            scopes: Set(scopes.join(",")),
+            created_at: Set(now),
+            expires_at: Set(now + lifetime),
+            last_used_at: Set(None),
+            ..Default::default()
+        }
+        .insert(&self.db)
+        .await?;
+
+        Ok((token_entity, token))
+    }
+
+    /// Get a user's unexpired API tokens, newest first
+    pub async fn api_tokens(&self, user_id: i64) -> Result<Vec<db::api_tokens::Model>, Error> {
+        Ok(db::api_tokens::Entity::find()
+            .filter(db::api_tokens::Column::UserId.eq(user_id))
+            .filter(db::api_tokens::Column::ExpiresAt.gt(OffsetDateTime::now_utc()))
+            .order_by_desc(db::api_tokens::Column::CreatedAt)
+            .all(&self.db)
+            .await?)
+    }
+
+    /// Revoke one of a user's API tokens, returning whether it existed
+    pub async fn revoke_api_token(&self, user_id: i64, token_id: i64) -> Result<bool, Error> {
+        let result = db::api_tokens::Entity::delete_many()
+            .filter(db::api_tokens::Column::Id.eq(token_id))
+            .filter(db::api_tokens::Column::UserId.eq(user_id))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Get the active user an unexpired API token belongs to, limited to the token's scopes
+    pub async fn authenticate_api_token(&self, token: impl AsRef<str>) -> Result<Option<User>, Error> {
+        // Find the token
+        let now = OffsetDateTime::now_utc();
+        let Some(token_entity) = db::api_tokens::Entity::find()
+            .filter(db::api_tokens::Column::TokenHash.eq(tokens::hash(token.as_ref())))
+            .filter(db::api_tokens::Column::ExpiresAt.gt(now))
+            .one(&self.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let Some(mut user) = self.get_user(&token_entity.user_id).await? else {
+            return Ok(None);
+        };
+
+        // Remember when it was last used
+        db::api_tokens::Entity::update_many()
+            .col_expr(db::api_tokens::Column::LastUsedAt, Expr::value(now))
+            .filter(db::api_tokens::Column::Id.eq(token_entity.id))
+            .exec(&self.db)
+            .await?;
+
+        user.token_scopes = Some(
+            token_entity
+                .scopes
+                .split(',')
+                .map(str::to_string)
+                .collect(),
+        );
+        Ok(Some(user))
+    }
+
+    /// Suspend or ban a user until a time, or until lifted when there is no end
+    ///
+    /// Suspended users can't log in, and their sessions and API tokens stop working on their next
+    /// request.
+    pub async fn suspend_user(
+        &self,
+        issuer: &User,
+        user_id: i64,
+        kind: impl AsRef<str>,
+        reason: impl AsRef<str>,
+        ends_at: Option<OffsetDateTime>,
+    ) -> Result<db::suspensions::Model, Error> {
+        Ok(db::suspensions::ActiveModel {
+            user_id: Set(user_id),
+            issued_by: Set(Some(issuer.id)),
+            kind: Set(kind.as_ref().to_string()),
+            reason: Set(reason.as_ref().to_string()),
+            created_at: Set(OffsetDateTime::now_utc()),
+            ends_at: Set(ends_at),
+            lifted_at: Set(None),
+            ..Default::default()
+        }
+        .insert(&self.db)
+        .await?)
+    }
+
+    /// Get every suspension still in force along with the suspended user, newest first
+    pub async fn suspensions(&self) -> Result<Vec<(db::suspensions::Model, db::users::Model)>, Error> {
+        Ok(db::suspensions::Entity::find()
+            .filter(active_suspension_condition())
+            .find_also_related(db::users::Entity)
+            .order_by_desc(db::suspensions::Column::CreatedAt)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .filter_map(|(suspension, user)| Some((suspension, user?)))
+            .collect())
+    }
+
+    /// Lift a suspension still in force, returning it along with the user if there was one
+    pub async fn lift_suspension(
+        &self,
+        suspension_id: i64,
+    ) -> Result<Option<(db::suspensions::Model, db::users::Model)>, Error> {
+        let Some((suspension, Some(user))) = db::suspensions::Entity::find_by_id(suspension_id)
+            .filter(active_suspension_condition())
+            .find_also_related(db::users::Entity)
+            .one(&self.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let result = db::suspensions::Entity::update_many()
+            .col_expr(
+                db::suspensions::Column::LiftedAt,
+                Expr::value(OffsetDateTime::now_utc()),
+            )
+            .filter(db::suspensions::Column::Id.eq(suspension_id))
+            .filter(active_suspension_condition())
+            .exec(&self.db)
+            .await?;
+        Ok((result.rows_affected > 0).then_some((suspension, user)))
+    }
+
+    /// Get the suspension keeping a user out the longest, if any is in force
+    pub async fn active_suspension(&self, user_id: i64) -> Result<Option<db::suspensions::Model>, Error> {
+        Ok(db::suspensions::Entity::find()
+            .filter(db::suspensions::Column::UserId.eq(user_id))
+            .filter(active_suspension_condition())
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .max_by_key(|suspension| {
+                suspension
+                    .ends_at
+                    .map_or(i64::MAX, |ends_at| ends_at.unix_timestamp())
+            }))
+    }
+
+    /// Refuse users with a suspension in force
+    async fn ensure_not_suspended(&self, user_id: i64) -> Result<(), Error> {
+        match self.active_suspension(user_id).await? {
+            Some(suspension) => Err(Error::Suspended(Box::new(suspension))),
+            None => Ok(()),
+        }
+    }
+
+    /// Record an admin starting to view the site as another user
+    pub async fn start_impersonation(&self, admin: &User, user_id: i64) -> Result<Impersonation, Error> {
+        let impersonation = db::impersonations::ActiveModel {
+            admin_id: Set(Some(admin.id)),
+            user_id: Set(Some(user_id)),
+            started_at: Set(OffsetDateTime::now_utc()),
+            ended_at: Set(None),
+            ..Default::default()
+        }
+        .insert(&self.db)
+        .await?;
+        Ok(Impersonation {
+            id: impersonation.id,
+            admin_id: admin.id,
+            admin_username: admin.username.clone(),
+        })
+    }
+
+    /// Record an admin no longer viewing the site as another user
+    pub async fn end_impersonation(&self, impersonation_id: i64) -> Result<(), Error> {
+        db::impersonations::Entity::update_many()
+            .col_expr(
+                db::impersonations::Column::EndedAt,
+                Expr::value(OffsetDateTime::now_utc()),
+            )
+            .filter(db::impersonations::Column::Id.eq(impersonation_id))
+            .filter(db::impersonations::Column::EndedAt.is_null())
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Get the most recent times admins viewed the site as other users, along with both of them
+    pub async fn impersonations(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<(db::impersonations::Model, Option<String>, Option<String>)>, Error> {
+        let impersonations = db::impersonations::Entity::find()
+            .order_by_desc(db::impersonations::Column::StartedAt)
+            .limit(limit)
+            .all(&self.db)
+            .await?;
+
+        // Look up the usernames of everyone involved at once
+        let user_ids = impersonations
+            .iter()
+            .flat_map(|impersonation| [impersonation.admin_id, impersonation.user_id])
+            .flatten()
+            .collect::<HashSet<_>>();
+        let usernames = db::users::Entity::find()
+            .filter(db::users::Column::Id.is_in(user_ids))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|user| (user.id, user.username))
+            .collect::<HashMap<_, _>>();
+        Ok(impersonations
+            .into_iter()
+            .map(|impersonation| {
+                let admin = impersonation.admin_id.and_then(|id| usernames.get(&id).cloned());
+                let user = impersonation.user_id.and_then(|id| usernames.get(&id).cloned());
+                (impersonation, admin, user)
+            })
+            .collect())
+    }
+
+    /// Get all roles along with the permissions they grant
+    pub async fn roles(&self) -> Result<Vec<Role>, Error> {
+        let role_entities = db::roles::Entity::find()
+            .order_by_asc(db::roles::Column::Id)
+            .all(&self.db)
+            .await?;
+
+        let mut roles = Vec::with_capacity(role_entities.len());
+        for role_entity in role_entities {
+            let mut permissions = self
+                .role_permissions(role_entity.id)
+                .await?
+                .into_iter()
+                .map(|permission| permission.0)
+                .collect::<Vec<_>>();
+            permissions.sort();
+            roles.push(Role {
+                name: role_entity.name,
+                permissions,
+            });
+        }
+        Ok(roles)
+    }
+
+    /// Check whether a user holds every permission a role grants
+    pub async fn can_grant_role(&self, user: &User, role: impl AsRef<str>) -> Result<bool, Error> {
+        let role_entity = db::roles::Entity::find()
+            .filter(db::roles::Column::Name.eq(role.as_ref()))
+            .one(&self.db)
+            .await?
+            .ok_or(Error::RoleNotFound)?;
+        let role_permissions = self.role_permissions(role_entity.id).await?;
+        let user_permissions = self.get_all_permissions(user).await?;
+        Ok(role_permissions.is_subset(&user_permissions))
+    }
+
+    /// Hash a password with the configured Argon2 settings
+    fn hash_password(&self, password: impl AsRef<str>) -> Result<String, Error> {
+        Ok(self
+            .password_settings
+            .hasher()
+            .hash_password(
+                password.as_ref().as_bytes(),
+                &SaltString::generate(&mut OsRng),
+            )?
+            .to_string())
+    }
+
+    /// Get the permissions granted by a role
+    async fn role_permissions(&self, role_id: i64) -> Result<HashSet<Permission>, Error> {
+        let permission_entities = db::permissions::Entity::find()
+            .inner_join(db::role_permissions::Entity)
+            .filter(db::role_permissions::Column::RoleId.eq(role_id))
+            .all(&self.db)
+            .await?;
+        Ok(permission_entities
+            .into_iter()
+            .map(|entity| Permission(entity.name))
+            .collect())
+    }
+}
+
+/// Normalize a username so names differing only by case or surrounding whitespace match
+pub fn normalize_username(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+/// Normalize a student ID so IDs differing only by case or surrounding whitespace match
+pub fn normalize_student_id(student_id: &str) -> String {
+    student_id.trim().to_uppercase()
+}
+
+/// Match a username ignoring case
+fn username_condition(username: &str) -> Condition {
+    Condition::all().add(db::users::Column::NormalizedUsername.eq(normalize_username(username)))
+}
+
+/// Match text containing a search, taking wildcards in it literally
+fn contains_pattern(search: &str) -> LikeExpr {
+    let escaped = search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    LikeExpr::new(format!("%{}%", escaped)).escape('\\')
+}
+
+/// Match suspensions that haven't been lifted or run out
+fn active_suspension_condition() -> Condition {
+    Condition::all()
+        .add(db::suspensions::Column::LiftedAt.is_null())
+        .add(
+            Condition::any()
+                .add(db::suspensions::Column::EndsAt.is_null())
+                .add(db::suspensions::Column::EndsAt.gt(OffsetDateTime::now_utc())),
+        )
+}
+
+/// Turn unique index violations on the users table into the errors they stand for
+fn user_conflict(err: sea_orm::DbErr) -> Error {
+    match err.sql_err() {
+        Some(SqlErr::UniqueConstraintViolation(message))
+            if message.contains("normalizedUsername")
+                || message.contains("idx_users_normalized_username") =>
+        {
+            Error::UsernameTaken
+        }
+        Some(SqlErr::UniqueConstraintViolation(message))
+            if message.contains("email") || message.contains("idx_users_email") =>
+        {
+            Error::EmailAlreadyExists
+        }
+        Some(SqlErr::UniqueConstraintViolation(message))
+            if message.contains("studentId") || message.contains("idx_users_student_id") =>
+        {
+            Error::StudentIdTaken
+        }
+        _ => Error::DatabaseError(err),
+    }
+}
+
+/// Find an unused username based on the local part of an email
+async fn available_username(db: &impl ConnectionTrait, email: &str) -> Result<String, Error> {
+    let base = email
+        .split('@')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        .collect::<String>();
+    let base = if base.is_empty() { "user".to_string() } else { base };
+
+    // Add a number to the end until nobody has it, shortening the name to make room for it
+    let mut number = 1;
+    loop {
+        let suffix = if number == 1 {
+            String::new()
+        } else {
+            number.to_string()
+        };
+        let length = base.len().min(USERNAME_MAX_LENGTH - suffix.len());
+        let username = format!("{}{}", &base[..length], suffix);
+        if db::users::Entity::find()
+            .filter(db::users::Column::NormalizedUsername.eq(normalize_username(&username)))
+            .one(db)
+            .await?
+            .is_none()
+        {
+            return Ok(username);
+        }
+        number += 1;
+    }
+}
+
+/// Check a password against a stored Argon2 hash, using the settings it was made with
+fn verify_password(password: impl AsRef<str>, password_hash: &str) -> Result<bool, Error> {
+    let password_hash = PasswordHash::new(password_hash)?;
+    Ok(Argon2::default()
+        .verify_password(password.as_ref().as_bytes(), &password_hash)
+        .is_ok())
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Credentials {
     pub username: String,
     pub password: SecretString,
+    /// Keep the login after the browser closes, instead of only until then
+    #[serde(default)]
+    pub remember: bool,
 }
 
 #[async_trait]
@@ -126,24 +2007,30 @@ impl AuthnBackend for Backend {
         &self,
         credentials: Self::Credentials,
     ) -> Result<Option<Self::User>, Self::Error> {
-        // Get the user entity in the datbase
+        // Get the user entity in the datbase, matching older accounts whose names clashed exactly
+        let username = credentials.username.trim();
         let user_entity = db::users::Entity::find()
-            .filter(db::users::Column::Username.eq(credentials.username.clone()))
-            .one(&self.db)
-            .await?;
-
-        // If the user exists...
-        if let Some(entity) = user_entity {
-            let password_hash = PasswordHash::new(&entity.password_hash)?;
-            if Argon2::default()
-                .verify_password(credentials.password.expose_secret().as_bytes(), &password_hash)
-                .is_ok()
-            {
-                Ok(Some(User {
-                    id: entity.id,
-                    username: entity.username,
-                    admin: entity.admin,
-                }))
+            .filter(username_condition(username))
+            .find_also_related(db::roles::Entity)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .max_by_key(|(entity, _)| entity.username == username);
+
+        // If the user exists and is active...
+        if let Some((entity, Some(role))) = user_entity.filter(|(entity, _)| entity.active) {
+            if verify_password(credentials.password.expose_secret(), &entity.password_hash)? {
+                // Only tell suspended users why once they proved who they are
+                self.ensure_not_suspended(entity.id).await?;
+
+                // Bring hashes made with weaker settings up to date while we have the password
+                let entity = if self.password_settings.is_weaker(&entity.password_hash) {
+                    self.rehash_password(entity, credentials.password.expose_secret())
+                        .await?
+                } else {
+                    entity
+                };
+                Ok(Some((entity, role).into()))
             } else {
                 Ok(None)
             }
@@ -153,11 +2040,510 @@ impl AuthnBackend for Backend {
     }
 
     async fn get_user(&self, user_id: &UserId<Self>) -> Result<Option<Self::User>, Self::Error> {
-        let user_entity = db::users::Entity::find_by_id(*user_id).one(&self.db).await?;
-        Ok(user_entity.map(|entity| User {
-            id: entity.id,
-            username: entity.username,
-            admin: entity.admin,
-        }))
+        let user_entity = db::users::Entity::find_by_id(*user_id)
+            .filter(db::users::Column::Active.eq(true))
+            .find_also_related(db::roles::Entity)
+            .one(&self.db)
+            .await?;
+
+        // Suspended users are signed out of every session they still have
+        if let Some((entity, _)) = &user_entity
+            && self.active_suspension(entity.id).await?.is_some()
+        {
+            return Ok(None);
+        }
+        Ok(user_entity.and_then(|(entity, role)| Some((entity, role?).into())))
+    }
+}
+
+#[async_trait]
+impl AuthzBackend for Backend {
+    type Permission = Permission;
+
+    async fn get_group_permissions(
+        &self,
+        user: &Self::User,
+    ) -> Result<HashSet<Self::Permission>, Self::Error> {
+        // Roles that require two-factor authentication grant nothing until the user enrolls
+        if user.require_two_factor && !self.has_second_factor(user.id).await? {
+            return Ok(HashSet::new());
+        }
+
+        // API tokens only carry the role's permissions with the admin scope
+        if !user.has_scope(scopes::ADMIN) {
+            return Ok(HashSet::new());
+        }
+        self.role_permissions(user.role_id).await
+    }
+}
+
+/// Route guard requiring the current user to hold a permission
+///
+/// Attach with `axum::middleware::from_fn_with_state(permission, require_permission)`.
+/// Responds with 401 when nobody is logged in and 403 when the permission is missing.
+pub async fn require_permission(
+    State(permission): State<&'static str>,
+    auth_session: AuthSession<Backend>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(user) = &auth_session.user else {
+        return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+
+    match auth_session.backend.has_perm(user, permission.into()).await {
+        Ok(true) => next.run(request).await,
+        // Tell users whose role is only waiting on a second factor what to do about it
+        Ok(false) if user.require_two_factor => {
+            match auth_session.backend.has_second_factor(user.id).await {
+                Ok(true) => (http::StatusCode::FORBIDDEN, "Forbidden").into_response(),
+                Ok(false) => (http::StatusCode::FORBIDDEN, SECOND_FACTOR_REQUIRED).into_response(),
+                Err(err) => {
+                    (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+                }
+            }
+        }
+        Ok(false) => (http::StatusCode::FORBIDDEN, "Forbidden").into_response(),
+        Err(err) => (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response(),
+    }
+}
+
+/// Middleware logging in requests that carry a personal API token in their `Authorization` header
+///
+/// Attach inside the auth layer so handlers see the token's user through `AuthSession`.
+/// Responds with 401 for unknown or expired tokens and 403 when a token without the admin scope
+/// tries to change something.
+pub async fn bearer_token(
+    mut auth_session: AuthSession<Backend>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(header) = request.headers().get(http::header::AUTHORIZATION) else {
+        return next.run(request).await;
+    };
+    let Some(token) = header
+        .to_str()
+        .ok()
+        .and_then(|header| header.strip_prefix("Bearer "))
+    else {
+        return (http::StatusCode::UNAUTHORIZED, "Invalid authorization header").into_response();
+    };
+
+    let user = match auth_session.backend.authenticate_api_token(token.trim()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return (http::StatusCode::UNAUTHORIZED, "Invalid or expired token").into_response();
+        }
+        Err(err) => {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+    };
+    if !request.method().is_safe() && !user.has_scope(scopes::ADMIN) {
+        return (http::StatusCode::FORBIDDEN, "This token can only read").into_response();
+    }
+
+    auth_session.user = Some(user);
+    request.extensions_mut().insert(auth_session);
+    next.run(request).await
+}
+
+/// Make a new login last for a while after the browser closes, or only until it does
+pub async fn remember_login(
+    session: &Session,
+    lifetime: Option<Duration>,
+) -> Result<(), tower_sessions::session::Error> {
+    match lifetime {
+        Some(lifetime) => {
+            let remember_until = OffsetDateTime::now_utc() + lifetime;
+            session
+                .insert(REMEMBER_UNTIL_KEY, remember_until.unix_timestamp())
+                .await
+        }
+        None => session.remove::<i64>(REMEMBER_UNTIL_KEY).await.map(|_| ()),
+    }
+}
+
+/// Middleware keeping remembered logins alive after the browser closes
+///
+/// The session layer gives every session it saves the same expiry, which ends with the browser.
+/// Attach inside the auth layer, so sessions of remembered logins get their own expiry back
+/// whenever they're saved.
+pub async fn keep_remembered(session: Session, request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    if session.is_modified()
+        && let Ok(Some(remember_until)) = session.get::<i64>(REMEMBER_UNTIL_KEY).await
+        && let Ok(remember_until) = OffsetDateTime::from_unix_timestamp(remember_until)
+    {
+        session.set_expiry(Some(Expiry::AtDateTime(remember_until)));
+    }
+    response
+}
+
+/// Middleware refusing changes while an admin is viewing the site as another user
+///
+/// Attach inside the auth layer to every route except the ones that end the impersonation.
+/// Responds with 403 to any request that isn't a safe method.
+pub async fn forbid_while_impersonating(session: Session, request: Request, next: Next) -> Response {
+    if request.method().is_safe() {
+        return next.run(request).await;
+    }
+    match session.get::<Impersonation>(IMPERSONATION_KEY).await {
+        Ok(Some(_)) => (
+            http::StatusCode::FORBIDDEN,
+            "Changes are blocked while viewing the site as another user",
+        )
+            .into_response(),
+        Ok(None) => next.run(request).await,
+        Err(err) => (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response(),
+    }
+}
+
+/// Route guard refusing requests made with a personal API token
+///
+/// Attach with `axum::middleware::from_fn(require_session)` to routes that manage the account itself.
+pub async fn require_session(
+    auth_session: AuthSession<Backend>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if auth_session
+        .user
+        .as_ref()
+        .is_some_and(|user| user.token_scopes.is_some())
+    {
+        return (http::StatusCode::FORBIDDEN, "API tokens can't be used here").into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ISSUER: &str = "https://issuer.example";
+    const SCHOOL: &str = "school.example";
+
+    /// Get a backend over a fresh in-memory database, hashing passwords cheaply
+    async fn backend() -> Backend {
+        Backend::new(db::test_db().await, settings(Params::MIN_M_COST, 1))
+    }
+
+    /// Find a user's row
+    async fn user_row(backend: &Backend, user_id: i64) -> db::users::Model {
+        db::users::Entity::find_by_id(user_id).one(&backend.db).await.unwrap().unwrap()
+    }
+
+    /// Argon2id settings with the given memory and time costs
+    fn settings(m_cost: u32, t_cost: u32) -> PasswordSettings {
+        PasswordSettings {
+            algorithm: Algorithm::Argon2id,
+            version: Version::V0x13,
+            params: Params::new(m_cost, t_cost, 1, None).unwrap(),
+        }
+    }
+
+    fn credentials(password: &str) -> Credentials {
+        Credentials {
+            username: "alice".to_string(),
+            password: SecretString::from(password),
+            remember: false,
+        }
+    }
+
+    #[test]
+    fn finds_weaker_hashes() {
+        let current = settings(2 * Params::MIN_M_COST, 2);
+        let hash = |settings: &PasswordSettings| {
+            settings
+                .hasher()
+                .hash_password(b"pw", &SaltString::generate(&mut OsRng))
+                .unwrap()
+                .to_string()
+        };
+
+        assert!(!current.is_weaker(&hash(&current)));
+        assert!(!current.is_weaker(&hash(&settings(4 * Params::MIN_M_COST, 3))));
+        assert!(current.is_weaker(&hash(&settings(Params::MIN_M_COST, 2))));
+        assert!(current.is_weaker(&hash(&settings(2 * Params::MIN_M_COST, 1))));
+        let mut argon2i = settings(2 * Params::MIN_M_COST, 2);
+        argon2i.algorithm = Algorithm::Argon2i;
+        assert!(current.is_weaker(&hash(&argon2i)));
+        let mut old_version = settings(2 * Params::MIN_M_COST, 2);
+        old_version.version = Version::V0x10;
+        assert!(current.is_weaker(&hash(&old_version)));
+        assert!(current.is_weaker("not a hash"));
+    }
+
+    #[tokio::test]
+    async fn upgrades_weaker_hashes_on_login() {
+        let weak = backend().await;
+        let user = weak.create_user("alice", "pw", roles::STUDENT, None).await.unwrap();
+        let weak_hash = user_row(&weak, user.id).await.password_hash;
+        let strong = Backend::new(weak.db.clone(), settings(2 * Params::MIN_M_COST, 2));
+
+        // A wrong password leaves the hash alone
+        assert!(strong.authenticate(credentials("wrong")).await.unwrap().is_none());
+        assert_eq!(user_row(&strong, user.id).await.password_hash, weak_hash);
+
+        // The right one rehashes it with the current settings, and it still works afterwards
+        assert!(strong.authenticate(credentials("pw")).await.unwrap().is_some());
+        let strong_hash = user_row(&strong, user.id).await.password_hash;
+        assert_ne!(strong_hash, weak_hash);
+        assert!(!strong.password_settings.is_weaker(&strong_hash));
+        assert!(strong.authenticate(credentials("pw")).await.unwrap().is_some());
+        assert_eq!(user_row(&strong, user.id).await.password_hash, strong_hash);
+    }
+
+    #[tokio::test]
+    async fn other_sessions_survive_a_rehash_but_not_a_password_change() {
+        let weak = backend().await;
+        let user = weak.create_user("alice", "pw", roles::STUDENT, None).await.unwrap();
+        let weak_hash = user_row(&weak, user.id).await.password_hash;
+        let strong = Backend::new(weak.db.clone(), settings(2 * Params::MIN_M_COST, 2));
+
+        // A session logged in before the rehash still matches the user afterwards
+        let rehashed = strong.authenticate(credentials("pw")).await.unwrap().unwrap();
+        assert_ne!(user_row(&strong, user.id).await.password_hash, weak_hash);
+        assert_eq!(rehashed.session_auth_hash(), user.session_auth_hash());
+
+        // Changing the password signs it out
+        let changed = strong.change_password(user.id, "pw", "new").await.unwrap();
+        assert_ne!(changed.session_auth_hash(), user.session_auth_hash());
+    }
+
+    #[tokio::test]
+    async fn expired_registrations_free_their_username_and_email() {
+        let backend = backend().await;
+        let (expired, _) =
+            backend.register_user("alice", "alice@school.example", "pw").await.unwrap();
+        let (pending, _) = backend.register_user("bob", "bob@school.example", "pw").await.unwrap();
+        db::email_verifications::Entity::update_many()
+            .col_expr(
+                db::email_verifications::Column::ExpiresAt,
+                Expr::value(OffsetDateTime::now_utc() - Duration::minutes(1)),
+            )
+            .filter(db::email_verifications::Column::UserId.eq(expired.id))
+            .exec(&backend.db)
+            .await
+            .unwrap();
+
+        backend.delete_expired_registrations().await.unwrap();
+        let remaining: Vec<i64> = db::users::Entity::find()
+            .all(&backend.db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|user_entity| user_entity.id)
+            .collect();
+        assert_eq!(remaining, vec![pending.id]);
+        backend.register_user("alice", "alice@school.example", "pw").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn external_login_activates_a_pending_registration() {
+        let backend = backend().await;
+        let (user, token) =
+            backend.register_user("alice", "alice@school.example", "pw").await.unwrap();
+
+        let logged_in = backend
+            .external_login(ISSUER, "alice-sub", Some("alice@school.example"), &[SCHOOL.to_string()])
+            .await
+            .unwrap();
+        assert_eq!(logged_in.id, user.id);
+        assert!(user_row(&backend, user.id).await.active);
+
+        // Whoever registered the email can't get in with their password or verification link
+        assert!(backend.authenticate(credentials("pw")).await.unwrap().is_none());
+        assert!(matches!(backend.verify_email(token).await, Err(Error::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn external_login_keeps_a_deactivated_account_inactive() {
+        let backend = backend().await;
+        let user = backend.create_user("bob", "pw", roles::STUDENT, None).await.unwrap();
+        let mut user_entity: db::users::ActiveModel = user_row(&backend, user.id).await.into();
+        user_entity.email = Set(Some("bob@school.example".to_string()));
+        user_entity.active = Set(false);
+        user_entity.update(&backend.db).await.unwrap();
+
+        assert!(matches!(
+            backend
+                .external_login(ISSUER, "bob-sub", Some("bob@school.example"), &[SCHOOL.to_string()])
+                .await,
+            Err(Error::UserNotFound)
+        ));
+        assert!(!user_row(&backend, user.id).await.active);
+    }
+
+    #[tokio::test]
+    async fn external_login_shortens_long_usernames_before_numbering_them() {
+        let backend = backend().await;
+        let local_part = "a".repeat(USERNAME_MAX_LENGTH + 10);
+        let first = backend
+            .external_login(
+                ISSUER,
+                "first-sub",
+                Some(&format!("{}@{}", local_part, SCHOOL)),
+                &[SCHOOL.to_string()],
+            )
+            .await
+            .unwrap();
+        let second = backend
+            .external_login(
+                ISSUER,
+                "second-sub",
+                Some(&format!("{}b@{}", local_part, SCHOOL)),
+                &[SCHOOL.to_string()],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(first.username, "a".repeat(USERNAME_MAX_LENGTH));
+        assert_eq!(
+            second.username,
+            format!("{}2", "a".repeat(USERNAME_MAX_LENGTH - 1))
+        );
+    }
+
+    #[tokio::test]
+    async fn password_reset_tokens_only_work_once() {
+        let backend = backend().await;
+        let user = backend
+            .create_user("alice", "pw", roles::STUDENT, None)
+            .await
+            .unwrap();
+        let mut user_entity: db::users::ActiveModel = user_row(&backend, user.id).await.into();
+        user_entity.email = Set(Some("alice@school.example".to_string()));
+        user_entity.update(&backend.db).await.unwrap();
+        let (_, token) = backend
+            .request_password_reset("alice@school.example")
+            .await
+            .unwrap()
+            .unwrap();
+
+        backend.reset_password(&token, "new").await.unwrap();
+        assert!(matches!(
+            backend.reset_password(&token, "other").await,
+            Err(Error::InvalidToken)
+        ));
+        assert!(backend.authenticate(credentials("new")).await.unwrap().is_some());
+        assert!(matches!(
+            backend.reset_password("not a token", "other").await,
+            Err(Error::InvalidToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn sessions_never_hold_the_password_hash() {
+        let backend = backend().await;
+        let user = backend
+            .create_user("alice", "pw", roles::STUDENT, None)
+            .await
+            .unwrap();
+        let password_hash = user_row(&backend, user.id).await.password_hash;
+
+        assert_ne!(user.session_auth_hash(), password_hash.as_bytes());
+        assert!(!serde_json::to_string(&user).unwrap().contains(&password_hash));
+    }
+
+    #[tokio::test]
+    async fn usernames_differing_only_by_case_clash() {
+        let backend = backend().await;
+        backend
+            .create_user("Alice", "pw", roles::STUDENT, None)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            backend
+                .create_user("alice", "pw", roles::STUDENT, None)
+                .await,
+            Err(Error::UsernameTaken)
+        ));
+        assert!(matches!(
+            backend
+                .register_user(" ALICE ", "alice@school.example", "pw")
+                .await,
+            Err(Error::UsernameTaken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn renames_users_whose_names_clashed_before_they_were_normalized() {
+        use sea_orm::ConnectionTrait as _;
+        use sea_orm_migration::MigratorTrait as _;
+
+        // Add two users differing only by case before usernames were normalized
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        let before_normalizing = db::migrator::Migrator::migrations()
+            .iter()
+            .position(|migration| migration.name() == "users_normalized_username")
+            .unwrap();
+        db::migrator::Migrator::up(&db, Some(before_normalizing as u32))
+            .await
+            .unwrap();
+        db.execute_unprepared(
+            "INSERT INTO users (username, passwordHash, roleId, active) \
+             VALUES ('Alice', '', 1, true), ('alice', '', 1, true)",
+        )
+        .await
+        .unwrap();
+        db::migrator::Migrator::up(&db, None).await.unwrap();
+
+        // The newer one gets a free name with a number on the end
+        let users = db::users::Entity::find()
+            .order_by_asc(db::users::Column::Id)
+            .all(&db)
+            .await
+            .unwrap();
+        let names = users
+            .iter()
+            .map(|user| (user.username.as_str(), user.normalized_username.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(names, [("Alice", "alice"), ("alice2", "alice2")]);
+
+        // And nobody can be left without a normalized username again
+        assert!(
+            db.execute_unprepared(
+                "INSERT INTO users (username, passwordHash, roleId, active, normalizedUsername) \
+                 VALUES ('bob', '', 1, true, NULL)",
+            )
+            .await
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn searches_for_wildcards_literally() {
+        let backend = backend().await;
+        for username in ["a_b", "axb", "100%"] {
+            backend
+                .create_user(username, "pw", roles::STUDENT, None)
+                .await
+                .unwrap();
+        }
+        let search = |search: &str| {
+            let listing = UserListing {
+                search: Some(search.to_string()),
+                role: None,
+                active: None,
+                sort: UserSort::Username,
+                descending: false,
+                page: 0,
+                per_page: 10,
+            };
+            let backend = backend.clone();
+            async move {
+                let (users, _) = backend.list_users(&listing).await.unwrap();
+                users
+                    .into_iter()
+                    .map(|(user, _)| user.username)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(search("_").await, ["a_b"]);
+        assert_eq!(search("%").await, ["100%"]);
+        assert_eq!(search("x").await, ["axb"]);
     }
 }
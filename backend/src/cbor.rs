@@ -0,0 +1,228 @@
+/// How deeply values may nest, which is far more than authenticators ever send
+const MAX_DEPTH: usize = 16;
+
+/// A decoded CBOR value, limited to the kinds authenticators send
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    /// Look up an entry of a map by its key
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(entry_key, _)| entry_key == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Value::Integer(integer) => Some(*integer),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    UnexpectedEnd,
+    TooDeep,
+    Unsupported(u8),
+    InvalidText,
+    TrailingBytes,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::UnexpectedEnd => write!(f, "CBOR ended in the middle of a value"),
+            Error::TooDeep => write!(f, "CBOR nests too deeply"),
+            Error::Unsupported(byte) => write!(f, "Unsupported CBOR value 0x{:02x}", byte),
+            Error::InvalidText => write!(f, "CBOR text is not UTF-8"),
+            Error::TrailingBytes => write!(f, "CBOR has bytes after its value"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Decode a buffer holding exactly one value
+pub fn decode(bytes: &[u8]) -> Result<Value, Error> {
+    let (value, length) = decode_prefix(bytes)?;
+    if length != bytes.len() {
+        return Err(Error::TrailingBytes);
+    }
+    Ok(value)
+}
+
+/// Decode the value at the start of a buffer, returning it with how many bytes it took
+pub fn decode_prefix(bytes: &[u8]) -> Result<(Value, usize), Error> {
+    let mut decoder = Decoder { bytes, position: 0 };
+    let value = decoder.value(0)?;
+    Ok((value, decoder.position))
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, length: usize) -> Result<&[u8], Error> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(Error::UnexpectedEnd)?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    /// Read the number following an initial byte, which is a length for most major types
+    fn argument(&mut self, initial: u8) -> Result<u64, Error> {
+        let size = match initial & 0x1f {
+            additional @ 0..24 => return Ok(additional.into()),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            // Indefinite lengths aren't allowed in the canonical encoding authenticators use
+            _ => return Err(Error::Unsupported(initial)),
+        };
+        Ok(self
+            .take(size)?
+            .iter()
+            .fold(0, |argument, byte| argument << 8 | u64::from(*byte)))
+    }
+
+    fn length(&mut self, initial: u8) -> Result<usize, Error> {
+        let length = usize::try_from(self.argument(initial)?).map_err(|_| Error::UnexpectedEnd)?;
+        // Every item takes at least a byte, so longer lengths can't be honest
+        if length > self.bytes.len() - self.position {
+            return Err(Error::UnexpectedEnd);
+        }
+        Ok(length)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::TooDeep);
+        }
+        let initial = *self.take(1)?.first().ok_or(Error::UnexpectedEnd)?;
+        match initial >> 5 {
+            0 => Ok(Value::Integer(self.argument(initial)?.into())),
+            1 => Ok(Value::Integer(-1 - i128::from(self.argument(initial)?))),
+            2 => {
+                let length = self.length(initial)?;
+                Ok(Value::Bytes(self.take(length)?.to_vec()))
+            }
+            3 => {
+                let length = self.length(initial)?;
+                let text = std::str::from_utf8(self.take(length)?).map_err(|_| Error::InvalidText)?;
+                Ok(Value::Text(text.to_string()))
+            }
+            4 => {
+                let length = self.length(initial)?;
+                let mut items = Vec::with_capacity(length);
+                for _ in 0..length {
+                    items.push(self.value(depth + 1)?);
+                }
+                Ok(Value::Array(items))
+            }
+            5 => {
+                let length = self.length(initial)?;
+                let mut entries = Vec::with_capacity(length);
+                for _ in 0..length {
+                    let key = self.value(depth + 1)?;
+                    let value = self.value(depth + 1)?;
+                    entries.push((key, value));
+                }
+                Ok(Value::Map(entries))
+            }
+            7 => match initial {
+                0xf4 => Ok(Value::Bool(false)),
+                0xf5 => Ok(Value::Bool(true)),
+                0xf6 => Ok(Value::Null),
+                _ => Err(Error::Unsupported(initial)),
+            },
+            // Tags never appear in what authenticators send
+            _ => Err(Error::Unsupported(initial)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_a_cose_key() {
+        // {1: 2, 3: -7, -2: h'0102', "fmt": "none", 4: [true, null]}
+        let bytes = [
+            0xa5, 0x01, 0x02, 0x03, 0x26, 0x21, 0x42, 0x01, 0x02, 0x63, b'f', b'm', b't', 0x64,
+            b'n', b'o', b'n', b'e', 0x04, 0x82, 0xf5, 0xf6,
+        ];
+        let value = decode(&bytes).unwrap();
+        assert_eq!(value.get(&Value::Integer(1)).and_then(Value::as_integer), Some(2));
+        assert_eq!(value.get(&Value::Integer(3)).and_then(Value::as_integer), Some(-7));
+        assert_eq!(value.get(&Value::Integer(-2)).and_then(Value::as_bytes), Some(&[1, 2][..]));
+        assert_eq!(
+            value.get(&Value::Text("fmt".to_string())),
+            Some(&Value::Text("none".to_string()))
+        );
+        assert_eq!(
+            value.get(&Value::Integer(4)),
+            Some(&Value::Array(vec![Value::Bool(true), Value::Null]))
+        );
+    }
+
+    #[test]
+    fn decodes_long_arguments() {
+        assert_eq!(decode(&[0x18, 0xff]).unwrap(), Value::Integer(255));
+        assert_eq!(decode(&[0x19, 0x01, 0x00]).unwrap(), Value::Integer(256));
+        assert_eq!(
+            decode(&[0x3b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).unwrap(),
+            Value::Integer(-1 - i128::from(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn reports_how_long_a_prefix_is() {
+        let (value, length) = decode_prefix(&[0x42, 0x01, 0x02, 0xff]).unwrap();
+        assert_eq!(value, Value::Bytes(vec![1, 2]));
+        assert_eq!(length, 3);
+        assert!(matches!(decode(&[0x42, 0x01, 0x02, 0xff]), Err(Error::TrailingBytes)));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(matches!(decode(&[]), Err(Error::UnexpectedEnd)));
+        assert!(matches!(decode(&[0x43, 0x01]), Err(Error::UnexpectedEnd)));
+        assert!(matches!(
+            decode(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            Err(Error::UnexpectedEnd)
+        ));
+        assert!(matches!(decode(&[0x62, 0xff, 0xfe]), Err(Error::InvalidText)));
+        assert!(matches!(decode(&[0x5f, 0x41, 0x00, 0xff]), Err(Error::Unsupported(0x5f))));
+        assert!(matches!(decode(&[0xc1, 0x00]), Err(Error::Unsupported(0xc1))));
+        assert!(matches!(decode(&[0x81; MAX_DEPTH + 2]), Err(Error::TooDeep)));
+    }
+}
@@ -0,0 +1,44 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{self, request::Parts},
+};
+
+use crate::states::BackendState;
+
+/// The address of the client that sent a request
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+impl FromRequestParts<BackendState> for ClientIp {
+    type Rejection = (http::StatusCode, &'static str);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &BackendState,
+    ) -> Result<Self, Self::Rejection> {
+        // Behind a reverse proxy the last forwarded address is the one the proxy saw
+        if state.trust_proxy_headers
+            && let Some(ip) = parts
+                .headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .next_back()
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .and_then(|ip| ip.trim().parse().ok())
+        {
+            return Ok(ClientIp(ip));
+        }
+
+        parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| ClientIp(address.ip()))
+            .ok_or((
+                http::StatusCode::INTERNAL_SERVER_ERROR,
+                "The client address is unknown",
+            ))
+    }
+}
@@ -0,0 +1,182 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore as _};
+use axum::{
+    extract::Request,
+    http::{self, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tower_sessions::cookie::{Cookie, SameSite};
+use tracing::{Level, event};
+
+/// The cookie holding the CSRF token, which the frontend reads to send it back
+pub const COOKIE_NAME: &str = "connectia_csrf";
+
+/// The header requests that change something have to repeat the CSRF token in
+pub const HEADER_NAME: &str = "x-csrf-token";
+
+/// Refuse requests that change something unless they repeat the CSRF cookie in a header
+///
+/// Other sites can make a browser send the cookie but can't read it, so they can't send the header.
+/// Requests with an `Authorization` header are left to `auth::bearer_token`, since API tokens don't
+/// rely on cookies. Attach outside the auth layer, so every response to a browser without the
+/// cookie hands one out.
+pub async fn protect(request: Request, next: Next) -> Response {
+    let cookie_token = cookie_token(request.headers());
+
+    if !request.method().is_safe() && !request.headers().contains_key(http::header::AUTHORIZATION) {
+        let header_token = request
+            .headers()
+            .get(HEADER_NAME)
+            .and_then(|value| value.to_str().ok());
+        let valid = match (&cookie_token, header_token) {
+            (Some(cookie_token), Some(header_token)) => {
+                constant_time_eq(cookie_token.as_bytes(), header_token.as_bytes())
+            }
+            _ => false,
+        };
+        if !valid {
+            event!(
+                Level::WARN,
+                "Refused {} {} without a matching CSRF token",
+                request.method(),
+                request.uri().path()
+            );
+            let mut response =
+                (http::StatusCode::FORBIDDEN, "Missing or invalid CSRF token").into_response();
+            if cookie_token.is_none() {
+                set_cookie(&mut response);
+            }
+            return response;
+        }
+    }
+
+    let mut response = next.run(request).await;
+    if cookie_token.is_none() {
+        set_cookie(&mut response);
+    }
+    response
+}
+
+/// Get the CSRF token from the request's cookies, if it has one
+fn cookie_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(http::header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(Cookie::split_parse)
+        .filter_map(Result::ok)
+        .find(|cookie| cookie.name() == COOKIE_NAME && !cookie.value().is_empty())
+        .map(|cookie| cookie.value().to_string())
+}
+
+/// Hand out a new CSRF token with the same protections as the session cookie, but readable
+fn set_cookie(response: &mut Response) {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let cookie = Cookie::build((COOKIE_NAME, hex::encode(bytes)))
+        .path("/")
+        .same_site(SameSite::Strict)
+        .secure(true)
+        .http_only(false)
+        .build();
+    match HeaderValue::try_from(cookie.to_string()) {
+        Ok(value) => {
+            response.headers_mut().append(http::header::SET_COOKIE, value);
+        }
+        Err(err) => event!(Level::ERROR, "Failed to set the CSRF cookie: {}", err),
+    }
+}
+
+/// Compare two tokens without taking longer the more of them matches
+fn constant_time_eq(first: &[u8], second: &[u8]) -> bool {
+    first.len() == second.len()
+        && first
+            .iter()
+            .zip(second)
+            .fold(0, |difference, (first, second)| difference | (first ^ second))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt as _;
+
+    use super::*;
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    /// Send a request through the middleware to a route that always succeeds
+    async fn send(method: http::Method, headers: &[(&str, &str)]) -> Response {
+        let app = Router::new()
+            .route("/", get(|| async { "OK" }).post(|| async { "OK" }))
+            .layer(middleware::from_fn(protect));
+        let mut request = http::Request::builder().method(method).uri("/");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        app.oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn cookie() -> String {
+        format!("{}={}", COOKIE_NAME, TOKEN)
+    }
+
+    /// Get the CSRF token a response hands out, if it does
+    fn handed_out(response: &Response) -> Option<String> {
+        cookie_token(&HeaderMap::from_iter(
+            response
+                .headers()
+                .get_all(http::header::SET_COOKIE)
+                .iter()
+                .map(|value| (http::header::COOKIE, value.clone())),
+        ))
+    }
+
+    #[tokio::test]
+    async fn refuses_a_missing_token_and_hands_one_out() {
+        let response = send(http::Method::POST, &[]).await;
+        assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+        assert!(handed_out(&response).is_some_and(|token| token.len() == 64));
+
+        let response = send(http::Method::POST, &[("cookie", &cookie())]).await;
+        assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+        assert!(handed_out(&response).is_none());
+    }
+
+    #[tokio::test]
+    async fn refuses_a_mismatched_token() {
+        let response = send(
+            http::Method::POST,
+            &[("cookie", &cookie()), (HEADER_NAME, "fedcba9876543210")],
+        )
+        .await;
+        assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn passes_a_matching_token() {
+        let response = send(
+            http::Method::POST,
+            &[("cookie", &cookie()), (HEADER_NAME, TOKEN)],
+        )
+        .await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert!(handed_out(&response).is_none());
+    }
+
+    #[tokio::test]
+    async fn passes_safe_methods_and_hands_out_a_token() {
+        let response = send(http::Method::GET, &[]).await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert!(handed_out(&response).is_some());
+    }
+
+    #[tokio::test]
+    async fn leaves_api_token_requests_to_the_auth_layer() {
+        let response = send(http::Method::POST, &[("authorization", "Bearer token")]).await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+    }
+}
@@ -0,0 +1,75 @@
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelBehavior, DbErr, DeriveEntityModel, DerivePrimaryKey, DeriveRelation, EnumIter,
+    PrimaryKeyTrait,
+    prelude::TimeDateTimeWithTimeZone,
+    sea_query::{ColumnDef, Index, Table},
+};
+use sea_orm_migration::{MigrationName, MigrationTrait, SchemaManager};
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "login_throttles", rename_all = "camelCase")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub scope: String,
+    pub identifier: String,
+    pub failures: i32,
+    pub last_failure_at: TimeDateTimeWithTimeZone,
+    pub locked_until: Option<TimeDateTimeWithTimeZone>,
+}
+
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "login_throttles"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Entity)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Column::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Column::Scope).string_len(16).not_null())
+                    .col(ColumnDef::new(Column::Identifier).string().not_null())
+                    .col(ColumnDef::new(Column::Failures).integer().not_null())
+                    .col(
+                        ColumnDef::new(Column::LastFailureAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Column::LockedUntil).timestamp_with_time_zone())
+                    .index(
+                        Index::create()
+                            .col(Column::Scope)
+                            .col(Column::Identifier)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Entity).to_owned())
+            .await
+    }
+}
@@ -1,11 +1,43 @@
 use sea_orm_migration::{MigrationTrait, MigratorTrait};
 
-use crate::db::users;
+use crate::db::{
+    api_tokens, audit_events, email_verifications, external_identities, impersonations,
+    login_throttles, passkeys, password_reset_tokens, permissions, profiles, recovery_codes,
+    role_permissions, roles, sessions, suspensions, terms_acceptances, totp_credentials, users,
+};
 
 pub struct Migrator;
 
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(users::Migration)]
+        vec![
+            Box::new(users::Migration),
+            Box::new(roles::Migration),
+            Box::new(permissions::Migration),
+            Box::new(role_permissions::Migration),
+            Box::new(users::RolesMigration),
+            Box::new(sessions::Migration),
+            Box::new(users::RegistrationMigration),
+            Box::new(email_verifications::Migration),
+            Box::new(password_reset_tokens::Migration),
+            Box::new(roles::TwoFactorMigration),
+            Box::new(totp_credentials::Migration),
+            Box::new(recovery_codes::Migration),
+            Box::new(external_identities::Migration),
+            Box::new(login_throttles::Migration),
+            Box::new(api_tokens::Migration),
+            Box::new(users::NormalizedUsernameMigration),
+            Box::new(suspensions::Migration),
+            Box::new(users::GradeMigration),
+            Box::new(impersonations::Migration),
+            Box::new(audit_events::Migration),
+            Box::new(terms_acceptances::Migration),
+            Box::new(users::StudentIdMigration),
+            Box::new(sessions::MetadataMigration),
+            Box::new(passkeys::Migration),
+            Box::new(profiles::Migration),
+            Box::new(users::SessionSecretMigration),
+            Box::new(users::RequiredNormalizedUsernameMigration),
+        ]
     }
 }
@@ -1,2 +1,29 @@
+pub mod api_tokens;
+pub mod audit_events;
+pub mod email_verifications;
+pub mod external_identities;
+pub mod impersonations;
+pub mod login_throttles;
 pub mod migrator;
+pub mod passkeys;
+pub mod password_reset_tokens;
+pub mod permissions;
+pub mod profiles;
+pub mod recovery_codes;
+pub mod role_permissions;
+pub mod roles;
+pub mod sessions;
+pub mod suspensions;
+pub mod terms_acceptances;
+pub mod totp_credentials;
 pub mod users;
+
+/// Get a fresh in-memory database with every migration applied
+#[cfg(test)]
+pub(crate) async fn test_db() -> sea_orm::DatabaseConnection {
+    use sea_orm_migration::MigratorTrait as _;
+
+    let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+    migrator::Migrator::up(&db, None).await.unwrap();
+    db
+}
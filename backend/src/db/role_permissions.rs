@@ -0,0 +1,152 @@
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelBehavior, ColumnTrait as _, DbErr, DeriveEntityModel, DerivePrimaryKey,
+    DeriveRelation, EntityTrait, EnumIter, PrimaryKeyTrait, QueryFilter as _, QuerySelect as _,
+    Related, RelationDef, RelationTrait,
+    sea_query::{ColumnDef, ForeignKey, ForeignKeyAction, Index, Query, Table},
+};
+use sea_orm_migration::{MigrationName, MigrationTrait, SchemaManager};
+
+use crate::db::{permissions, roles};
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "role_permissions", rename_all = "camelCase")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub role_id: i64,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub permission_id: i64,
+}
+
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::roles::Entity",
+        from = "Column::RoleId",
+        to = "super::roles::Column::Id"
+    )]
+    Role,
+    #[sea_orm(
+        belongs_to = "super::permissions::Entity",
+        from = "Column::PermissionId",
+        to = "super::permissions::Column::Id"
+    )]
+    Permission,
+}
+
+impl Related<super::roles::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Role.def()
+    }
+}
+
+impl Related<super::permissions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Permission.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// The permissions each of the default roles starts out with
+const DEFAULT_GRANTS: &[(&str, &[&str])] = &[
+    ("student", &[]),
+    ("moderator", &["users.moderate"]),
+    ("staff", &["admin.panel", "users.create", "users.moderate"]),
+    (
+        "admin",
+        &[
+            "admin.panel",
+            "users.create",
+            "users.manage",
+            "users.moderate",
+            "users.impersonate",
+            "audit.read",
+        ],
+    ),
+];
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "role_permissions"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Entity)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Column::RoleId).big_integer().not_null())
+                    .col(ColumnDef::new(Column::PermissionId).big_integer().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(Column::RoleId)
+                            .col(Column::PermissionId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Entity, Column::RoleId)
+                            .to(roles::Entity, roles::Column::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Entity, Column::PermissionId)
+                            .to(permissions::Entity, permissions::Column::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Grant the default permissions to the default roles
+        let db = manager.get_connection();
+        for (role_name, permission_names) in DEFAULT_GRANTS {
+            // Only select the id since later migrations add columns to the model
+            let Some(role_id) = roles::Entity::find()
+                .select_only()
+                .column(roles::Column::Id)
+                .filter(roles::Column::Name.eq(*role_name))
+                .into_tuple::<i64>()
+                .one(db)
+                .await?
+            else {
+                continue;
+            };
+            for permission_name in permission_names.iter() {
+                let Some(permission_id) = permissions::Entity::find()
+                    .select_only()
+                    .column(permissions::Column::Id)
+                    .filter(permissions::Column::Name.eq(*permission_name))
+                    .into_tuple::<i64>()
+                    .one(db)
+                    .await?
+                else {
+                    continue;
+                };
+                manager
+                    .exec_stmt(
+                        Query::insert()
+                            .into_table(Entity)
+                            .columns([Column::RoleId, Column::PermissionId])
+                            .values_panic([role_id.into(), permission_id.into()])
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Entity).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,139 @@
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelBehavior, DbErr, DeriveEntityModel, DerivePrimaryKey, DeriveRelation, EntityTrait,
+    EnumIter, PrimaryKeyTrait, Related, RelationDef, RelationTrait,
+    sea_query::{ColumnDef, Expr, Index, Query, Table},
+};
+use sea_orm_migration::{MigrationName, MigrationTrait, SchemaManager};
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "roles", rename_all = "camelCase")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub name: String,
+    pub require_two_factor: bool,
+}
+
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::role_permissions::Entity")]
+    RolePermissions,
+    #[sea_orm(has_many = "super::users::Entity")]
+    Users,
+}
+
+impl Related<super::role_permissions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RolePermissions.def()
+    }
+}
+
+impl Related<super::users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Users.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "roles"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Entity)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Column::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Column::Name).string().not_null())
+                    .index(Index::create().col(Column::Name).unique())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Seed the default roles
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(Entity)
+                    .columns([Column::Name])
+                    .values_panic(["student".into()])
+                    .values_panic(["moderator".into()])
+                    .values_panic(["staff".into()])
+                    .values_panic(["admin".into()])
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Entity).to_owned())
+            .await
+    }
+}
+
+/// Adds a flag for roles whose users must enroll in two-factor authentication
+pub struct TwoFactorMigration;
+
+impl MigrationName for TwoFactorMigration {
+    fn name(&self) -> &str {
+        "roles_two_factor"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for TwoFactorMigration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Entity)
+                    .add_column(
+                        ColumnDef::new(Column::RequireTwoFactor)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Require it for the roles that can manage other users
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Entity)
+                    .value(Column::RequireTwoFactor, true)
+                    .and_where(Expr::col(Column::Name).is_in(["staff", "admin"]))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Entity)
+                    .drop_column(Column::RequireTwoFactor)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,144 @@
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelBehavior, DbErr, DeriveEntityModel, DerivePrimaryKey, DeriveRelation, EnumIter,
+    PrimaryKeyTrait,
+    prelude::TimeDateTimeWithTimeZone,
+    sea_query::{ColumnDef, Index, Table},
+};
+use sea_orm_migration::{MigrationName, MigrationTrait, SchemaManager};
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "sessions", rename_all = "camelCase")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: String,
+    #[sea_orm(column_type = "Text")]
+    pub data: String,
+    pub expiry_date: TimeDateTimeWithTimeZone,
+    /// The user logged in with the session, once it was used
+    pub user_id: Option<i64>,
+    pub created_at: Option<TimeDateTimeWithTimeZone>,
+    pub last_seen_at: Option<TimeDateTimeWithTimeZone>,
+    pub ip: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "sessions"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Entity)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Column::Id)
+                            .string_len(64)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Column::Data).text().not_null())
+                    .col(
+                        ColumnDef::new(Column::ExpiryDate)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_sessions_expiry_date")
+                    .table(Entity)
+                    .col(Column::ExpiryDate)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Entity).to_owned())
+            .await
+    }
+}
+
+/// Adds who a session belongs to and where and when it was used, so users can see their sessions
+pub struct MetadataMigration;
+
+impl MigrationName for MetadataMigration {
+    fn name(&self) -> &str {
+        "sessions_metadata"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for MetadataMigration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Sessions from before don't know any of it, and only show up once they're used again
+        for column in [
+            ColumnDef::new(Column::UserId).integer().null().to_owned(),
+            ColumnDef::new(Column::CreatedAt)
+                .timestamp_with_time_zone()
+                .null()
+                .to_owned(),
+            ColumnDef::new(Column::LastSeenAt)
+                .timestamp_with_time_zone()
+                .null()
+                .to_owned(),
+            ColumnDef::new(Column::Ip).string_len(64).null().to_owned(),
+            ColumnDef::new(Column::UserAgent).text().null().to_owned(),
+        ] {
+            manager
+                .alter_table(Table::alter().table(Entity).add_column(column).to_owned())
+                .await?;
+        }
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_sessions_user_id")
+                    .table(Entity)
+                    .col(Column::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_sessions_user_id")
+                    .table(Entity)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [
+            Column::UserAgent,
+            Column::Ip,
+            Column::LastSeenAt,
+            Column::CreatedAt,
+            Column::UserId,
+        ] {
+            manager
+                .alter_table(Table::alter().table(Entity).drop_column(column).to_owned())
+                .await?;
+        }
+        Ok(())
+    }
+}
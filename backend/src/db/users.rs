@@ -1,11 +1,16 @@
 use async_trait::async_trait;
 use sea_orm::{
-    ActiveModelBehavior, DbErr, DeriveEntityModel, DerivePrimaryKey, DeriveRelation, EnumIter,
-    PrimaryKeyTrait,
-    sea_query::{ColumnDef, Table},
+    ActiveModelBehavior, ColumnTrait as _, DbErr, DeriveEntityModel, DeriveIden, DerivePrimaryKey,
+    DeriveRelation, EntityTrait, EnumIter, PrimaryKeyTrait, QueryFilter as _, QueryOrder as _,
+    QuerySelect as _, Related, RelationDef, RelationTrait,
+    sea_query::{ColumnDef, Expr, Index, Query, Table},
 };
 use sea_orm_migration::{MigrationName, MigrationTrait, SchemaManager};
 
+use tracing::{Level, event};
+
+use crate::{db::roles, tokens};
+
 #[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
 #[sea_orm(table_name = "users", rename_all = "camelCase")]
 pub struct Model {
@@ -13,14 +18,58 @@ pub struct Model {
     pub id: i64,
     pub username: String,
     pub password_hash: String,
-    pub admin: bool,
+    pub role_id: i64,
+    pub email: Option<String>,
+    pub active: bool,
+    pub normalized_username: String,
+    pub grade: Option<String>,
+    pub student_id: Option<String>,
+    /// What sessions are checked against, rotated whenever the password changes
+    pub session_secret: String,
 }
 
 #[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::roles::Entity",
+        from = "Column::RoleId",
+        to = "super::roles::Column::Id"
+    )]
+    Role,
+}
+
+impl Related<super::roles::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Role.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
 
+/// The identifiers of the users table across all of its migrations
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+    Username,
+    #[sea_orm(iden = "passwordHash")]
+    PasswordHash,
+    Admin,
+    #[sea_orm(iden = "roleId")]
+    RoleId,
+    Email,
+    Active,
+    #[sea_orm(iden = "normalizedUsername")]
+    NormalizedUsername,
+    #[sea_orm(iden = "requiredNormalizedUsername")]
+    RequiredNormalizedUsername,
+    Grade,
+    #[sea_orm(iden = "studentId")]
+    StudentId,
+    #[sea_orm(iden = "sessionSecret")]
+    SessionSecret,
+}
+
 pub struct Migration;
 
 impl MigrationName for Migration {
@@ -35,18 +84,185 @@ impl MigrationTrait for Migration {
         manager
             .create_table(
                 Table::create()
-                    .table(Entity)
+                    .table(Users::Table)
                     .if_not_exists()
                     .col(
-                        ColumnDef::new(Column::Id)
+                        ColumnDef::new(Users::Id)
                             .integer()
                             .not_null()
                             .auto_increment()
                             .primary_key(),
                     )
-                    .col(ColumnDef::new(Column::Username).string().not_null())
-                    .col(ColumnDef::new(Column::PasswordHash).string().not_null())
-                    .col(ColumnDef::new(Column::Admin).boolean().not_null())
+                    .col(ColumnDef::new(Users::Username).string().not_null())
+                    .col(ColumnDef::new(Users::PasswordHash).string().not_null())
+                    .col(ColumnDef::new(Users::Admin).boolean().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Users::Table).to_owned())
+            .await
+    }
+}
+
+/// Replaces the `admin` flag with a reference to a role
+pub struct RolesMigration;
+
+impl MigrationName for RolesMigration {
+    fn name(&self) -> &str {
+        "users_roles"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for RolesMigration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Look up the roles existing users are moved to
+        let db = manager.get_connection();
+        let role_id = |name: &'static str| async move {
+            // Only select the id since later migrations add columns to the model
+            roles::Entity::find()
+                .select_only()
+                .column(roles::Column::Id)
+                .filter(roles::Column::Name.eq(name))
+                .into_tuple::<i64>()
+                .one(db)
+                .await?
+                .ok_or_else(|| DbErr::RecordNotFound(format!("Role {} does not exist", name)))
+        };
+        let student_role_id = role_id("student").await?;
+        let admin_role_id = role_id("admin").await?;
+
+        // Add the role column, defaulting everyone to a student
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(
+                        ColumnDef::new(Users::RoleId)
+                            .big_integer()
+                            .not_null()
+                            .default(student_role_id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Promote the existing admins
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Users::Table)
+                    .value(Users::RoleId, admin_role_id)
+                    .and_where(Expr::col(Users::Admin).eq(true))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Drop the old admin flag
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::Admin)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let admin_role_id = roles::Entity::find()
+            .select_only()
+            .column(roles::Column::Id)
+            .filter(roles::Column::Name.eq("admin"))
+            .into_tuple::<i64>()
+            .one(db)
+            .await?;
+
+        // Restore the admin flag
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(
+                        ColumnDef::new(Users::Admin)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        if let Some(admin_role_id) = admin_role_id {
+            manager
+                .exec_stmt(
+                    Query::update()
+                        .table(Users::Table)
+                        .value(Users::Admin, true)
+                        .and_where(Expr::col(Users::RoleId).eq(admin_role_id))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // Drop the role column
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::RoleId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Adds an email address and an activation flag for self-service registration
+pub struct RegistrationMigration;
+
+impl MigrationName for RegistrationMigration {
+    fn name(&self) -> &str {
+        "users_registration"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for RegistrationMigration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::Email).string().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_users_email")
+                    .table(Users::Table)
+                    .col(Users::Email)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // Existing users were all created by an admin so they are already active
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(
+                        ColumnDef::new(Users::Active)
+                            .boolean()
+                            .not_null()
+                            .default(true),
+                    )
                     .to_owned(),
             )
             .await
@@ -54,7 +270,400 @@ impl MigrationTrait for Migration {
 
     async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
         manager
-            .drop_table(Table::drop().table(Entity).to_owned())
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::Active)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_users_email")
+                    .table(Users::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::Email)
+                    .to_owned(),
+            )
             .await
     }
 }
+
+/// Adds a normalized copy of the username with a unique index so names can't differ only by case
+pub struct NormalizedUsernameMigration;
+
+impl MigrationName for NormalizedUsernameMigration {
+    fn name(&self) -> &str {
+        "users_normalized_username"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for NormalizedUsernameMigration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::NormalizedUsername).string().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Fill it in for existing users the same way `auth::normalize_username` does,
+        // leaving it empty for newer accounts that clash with an older one so the index can be made
+        let db = manager.get_connection();
+        let usernames = Entity::find()
+            .select_only()
+            .column(Column::Id)
+            .column(Column::Username)
+            .order_by_asc(Column::Id)
+            .into_tuple::<(i64, String)>()
+            .all(db)
+            .await?;
+        let mut taken = std::collections::HashSet::new();
+        for (id, username) in usernames {
+            let normalized_username = username.trim().to_lowercase();
+            if !taken.insert(normalized_username.clone()) {
+                continue;
+            }
+            manager
+                .exec_stmt(
+                    Query::update()
+                        .table(Users::Table)
+                        .value(Users::NormalizedUsername, normalized_username)
+                        .and_where(Expr::col(Users::Id).eq(id))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_users_normalized_username")
+                    .table(Users::Table)
+                    .col(Users::NormalizedUsername)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_users_normalized_username")
+                    .table(Users::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::NormalizedUsername)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Adds the grade a student is in, as given by the school's roster
+pub struct GradeMigration;
+
+impl MigrationName for GradeMigration {
+    fn name(&self) -> &str {
+        "users_grade"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for GradeMigration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::Grade).string_len(16).null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::Grade)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Adds the student ID the school verified an account belongs to, so each student has one account
+pub struct StudentIdMigration;
+
+impl MigrationName for StudentIdMigration {
+    fn name(&self) -> &str {
+        "users_student_id"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for StudentIdMigration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::StudentId).string_len(32).null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_users_student_id")
+                    .table(Users::Table)
+                    .col(Users::StudentId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_users_student_id")
+                    .table(Users::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::StudentId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Adds a secret per user that sessions are checked against, so rehashing a password keeps them
+pub struct SessionSecretMigration;
+
+impl MigrationName for SessionSecretMigration {
+    fn name(&self) -> &str {
+        "users_session_secret"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for SessionSecretMigration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(
+                        ColumnDef::new(Users::SessionSecret)
+                            .string_len(64)
+                            .not_null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Give every existing user their own secret, which signs them out once
+        let db = manager.get_connection();
+        let user_ids = Entity::find()
+            .select_only()
+            .column(Column::Id)
+            .into_tuple::<i64>()
+            .all(db)
+            .await?;
+        for id in user_ids {
+            manager
+                .exec_stmt(
+                    Query::update()
+                        .table(Users::Table)
+                        .value(Users::SessionSecret, tokens::generate().0)
+                        .and_where(Expr::col(Users::Id).eq(id))
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::SessionSecret)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Makes the normalized username required, renaming accounts left without one because their
+/// names clashed with an older account
+pub struct RequiredNormalizedUsernameMigration;
+
+impl MigrationName for RequiredNormalizedUsernameMigration {
+    fn name(&self) -> &str {
+        "users_required_normalized_username"
+    }
+}
+
+#[async_trait]
+impl MigrationTrait for RequiredNormalizedUsernameMigration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Give each clashing account the first free name with a number on the end
+        let db = manager.get_connection();
+        let mut taken = Entity::find()
+            .select_only()
+            .column(Column::NormalizedUsername)
+            .filter(Column::NormalizedUsername.is_not_null())
+            .into_tuple::<String>()
+            .all(db)
+            .await?
+            .into_iter()
+            .collect::<std::collections::HashSet<_>>();
+        let clashing = Entity::find()
+            .select_only()
+            .column(Column::Id)
+            .column(Column::Username)
+            .filter(Column::NormalizedUsername.is_null())
+            .order_by_asc(Column::Id)
+            .into_tuple::<(i64, String)>()
+            .all(db)
+            .await?;
+        for (id, username) in clashing {
+            let mut suffix = 2;
+            let (renamed, normalized_username) = loop {
+                let renamed = format!("{}{}", username.trim(), suffix);
+                let normalized_username = renamed.to_lowercase();
+                if taken.insert(normalized_username.clone()) {
+                    break (renamed, normalized_username);
+                }
+                suffix += 1;
+            };
+            event!(
+                Level::WARN,
+                "Renamed user {} to {} since the name clashed with another user's",
+                username,
+                renamed
+            );
+            manager
+                .exec_stmt(
+                    Query::update()
+                        .table(Users::Table)
+                        .value(Users::Username, renamed)
+                        .value(Users::NormalizedUsername, normalized_username)
+                        .and_where(Expr::col(Users::Id).eq(id))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        redefine_normalized_username(
+            manager,
+            ColumnDef::new(Users::RequiredNormalizedUsername)
+                .string()
+                .not_null()
+                .default("")
+                .to_owned(),
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        redefine_normalized_username(
+            manager,
+            ColumnDef::new(Users::RequiredNormalizedUsername)
+                .string()
+                .null()
+                .to_owned(),
+        )
+        .await
+    }
+}
+
+/// Replace the normalized username with a column defined under a temporary name, copying it
+/// over and keeping its index, since SQLite can't change a column in place
+async fn redefine_normalized_username(
+    manager: &SchemaManager<'_>,
+    column: ColumnDef,
+) -> Result<(), DbErr> {
+    manager
+        .alter_table(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(column)
+                .to_owned(),
+        )
+        .await?;
+    manager
+        .exec_stmt(
+            Query::update()
+                .table(Users::Table)
+                .value(
+                    Users::RequiredNormalizedUsername,
+                    Expr::col(Users::NormalizedUsername),
+                )
+                .to_owned(),
+        )
+        .await?;
+    manager
+        .drop_index(
+            Index::drop()
+                .name("idx_users_normalized_username")
+                .table(Users::Table)
+                .to_owned(),
+        )
+        .await?;
+    manager
+        .alter_table(
+            Table::alter()
+                .table(Users::Table)
+                .drop_column(Users::NormalizedUsername)
+                .to_owned(),
+        )
+        .await?;
+    manager
+        .alter_table(
+            Table::alter()
+                .table(Users::Table)
+                .rename_column(Users::RequiredNormalizedUsername, Users::NormalizedUsername)
+                .to_owned(),
+        )
+        .await?;
+    manager
+        .create_index(
+            Index::create()
+                .name("idx_users_normalized_username")
+                .table(Users::Table)
+                .col(Users::NormalizedUsername)
+                .unique()
+                .to_owned(),
+        )
+        .await
+}
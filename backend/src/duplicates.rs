@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+
+use crate::db;
+
+/// The shortest name compared loosely, since short names are alike too often to mean anything
+const MIN_FUZZY_NAME_LENGTH: usize = 5;
+
+/// Why some accounts might belong to the same person
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// Their emails reach the same mailbox, given here
+    SharedEmail(String),
+    /// Their usernames or the names in their emails are alike
+    SimilarNames,
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::SharedEmail(mailbox) => write!(f, "Emails reach the same mailbox {}", mailbox),
+            Reason::SimilarNames => write!(f, "Similar names"),
+        }
+    }
+}
+
+/// Accounts that might belong to the same person
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub reason: Reason,
+    pub users: Vec<db::users::Model>,
+}
+
+/// Find accounts that might belong to the same person
+///
+/// Accounts with different student IDs belong to different people, so their names are never
+/// flagged as similar.
+pub fn find(users: &[db::users::Model]) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+
+    // Group accounts by the mailbox their email reaches
+    let user_mailboxes = users
+        .iter()
+        .map(|user| user.email.as_deref().and_then(mailbox))
+        .collect::<Vec<_>>();
+    let mut mailboxes = BTreeMap::<String, Vec<usize>>::new();
+    for (index, mailbox) in user_mailboxes.iter().enumerate() {
+        if let Some(mailbox) = mailbox {
+            mailboxes.entry(mailbox.clone()).or_default().push(index);
+        }
+    }
+    for (mailbox, indices) in mailboxes {
+        if indices.len() > 1 {
+            candidates.push(Candidate {
+                reason: Reason::SharedEmail(mailbox),
+                users: indices.into_iter().map(|index| users[index].clone()).collect(),
+            });
+        }
+    }
+
+    // Join accounts with alike names into groups, leaving out ones already flagged for their email
+    let names = users.iter().map(names).collect::<Vec<_>>();
+    let mut groups = (0..users.len()).collect::<Vec<_>>();
+    for first in 0..users.len() {
+        for second in first + 1..users.len() {
+            if user_mailboxes[first].is_some() && user_mailboxes[first] == user_mailboxes[second] {
+                continue;
+            }
+            if let (Some(first_id), Some(second_id)) =
+                (&users[first].student_id, &users[second].student_id)
+                && first_id != second_id
+            {
+                continue;
+            }
+            let alike = names[first].iter().any(|first_name| {
+                names[second]
+                    .iter()
+                    .any(|second_name| names_alike(first_name, second_name))
+            });
+            if alike {
+                let first_group = group_of(&mut groups, first);
+                let second_group = group_of(&mut groups, second);
+                groups[second_group.max(first_group)] = second_group.min(first_group);
+            }
+        }
+    }
+    let mut similar = BTreeMap::<usize, Vec<usize>>::new();
+    for index in 0..users.len() {
+        let group = group_of(&mut groups, index);
+        similar.entry(group).or_default().push(index);
+    }
+    for indices in similar.into_values() {
+        if indices.len() > 1 {
+            candidates.push(Candidate {
+                reason: Reason::SimilarNames,
+                users: indices.into_iter().map(|index| users[index].clone()).collect(),
+            });
+        }
+    }
+
+    candidates
+}
+
+/// Find the group an account was joined into, keeping the lookup short for next time
+fn group_of(groups: &mut [usize], index: usize) -> usize {
+    let mut group = index;
+    while groups[group] != group {
+        groups[group] = groups[groups[group]];
+        group = groups[group];
+    }
+    group
+}
+
+/// Work out which mailbox an email reaches, ignoring `+` tags and the dots Gmail ignores
+fn mailbox(email: &str) -> Option<String> {
+    let email = email.trim().to_lowercase();
+    let (local, domain) = email.rsplit_once('@')?;
+    let local = local.split('+').next().unwrap_or_default();
+    Some(match domain {
+        "gmail.com" | "googlemail.com" => format!("{}@gmail.com", local.replace('.', "")),
+        _ => format!("{}@{}", local, domain),
+    })
+}
+
+/// The names an account goes by, reduced to their lowercase letters
+fn names(user: &db::users::Model) -> Vec<String> {
+    let local = user
+        .email
+        .as_deref()
+        .and_then(|email| email.rsplit_once('@'))
+        .map(|(local, _)| local.split('+').next().unwrap_or_default());
+    let mut names = [Some(user.username.as_str()), local]
+        .into_iter()
+        .flatten()
+        .map(|name| {
+            name.chars()
+                .filter(|c| c.is_alphabetic())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>();
+    names.dedup();
+    names
+}
+
+/// Check whether two names are the same, or one typo apart when they're long enough
+fn names_alike(first: &str, second: &str) -> bool {
+    if first == second {
+        return true;
+    }
+    let first = first.chars().collect::<Vec<_>>();
+    let second = second.chars().collect::<Vec<_>>();
+    first.len().min(second.len()) >= MIN_FUZZY_NAME_LENGTH
+        && first.len().abs_diff(second.len()) <= 1
+        && edit_distance(&first, &second) <= 1
+}
+
+/// Count the single character insertions, deletions and substitutions between two names
+fn edit_distance(first: &[char], second: &[char]) -> usize {
+    let mut previous = (0..=second.len()).collect::<Vec<_>>();
+    let mut current = vec![0; second.len() + 1];
+    for (i, first_char) in first.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, second_char) in second.iter().enumerate() {
+            let substitution = previous[j] + usize::from(first_char != second_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[second.len()]
+}
+
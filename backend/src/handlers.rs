@@ -1,14 +1,23 @@
-use axum_login::AuthSession;
+use axum_login::{AuthSession, AuthnBackend as _, AuthzBackend as _};
+use secrecy::ExposeSecret as _;
 
 use axum::{
     Json,
-    extract::State,
+    extract::{Path, Query, State},
     http,
-    response::{Html, IntoResponse},
+    response::{Html, IntoResponse, Redirect, Response},
 };
 use tokio::{fs, io};
+use tower_sessions::Session;
+use tracing::{Level, event};
 
-use crate::{auth, response_bodies, states::RootState};
+use crate::{
+    audit_log::{self, RequestOrigin, actions},
+    auth, client_ip::ClientIp, mailer, oidc, request_bodies, response_bodies, roster,
+    session_store,
+    states::{BackendState, RootState},
+    webauthn,
+};
 
 /// A response error
 #[derive(Debug)]
@@ -49,7 +58,7 @@ pub async fn get_index(State(state): State<RootState>) -> Result<impl IntoRespon
     fs::read_to_string(state.static_dir.join("frontend/index.html"))
         .await
         .map(|content| Html(content).into_response())
-        .map_err(|err| ErrorResponse::IoError(err))
+        .map_err(ErrorResponse::IoError)
 }
 
 pub mod backend {
@@ -61,33 +70,623 @@ pub mod backend {
 
     pub async fn post_login(
         mut auth_session: AuthSession<auth::Backend>,
+        session: Session,
+        State(state): State<BackendState>,
+        ClientIp(ip): ClientIp,
+        origin: RequestOrigin,
         Json(credentials): Json<auth::Credentials>,
     ) -> impl IntoResponse {
-        match auth_session.authenticate(credentials).await {
-            Ok(Some(user)) => match auth_session.login(&user).await {
-                Ok(_) => (
+        // Make the username or address wait if it failed too often
+        let username = credentials.username.clone();
+        let remember = credentials.remember;
+        match state.login_throttle.retry_after(&username, ip).await {
+            Ok(Some(retry_after)) => {
+                let event = audit_log::Event::new(actions::LOGIN_FAILED)
+                    .actor_name(&username)
+                    .details("Too many failed logins");
+                state.audit_log.record(&origin, event).await;
+                return too_many_logins(retry_after);
+            }
+            Ok(None) => {}
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        }
+
+        let user = match auth_session.authenticate(credentials).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                let event = audit_log::Event::new(actions::LOGIN_FAILED)
+                    .actor_name(&username)
+                    .details("Wrong username or password");
+                state.audit_log.record(&origin, event).await;
+                return match state.login_throttle.record_failure(&username, ip).await {
+                    Ok(_) => (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response(),
+                    Err(err) => {
+                        (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                            .into_response()
+                    }
+                };
+            }
+            Err(axum_login::Error::Backend(auth::Error::Suspended(suspension))) => {
+                let event = audit_log::Event::new(actions::LOGIN_FAILED)
+                    .actor_name(&username)
+                    .details(format!("Account is {}", suspension.kind));
+                state.audit_log.record(&origin, event).await;
+                return (
+                    http::StatusCode::FORBIDDEN,
+                    Json(response_bodies::SuspendedResponse::from(*suspension)),
+                )
+                    .into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        // Users with a second factor have to use it before they are logged in or their failures are forgotten
+        match auth_session.backend.has_second_factor(user.id).await {
+            Ok(true) => {
+                return match session
+                    .insert(
+                        auth::PENDING_TWO_FACTOR_KEY,
+                        auth::PendingTwoFactor::new(user.id, remember),
+                    )
+                    .await
+                {
+                    Ok(_) => (
+                        http::StatusCode::ACCEPTED,
+                        Json(response_bodies::LoginResponse {
+                            username: user.username,
+                            two_factor_required: true,
+                        }),
+                    )
+                        .into_response(),
+                    Err(err) => {
+                        (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                            .into_response()
+                    }
+                };
+            }
+            Ok(false) => {}
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        }
+        if let Err(err) = state.login_throttle.record_success(&username).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+
+        if let Err(err) = auth_session.login(&user).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        if let Err(err) = session_store::track_login(&state, &session, user.id, &origin).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        let lifetime = remember.then_some(state.remember_me_lifetime);
+        match auth::remember_login(&session, lifetime).await {
+            Ok(_) => {
+                let event = audit_log::Event::new(actions::LOGIN).actor(&user);
+                state.audit_log.record(&origin, event).await;
+                (
                     http::StatusCode::OK,
                     Json(response_bodies::LoginResponse {
                         username: user.username,
+                        two_factor_required: false,
                     }),
                 )
-                    .into_response(),
-                Err(err) => {
-                    (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+                    .into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    /// Tell a client it has to wait before trying to log in again
+    fn too_many_logins(retry_after: time::Duration) -> Response {
+        let seconds = retry_after.whole_seconds().max(0) + 1;
+        (
+            http::StatusCode::TOO_MANY_REQUESTS,
+            [(http::header::RETRY_AFTER, seconds.to_string())],
+            "Too many failed logins",
+        )
+            .into_response()
+    }
+
+    pub async fn post_login_two_factor(
+        mut auth_session: AuthSession<auth::Backend>,
+        session: Session,
+        State(state): State<BackendState>,
+        ClientIp(ip): ClientIp,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::TwoFactorCodeBody>,
+    ) -> impl IntoResponse {
+        // Get the login waiting for its second factor
+        let mut pending = match session
+            .get::<auth::PendingTwoFactor>(auth::PENDING_TWO_FACTOR_KEY)
+            .await
+        {
+            Ok(Some(pending)) if !pending.is_expired() => pending,
+            Ok(_) => {
+                return (
+                    http::StatusCode::BAD_REQUEST,
+                    "No login is waiting for a second factor",
+                )
+                    .into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        let user = match auth_session.backend.get_user(&pending.user_id).await {
+            Ok(Some(user)) => user,
+            Ok(None) => return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response(),
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        // Wrong codes count as failed logins too, so make the user wait if there were too many
+        match state.login_throttle.retry_after(&user.username, ip).await {
+            Ok(Some(retry_after)) => {
+                let event = audit_log::Event::new(actions::LOGIN_FAILED)
+                    .actor(&user)
+                    .details("Too many failed logins");
+                state.audit_log.record(&origin, event).await;
+                return too_many_logins(retry_after);
+            }
+            Ok(None) => {}
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        }
+
+        // Check the code, dropping the login after too many wrong ones
+        match auth_session
+            .backend
+            .verify_two_factor(pending.user_id, &body.code)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                let event = audit_log::Event::new(actions::LOGIN_FAILED)
+                    .actor(&user)
+                    .details("Wrong second factor code");
+                state.audit_log.record(&origin, event).await;
+                if let Err(err) = state
+                    .login_throttle
+                    .record_failure(&user.username, ip)
+                    .await
+                {
+                    return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                        .into_response();
                 }
-            },
-            Ok(None) => (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response(),
+                pending.attempts += 1;
+                let result = if pending.attempts >= auth::PENDING_TWO_FACTOR_ATTEMPTS {
+                    session
+                        .remove::<auth::PendingTwoFactor>(auth::PENDING_TWO_FACTOR_KEY)
+                        .await
+                        .map(|_| ())
+                } else {
+                    session
+                        .insert(auth::PENDING_TWO_FACTOR_KEY, pending)
+                        .await
+                };
+                return match result {
+                    Ok(_) => (http::StatusCode::UNAUTHORIZED, "Invalid code").into_response(),
+                    Err(err) => {
+                        (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                            .into_response()
+                    }
+                };
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        }
+
+        // Finish logging the user in
+        if let Err(err) = state.login_throttle.record_success(&user.username).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        if let Err(err) = session
+            .remove::<auth::PendingTwoFactor>(auth::PENDING_TWO_FACTOR_KEY)
+            .await
+        {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        if let Err(err) = auth_session.login(&user).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        if let Err(err) = session_store::track_login(&state, &session, user.id, &origin).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        let lifetime = pending.remember.then_some(state.remember_me_lifetime);
+        match auth::remember_login(&session, lifetime).await {
+            Ok(_) => {
+                let event = audit_log::Event::new(actions::LOGIN)
+                    .actor(&user)
+                    .details("With a second factor");
+                state.audit_log.record(&origin, event).await;
+                (
+                    http::StatusCode::OK,
+                    Json(response_bodies::LoginResponse {
+                        username: user.username,
+                        two_factor_required: false,
+                    }),
+                )
+                    .into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn get_login_two_factor(
+        auth_session: AuthSession<auth::Backend>,
+        session: Session,
+    ) -> impl IntoResponse {
+        let pending = match session
+            .get::<auth::PendingTwoFactor>(auth::PENDING_TWO_FACTOR_KEY)
+            .await
+        {
+            Ok(Some(pending)) if !pending.is_expired() => pending,
+            Ok(_) => {
+                return (
+                    http::StatusCode::BAD_REQUEST,
+                    "No login is waiting for a second factor",
+                )
+                    .into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        let code = match auth_session.backend.two_factor_enabled(pending.user_id).await {
+            Ok(code) => code,
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        match auth_session.backend.passkeys(pending.user_id).await {
+            Ok(passkeys) => (
+                http::StatusCode::OK,
+                Json(response_bodies::TwoFactorMethodsResponse {
+                    code,
+                    passkey: !passkeys.is_empty(),
+                }),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_login_two_factor_passkey_start(
+        auth_session: AuthSession<auth::Backend>,
+        session: Session,
+        State(state): State<BackendState>,
+    ) -> impl IntoResponse {
+        let pending = match session
+            .get::<auth::PendingTwoFactor>(auth::PENDING_TWO_FACTOR_KEY)
+            .await
+        {
+            Ok(Some(pending)) if !pending.is_expired() => pending,
+            Ok(_) => {
+                return (
+                    http::StatusCode::BAD_REQUEST,
+                    "No login is waiting for a second factor",
+                )
+                    .into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        // Only the passkeys of the user who typed their password will do
+        let passkeys = match auth_session.backend.passkeys(pending.user_id).await {
+            Ok(passkeys) if !passkeys.is_empty() => passkeys,
+            Ok(_) => {
+                return (http::StatusCode::BAD_REQUEST, "You have no passkeys").into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        let (options, ceremony) = state.relying_party.start_authentication(
+            Some(pending.user_id),
+            passkeys
+                .into_iter()
+                .map(|passkey_entity| passkey_entity.credential_id),
+        );
+        match session
+            .insert(webauthn::PENDING_AUTHENTICATION_KEY, ceremony)
+            .await
+        {
+            Ok(_) => (http::StatusCode::OK, Json(options)).into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_login_two_factor_passkey_finish(
+        mut auth_session: AuthSession<auth::Backend>,
+        session: Session,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::PasskeyAssertionBody>,
+    ) -> impl IntoResponse {
+        let pending = match session
+            .get::<auth::PendingTwoFactor>(auth::PENDING_TWO_FACTOR_KEY)
+            .await
+        {
+            Ok(Some(pending)) if !pending.is_expired() => pending,
+            Ok(_) => {
+                return (
+                    http::StatusCode::BAD_REQUEST,
+                    "No login is waiting for a second factor",
+                )
+                    .into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        // Take the challenge so each one can only be answered once
+        let ceremony = match session
+            .remove::<webauthn::PendingCeremony>(webauthn::PENDING_AUTHENTICATION_KEY)
+            .await
+        {
+            Ok(Some(ceremony)) if ceremony.user_id == Some(pending.user_id) => ceremony,
+            Ok(_) => {
+                return (http::StatusCode::BAD_REQUEST, "No passkey login is waiting")
+                    .into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        let user = match auth_session
+            .backend
+            .authenticate_passkey(&state.relying_party, &ceremony, passkey_assertion(&body))
+            .await
+        {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                return passkey_login_failed(&state, &origin, "Unknown passkey").await;
+            }
+            Err(auth::Error::Passkey(err)) => {
+                return passkey_login_failed(&state, &origin, &err.to_string()).await;
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        if let Err(err) = state.login_throttle.record_success(&user.username).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        if let Err(err) = session
+            .remove::<auth::PendingTwoFactor>(auth::PENDING_TWO_FACTOR_KEY)
+            .await
+        {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        log_in_with_passkey(
+            &mut auth_session,
+            &session,
+            &state,
+            &origin,
+            user,
+            pending.remember,
+            "With a passkey as second factor",
+        )
+        .await
+    }
+
+    pub async fn post_passkey_login_start(
+        session: Session,
+        State(state): State<BackendState>,
+    ) -> impl IntoResponse {
+        // Any passkey on the device will do, since it tells who the user is
+        let (options, ceremony) = state.relying_party.start_authentication(None, []);
+        match session
+            .insert(webauthn::PENDING_AUTHENTICATION_KEY, ceremony)
+            .await
+        {
+            Ok(_) => (http::StatusCode::OK, Json(options)).into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_passkey_login_finish(
+        mut auth_session: AuthSession<auth::Backend>,
+        session: Session,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::PasskeyAssertionBody>,
+    ) -> impl IntoResponse {
+        // Take the challenge so each one can only be answered once
+        let ceremony = match session
+            .remove::<webauthn::PendingCeremony>(webauthn::PENDING_AUTHENTICATION_KEY)
+            .await
+        {
+            Ok(Some(ceremony)) if ceremony.user_id.is_none() => ceremony,
+            Ok(_) => {
+                return (http::StatusCode::BAD_REQUEST, "No passkey login is waiting")
+                    .into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        let user = match auth_session
+            .backend
+            .authenticate_passkey(&state.relying_party, &ceremony, passkey_assertion(&body))
+            .await
+        {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                return passkey_login_failed(&state, &origin, "Unknown passkey").await;
+            }
+            Err(auth::Error::Passkey(err)) => {
+                return passkey_login_failed(&state, &origin, &err.to_string()).await;
+            }
+            Err(auth::Error::Suspended(suspension)) => {
+                let event = audit_log::Event::new(actions::LOGIN_FAILED)
+                    .details(format!("Passkey of a user who is {}", suspension.kind));
+                state.audit_log.record(&origin, event).await;
+                return (
+                    http::StatusCode::FORBIDDEN,
+                    Json(response_bodies::SuspendedResponse::from(*suspension)),
+                )
+                    .into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        // A passkey that verified the user is already two factors, so no code is asked for
+        log_in_with_passkey(
+            &mut auth_session,
+            &session,
+            &state,
+            &origin,
+            user,
+            body.remember,
+            "With a passkey",
+        )
+        .await
+    }
+
+    /// Pass the browser's answer to a passkey login on without copying it
+    fn passkey_assertion(body: &request_bodies::PasskeyAssertionBody) -> webauthn::Assertion<'_> {
+        webauthn::Assertion {
+            credential_id: &body.id,
+            client_data_json: &body.client_data_json,
+            authenticator_data: &body.authenticator_data,
+            signature: &body.signature,
+            user_handle: body.user_handle.as_deref(),
+        }
+    }
+
+    /// Record a passkey that didn't let its holder in, and tell them why
+    async fn passkey_login_failed(
+        state: &BackendState,
+        origin: &RequestOrigin,
+        reason: &str,
+    ) -> Response {
+        event!(Level::WARN, "Refused a passkey login: {}", reason);
+        let event = audit_log::Event::new(actions::LOGIN_FAILED).details(reason);
+        state.audit_log.record(origin, event).await;
+        (http::StatusCode::UNAUTHORIZED, reason.to_string()).into_response()
+    }
+
+    /// Log in a user whose passkey checked out, for as long as they asked to stay
+    async fn log_in_with_passkey(
+        auth_session: &mut AuthSession<auth::Backend>,
+        session: &Session,
+        state: &BackendState,
+        origin: &RequestOrigin,
+        user: auth::User,
+        remember: bool,
+        details: &str,
+    ) -> Response {
+        if let Err(err) = auth_session.login(&user).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        if let Err(err) = session_store::track_login(state, session, user.id, origin).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        let lifetime = remember.then_some(state.remember_me_lifetime);
+        match auth::remember_login(session, lifetime).await {
+            Ok(_) => {
+                let event = audit_log::Event::new(actions::LOGIN)
+                    .actor(&user)
+                    .details(details);
+                state.audit_log.record(origin, event).await;
+                (
+                    http::StatusCode::OK,
+                    Json(response_bodies::LoginResponse {
+                        username: user.username,
+                        two_factor_required: false,
+                    }),
+                )
+                    .into_response()
+            }
             Err(err) => {
                 (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
             }
         }
     }
 
-    pub async fn post_logout(mut auth_session: AuthSession<auth::Backend>) -> impl IntoResponse {
-        match auth_session.user {
-            Some(_) => {
+    pub async fn post_logout(
+        mut auth_session: AuthSession<auth::Backend>,
+        session: Session,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+    ) -> impl IntoResponse {
+        // Logging out also ends viewing the site as another user
+        match session.get::<auth::Impersonation>(auth::IMPERSONATION_KEY).await {
+            Ok(Some(impersonation)) => {
+                if let Err(err) = auth_session.backend.end_impersonation(impersonation.id).await {
+                    return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                        .into_response();
+                }
+                event!(
+                    Level::INFO,
+                    "{} stopped viewing the site as another user by logging out",
+                    impersonation.admin_username
+                );
+                let mut event = audit_log::Event::new(actions::IMPERSONATION_STOP)
+                    .actor_name(&impersonation.admin_username)
+                    .details("By logging out");
+                if let Some(user) = &auth_session.user {
+                    event = event.target(user.id, &user.username);
+                }
+                state.audit_log.record(&origin, event).await;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+            }
+        }
+
+        match auth_session.user.clone() {
+            Some(user) => {
                 match auth_session.logout().await {
-                    Ok(_) => (http::StatusCode::OK, "OK").into_response(),
+                    Ok(_) => {
+                        let event = audit_log::Event::new(actions::LOGOUT).actor(&user);
+                        state.audit_log.record(&origin, event).await;
+                        (http::StatusCode::OK, "OK").into_response()
+                    }
                     Err(err) => (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
                 }
             },
@@ -95,21 +694,3370 @@ pub mod backend {
         }
     }
 
-    pub async fn get_current_user(auth_session: AuthSession<auth::Backend>) -> impl IntoResponse {
-        match auth_session.user {
-            Some(user) => (
-                http::StatusCode::OK,
-                Json(response_bodies::MeResponse {
-                    username: user.username,
-                    admin: user.admin,
-                }),
+    pub async fn get_current_user(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        session: Session,
+    ) -> impl IntoResponse {
+        let Some(user) = auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        // API tokens act as their owner, even when sent along with an impersonating session
+        let impersonator = if user.token_scopes.is_none() {
+            match session.get::<auth::Impersonation>(auth::IMPERSONATION_KEY).await {
+                Ok(impersonation) => impersonation.map(|impersonation| impersonation.admin_username),
+                Err(err) => {
+                    return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                        .into_response();
+                }
+            }
+        } else {
+            None
+        };
+
+        let permissions = match auth_session.backend.get_all_permissions(&user).await {
+            Ok(permissions) => permissions,
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        let mut permissions = permissions
+            .into_iter()
+            .map(|permission| permission.0)
+            .collect::<Vec<_>>();
+        permissions.sort();
+
+        let terms_accepted = match state.terms.accepted_at(user.id).await {
+            Ok(accepted_at) => accepted_at.is_some(),
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        let (two_factor_enabled, has_second_factor) = match (
+            auth_session.backend.two_factor_enabled(user.id).await,
+            auth_session.backend.has_second_factor(user.id).await,
+        ) {
+            (Ok(two_factor_enabled), Ok(has_second_factor)) => {
+                (two_factor_enabled, has_second_factor)
+            }
+            (Err(err), _) | (_, Err(err)) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        (
+            http::StatusCode::OK,
+            Json(response_bodies::MeResponse {
+                username: user.username,
+                role: user.role,
+                permissions,
+                two_factor_enabled,
+                two_factor_required: user.require_two_factor,
+                second_factor_missing: user.require_two_factor && !has_second_factor,
+                impersonator,
+                terms_accepted,
+            }),
+        )
+            .into_response()
+    }
+
+    pub async fn get_terms(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+    ) -> impl IntoResponse {
+        // Anyone can read the terms, but only users can have agreed to them
+        let accepted_at = match &auth_session.user {
+            Some(user) => match state.terms.accepted_at(user.id).await {
+                Ok(accepted_at) => accepted_at,
+                Err(err) => {
+                    return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                        .into_response();
+                }
+            },
+            None => None,
+        };
+
+        (
+            http::StatusCode::OK,
+            Json(response_bodies::TermsResponse::new(&state.terms, accepted_at)),
+        )
+            .into_response()
+    }
+
+    pub async fn post_accept_terms(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::AcceptTermsBody>,
+    ) -> impl IntoResponse {
+        let Some(user) = auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        // Only agree to the version the user actually read
+        if body.version != state.terms.version() {
+            return (
+                http::StatusCode::CONFLICT,
+                "The terms of use have changed, please read them again",
+            )
+                .into_response();
+        }
+
+        match state.terms.accept(user.id).await {
+            Ok(newly_accepted) => {
+                if newly_accepted {
+                    let event = audit_log::Event::new(actions::TERMS_ACCEPT)
+                        .actor(&user)
+                        .details(format!("version {}", state.terms.version()));
+                    state.audit_log.record(&origin, event).await;
+                }
+                (http::StatusCode::OK, "OK").into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_create_user(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::CreateUserBody>,
+    ) -> impl IntoResponse {
+        // Refuse empty usernames and passwords
+        if body.username.trim().is_empty() || body.password.expose_secret().is_empty() {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                "Username and password must not be empty",
+            )
+                .into_response();
+        }
+        if body.username.trim().chars().count() > auth::USERNAME_MAX_LENGTH {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                format!("Username is longer than {} characters", auth::USERNAME_MAX_LENGTH),
+            )
+                .into_response();
+        }
+
+        let student_id = body
+            .student_id
+            .as_deref()
+            .map(str::trim)
+            .filter(|student_id| !student_id.is_empty());
+        if student_id
+            .is_some_and(|student_id| student_id.chars().count() > auth::STUDENT_ID_MAX_LENGTH)
+        {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                format!("Student ID is longer than {} characters", auth::STUDENT_ID_MAX_LENGTH),
+            )
+                .into_response();
+        }
+
+        // Only allow granting roles whose permissions the creator already holds
+        let role = body.role.as_deref().unwrap_or(auth::roles::STUDENT);
+        let creator = match auth_session.user.as_ref() {
+            Some(user) => match auth_session.backend.can_grant_role(user, role).await {
+                Ok(true) => user,
+                Ok(false) => return (http::StatusCode::FORBIDDEN, "Forbidden").into_response(),
+                Err(auth::Error::RoleNotFound) => {
+                    return (http::StatusCode::BAD_REQUEST, "Role not found").into_response();
+                }
+                Err(err) => {
+                    return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                        .into_response();
+                }
+            },
+            None => return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response(),
+        };
+
+        match auth_session
+            .backend
+            .create_user(body.username.trim(), body.password.expose_secret(), role, student_id)
+            .await
+        {
+            Ok(user) => {
+                let event = audit_log::Event::new(actions::USER_CREATE)
+                    .actor(creator)
+                    .target(user.id, &user.username)
+                    .details(format!("Role {}", user.role));
+                state.audit_log.record(&origin, event).await;
+                (
+                    http::StatusCode::CREATED,
+                    Json(response_bodies::UserResponse {
+                        id: user.id,
+                        username: user.username,
+                        role: user.role,
+                    }),
+                )
+                    .into_response()
+            }
+            Err(auth::Error::UsernameTaken) => {
+                (http::StatusCode::CONFLICT, "Username is already taken").into_response()
+            }
+            Err(auth::Error::StudentIdTaken) => (
+                http::StatusCode::CONFLICT,
+                "Student ID already belongs to another user",
             )
                 .into_response(),
-            None => (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response(),
+            Err(auth::Error::RoleNotFound) => {
+                (http::StatusCode::BAD_REQUEST, "Role not found").into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
         }
     }
 
-    pub async fn get_404() -> impl IntoResponse {
-        (http::StatusCode::NOT_FOUND, "Not Found").into_response()
+    pub async fn post_change_password(
+        mut auth_session: AuthSession<auth::Backend>,
+        session: Session,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::ChangePasswordBody>,
+    ) -> impl IntoResponse {
+        let Some(user) = auth_session.user.clone() else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        // Refuse empty passwords
+        if body.new_password.expose_secret().is_empty() {
+            return (http::StatusCode::BAD_REQUEST, "Password must not be empty").into_response();
+        }
+
+        match auth_session
+            .backend
+            .change_password(
+                user.id,
+                body.current_password.expose_secret(),
+                body.new_password.expose_secret(),
+            )
+            .await
+        {
+            // Log the current session back in so only the other sessions are invalidated
+            Ok(user) => match auth_session.login(&user).await {
+                Ok(_) => {
+                    if let Some(session_id) = session.id() {
+                        forget_sessions(
+                            state
+                                .session_store
+                                .delete_other_user_sessions(user.id, &session_id)
+                                .await,
+                        );
+                    }
+                    let event = audit_log::Event::new(actions::PASSWORD_CHANGE).actor(&user);
+                    state.audit_log.record(&origin, event).await;
+                    (http::StatusCode::OK, "OK").into_response()
+                }
+                Err(err) => {
+                    (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+                }
+            },
+            Err(auth::Error::IncorrectPassword) => {
+                (http::StatusCode::FORBIDDEN, "Incorrect password").into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_register(
+        State(state): State<BackendState>,
+        auth_session: AuthSession<auth::Backend>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::RegisterBody>,
+    ) -> impl IntoResponse {
+        // Registration is disabled without any allowed domains
+        if state.allowed_email_domains.is_empty() {
+            return (http::StatusCode::FORBIDDEN, "Registration is disabled").into_response();
+        }
+
+        // Refuse empty usernames and passwords
+        let username = body.username.trim();
+        if username.is_empty() || body.password.expose_secret().is_empty() {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                "Username and password must not be empty",
+            )
+                .into_response();
+        }
+        if username.chars().count() > auth::USERNAME_MAX_LENGTH {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                format!("Username is longer than {} characters", auth::USERNAME_MAX_LENGTH),
+            )
+                .into_response();
+        }
+
+        // Make sure the email is valid and belongs to an allowed domain
+        let email = body.email.trim().to_lowercase();
+        let address = match email.parse::<lettre::Address>() {
+            Ok(address) => address,
+            Err(_) => {
+                return (http::StatusCode::BAD_REQUEST, "Invalid email address").into_response();
+            }
+        };
+        if !state
+            .allowed_email_domains
+            .iter()
+            .any(|domain| domain == address.domain())
+        {
+            return (http::StatusCode::BAD_REQUEST, "Email domain is not allowed").into_response();
+        }
+
+        // Create the inactive user
+        let (user, token) = match auth_session
+            .backend
+            .register_user(username, &email, body.password.expose_secret())
+            .await
+        {
+            Ok(result) => result,
+            Err(auth::Error::UsernameTaken) => {
+                return (http::StatusCode::CONFLICT, "Username is already taken").into_response();
+            }
+            Err(auth::Error::EmailAlreadyExists) => {
+                return (http::StatusCode::CONFLICT, "Email already in use").into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        // Send the verification link, removing the user again if it can't be sent
+        let email = mailer::Email {
+            to: email,
+            subject: "Verify your ConnectIA account".to_string(),
+            body: format!(
+                "Welcome to ConnectIA, {}!\n\nOpen the link below to verify your email address and activate your account:\n\n{}/verify-email?token={}\n\nThe link expires in 24 hours. If you did not sign up, you can ignore this email.\n",
+                user.username, state.public_url, token
+            ),
+        };
+        if let Err(err) = state.mailer.send(email).await {
+            event!(Level::ERROR, "Failed to send verification email: {}", err);
+            if let Err(err) = auth_session.backend.delete_user(user.id).await {
+                event!(Level::ERROR, "Failed to remove unverified user: {}", err);
+            }
+            return (
+                http::StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to send verification email",
+            )
+                .into_response();
+        }
+
+        let event = audit_log::Event::new(actions::USER_REGISTER)
+            .actor(&user)
+            .target(user.id, &user.username);
+        state.audit_log.record(&origin, event).await;
+        (http::StatusCode::CREATED, "Created").into_response()
+    }
+
+    pub async fn post_verify_email(
+        auth_session: AuthSession<auth::Backend>,
+        Json(body): Json<request_bodies::VerifyEmailBody>,
+    ) -> impl IntoResponse {
+        match auth_session.backend.verify_email(&body.token).await {
+            Ok(user) => (
+                http::StatusCode::OK,
+                Json(response_bodies::UserResponse {
+                    id: user.id,
+                    username: user.username,
+                    role: user.role,
+                }),
+            )
+                .into_response(),
+            Err(auth::Error::InvalidToken) => {
+                (http::StatusCode::BAD_REQUEST, "Invalid or expired token").into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_password_reset_request(
+        State(state): State<BackendState>,
+        auth_session: AuthSession<auth::Backend>,
+        ClientIp(ip): ClientIp,
+        Json(body): Json<request_bodies::PasswordResetRequestBody>,
+    ) -> impl IntoResponse {
+        // Make the email or address wait if it asked too often, whether or not the account exists
+        let email = body.email.trim().to_lowercase();
+        match state.login_throttle.reset_retry_after(&email, ip).await {
+            Ok(Some(retry_after)) => {
+                let seconds = retry_after.whole_seconds().max(0) + 1;
+                return (
+                    http::StatusCode::TOO_MANY_REQUESTS,
+                    [(http::header::RETRY_AFTER, seconds.to_string())],
+                    "Too many password reset requests",
+                )
+                    .into_response();
+            }
+            Ok(None) => {}
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        }
+        if let Err(err) = state.login_throttle.record_reset_request(&email, ip).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+
+        // Look the account up and send the reset link in the background, so the response takes
+        // as long whether or not the account exists
+        tokio::spawn(async move {
+            let (user, token) = match auth_session.backend.request_password_reset(&email).await {
+                Ok(Some(result)) => result,
+                Ok(None) => return,
+                Err(err) => {
+                    event!(
+                        Level::ERROR,
+                        "Failed to issue password reset token: {}",
+                        err
+                    );
+                    return;
+                }
+            };
+            let email = mailer::Email {
+                to: email,
+                subject: "Reset your ConnectIA password".to_string(),
+                body: format!(
+                    "Hi {},\n\nSomeone asked to reset the password for your ConnectIA account. Open the link below to choose a new one:\n\n{}/reset-password?token={}\n\nThe link expires in 1 hour and can only be used once. If you did not ask for this, you can ignore this email.\n",
+                    user.username, state.public_url, token
+                ),
+            };
+            if let Err(err) = state.mailer.send(email).await {
+                event!(Level::ERROR, "Failed to send password reset email: {}", err);
+            }
+        });
+
+        (http::StatusCode::OK, "OK").into_response()
+    }
+
+    pub async fn post_password_reset(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::PasswordResetBody>,
+    ) -> impl IntoResponse {
+        // Refuse empty passwords
+        if body.new_password.expose_secret().is_empty() {
+            return (http::StatusCode::BAD_REQUEST, "Password must not be empty").into_response();
+        }
+
+        match auth_session
+            .backend
+            .reset_password(&body.token, body.new_password.expose_secret())
+            .await
+        {
+            Ok(user) => {
+                forget_sessions(state.session_store.delete_user_sessions(user.id).await);
+                let event = audit_log::Event::new(actions::PASSWORD_RESET).actor(&user);
+                state.audit_log.record(&origin, event).await;
+                (http::StatusCode::OK, "OK").into_response()
+            }
+            Err(auth::Error::InvalidToken) => {
+                (http::StatusCode::BAD_REQUEST, "Invalid or expired token").into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_two_factor_setup(
+        auth_session: AuthSession<auth::Backend>,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        match auth_session.backend.begin_two_factor_setup(user).await {
+            Ok((secret, provisioning_uri)) => (
+                http::StatusCode::OK,
+                Json(response_bodies::TwoFactorSetupResponse {
+                    secret,
+                    provisioning_uri,
+                }),
+            )
+                .into_response(),
+            Err(auth::Error::TwoFactorAlreadyEnabled) => (
+                http::StatusCode::CONFLICT,
+                "Two-factor authentication is already enabled",
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_two_factor_enable(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::TwoFactorCodeBody>,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        match auth_session
+            .backend
+            .enable_two_factor(user.id, &body.code)
+            .await
+        {
+            Ok(recovery_codes) => {
+                let event = audit_log::Event::new(actions::TWO_FACTOR_ENABLE).actor(user);
+                state.audit_log.record(&origin, event).await;
+                (
+                    http::StatusCode::OK,
+                    Json(response_bodies::RecoveryCodesResponse { recovery_codes }),
+                )
+                    .into_response()
+            }
+            Err(auth::Error::TwoFactorNotSetUp) => (
+                http::StatusCode::BAD_REQUEST,
+                "Two-factor setup has not been started",
+            )
+                .into_response(),
+            Err(auth::Error::InvalidTwoFactorCode) => {
+                (http::StatusCode::FORBIDDEN, "Invalid code").into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_two_factor_disable(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::TwoFactorDisableBody>,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        match auth_session
+            .backend
+            .disable_two_factor(user.id, body.password.expose_secret())
+            .await
+        {
+            Ok(_) => {
+                let event = audit_log::Event::new(actions::TWO_FACTOR_DISABLE).actor(user);
+                state.audit_log.record(&origin, event).await;
+                (http::StatusCode::OK, "OK").into_response()
+            }
+            Err(auth::Error::IncorrectPassword) => {
+                (http::StatusCode::FORBIDDEN, "Incorrect password").into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_recovery_codes(
+        auth_session: AuthSession<auth::Backend>,
+        Json(body): Json<request_bodies::TwoFactorCodeBody>,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        match auth_session
+            .backend
+            .regenerate_recovery_codes(user.id, &body.code)
+            .await
+        {
+            Ok(recovery_codes) => (
+                http::StatusCode::OK,
+                Json(response_bodies::RecoveryCodesResponse { recovery_codes }),
+            )
+                .into_response(),
+            Err(auth::Error::TwoFactorNotSetUp) => (
+                http::StatusCode::BAD_REQUEST,
+                "Two-factor authentication is not enabled",
+            )
+                .into_response(),
+            Err(auth::Error::InvalidTwoFactorCode) => {
+                (http::StatusCode::FORBIDDEN, "Invalid code").into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn get_api_tokens(auth_session: AuthSession<auth::Backend>) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        match auth_session.backend.api_tokens(user.id).await {
+            Ok(api_tokens) => (
+                http::StatusCode::OK,
+                Json(
+                    api_tokens
+                        .into_iter()
+                        .map(response_bodies::ApiTokenResponse::from)
+                        .collect::<Vec<_>>(),
+                ),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_api_token(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::CreateApiTokenBody>,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        // Validate the name and lifetime
+        let name = body.name.trim();
+        if name.is_empty() || name.len() > 100 {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                "Token name must be between 1 and 100 characters",
+            )
+                .into_response();
+        }
+        if !(1..=auth::API_TOKEN_MAX_LIFETIME_DAYS).contains(&body.expires_in_days) {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                format!(
+                    "Tokens must expire within 1 to {} days",
+                    auth::API_TOKEN_MAX_LIFETIME_DAYS
+                ),
+            )
+                .into_response();
+        }
+
+        match auth_session
+            .backend
+            .create_api_token(
+                user,
+                name,
+                &body.scopes,
+                time::Duration::days(body.expires_in_days.into()),
+            )
+            .await
+        {
+            Ok((api_token, token)) => {
+                let event = audit_log::Event::new(actions::API_TOKEN_CREATE)
+                    .actor(user)
+                    .details(format!("{} with {}", api_token.name, api_token.scopes));
+                state.audit_log.record(&origin, event).await;
+                (
+                    http::StatusCode::CREATED,
+                    Json(response_bodies::NewApiTokenResponse {
+                        token,
+                        api_token: api_token.into(),
+                    }),
+                )
+                    .into_response()
+            }
+            Err(auth::Error::InvalidScope) => {
+                (http::StatusCode::BAD_REQUEST, "Invalid token scope").into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_revoke_api_token(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::RevokeApiTokenBody>,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        match auth_session.backend.revoke_api_token(user.id, body.id).await {
+            Ok(true) => {
+                let event = audit_log::Event::new(actions::API_TOKEN_REVOKE)
+                    .actor(user)
+                    .details(format!("Token {}", body.id));
+                state.audit_log.record(&origin, event).await;
+                (http::StatusCode::OK, "OK").into_response()
+            }
+            Ok(false) => (http::StatusCode::NOT_FOUND, "Token not found").into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn get_profile(
+        auth_session: AuthSession<auth::Backend>,
+        Path(username): Path<String>,
+    ) -> impl IntoResponse {
+        if auth_session.user.is_none() {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        }
+
+        match auth_session.backend.profile(&username).await {
+            Ok(Some(profile)) => (
+                http::StatusCode::OK,
+                Json(response_bodies::ProfileResponse::from(profile)),
+            )
+                .into_response(),
+            Ok(None) => (http::StatusCode::NOT_FOUND, "User not found").into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn patch_profile(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::UpdateProfileBody>,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        // Check the short fields, which have to fit on a line
+        let text_fields = [
+            ("Display name", &body.display_name, auth::DISPLAY_NAME_MAX_LENGTH),
+            ("Pronouns", &body.pronouns, auth::PRONOUNS_MAX_LENGTH),
+        ];
+        for (field, value, max_length) in text_fields {
+            let Some(value) = value.as_deref().map(str::trim) else {
+                continue;
+            };
+            if value.chars().count() > max_length {
+                return (
+                    http::StatusCode::BAD_REQUEST,
+                    format!("{} is longer than {} characters", field, max_length),
+                )
+                    .into_response();
+            }
+            if value.chars().any(char::is_control) {
+                return (
+                    http::StatusCode::BAD_REQUEST,
+                    format!("{} must fit on one line", field),
+                )
+                    .into_response();
+            }
+        }
+        if body
+            .bio
+            .as_deref()
+            .is_some_and(|bio| bio.trim().chars().count() > auth::BIO_MAX_LENGTH)
+        {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                format!("Bio is longer than {} characters", auth::BIO_MAX_LENGTH),
+            )
+                .into_response();
+        }
+
+        // Students graduate within a lifetime, and not too far ahead
+        let this_year = time::OffsetDateTime::now_utc().year();
+        if let Some(year) = body.graduation_year
+            && year != 0
+            && !(this_year - 100..=this_year + 10).contains(&year)
+        {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                format!(
+                    "Graduation year must be between {} and {}",
+                    this_year - 100,
+                    this_year + 10
+                ),
+            )
+                .into_response();
+        }
+
+        // Only allow web links, stored the way browsers would read them
+        let links = match body.links.as_ref().map(|links| profile_links(links)).transpose() {
+            Ok(links) => links,
+            Err(message) => return (http::StatusCode::BAD_REQUEST, message).into_response(),
+        };
+
+        // Update the profile
+        let changed = [
+            ("display name", body.display_name.is_some()),
+            ("pronouns", body.pronouns.is_some()),
+            ("graduation year", body.graduation_year.is_some()),
+            ("bio", body.bio.is_some()),
+            ("links", links.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(field, changed)| changed.then_some(field))
+        .collect::<Vec<_>>();
+        let removable =
+            |value: String| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+        let changes = auth::ProfileChanges {
+            display_name: body.display_name.map(removable),
+            pronouns: body.pronouns.map(removable),
+            graduation_year: body.graduation_year.map(|year| Some(year).filter(|year| *year != 0)),
+            bio: body.bio.map(removable),
+            links,
+        };
+        if let Err(err) = auth_session.backend.update_profile(user.id, changes).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        let event = audit_log::Event::new(actions::PROFILE_UPDATE)
+            .actor(user)
+            .details(changed.join(", "));
+        state.audit_log.record(&origin, event).await;
+
+        // Answer with the whole profile, like reading it would
+        match auth_session.backend.profile(&user.username).await {
+            Ok(Some(profile)) => (
+                http::StatusCode::OK,
+                Json(response_bodies::ProfileResponse::from(profile)),
+            )
+                .into_response(),
+            Ok(None) => (http::StatusCode::NOT_FOUND, "User not found").into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    /// Check the links for a profile, dropping blank ones
+    fn profile_links(links: &[String]) -> Result<Vec<String>, String> {
+        let links = links
+            .iter()
+            .map(|link| link.trim())
+            .filter(|link| !link.is_empty())
+            .collect::<Vec<_>>();
+        if links.len() > auth::PROFILE_MAX_LINKS {
+            return Err(format!("A profile can have at most {} links", auth::PROFILE_MAX_LINKS));
+        }
+        links
+            .into_iter()
+            .map(|link| {
+                if link.chars().count() > auth::PROFILE_LINK_MAX_LENGTH {
+                    return Err(format!(
+                        "Links must be at most {} characters",
+                        auth::PROFILE_LINK_MAX_LENGTH
+                    ));
+                }
+                match reqwest::Url::parse(link) {
+                    Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(url.to_string()),
+                    _ => Err(format!("{} is not a web link", link)),
+                }
+            })
+            .collect()
+    }
+
+    pub async fn get_passkeys(auth_session: AuthSession<auth::Backend>) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        match auth_session.backend.passkeys(user.id).await {
+            Ok(passkeys) => (
+                http::StatusCode::OK,
+                Json(
+                    passkeys
+                        .into_iter()
+                        .map(response_bodies::PasskeyResponse::from)
+                        .collect::<Vec<_>>(),
+                ),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_passkey_register_start(
+        auth_session: AuthSession<auth::Backend>,
+        session: Session,
+        State(state): State<BackendState>,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        // Authenticators that already hold one of the user's passkeys shouldn't make another
+        let passkeys = match auth_session.backend.passkeys(user.id).await {
+            Ok(passkeys) => passkeys,
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        let (options, ceremony) = state.relying_party.start_registration(
+            user.id,
+            &user.username,
+            passkeys
+                .into_iter()
+                .map(|passkey_entity| passkey_entity.credential_id),
+        );
+        match session
+            .insert(webauthn::PENDING_REGISTRATION_KEY, ceremony)
+            .await
+        {
+            Ok(_) => (http::StatusCode::OK, Json(options)).into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_passkey_register_finish(
+        auth_session: AuthSession<auth::Backend>,
+        session: Session,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::PasskeyRegistrationBody>,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        // Validate the name
+        let name = body.name.trim();
+        if name.is_empty() || name.len() > 100 {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                "Passkey name must be between 1 and 100 characters",
+            )
+                .into_response();
+        }
+
+        // Take the challenge so each one can only be answered once
+        let ceremony = match session
+            .remove::<webauthn::PendingCeremony>(webauthn::PENDING_REGISTRATION_KEY)
+            .await
+        {
+            Ok(Some(ceremony)) if ceremony.user_id == Some(user.id) => ceremony,
+            Ok(_) => {
+                return (
+                    http::StatusCode::BAD_REQUEST,
+                    "No passkey registration is waiting",
+                )
+                    .into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        let credential = match state.relying_party.finish_registration(
+            &ceremony,
+            &body.id,
+            &body.client_data_json,
+            &body.attestation_object,
+        ) {
+            Ok(credential) => credential,
+            Err(err) => {
+                event!(Level::WARN, "Refused a passkey registration: {}", err);
+                return (http::StatusCode::BAD_REQUEST, format!("{}", err)).into_response();
+            }
+        };
+
+        match auth_session.backend.add_passkey(user.id, name, credential).await {
+            Ok(passkey_entity) => {
+                let event = audit_log::Event::new(actions::PASSKEY_ADD)
+                    .actor(user)
+                    .details(&passkey_entity.name);
+                state.audit_log.record(&origin, event).await;
+                (
+                    http::StatusCode::CREATED,
+                    Json(response_bodies::PasskeyResponse::from(passkey_entity)),
+                )
+                    .into_response()
+            }
+            Err(auth::Error::PasskeyAlreadyAdded) => {
+                (http::StatusCode::CONFLICT, "Passkey is already added").into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_remove_passkey(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::RemovePasskeyBody>,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        match auth_session
+            .backend
+            .remove_passkey(user.id, body.id, body.password.expose_secret())
+            .await
+        {
+            Ok(Some(passkey_entity)) => {
+                let event = audit_log::Event::new(actions::PASSKEY_REMOVE)
+                    .actor(user)
+                    .details(&passkey_entity.name);
+                state.audit_log.record(&origin, event).await;
+                (http::StatusCode::OK, "OK").into_response()
+            }
+            Ok(None) => (http::StatusCode::NOT_FOUND, "Passkey not found").into_response(),
+            Err(auth::Error::IncorrectPassword) => {
+                (http::StatusCode::FORBIDDEN, "Incorrect password").into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn get_sessions(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        session: Session,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        let current_id = session.id().map(|id| id.to_string());
+        match state.session_store.user_sessions(user.id).await {
+            Ok(session_entities) => (
+                http::StatusCode::OK,
+                Json(
+                    session_entities
+                        .into_iter()
+                        .map(|session_entity| {
+                            response_bodies::SessionResponse::new(
+                                session_entity,
+                                current_id.as_deref(),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_revoke_session(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        session: Session,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::RevokeSessionBody>,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        // Ending the current session is what logging out is for
+        if session
+            .id()
+            .is_some_and(|id| session_store::public_id(&id.to_string()) == body.id)
+        {
+            return (http::StatusCode::BAD_REQUEST, "Log out to end this session").into_response();
+        }
+
+        match state.session_store.delete_user_session(user.id, &body.id).await {
+            Ok(Some(session_entity)) => {
+                let event = audit_log::Event::new(actions::SESSION_REVOKE)
+                    .actor(user)
+                    .details(format!(
+                        "Session last seen from {}",
+                        session_entity.ip.as_deref().unwrap_or("an unknown address")
+                    ));
+                state.audit_log.record(&origin, event).await;
+                (http::StatusCode::OK, "OK").into_response()
+            }
+            Ok(None) => (http::StatusCode::NOT_FOUND, "Session not found").into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    /// Log a failure to delete sessions that were already logged out some other way
+    ///
+    /// They can't be used anymore and only linger in the session list until they expire, so this
+    /// shouldn't fail a request whose action already happened.
+    fn forget_sessions(result: Result<u64, sea_orm::DbErr>) {
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to delete ended sessions: {}", err);
+        }
+    }
+
+    pub async fn post_revoke_other_sessions(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        session: Session,
+        origin: RequestOrigin,
+    ) -> impl IntoResponse {
+        let Some(user) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+        let Some(session_id) = session.id() else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        match state
+            .session_store
+            .delete_other_user_sessions(user.id, &session_id)
+            .await
+        {
+            Ok(revoked) => {
+                if revoked > 0 {
+                    let event = audit_log::Event::new(actions::SESSION_REVOKE)
+                        .actor(user)
+                        .details(format!("All other sessions ({})", revoked));
+                    state.audit_log.record(&origin, event).await;
+                }
+                (http::StatusCode::OK, Json(revoked)).into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    /// Send a failed single sign-on back to the login page with a message
+    fn sso_error(message: &str) -> Response {
+        Redirect::to(&format!(
+            "/login?sso_error={}",
+            urlencoding::encode(message)
+        ))
+        .into_response()
+    }
+
+    /// Explain to a suspended user why they can't sign in
+    fn suspended_message(suspension: &crate::db::suspensions::Model) -> String {
+        let state = if suspension.kind == auth::suspension_kinds::BANNED {
+            "banned"
+        } else {
+            "suspended"
+        };
+        match suspension.ends_at {
+            Some(ends_at) => format!(
+                "Your account is {} until {}: {}",
+                state,
+                ends_at.date(),
+                suspension.reason
+            ),
+            None => format!("Your account is {}: {}", state, suspension.reason),
+        }
+    }
+
+    pub async fn get_oidc(State(state): State<BackendState>) -> impl IntoResponse {
+        (
+            http::StatusCode::OK,
+            Json(response_bodies::OidcResponse {
+                enabled: state.oidc.is_some(),
+                name: state.oidc.as_ref().map(|provider| provider.name.clone()),
+            }),
+        )
+            .into_response()
+    }
+
+    pub async fn get_oidc_login(
+        State(state): State<BackendState>,
+        session: Session,
+        Query(query): Query<request_bodies::OidcLoginQuery>,
+    ) -> impl IntoResponse {
+        let Some(provider) = &state.oidc else {
+            return (http::StatusCode::NOT_FOUND, "Single sign-on is disabled").into_response();
+        };
+
+        // Only return to paths on this site
+        let next = query
+            .next
+            .filter(|next| next.starts_with('/') && !next.starts_with("//") && !next.contains('\\'))
+            .unwrap_or_else(|| "/".to_string());
+
+        // Remember the login and send the user to the provider
+        let (url, pending) = provider.authorize(next);
+        match session.insert(oidc::PENDING_SSO_KEY, pending).await {
+            Ok(_) => Redirect::to(&url).into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn get_oidc_callback(
+        State(state): State<BackendState>,
+        mut auth_session: AuthSession<auth::Backend>,
+        session: Session,
+        origin: RequestOrigin,
+        Query(query): Query<request_bodies::OidcCallbackQuery>,
+    ) -> impl IntoResponse {
+        let Some(provider) = &state.oidc else {
+            return (http::StatusCode::NOT_FOUND, "Single sign-on is disabled").into_response();
+        };
+
+        // Take the pending login so it can only be finished once
+        let pending = match session.remove::<oidc::PendingSso>(oidc::PENDING_SSO_KEY).await {
+            Ok(Some(pending)) => pending,
+            Ok(None) => return sso_error("Your sign in expired, please try again"),
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        // Make sure the provider is answering the login this session started
+        if let Some(error) = &query.error {
+            event!(Level::WARN, "Single sign-on provider returned an error: {}", error);
+            return sso_error("Signing in with your school account was cancelled");
+        }
+        let (Some(code), Some(returned_state)) = (query.code, query.state) else {
+            return sso_error("Invalid response from your school account");
+        };
+        if returned_state != pending.state {
+            return sso_error("Invalid response from your school account");
+        }
+
+        // Exchange the code for the identity
+        let next = pending.next.clone();
+        let identity = match provider.exchange(code, pending).await {
+            Ok(identity) => identity,
+            Err(err) => {
+                event!(Level::ERROR, "Failed to finish single sign-on: {}", err);
+                return sso_error("Could not verify your school account");
+            }
+        };
+
+        // Find, link or create the user, only trusting verified emails
+        let email = identity
+            .email
+            .as_deref()
+            .filter(|_| identity.email_verified);
+        let user = match auth_session
+            .backend
+            .external_login(
+                &provider.issuer,
+                &identity.subject,
+                email,
+                &state.allowed_email_domains,
+            )
+            .await
+        {
+            Ok(user) => user,
+            Err(auth::Error::EmailDomainNotAllowed) => {
+                return sso_error("Your school account's email is not allowed to use ConnectIA");
+            }
+            Err(auth::Error::UserNotFound) => {
+                return sso_error("The account linked to your school account is not active");
+            }
+            Err(auth::Error::Suspended(suspension)) => {
+                let event = audit_log::Event::new(actions::LOGIN_FAILED)
+                    .details(format!(
+                        "Single sign-on for {}, account is {}",
+                        identity.subject, suspension.kind
+                    ));
+                state.audit_log.record(&origin, event).await;
+                return sso_error(&suspended_message(&suspension));
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        // Users with a second factor still have to use it
+        match auth_session.backend.has_second_factor(user.id).await {
+            Ok(true) => {
+                return match session
+                    .insert(
+                        auth::PENDING_TWO_FACTOR_KEY,
+                        auth::PendingTwoFactor::new(user.id, false),
+                    )
+                    .await
+                {
+                    Ok(_) => Redirect::to("/login?two_factor=true").into_response(),
+                    Err(err) => {
+                        (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                            .into_response()
+                    }
+                };
+            }
+            Ok(false) => {}
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        }
+
+        // Single sign-on logins end with the browser, since there's nowhere to ask
+        if let Err(err) = auth_session.login(&user).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        if let Err(err) = session_store::track_login(&state, &session, user.id, &origin).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        match auth::remember_login(&session, None).await {
+            Ok(_) => {
+                let event = audit_log::Event::new(actions::LOGIN)
+                    .actor(&user)
+                    .details("With single sign-on");
+                state.audit_log.record(&origin, event).await;
+                Redirect::to(&next).into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn get_roles(auth_session: AuthSession<auth::Backend>) -> impl IntoResponse {
+        match auth_session.backend.roles().await {
+            Ok(roles) => (
+                http::StatusCode::OK,
+                Json(
+                    roles
+                        .into_iter()
+                        .map(|role| response_bodies::RoleResponse {
+                            name: role.name,
+                            permissions: role.permissions,
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn get_login_locks(State(state): State<BackendState>) -> impl IntoResponse {
+        let now = time::OffsetDateTime::now_utc();
+        match state.login_throttle.locked().await {
+            Ok(throttles) => (
+                http::StatusCode::OK,
+                Json(
+                    throttles
+                        .into_iter()
+                        .map(|throttle| response_bodies::LoginLockResponse {
+                            id: throttle.id,
+                            scope: throttle.scope,
+                            identifier: throttle.identifier,
+                            failures: throttle.failures,
+                            remaining_seconds: throttle
+                                .locked_until
+                                .map(|locked_until| (locked_until - now).whole_seconds() + 1)
+                                .unwrap_or_default(),
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_unlock_login(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::UnlockLoginBody>,
+    ) -> impl IntoResponse {
+        let Some(admin) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        match state.login_throttle.unlock(body.id).await {
+            Ok(Some(throttle)) => {
+                let event = audit_log::Event::new(actions::LOGIN_UNLOCK)
+                    .actor(admin)
+                    .details(format!("{} {}", throttle.scope, throttle.identifier));
+                state.audit_log.record(&origin, event).await;
+                (http::StatusCode::OK, "OK").into_response()
+            }
+            Ok(None) => (http::StatusCode::NOT_FOUND, "Lock not found").into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn get_suspensions(auth_session: AuthSession<auth::Backend>) -> impl IntoResponse {
+        match auth_session.backend.suspensions().await {
+            Ok(suspensions) => (
+                http::StatusCode::OK,
+                Json(
+                    suspensions
+                        .into_iter()
+                        .map(response_bodies::SuspensionResponse::from)
+                        .collect::<Vec<_>>(),
+                ),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_suspension(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::SuspendUserBody>,
+    ) -> impl IntoResponse {
+        let Some(issuer) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        // Check the kind, reason and length
+        if !auth::suspension_kinds::ALL.contains(&body.kind.as_str()) {
+            return (http::StatusCode::BAD_REQUEST, "Unknown suspension kind").into_response();
+        }
+        let reason = body.reason.trim();
+        if reason.is_empty() || reason.chars().count() > 500 {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                "Reason must be between 1 and 500 characters",
+            )
+                .into_response();
+        }
+        if body.days.is_some_and(|days| !(1..=3650).contains(&days)) {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                "Suspensions must last between 1 and 3650 days",
+            )
+                .into_response();
+        }
+
+        // Find the user, who has to rank no higher than the moderator
+        let user = match auth_session.backend.find_user(&body.username).await {
+            Ok(Some(user)) => user,
+            Ok(None) => return (http::StatusCode::NOT_FOUND, "User not found").into_response(),
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        if user.id == issuer.id {
+            return (http::StatusCode::BAD_REQUEST, "You can't suspend yourself").into_response();
+        }
+        match auth_session.backend.can_grant_role(issuer, &user.role).await {
+            Ok(true) => {}
+            Ok(false) => return (http::StatusCode::FORBIDDEN, "Forbidden").into_response(),
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        }
+
+        let ends_at = body
+            .days
+            .map(|days| time::OffsetDateTime::now_utc() + time::Duration::days(days.into()));
+        match auth_session
+            .backend
+            .suspend_user(issuer, user.id, &body.kind, reason, ends_at)
+            .await
+        {
+            Ok(suspension) => {
+                forget_sessions(state.session_store.delete_user_sessions(user.id).await);
+                event!(
+                    Level::INFO,
+                    "{} {} {} until {}",
+                    issuer.username,
+                    suspension.kind,
+                    user.username,
+                    suspension
+                        .ends_at
+                        .map_or("lifted".to_string(), |ends_at| ends_at.date().to_string())
+                );
+                let event = audit_log::Event::new(actions::SUSPENSION_CREATE)
+                    .actor(issuer)
+                    .target(user.id, &user.username)
+                    .details(format!(
+                        "{} until {}: {}",
+                        suspension.kind,
+                        suspension
+                            .ends_at
+                            .map_or("lifted".to_string(), |ends_at| ends_at.date().to_string()),
+                        suspension.reason
+                    ));
+                state.audit_log.record(&origin, event).await;
+                (
+                    http::StatusCode::CREATED,
+                    Json(response_bodies::SuspensionResponse {
+                        id: suspension.id,
+                        username: user.username,
+                        kind: suspension.kind,
+                        reason: suspension.reason,
+                        created_on: suspension.created_at.date().to_string(),
+                        ends_on: suspension.ends_at.map(|ends_at| ends_at.date().to_string()),
+                    }),
+                )
+                    .into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_lift_suspension(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Json(body): Json<request_bodies::LiftSuspensionBody>,
+    ) -> impl IntoResponse {
+        let Some(moderator) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        match auth_session.backend.lift_suspension(body.id).await {
+            Ok(Some((suspension, user))) => {
+                event!(
+                    Level::INFO,
+                    "{} lifted the {} of {}",
+                    moderator.username,
+                    suspension.kind,
+                    user.username
+                );
+                let event = audit_log::Event::new(actions::SUSPENSION_LIFT)
+                    .actor(moderator)
+                    .target(user.id, &user.username)
+                    .details(format!("{}: {}", suspension.kind, suspension.reason));
+                state.audit_log.record(&origin, event).await;
+                (http::StatusCode::OK, "OK").into_response()
+            }
+            Ok(None) => (http::StatusCode::NOT_FOUND, "Suspension not found").into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    /// How many users a page of the admin user list shows unless asked otherwise
+    const DEFAULT_USERS_PER_PAGE: u64 = 25;
+
+    /// The most users a page of the admin user list can show
+    const MAX_USERS_PER_PAGE: u64 = 100;
+
+    pub async fn get_users(
+        auth_session: AuthSession<auth::Backend>,
+        Query(query): Query<request_bodies::ListUsersQuery>,
+    ) -> impl IntoResponse {
+        // Check the paging and sorting
+        let page = query.page.unwrap_or(1);
+        let per_page = query.per_page.unwrap_or(DEFAULT_USERS_PER_PAGE);
+        if page == 0 || !(1..=MAX_USERS_PER_PAGE).contains(&per_page) {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                format!("Pages start at 1 and hold between 1 and {} users", MAX_USERS_PER_PAGE),
+            )
+                .into_response();
+        }
+        let sort = match query.sort.as_deref().unwrap_or("id") {
+            "id" => auth::UserSort::Id,
+            "username" => auth::UserSort::Username,
+            "email" => auth::UserSort::Email,
+            "role" => auth::UserSort::Role,
+            _ => return (http::StatusCode::BAD_REQUEST, "Unknown sort").into_response(),
+        };
+        let descending = match query.order.as_deref().unwrap_or("asc") {
+            "asc" => false,
+            "desc" => true,
+            _ => return (http::StatusCode::BAD_REQUEST, "Unknown order").into_response(),
+        };
+
+        let listing = auth::UserListing {
+            search: query.search,
+            role: query.role,
+            active: query.active,
+            sort,
+            descending,
+            page: page - 1,
+            per_page,
+        };
+        let (users, total) = match auth_session.backend.list_users(&listing).await {
+            Ok(users) => users,
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        let user_ids = users.iter().map(|(user, _)| user.id).collect::<Vec<_>>();
+        let suspended = match auth_session.backend.suspended_user_ids(&user_ids).await {
+            Ok(suspended) => suspended,
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        (
+            http::StatusCode::OK,
+            Json(response_bodies::UserPageResponse {
+                users: users
+                    .into_iter()
+                    .map(|(user, role)| admin_user_response(user, role, &suspended))
+                    .collect(),
+                page,
+                per_page,
+                total,
+                pages: total.div_ceil(per_page),
+            }),
+        )
+            .into_response()
+    }
+
+    pub async fn get_user(
+        auth_session: AuthSession<auth::Backend>,
+        Path(user_id): Path<i64>,
+    ) -> impl IntoResponse {
+        let (user, role) = match auth_session.backend.user_by_id(user_id).await {
+            Ok(Some(user)) => user,
+            Ok(None) => return (http::StatusCode::NOT_FOUND, "User not found").into_response(),
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        match auth_session.backend.suspended_user_ids(&[user.id]).await {
+            Ok(suspended) => (
+                http::StatusCode::OK,
+                Json(admin_user_response(user, role, &suspended)),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn patch_user(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Path(user_id): Path<i64>,
+        Json(body): Json<request_bodies::UpdateUserBody>,
+    ) -> impl IntoResponse {
+        let Some(admin) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        // Check the changes
+        if body.username.as_deref().is_some_and(|username| username.trim().is_empty()) {
+            return (http::StatusCode::BAD_REQUEST, "Username must not be empty").into_response();
+        }
+        if body
+            .username
+            .as_deref()
+            .is_some_and(|username| username.trim().chars().count() > auth::USERNAME_MAX_LENGTH)
+        {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                format!("Username is longer than {} characters", auth::USERNAME_MAX_LENGTH),
+            )
+                .into_response();
+        }
+        if body
+            .email
+            .as_deref()
+            .map(str::trim)
+            .is_some_and(|email| !email.is_empty() && email.parse::<lettre::Address>().is_err())
+        {
+            return (http::StatusCode::BAD_REQUEST, "Invalid email address").into_response();
+        }
+        if body.student_id.as_deref().is_some_and(|student_id| {
+            student_id.trim().chars().count() > auth::STUDENT_ID_MAX_LENGTH
+        }) {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                format!("Student ID is longer than {} characters", auth::STUDENT_ID_MAX_LENGTH),
+            )
+                .into_response();
+        }
+        if user_id == admin.id && (body.role.is_some() || body.active == Some(false)) {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                "You can't change your own role or deactivate yourself",
+            )
+                .into_response();
+        }
+
+        // Only allow changing users, and giving roles, that rank no higher than the admin
+        let (previous_user, previous_role) =
+            match check_outranks(&auth_session, admin, user_id).await {
+                Ok(user) => user,
+                Err(response) => return response,
+            };
+        if let Some(role) = &body.role {
+            match auth_session.backend.can_grant_role(admin, role).await {
+                Ok(true) => {}
+                Ok(false) => return (http::StatusCode::FORBIDDEN, "Forbidden").into_response(),
+                Err(auth::Error::RoleNotFound) => {
+                    return (http::StatusCode::BAD_REQUEST, "Role not found").into_response();
+                }
+                Err(err) => {
+                    return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                        .into_response();
+                }
+            }
+        }
+
+        let changes = auth::UserChanges {
+            username: body.username,
+            email: body
+                .email
+                .map(|email| Some(email).filter(|email| !email.trim().is_empty())),
+            role: body.role,
+            active: body.active,
+            student_id: body.student_id.map(|student_id| {
+                Some(student_id).filter(|student_id| !student_id.trim().is_empty())
+            }),
+        };
+        let (user, role) = match auth_session.backend.update_user(user_id, changes).await {
+            Ok(user) => user,
+            Err(auth::Error::UserNotFound) => {
+                return (http::StatusCode::NOT_FOUND, "User not found").into_response();
+            }
+            Err(auth::Error::UsernameTaken) => {
+                return (http::StatusCode::CONFLICT, "Username is already taken").into_response();
+            }
+            Err(auth::Error::EmailAlreadyExists) => {
+                return (http::StatusCode::CONFLICT, "Email already in use").into_response();
+            }
+            Err(auth::Error::StudentIdTaken) => {
+                return (
+                    http::StatusCode::CONFLICT,
+                    "Student ID already belongs to another user",
+                )
+                    .into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        event!(Level::INFO, "{} updated user {}", admin.username, user.username);
+        if previous_user.active && !user.active {
+            forget_sessions(state.session_store.delete_user_sessions(user.id).await);
+        }
+        let changes = [
+            ("username", Some(previous_user.username), Some(user.username.clone())),
+            ("email", previous_user.email, user.email.clone()),
+            ("role", Some(previous_role.name), Some(role.name.clone())),
+            ("active", Some(previous_user.active.to_string()), Some(user.active.to_string())),
+            ("student ID", previous_user.student_id, user.student_id.clone()),
+        ]
+        .into_iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(field, before, after)| {
+            format!(
+                "{}: {} → {}",
+                field,
+                before.as_deref().unwrap_or("none"),
+                after.as_deref().unwrap_or("none")
+            )
+        })
+        .collect::<Vec<_>>();
+        let event = audit_log::Event::new(actions::USER_UPDATE)
+            .actor(admin)
+            .target(user.id, &user.username)
+            .details(changes.join(", "));
+        state.audit_log.record(&origin, event).await;
+
+        match auth_session.backend.suspended_user_ids(&[user.id]).await {
+            Ok(suspended) => (
+                http::StatusCode::OK,
+                Json(admin_user_response(user, role, &suspended)),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn get_duplicate_users(auth_session: AuthSession<auth::Backend>) -> impl IntoResponse {
+        match auth_session.backend.duplicate_candidates().await {
+            Ok(candidates) => (
+                http::StatusCode::OK,
+                Json(
+                    candidates
+                        .into_iter()
+                        .map(response_bodies::DuplicateCandidateResponse::from)
+                        .collect::<Vec<_>>(),
+                ),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn delete_user(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Path(user_id): Path<i64>,
+    ) -> impl IntoResponse {
+        let Some(admin) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+        if user_id == admin.id {
+            return (http::StatusCode::BAD_REQUEST, "You can't delete yourself").into_response();
+        }
+        let (user, role) = match check_outranks(&auth_session, admin, user_id).await {
+            Ok(user) => user,
+            Err(response) => return response,
+        };
+
+        match auth_session.backend.delete_user(user_id).await {
+            Ok(_) => {
+                forget_sessions(state.session_store.delete_user_sessions(user_id).await);
+                event!(Level::INFO, "{} deleted user {}", admin.username, user_id);
+                // The target's id is left out since the row is gone
+                let event = audit_log::Event::new(actions::USER_DELETE)
+                    .actor(admin)
+                    .details(format!("{} ({}, id {})", user.username, role.name, user.id));
+                state.audit_log.record(&origin, event).await;
+                http::StatusCode::NO_CONTENT.into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_reset_user_password(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Path(user_id): Path<i64>,
+    ) -> impl IntoResponse {
+        let Some(admin) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+        if user_id == admin.id {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                "Change your own password in your settings",
+            )
+                .into_response();
+        }
+        let (user, _) = match check_outranks(&auth_session, admin, user_id).await {
+            Ok(user) => user,
+            Err(response) => return response,
+        };
+
+        match auth_session.backend.set_temporary_password(user_id).await {
+            Ok(password) => {
+                forget_sessions(state.session_store.delete_user_sessions(user_id).await);
+                event!(Level::INFO, "{} reset the password of user {}", admin.username, user_id);
+                let event = audit_log::Event::new(actions::USER_RESET_PASSWORD)
+                    .actor(admin)
+                    .target(user.id, &user.username);
+                state.audit_log.record(&origin, event).await;
+                (
+                    http::StatusCode::OK,
+                    Json(response_bodies::TemporaryPasswordResponse { password }),
+                )
+                    .into_response()
+            }
+            Err(auth::Error::UserNotFound) => {
+                (http::StatusCode::NOT_FOUND, "User not found").into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_impersonate(
+        mut auth_session: AuthSession<auth::Backend>,
+        session: Session,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Path(user_id): Path<i64>,
+    ) -> impl IntoResponse {
+        let Some(admin) = auth_session.user.clone() else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+        if user_id == admin.id {
+            return (http::StatusCode::BAD_REQUEST, "You can't view the site as yourself")
+                .into_response();
+        }
+        if let Err(response) = check_outranks(&auth_session, &admin, user_id).await {
+            return response;
+        }
+
+        // Only users who could log in themselves can be viewed as
+        let user = match auth_session.backend.get_user(&user_id).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                return (
+                    http::StatusCode::CONFLICT,
+                    "Inactive and suspended users can't be viewed as",
+                )
+                    .into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        // Remember the admin in the session before switching it over to the user
+        let impersonation = match auth_session.backend.start_impersonation(&admin, user.id).await {
+            Ok(impersonation) => impersonation,
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        if let Err(err) = session.insert(auth::IMPERSONATION_KEY, impersonation).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        match auth_session.login(&user).await {
+            Ok(_) => {
+                event!(
+                    Level::INFO,
+                    "{} started viewing the site as {}",
+                    admin.username,
+                    user.username
+                );
+                let event = audit_log::Event::new(actions::IMPERSONATION_START)
+                    .actor(&admin)
+                    .target(user.id, &user.username);
+                state.audit_log.record(&origin, event).await;
+                (
+                    http::StatusCode::OK,
+                    Json(response_bodies::UserResponse {
+                        id: user.id,
+                        username: user.username,
+                        role: user.role,
+                    }),
+                )
+                    .into_response()
+            }
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn post_stop_impersonation(
+        mut auth_session: AuthSession<auth::Backend>,
+        session: Session,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+    ) -> impl IntoResponse {
+        let impersonation = match session
+            .remove::<auth::Impersonation>(auth::IMPERSONATION_KEY)
+            .await
+        {
+            Ok(Some(impersonation)) => impersonation,
+            Ok(None) => {
+                return (
+                    http::StatusCode::BAD_REQUEST,
+                    "You aren't viewing the site as another user",
+                )
+                    .into_response();
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        if let Err(err) = auth_session.backend.end_impersonation(impersonation.id).await {
+            return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response();
+        }
+        event!(
+            Level::INFO,
+            "{} stopped viewing the site as {}",
+            impersonation.admin_username,
+            auth_session
+                .user
+                .as_ref()
+                .map_or("a signed out user", |user| user.username.as_str())
+        );
+        let mut event = audit_log::Event::new(actions::IMPERSONATION_STOP)
+            .actor_name(&impersonation.admin_username);
+        if let Some(user) = &auth_session.user {
+            event = event.target(user.id, &user.username);
+        }
+        state.audit_log.record(&origin, event).await;
+
+        // Switch back to the admin, unless they lost their account in the meantime
+        let admin = match auth_session.backend.get_user(&impersonation.admin_id).await {
+            Ok(Some(admin)) => admin,
+            Ok(None) => {
+                return match auth_session.logout().await {
+                    Ok(_) => (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response(),
+                    Err(err) => {
+                        (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                            .into_response()
+                    }
+                };
+            }
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        match auth_session.login(&admin).await {
+            Ok(_) => (
+                http::StatusCode::OK,
+                Json(response_bodies::LoginResponse {
+                    username: admin.username,
+                    two_factor_required: false,
+                }),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    /// How many impersonations the audit log shows
+    const IMPERSONATION_LOG_LENGTH: u64 = 50;
+
+    pub async fn get_impersonations(auth_session: AuthSession<auth::Backend>) -> impl IntoResponse {
+        match auth_session
+            .backend
+            .impersonations(IMPERSONATION_LOG_LENGTH)
+            .await
+        {
+            Ok(impersonations) => (
+                http::StatusCode::OK,
+                Json(
+                    impersonations
+                        .into_iter()
+                        .map(response_bodies::ImpersonationResponse::from)
+                        .collect::<Vec<_>>(),
+                ),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn get_pending_terms(State(state): State<BackendState>) -> impl IntoResponse {
+        match state.terms.pending().await {
+            Ok(pending) => (
+                http::StatusCode::OK,
+                Json(
+                    pending
+                        .into_iter()
+                        .map(response_bodies::PendingTermsResponse::from)
+                        .collect::<Vec<_>>(),
+                ),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    /// How many audit events a page shows unless asked otherwise
+    const DEFAULT_AUDIT_EVENTS_PER_PAGE: u64 = 50;
+
+    /// The most audit events a page can show
+    const MAX_AUDIT_EVENTS_PER_PAGE: u64 = 200;
+
+    pub async fn get_audit_events(
+        State(state): State<BackendState>,
+        Query(query): Query<request_bodies::AuditEventsQuery>,
+    ) -> impl IntoResponse {
+        let page = query.page.unwrap_or(1);
+        let per_page = query.per_page.unwrap_or(DEFAULT_AUDIT_EVENTS_PER_PAGE);
+        if page == 0 || !(1..=MAX_AUDIT_EVENTS_PER_PAGE).contains(&per_page) {
+            return (
+                http::StatusCode::BAD_REQUEST,
+                format!(
+                    "Pages start at 1 and hold between 1 and {} events",
+                    MAX_AUDIT_EVENTS_PER_PAGE
+                ),
+            )
+                .into_response();
+        }
+        let filter = match audit_filter(query) {
+            Ok(filter) => filter,
+            Err(message) => return (http::StatusCode::BAD_REQUEST, message).into_response(),
+        };
+
+        match state.audit_log.search(&filter, page - 1, per_page).await {
+            Ok((events, total)) => (
+                http::StatusCode::OK,
+                Json(response_bodies::AuditPageResponse {
+                    events: events
+                        .into_iter()
+                        .map(response_bodies::AuditEventResponse::from)
+                        .collect(),
+                    actions: actions::ALL.iter().map(|action| action.to_string()).collect(),
+                    page,
+                    per_page,
+                    total,
+                    pages: total.div_ceil(per_page),
+                }),
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    pub async fn get_audit_events_export(
+        State(state): State<BackendState>,
+        Query(query): Query<request_bodies::AuditEventsQuery>,
+    ) -> impl IntoResponse {
+        let filter = match audit_filter(query) {
+            Ok(filter) => filter,
+            Err(message) => return (http::StatusCode::BAD_REQUEST, message).into_response(),
+        };
+        let events = match state.audit_log.export(&filter).await {
+            Ok(events) => events,
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+
+        match audit_log::write_csv(&events) {
+            Ok(csv) => (
+                http::StatusCode::OK,
+                [
+                    (http::header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                    (
+                        http::header::CONTENT_DISPOSITION,
+                        "attachment; filename=\"audit-events.csv\"",
+                    ),
+                ],
+                csv,
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    /// Work out which audit events a query asks for, leaving out blank fields
+    fn audit_filter(
+        query: request_bodies::AuditEventsQuery,
+    ) -> Result<audit_log::Filter, &'static str> {
+        let non_blank = |value: Option<String>| {
+            value
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let action = non_blank(query.action);
+        if action
+            .as_deref()
+            .is_some_and(|action| !actions::ALL.contains(&action))
+        {
+            return Err("Unknown action");
+        }
+        let date = |value: Option<String>| match non_blank(value) {
+            Some(value) => audit_log::parse_date(&value)
+                .map(Some)
+                .ok_or("Dates must look like YYYY-MM-DD"),
+            None => Ok(None),
+        };
+        Ok(audit_log::Filter {
+            action,
+            actor: non_blank(query.actor),
+            target: non_blank(query.target),
+            from: date(query.from)?,
+            to: date(query.to)?,
+        })
+    }
+
+    pub async fn post_import_roster(
+        auth_session: AuthSession<auth::Backend>,
+        State(state): State<BackendState>,
+        origin: RequestOrigin,
+        Query(query): Query<request_bodies::ImportRosterQuery>,
+        body: String,
+    ) -> impl IntoResponse {
+        let Some(admin) = &auth_session.user else {
+            return (http::StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        };
+
+        // Read and check every row
+        let rows = match roster::parse(body.as_bytes()) {
+            Ok(rows) => rows,
+            Err(err) => return (http::StatusCode::BAD_REQUEST, format!("{}", err)).into_response(),
+        };
+        let planned = match auth_session.backend.plan_roster(Some(admin), rows).await {
+            Ok(planned) => planned,
+            Err(err) => {
+                return (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))
+                    .into_response();
+            }
+        };
+        let valid = !planned
+            .iter()
+            .any(|planned_row| matches!(planned_row.action, roster::Action::Invalid(_)));
+
+        // Only show what would happen unless asked to apply a valid roster
+        let outcomes = planned
+            .iter()
+            .cloned()
+            .map(|planned| roster::Outcome {
+                planned,
+                temporary_password: None,
+                invite_token: None,
+            })
+            .collect();
+        if !query.apply {
+            return (
+                http::StatusCode::OK,
+                Json(roster_report(outcomes, false, &state.public_url)),
+            )
+                .into_response();
+        }
+        if !valid {
+            return (
+                http::StatusCode::UNPROCESSABLE_ENTITY,
+                Json(roster_report(outcomes, false, &state.public_url)),
+            )
+                .into_response();
+        }
+
+        match auth_session.backend.apply_roster(planned).await {
+            Ok(outcomes) => {
+                event!(
+                    Level::INFO,
+                    "{} imported a roster of {} rows",
+                    admin.username,
+                    outcomes.len()
+                );
+                let report = roster_report(outcomes, true, &state.public_url);
+                let event = audit_log::Event::new(actions::USER_IMPORT)
+                    .actor(admin)
+                    .details(format!(
+                        "{} created, {} updated, {} unchanged",
+                        report.creates, report.updates, report.skips
+                    ));
+                state.audit_log.record(&origin, event).await;
+                (http::StatusCode::OK, Json(report)).into_response()
+            }
+            Err(auth::Error::UsernameTaken) => (
+                http::StatusCode::CONFLICT,
+                "A username was taken while importing, nothing was changed",
+            )
+                .into_response(),
+            Err(auth::Error::EmailAlreadyExists) => (
+                http::StatusCode::CONFLICT,
+                "An email was taken while importing, nothing was changed",
+            )
+                .into_response(),
+            Err(auth::Error::StudentIdTaken) => (
+                http::StatusCode::CONFLICT,
+                "A student ID was taken while importing, nothing was changed",
+            )
+                .into_response(),
+            Err(err) => {
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
+            }
+        }
+    }
+
+    /// Describe what importing a roster does or did
+    fn roster_report(
+        outcomes: Vec<roster::Outcome>,
+        applied: bool,
+        public_url: &str,
+    ) -> response_bodies::RosterReportResponse {
+        let count = |action: &str| {
+            outcomes
+                .iter()
+                .filter(|outcome| outcome.planned.action.name() == action)
+                .count()
+        };
+        response_bodies::RosterReportResponse {
+            applied,
+            creates: count("create"),
+            updates: count("update"),
+            skips: count("skip"),
+            invalid: count("invalid"),
+            rows: outcomes
+                .into_iter()
+                .map(|outcome| {
+                    let action = outcome.planned.action;
+                    response_bodies::RosterRowResponse {
+                        line: outcome.planned.row.line,
+                        username: outcome.planned.row.username,
+                        action: action.name().to_string(),
+                        changes: match &action {
+                            roster::Action::Update { changes, .. } => changes.clone(),
+                            _ => Vec::new(),
+                        },
+                        error: match action {
+                            roster::Action::Invalid(error) => Some(error),
+                            _ => None,
+                        },
+                        temporary_password: outcome.temporary_password,
+                        invite_link: outcome
+                            .invite_token
+                            .map(|token| format!("{}/reset-password?token={}", public_url, token)),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Make sure a user exists and holds no permission the admin changing them lacks
+    async fn check_outranks(
+        auth_session: &AuthSession<auth::Backend>,
+        admin: &auth::User,
+        user_id: i64,
+    ) -> Result<(crate::db::users::Model, crate::db::roles::Model), Response> {
+        let (user, role) = match auth_session.backend.user_by_id(user_id).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                return Err((http::StatusCode::NOT_FOUND, "User not found").into_response());
+            }
+            Err(err) => {
+                return Err(
+                    (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response(),
+                );
+            }
+        };
+        match auth_session.backend.can_grant_role(admin, &role.name).await {
+            Ok(true) => Ok((user, role)),
+            Ok(false) => Err((http::StatusCode::FORBIDDEN, "Forbidden").into_response()),
+            Err(err) => Err(
+                (http::StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response(),
+            ),
+        }
+    }
+
+    /// Describe a user to admins
+    fn admin_user_response(
+        user: crate::db::users::Model,
+        role: crate::db::roles::Model,
+        suspended: &std::collections::HashSet<i64>,
+    ) -> response_bodies::AdminUserResponse {
+        response_bodies::AdminUserResponse {
+            suspended: suspended.contains(&user.id),
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            role: role.name,
+            active: user.active,
+            student_id: user.student_id,
+        }
+    }
+
+    pub async fn get_404() -> impl IntoResponse {
+        (http::StatusCode::NOT_FOUND, "Not Found").into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::Arc,
+    };
+
+    use argon2::{Algorithm, Params, Version};
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        extract::ConnectInfo,
+    };
+    use axum_login::AuthManagerLayerBuilder;
+    use sea_orm::{ActiveValue::Set, ColumnTrait as _, EntityTrait as _, QueryFilter as _};
+    use tower::ServiceExt as _;
+    use tower_sessions::SessionManagerLayer;
+
+    use super::*;
+    use crate::{
+        db, login_throttle::LoginThrottle, session_store::DatabaseStore, terms::Terms, tokens,
+    };
+
+    const PUBLIC_URL: &str = "http://localhost:8080";
+
+    /// Build the backend routes, sending emails to files in a directory, along with the auth
+    /// backend and database behind them
+    async fn app(
+        mail_dir: &std::path::Path,
+    ) -> (Router, auth::Backend, sea_orm::DatabaseConnection) {
+        let db = db::test_db().await;
+        let (router, auth_backend) = app_with_terms(mail_dir, db.clone(), "Be nice");
+        (router, auth_backend, db)
+    }
+
+    /// Build the backend routes over an existing database, serving some terms of use
+    fn app_with_terms(
+        mail_dir: &std::path::Path,
+        db: sea_orm::DatabaseConnection,
+        terms: &str,
+    ) -> (Router, auth::Backend) {
+        let auth_backend = auth::Backend::new(
+            db.clone(),
+            auth::PasswordSettings {
+                algorithm: Algorithm::Argon2id,
+                version: Version::V0x13,
+                params: Params::new(Params::MIN_M_COST, 1, 1, None).unwrap(),
+            },
+        );
+        let session_store = DatabaseStore::new(db.clone());
+        let auth_layer = AuthManagerLayerBuilder::new(
+            auth_backend.clone(),
+            SessionManagerLayer::new(session_store.clone()),
+        )
+        .build();
+        let state = BackendState {
+            db_connection: db.clone(),
+            mailer: Arc::new(
+                mailer::FileMailer::new(mail_dir.to_path_buf(), "noreply@connectia.example")
+                    .unwrap(),
+            ),
+            allowed_email_domains: vec!["school.example".to_string()].into(),
+            public_url: PUBLIC_URL.to_string(),
+            oidc: None,
+            relying_party: Arc::new(
+                webauthn::RelyingParty::from_public_url(PUBLIC_URL, "ConnectIA").unwrap(),
+            ),
+            login_throttle: LoginThrottle::new(db.clone(), 5, 50, time::Duration::minutes(15)),
+            session_store,
+            remember_me_lifetime: time::Duration::days(30),
+            audit_log: audit_log::AuditLog::new(db.clone()),
+            terms: Terms::new(db, terms),
+            trust_proxy_headers: false,
+        };
+        let router = crate::backend_router(&state, auth_layer).with_state(state);
+        (router, auth_backend)
+    }
+
+    /// Send a request from a local client, returning the status, the session cookie if one was
+    /// set and the body
+    async fn send(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        cookie: Option<&str>,
+        body: serde_json::Value,
+    ) -> (http::StatusCode, Option<String>, String) {
+        let mut request = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(http::header::CONTENT_TYPE, "application/json");
+        if let Some(cookie) = cookie {
+            request = request.header(http::header::COOKIE, cookie);
+        }
+        let mut request = request.body(Body::from(body.to_string())).unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((Ipv4Addr::LOCALHOST, 4000))));
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let cookie = response
+            .headers()
+            .get(http::header::SET_COOKIE)
+            .and_then(|cookie| cookie.to_str().ok())
+            .and_then(|cookie| cookie.split(';').next())
+            .map(str::to_string);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, cookie, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    /// Send a request carrying a personal API token, returning the status
+    async fn send_with_token(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        token: &str,
+        body: serde_json::Value,
+    ) -> http::StatusCode {
+        let mut request = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((Ipv4Addr::LOCALHOST, 4000))));
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    /// Turn on an authenticator app for a user, returning their recovery codes
+    async fn enable_two_factor(auth_backend: &auth::Backend, user: &auth::User) -> Vec<String> {
+        let (secret, _) = auth_backend.begin_two_factor_setup(user).await.unwrap();
+        let code = totp_rs::Builder::new()
+            .with_secret(totp_rs::Secret::try_from_base32(&secret).unwrap())
+            .build()
+            .unwrap()
+            .generate_current()
+            .to_string();
+        auth_backend.enable_two_factor(user.id, code).await.unwrap()
+    }
+
+    /// Log in a user with an authenticator app, returning the session cookie
+    async fn log_in_with_second_factor(app: &Router, username: &str, code: &str) -> String {
+        let credentials = serde_json::json!({ "username": username, "password": "correct horse" });
+        let (status, cookie, _) = send(app, "POST", "/login", None, credentials).await;
+        assert_eq!(status, http::StatusCode::ACCEPTED);
+        let (status, new_cookie, _) = send(
+            app,
+            "POST",
+            "/login/two-factor",
+            cookie.as_deref(),
+            serde_json::json!({ "code": code }),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::OK);
+        new_cookie.or(cookie).unwrap()
+    }
+
+    /// Give one of the default roles another permission
+    async fn grant(db: &sea_orm::DatabaseConnection, role: &str, permission: &str) {
+        let role_entity = db::roles::Entity::find()
+            .filter(db::roles::Column::Name.eq(role))
+            .one(db)
+            .await
+            .unwrap()
+            .unwrap();
+        let permission_entity = db::permissions::Entity::find()
+            .filter(db::permissions::Column::Name.eq(permission))
+            .one(db)
+            .await
+            .unwrap()
+            .unwrap();
+        db::role_permissions::Entity::insert(db::role_permissions::ActiveModel {
+            role_id: Set(role_entity.id),
+            permission_id: Set(permission_entity.id),
+        })
+        .exec(db)
+        .await
+        .unwrap();
+    }
+
+    /// Read the only email written to a directory, undoing the quoted-printable encoding of
+    /// long lines
+    fn read_email(dir: &std::path::Path) -> String {
+        let mut files = std::fs::read_dir(dir).unwrap();
+        let path = files.next().unwrap().unwrap().path();
+        assert!(files.next().is_none());
+        let raw = std::fs::read_to_string(path).unwrap().replace("=\r\n", "");
+        let mut email = String::new();
+        let mut rest = raw.as_str();
+        while let Some(position) = rest.find('=') {
+            email.push_str(&rest[..position]);
+            match u8::from_str_radix(rest.get(position + 1..position + 3).unwrap_or(""), 16) {
+                Ok(byte) => {
+                    email.push(char::from(byte));
+                    rest = &rest[position + 3..];
+                }
+                Err(_) => {
+                    email.push('=');
+                    rest = &rest[position + 1..];
+                }
+            }
+        }
+        email.push_str(rest);
+        email
+    }
+
+    #[tokio::test]
+    async fn registers_verifies_and_logs_in() {
+        let mail_dir =
+            std::env::temp_dir().join(format!("connectia-mail-{}", tokens::generate().0));
+        std::fs::create_dir_all(&mail_dir).unwrap();
+        let (app, _, _) = app(&mail_dir).await;
+        let credentials = serde_json::json!({ "username": "alice", "password": "correct horse" });
+
+        // Usernames a roster couldn't hold are refused
+        let (status, _, _) = send(
+            &app,
+            "POST",
+            "/register",
+            None,
+            serde_json::json!({
+                "username": "a".repeat(auth::USERNAME_MAX_LENGTH + 1),
+                "email": "alice@school.example",
+                "password": "correct horse",
+            }),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::BAD_REQUEST);
+
+        let (status, _, _) = send(
+            &app,
+            "POST",
+            "/register",
+            None,
+            serde_json::json!({
+                "username": "alice",
+                "email": "Alice@School.Example",
+                "password": "correct horse",
+            }),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::CREATED);
+
+        // The account can't be used until the email is verified
+        let (status, _, _) = send(&app, "POST", "/login", None, credentials.clone()).await;
+        assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+
+        // Follow the link in the email
+        let email = read_email(&mail_dir);
+        std::fs::remove_dir_all(&mail_dir).unwrap();
+        assert!(email.contains("To: alice@school.example"));
+        let link_start = format!("{}/verify-email?token=", PUBLIC_URL);
+        let token: String = email
+            .split(&link_start)
+            .nth(1)
+            .unwrap()
+            .chars()
+            .take_while(char::is_ascii_alphanumeric)
+            .collect();
+        let (status, _, _) =
+            send(&app, "POST", "/verify-email", None, serde_json::json!({ "token": token })).await;
+        assert_eq!(status, http::StatusCode::OK);
+
+        // Now the password works
+        let (status, cookie, _) = send(&app, "POST", "/login", None, credentials).await;
+        assert_eq!(status, http::StatusCode::OK);
+        let (status, _, body) =
+            send(&app, "GET", "/current-user", cookie.as_deref(), serde_json::Value::Null).await;
+        assert_eq!(status, http::StatusCode::OK);
+        assert!(body.contains("\"username\":\"alice\""));
+    }
+
+    #[tokio::test]
+    async fn wrong_second_factor_codes_count_as_failed_logins() {
+        let mail_dir =
+            std::env::temp_dir().join(format!("connectia-mail-{}", tokens::generate().0));
+        let (app, auth_backend, _) = app(&mail_dir).await;
+        let user = auth_backend
+            .create_user("alice", "correct horse", auth::roles::STUDENT, None)
+            .await
+            .unwrap();
+        enable_two_factor(&auth_backend, &user).await;
+        let credentials = serde_json::json!({ "username": "alice", "password": "correct horse" });
+
+        // The right password alone doesn't forget earlier failures
+        let (status, cookie, _) = send(&app, "POST", "/login", None, credentials.clone()).await;
+        assert_eq!(status, http::StatusCode::ACCEPTED);
+        for _ in 0..2 {
+            let (status, _, _) = send(
+                &app,
+                "POST",
+                "/login/two-factor",
+                cookie.as_deref(),
+                serde_json::json!({ "code": "wrong" }),
+            )
+            .await;
+            assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+        }
+
+        // So after a few wrong codes both steps have to wait
+        let (status, _, _) = send(
+            &app,
+            "POST",
+            "/login/two-factor",
+            cookie.as_deref(),
+            serde_json::json!({ "code": "wrong" }),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::TOO_MANY_REQUESTS);
+        let (status, _, _) = send(&app, "POST", "/login", None, credentials).await;
+        assert_eq!(status, http::StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn lists_logins_right_away_and_drops_them_on_password_change() {
+        let mail_dir =
+            std::env::temp_dir().join(format!("connectia-mail-{}", tokens::generate().0));
+        let (app, auth_backend, _) = app(&mail_dir).await;
+        auth_backend
+            .create_user("alice", "correct horse", auth::roles::STUDENT, None)
+            .await
+            .unwrap();
+        let credentials = serde_json::json!({ "username": "alice", "password": "correct horse" });
+        let sessions = |cookie: Option<String>| {
+            let app = app.clone();
+            async move {
+                let (status, _, body) = send(
+                    &app,
+                    "GET",
+                    "/sessions",
+                    cookie.as_deref(),
+                    serde_json::Value::Null,
+                )
+                .await;
+                assert_eq!(status, http::StatusCode::OK);
+                serde_json::from_str::<Vec<serde_json::Value>>(&body).unwrap()
+            }
+        };
+
+        // Both logins are listed before either is used again
+        let (_, laptop, _) = send(&app, "POST", "/login", None, credentials.clone()).await;
+        let (_, phone, _) = send(&app, "POST", "/login", None, credentials).await;
+        let listed = sessions(laptop.clone()).await;
+        assert_eq!(listed.len(), 2);
+        assert!(listed.iter().all(|session| session["ip"] == "127.0.0.1"));
+
+        // Changing the password on one ends the other
+        let (status, _, _) = send(
+            &app,
+            "POST",
+            "/change-password",
+            laptop.as_deref(),
+            serde_json::json!({ "current_password": "correct horse", "new_password": "battery" }),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::OK);
+        let listed = sessions(laptop).await;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0]["current"], true);
+        let (status, _, _) = send(
+            &app,
+            "GET",
+            "/sessions",
+            phone.as_deref(),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn drops_every_login_on_password_reset() {
+        let mail_dir =
+            std::env::temp_dir().join(format!("connectia-mail-{}", tokens::generate().0));
+        let (app, auth_backend, _) = app(&mail_dir).await;
+        let (_, token) = auth_backend
+            .register_user("alice", "alice@school.example", "correct horse")
+            .await
+            .unwrap();
+        auth_backend.verify_email(token).await.unwrap();
+        let credentials = serde_json::json!({ "username": "alice", "password": "correct horse" });
+        send(&app, "POST", "/login", None, credentials).await;
+
+        // Resetting the forgotten password ends the login, so only the next one is listed
+        let (_, token) = auth_backend
+            .request_password_reset("alice@school.example")
+            .await
+            .unwrap()
+            .unwrap();
+        let (status, _, _) = send(
+            &app,
+            "POST",
+            "/password-reset",
+            None,
+            serde_json::json!({ "token": token, "new_password": "battery" }),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::OK);
+        let credentials = serde_json::json!({ "username": "alice", "password": "battery" });
+        let (_, cookie, _) = send(&app, "POST", "/login", None, credentials).await;
+        let (_, _, body) = send(
+            &app,
+            "GET",
+            "/sessions",
+            cookie.as_deref(),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(serde_json::from_str::<Vec<serde_json::Value>>(&body).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn read_only_tokens_cant_change_anything_and_dead_tokens_are_refused() {
+        let mail_dir =
+            std::env::temp_dir().join(format!("connectia-mail-{}", tokens::generate().0));
+        let (app, auth_backend, _) = app(&mail_dir).await;
+        let admin = auth_backend
+            .create_user("root", "correct horse", auth::roles::ADMIN, None)
+            .await
+            .unwrap();
+        enable_two_factor(&auth_backend, &admin).await;
+        let student = auth_backend
+            .create_user("alice", "correct horse", auth::roles::STUDENT, None)
+            .await
+            .unwrap();
+        let (_, read_token) = auth_backend
+            .create_api_token(
+                &admin,
+                "read",
+                &[auth::scopes::READ_PROFILE.to_string()],
+                time::Duration::days(1),
+            )
+            .await
+            .unwrap();
+        let (admin_token_entity, admin_token) = auth_backend
+            .create_api_token(
+                &admin,
+                "admin",
+                &[auth::scopes::ADMIN.to_string()],
+                time::Duration::days(1),
+            )
+            .await
+            .unwrap();
+        let student_uri = format!("/admin/users/{}", student.id);
+        let deactivate = serde_json::json!({ "active": false });
+
+        // A read-only token can read the account, but neither change anything nor use the
+        // admin's permissions
+        let status = send_with_token(
+            &app,
+            "GET",
+            "/current-user",
+            &read_token,
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::OK);
+        let status = send_with_token(
+            &app,
+            "GET",
+            "/admin/users",
+            &read_token,
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+        let status =
+            send_with_token(&app, "PATCH", &student_uri, &read_token, deactivate.clone()).await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+        let status = send_with_token(
+            &app,
+            "POST",
+            "/create_user",
+            &read_token,
+            serde_json::json!({ "username": "bob", "password": "correct horse" }),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+
+        // A token with the admin scope can
+        let status = send_with_token(
+            &app,
+            "PATCH",
+            &student_uri,
+            &admin_token,
+            deactivate.clone(),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::OK);
+
+        // Revoked and expired tokens don't log anyone in
+        assert!(
+            auth_backend
+                .revoke_api_token(admin.id, admin_token_entity.id)
+                .await
+                .unwrap()
+        );
+        let status = send_with_token(
+            &app,
+            "GET",
+            "/current-user",
+            &admin_token,
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+        let (_, expired_token) = auth_backend
+            .create_api_token(
+                &admin,
+                "expired",
+                &[auth::scopes::ADMIN.to_string()],
+                time::Duration::minutes(-1),
+            )
+            .await
+            .unwrap();
+        let status = send_with_token(
+            &app,
+            "GET",
+            "/current-user",
+            &expired_token,
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn blocks_changes_while_impersonating_until_the_admin_stops() {
+        let mail_dir =
+            std::env::temp_dir().join(format!("connectia-mail-{}", tokens::generate().0));
+        let (app, auth_backend, _) = app(&mail_dir).await;
+        let admin = auth_backend
+            .create_user("root", "correct horse", auth::roles::ADMIN, None)
+            .await
+            .unwrap();
+        let codes = enable_two_factor(&auth_backend, &admin).await;
+        let student = auth_backend
+            .create_user("alice", "correct horse", auth::roles::STUDENT, None)
+            .await
+            .unwrap();
+        let other_student = auth_backend
+            .create_user("bob", "correct horse", auth::roles::STUDENT, None)
+            .await
+            .unwrap();
+        let cookie = log_in_with_second_factor(&app, "root", &codes[0]).await;
+
+        // Viewing the site as the student shows their account
+        let (status, new_cookie, _) = send(
+            &app,
+            "POST",
+            &format!("/admin/users/{}/impersonate", student.id),
+            Some(&cookie),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::OK);
+        let cookie = new_cookie.unwrap_or(cookie);
+        let (_, _, body) = send(
+            &app,
+            "GET",
+            "/current-user",
+            Some(&cookie),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert!(body.contains("\"username\":\"alice\""));
+
+        // But nothing can be changed or deleted in the meantime
+        let (status, _, body) = send(
+            &app,
+            "POST",
+            "/change-password",
+            Some(&cookie),
+            serde_json::json!({ "current_password": "correct horse", "new_password": "battery" }),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+        assert_eq!(
+            body,
+            "Changes are blocked while viewing the site as another user"
+        );
+        let (status, _, body) = send(
+            &app,
+            "DELETE",
+            &format!("/admin/users/{}", other_student.id),
+            Some(&cookie),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+        assert_eq!(
+            body,
+            "Changes are blocked while viewing the site as another user"
+        );
+        assert!(
+            auth_backend
+                .user_by_id(other_student.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        // Stopping switches back to the admin, who can make changes again
+        let (status, new_cookie, _) = send(
+            &app,
+            "POST",
+            "/impersonation/stop",
+            Some(&cookie),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::OK);
+        let cookie = new_cookie.unwrap_or(cookie);
+        let (_, _, body) = send(
+            &app,
+            "GET",
+            "/current-user",
+            Some(&cookie),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert!(body.contains("\"username\":\"root\""));
+        let (status, _, _) = send(
+            &app,
+            "DELETE",
+            &format!("/admin/users/{}", other_student.id),
+            Some(&cookie),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::NO_CONTENT);
+        assert!(
+            auth_backend
+                .user_by_id(other_student.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn only_impersonates_users_ranking_no_higher() {
+        let mail_dir =
+            std::env::temp_dir().join(format!("connectia-mail-{}", tokens::generate().0));
+        let (app, auth_backend, db) = app(&mail_dir).await;
+        grant(&db, "staff", auth::permissions::USERS_IMPERSONATE).await;
+        let staff = auth_backend
+            .create_user("teacher", "correct horse", "staff", None)
+            .await
+            .unwrap();
+        let codes = enable_two_factor(&auth_backend, &staff).await;
+        let admin = auth_backend
+            .create_user("root", "correct horse", auth::roles::ADMIN, None)
+            .await
+            .unwrap();
+        let student = auth_backend
+            .create_user("alice", "correct horse", auth::roles::STUDENT, None)
+            .await
+            .unwrap();
+        let cookie = log_in_with_second_factor(&app, "teacher", &codes[0]).await;
+
+        // Staff can't view the site as an admin, whose role has permissions they lack
+        let (status, _, _) = send(
+            &app,
+            "POST",
+            &format!("/admin/users/{}/impersonate", admin.id),
+            Some(&cookie),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+        let (_, _, body) = send(
+            &app,
+            "GET",
+            "/current-user",
+            Some(&cookie),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert!(body.contains("\"username\":\"teacher\""));
+
+        // But they can view it as a student
+        let (status, _, _) = send(
+            &app,
+            "POST",
+            &format!("/admin/users/{}/impersonate", student.id),
+            Some(&cookie),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn keeps_suspended_users_out_until_the_suspension_ends() {
+        let mail_dir =
+            std::env::temp_dir().join(format!("connectia-mail-{}", tokens::generate().0));
+        let (app, auth_backend, db) = app(&mail_dir).await;
+        let moderator = auth_backend
+            .create_user("root", "correct horse", auth::roles::ADMIN, None)
+            .await
+            .unwrap();
+        let student = auth_backend
+            .create_user("alice", "correct horse", auth::roles::STUDENT, None)
+            .await
+            .unwrap();
+        let credentials = serde_json::json!({ "username": "alice", "password": "correct horse" });
+        let (_, cookie, _) = send(&app, "POST", "/login", None, credentials.clone()).await;
+        let suspension = auth_backend
+            .suspend_user(
+                &moderator,
+                student.id,
+                auth::suspension_kinds::SUSPENDED,
+                "Spamming",
+                Some(time::OffsetDateTime::now_utc() + time::Duration::hours(1)),
+            )
+            .await
+            .unwrap();
+
+        // The login they already had stops working, and they can't log in again
+        let (status, _, _) = send(
+            &app,
+            "GET",
+            "/current-user",
+            cookie.as_deref(),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+        let (status, _, body) = send(&app, "POST", "/login", None, credentials.clone()).await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+        assert!(body.contains("Spamming"));
+
+        // Once the suspension's time is up they can
+        db::suspensions::Entity::update_many()
+            .col_expr(
+                db::suspensions::Column::EndsAt,
+                sea_orm::sea_query::Expr::value(
+                    time::OffsetDateTime::now_utc() - time::Duration::minutes(1),
+                ),
+            )
+            .filter(db::suspensions::Column::Id.eq(suspension.id))
+            .exec(&db)
+            .await
+            .unwrap();
+        let (status, _, _) = send(&app, "POST", "/login", None, credentials).await;
+        assert_eq!(status, http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn only_lets_users_with_the_permission_into_admin_routes() {
+        let mail_dir =
+            std::env::temp_dir().join(format!("connectia-mail-{}", tokens::generate().0));
+        let (app, auth_backend, _) = app(&mail_dir).await;
+        let admin = auth_backend
+            .create_user("root", "correct horse", auth::roles::ADMIN, None)
+            .await
+            .unwrap();
+        let codes = enable_two_factor(&auth_backend, &admin).await;
+        auth_backend
+            .create_user("alice", "correct horse", auth::roles::STUDENT, None)
+            .await
+            .unwrap();
+
+        // Nobody logged in gets told to, and a student is turned away
+        let (status, _, _) = send(&app, "GET", "/admin/users", None, serde_json::Value::Null).await;
+        assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+        let credentials = serde_json::json!({ "username": "alice", "password": "correct horse" });
+        let (_, cookie, _) = send(&app, "POST", "/login", None, credentials).await;
+        let (status, _, _) = send(
+            &app,
+            "GET",
+            "/admin/users",
+            cookie.as_deref(),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::FORBIDDEN);
+
+        // An admin gets through
+        let cookie = log_in_with_second_factor(&app, "root", &codes[0]).await;
+        let (status, _, _) = send(
+            &app,
+            "GET",
+            "/admin/users",
+            Some(&cookie),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn limits_password_reset_requests_whether_or_not_the_account_exists() {
+        let mail_dir =
+            std::env::temp_dir().join(format!("connectia-mail-{}", tokens::generate().0));
+        let (app, _, _) = app(&mail_dir).await;
+        let request = serde_json::json!({ "email": "nobody@school.example" });
+
+        for _ in 0..5 {
+            let (status, _, _) = send(
+                &app,
+                "POST",
+                "/password-reset/request",
+                None,
+                request.clone(),
+            )
+            .await;
+            assert_eq!(status, http::StatusCode::OK);
+        }
+        let (status, _, _) = send(&app, "POST", "/password-reset/request", None, request).await;
+        assert_eq!(status, http::StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn asks_for_the_terms_again_whenever_they_change() {
+        let mail_dir =
+            std::env::temp_dir().join(format!("connectia-mail-{}", tokens::generate().0));
+        let (app, auth_backend, db) = app(&mail_dir).await;
+        auth_backend
+            .create_user("alice", "correct horse", auth::roles::STUDENT, None)
+            .await
+            .unwrap();
+        let credentials = serde_json::json!({ "username": "alice", "password": "correct horse" });
+        let (_, cookie, _) = send(&app, "POST", "/login", None, credentials.clone()).await;
+        let current_user = |app: Router, cookie: Option<String>| async move {
+            let (_, _, body) = send(
+                &app,
+                "GET",
+                "/current-user",
+                cookie.as_deref(),
+                serde_json::Value::Null,
+            )
+            .await;
+            serde_json::from_str::<serde_json::Value>(&body).unwrap()
+        };
+        let version = |app: Router| async move {
+            let (_, _, body) = send(&app, "GET", "/terms", None, serde_json::Value::Null).await;
+            serde_json::from_str::<serde_json::Value>(&body).unwrap()["version"].clone()
+        };
+
+        // A new user is held at the terms until they agree to the version they read
+        assert_eq!(
+            current_user(app.clone(), cookie.clone()).await["terms_accepted"],
+            false
+        );
+        let (status, _, _) = send(
+            &app,
+            "POST",
+            "/terms/accept",
+            cookie.as_deref(),
+            serde_json::json!({ "version": "outdated" }),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::CONFLICT);
+        let old_version = version(app.clone()).await;
+        let (status, _, _) = send(
+            &app,
+            "POST",
+            "/terms/accept",
+            cookie.as_deref(),
+            serde_json::json!({ "version": old_version }),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::OK);
+        assert_eq!(
+            current_user(app.clone(), cookie.clone()).await["terms_accepted"],
+            true
+        );
+
+        // Changing the text makes a new version they have to agree to again
+        let (app, _) = app_with_terms(&mail_dir, db, "Be nicer");
+        let (_, cookie, _) = send(&app, "POST", "/login", None, credentials).await;
+        assert_ne!(version(app.clone()).await, old_version);
+        assert_eq!(
+            current_user(app.clone(), cookie).await["terms_accepted"],
+            false
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_profile_changes_and_only_edits_your_own_profile() {
+        let mail_dir =
+            std::env::temp_dir().join(format!("connectia-mail-{}", tokens::generate().0));
+        let (app, auth_backend, _) = app(&mail_dir).await;
+        for username in ["alice", "bob"] {
+            auth_backend
+                .create_user(username, "correct horse", auth::roles::STUDENT, None)
+                .await
+                .unwrap();
+        }
+        let log_in = |app: Router, username: &'static str| async move {
+            let credentials =
+                serde_json::json!({ "username": username, "password": "correct horse" });
+            send(&app, "POST", "/login", None, credentials).await.1
+        };
+        let alice = log_in(app.clone(), "alice").await;
+        let bob = log_in(app.clone(), "bob").await;
+
+        // Bad changes are turned away without touching the profile
+        let this_year = time::OffsetDateTime::now_utc().year();
+        let bad_changes = [
+            serde_json::json!({ "display_name": "a".repeat(auth::DISPLAY_NAME_MAX_LENGTH + 1) }),
+            serde_json::json!({ "pronouns": "she\nher" }),
+            serde_json::json!({ "bio": "a".repeat(auth::BIO_MAX_LENGTH + 1) }),
+            serde_json::json!({ "graduation_year": this_year + 11 }),
+            serde_json::json!({ "links": ["javascript:alert(1)"] }),
+            serde_json::json!({ "links": vec!["https://example.com"; auth::PROFILE_MAX_LINKS + 1] }),
+        ];
+        for changes in bad_changes {
+            let (status, _, _) = send(&app, "PATCH", "/profile", alice.as_deref(), changes).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+        }
+        let (_, _, body) = send(
+            &app,
+            "GET",
+            "/profiles/alice",
+            alice.as_deref(),
+            serde_json::Value::Null,
+        )
+        .await;
+        let profile = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        assert_eq!(profile["display_name"], serde_json::Value::Null);
+        assert_eq!(profile["links"], serde_json::json!([]));
+
+        // Good changes are cleaned up and saved
+        let changes = serde_json::json!({
+            "display_name": "  Alice  ",
+            "graduation_year": this_year,
+            "links": ["https://example.com", " "],
+        });
+        let (status, _, body) = send(&app, "PATCH", "/profile", alice.as_deref(), changes).await;
+        assert_eq!(status, http::StatusCode::OK);
+        let profile = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        assert_eq!(profile["display_name"], "Alice");
+        assert_eq!(profile["graduation_year"], this_year);
+        assert_eq!(
+            profile["links"],
+            serde_json::json!(["https://example.com/"])
+        );
+
+        // Someone else can read the profile, but their changes only ever go to their own
+        let (status, _, _) = send(
+            &app,
+            "PATCH",
+            "/profiles/alice",
+            bob.as_deref(),
+            serde_json::json!({ "display_name": "Mallory" }),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::METHOD_NOT_ALLOWED);
+        let (status, _, body) = send(
+            &app,
+            "PATCH",
+            "/profile",
+            bob.as_deref(),
+            serde_json::json!({ "username": "alice", "display_name": "Mallory" }),
+        )
+        .await;
+        assert_eq!(status, http::StatusCode::OK);
+        let profile = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        assert_eq!(profile["username"], "bob");
+        let (_, _, body) = send(
+            &app,
+            "GET",
+            "/profiles/alice",
+            bob.as_deref(),
+            serde_json::Value::Null,
+        )
+        .await;
+        let profile = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        assert_eq!(profile["display_name"], "Alice");
     }
 }
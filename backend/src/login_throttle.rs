@@ -0,0 +1,356 @@
+use std::net::IpAddr;
+
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait as _, Condition, DatabaseConnection, DbErr, EntityTrait as _,
+    QueryFilter as _, QueryOrder as _,
+    sea_query::{Expr, OnConflict},
+};
+use time::{Duration, OffsetDateTime};
+
+use crate::{auth::normalize_username, db};
+
+/// What failed logins and password reset requests are counted against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Username,
+    Ip,
+    ResetEmail,
+    ResetIp,
+}
+
+impl Scope {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Username => "username",
+            Scope::Ip => "ip",
+            Scope::ResetEmail => "reset-email",
+            Scope::ResetIp => "reset-ip",
+        }
+    }
+}
+
+/// Slows down and then locks out repeated failed logins for a username or an IP address
+///
+/// Password reset requests are counted separately, per email and per address.
+#[derive(Debug, Clone)]
+pub struct LoginThrottle {
+    db: DatabaseConnection,
+    max_failures: u32,
+    max_failures_per_ip: u32,
+    lockout: Duration,
+}
+
+impl LoginThrottle {
+    pub fn new(
+        db: DatabaseConnection,
+        max_failures: u32,
+        max_failures_per_ip: u32,
+        lockout: Duration,
+    ) -> Self {
+        Self {
+            db,
+            max_failures,
+            max_failures_per_ip,
+            lockout,
+        }
+    }
+
+    /// Get how long a login for a username from an address has to wait, if it has to
+    pub async fn retry_after(&self, username: &str, ip: IpAddr) -> Result<Option<Duration>, DbErr> {
+        self.longest_wait(
+            Condition::any()
+                .add(Self::condition(Scope::Username, &normalize_username(username)))
+                .add(Self::condition(Scope::Ip, &ip.to_string())),
+        )
+        .await
+    }
+
+    /// Count a failed login against a username and the address it came from
+    ///
+    /// Only usernames back off between failures, since many students can share one address
+    pub async fn record_failure(&self, username: &str, ip: IpAddr) -> Result<(), DbErr> {
+        self.fail(Scope::Username, &normalize_username(username), self.max_failures, true)
+            .await?;
+        self.fail(Scope::Ip, &ip.to_string(), self.max_failures_per_ip, false)
+            .await
+    }
+
+    /// Forget the failed logins for a username once it logged in
+    pub async fn record_success(&self, username: &str) -> Result<(), DbErr> {
+        db::login_throttles::Entity::delete_many()
+            .filter(Self::condition(Scope::Username, &normalize_username(username)))
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Get how long a password reset request for an email from an address has to wait, if it has to
+    pub async fn reset_retry_after(
+        &self,
+        email: &str,
+        ip: IpAddr,
+    ) -> Result<Option<Duration>, DbErr> {
+        self.longest_wait(
+            Condition::any()
+                .add(Self::condition(Scope::ResetEmail, email))
+                .add(Self::condition(Scope::ResetIp, &ip.to_string())),
+        )
+        .await
+    }
+
+    /// Count a password reset request against an email and the address it came from, whether or
+    /// not the email belongs to anyone
+    pub async fn record_reset_request(&self, email: &str, ip: IpAddr) -> Result<(), DbErr> {
+        self.fail(Scope::ResetEmail, email, self.max_failures, false)
+            .await?;
+        self.fail(
+            Scope::ResetIp,
+            &ip.to_string(),
+            self.max_failures_per_ip,
+            false,
+        )
+        .await
+    }
+
+    /// Get the usernames and addresses whose logins currently have to wait, longest first
+    pub async fn locked(&self) -> Result<Vec<db::login_throttles::Model>, DbErr> {
+        db::login_throttles::Entity::find()
+            .filter(
+                db::login_throttles::Column::Scope
+                    .is_in([Scope::Username.as_str(), Scope::Ip.as_str()]),
+            )
+            .filter(db::login_throttles::Column::LockedUntil.gt(OffsetDateTime::now_utc()))
+            .order_by_desc(db::login_throttles::Column::LockedUntil)
+            .all(&self.db)
+            .await
+    }
+
+    /// Lift a lock and forget its failures, returning the lock if there was one
+    pub async fn unlock(
+        &self,
+        throttle_id: i64,
+    ) -> Result<Option<db::login_throttles::Model>, DbErr> {
+        let Some(throttle_entity) = db::login_throttles::Entity::find_by_id(throttle_id)
+            .one(&self.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let result = db::login_throttles::Entity::delete_by_id(throttle_id)
+            .exec(&self.db)
+            .await?;
+        Ok((result.rows_affected > 0).then_some(throttle_entity))
+    }
+
+    /// Delete failures that are too old to count anymore
+    pub async fn delete_stale(&self) -> Result<(), DbErr> {
+        let now = OffsetDateTime::now_utc();
+        db::login_throttles::Entity::delete_many()
+            .filter(db::login_throttles::Column::LastFailureAt.lt(now - self.lockout))
+            .filter(
+                Condition::any()
+                    .add(db::login_throttles::Column::LockedUntil.is_null())
+                    .add(db::login_throttles::Column::LockedUntil.lt(now)),
+            )
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Get the longest wait left among the locks matching a condition, if any is still in force
+    async fn longest_wait(&self, condition: Condition) -> Result<Option<Duration>, DbErr> {
+        let now = OffsetDateTime::now_utc();
+        let throttle_entities = db::login_throttles::Entity::find()
+            .filter(condition)
+            .filter(db::login_throttles::Column::LockedUntil.gt(now))
+            .all(&self.db)
+            .await?;
+
+        Ok(throttle_entities
+            .into_iter()
+            .filter_map(|throttle_entity| throttle_entity.locked_until)
+            .max()
+            .map(|locked_until| locked_until - now))
+    }
+
+    /// Count one more failure for a username or address and work out how long it has to wait
+    async fn fail(
+        &self,
+        scope: Scope,
+        identifier: &str,
+        max_failures: u32,
+        back_off: bool,
+    ) -> Result<(), DbErr> {
+        let now = OffsetDateTime::now_utc();
+
+        // Make sure there is a row to count in, even when two failures arrive at once
+        db::login_throttles::Entity::insert(db::login_throttles::ActiveModel {
+            scope: Set(scope.as_str().to_string()),
+            identifier: Set(identifier.to_string()),
+            failures: Set(0),
+            last_failure_at: Set(now),
+            locked_until: Set(None),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns([
+                db::login_throttles::Column::Scope,
+                db::login_throttles::Column::Identifier,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec_without_returning(&self.db)
+        .await?;
+
+        // Count the failure in the database so concurrent ones can't overwrite each other, and
+        // start counting again once the last failure is old enough to be forgotten
+        db::login_throttles::Entity::update_many()
+            .col_expr(
+                db::login_throttles::Column::Failures,
+                Expr::case(
+                    db::login_throttles::Column::LastFailureAt.gt(now - self.lockout),
+                    Expr::col(db::login_throttles::Column::Failures).add(1),
+                )
+                .finally(1)
+                .into(),
+            )
+            .col_expr(db::login_throttles::Column::LastFailureAt, Expr::value(now))
+            .filter(Self::condition(scope, identifier))
+            .exec(&self.db)
+            .await?;
+        let Some(throttle_entity) = db::login_throttles::Entity::find()
+            .filter(Self::condition(scope, identifier))
+            .one(&self.db)
+            .await?
+        else {
+            return Ok(());
+        };
+
+        // Lock it for as long as that many failures call for, unless a later failure already
+        // counted itself and is setting the lock instead
+        let wait = self.wait(throttle_entity.failures.max(0) as u32, max_failures, back_off);
+        db::login_throttles::Entity::update_many()
+            .col_expr(
+                db::login_throttles::Column::LockedUntil,
+                Expr::value((wait > Duration::ZERO).then(|| now + wait)),
+            )
+            .filter(db::login_throttles::Column::Id.eq(throttle_entity.id))
+            .filter(db::login_throttles::Column::Failures.eq(throttle_entity.failures))
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Let the first failure go, then double the wait after each one until the limit locks it out fully
+    fn wait(&self, failures: u32, max_failures: u32, back_off: bool) -> Duration {
+        if failures >= max_failures {
+            self.lockout
+        } else if !back_off || failures < 2 {
+            Duration::ZERO
+        } else {
+            Duration::seconds(1 << (failures - 2).min(30)).min(self.lockout)
+        }
+    }
+
+    fn condition(scope: Scope, identifier: &str) -> Condition {
+        Condition::all()
+            .add(db::login_throttles::Column::Scope.eq(scope.as_str()))
+            .add(db::login_throttles::Column::Identifier.eq(identifier))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    async fn throttle() -> LoginThrottle {
+        LoginThrottle::new(db::test_db().await, 5, 50, Duration::minutes(15))
+    }
+
+    async fn username_failures(throttle: &LoginThrottle) -> i32 {
+        db::login_throttles::Entity::find()
+            .filter(LoginThrottle::condition(Scope::Username, "alice"))
+            .one(&throttle.db)
+            .await
+            .unwrap()
+            .unwrap()
+            .failures
+    }
+
+    #[tokio::test]
+    async fn counts_every_concurrent_failure() {
+        let throttle = throttle().await;
+        let tasks: Vec<_> = (0..10)
+            .map(|_| {
+                let throttle = throttle.clone();
+                tokio::spawn(async move { throttle.record_failure("Alice", IP).await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        assert_eq!(username_failures(&throttle).await, 10);
+        assert!(throttle.retry_after("alice", IP).await.unwrap().unwrap() > Duration::minutes(14));
+    }
+
+    #[tokio::test]
+    async fn forgets_old_failures() {
+        let throttle = throttle().await;
+        for _ in 0..3 {
+            throttle.record_failure("alice", IP).await.unwrap();
+        }
+        db::login_throttles::Entity::update_many()
+            .col_expr(
+                db::login_throttles::Column::LastFailureAt,
+                Expr::value(OffsetDateTime::now_utc() - Duration::hours(1)),
+            )
+            .exec(&throttle.db)
+            .await
+            .unwrap();
+
+        throttle.record_failure("alice", IP).await.unwrap();
+        assert_eq!(username_failures(&throttle).await, 1);
+        assert_eq!(throttle.retry_after("alice", IP).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn counts_reset_requests_apart_from_failed_logins() {
+        let throttle = throttle().await;
+        for _ in 0..5 {
+            assert_eq!(
+                throttle
+                    .reset_retry_after("alice@school.example", IP)
+                    .await
+                    .unwrap(),
+                None
+            );
+            throttle
+                .record_reset_request("alice@school.example", IP)
+                .await
+                .unwrap();
+        }
+
+        assert!(
+            throttle
+                .reset_retry_after("alice@school.example", IP)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert_eq!(
+            throttle
+                .reset_retry_after("bob@school.example", IP)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(throttle.retry_after("alice", IP).await.unwrap(), None);
+        assert!(throttle.locked().await.unwrap().is_empty());
+    }
+}
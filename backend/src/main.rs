@@ -1,10 +1,13 @@
+use std::{net::SocketAddr, path::Path, sync::Arc};
+
 use args::ProgramArgs;
 use axum::{
     Router, ServiceExt,
     extract::Request,
-    routing::{get, post},
+    middleware,
+    routing::{get, patch, post},
 };
-use axum_login::AuthManagerLayerBuilder;
+use axum_login::{AuthManagerLayer, AuthManagerLayerBuilder};
 use clap::Parser;
 use sea_orm::Database;
 use sea_orm_migration::MigratorTrait as _;
@@ -16,16 +19,34 @@ use tower_http::{
     services::ServeDir,
     trace::{DefaultOnFailure, DefaultOnRequest, DefaultOnResponse, TraceLayer},
 };
-use tower_sessions::{MemoryStore, SessionManagerLayer, cookie::time::Duration};
+use tower_sessions::{
+    ExpiredDeletion as _, SessionManagerLayer,
+    cookie::{SameSite, time::Duration},
+};
 use tracing::{Level, event, level_filters::LevelFilter};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt as _};
 
 mod args;
+mod audit_log;
 mod auth;
+mod cbor;
+mod client_ip;
+mod csrf;
 mod db;
+mod duplicates;
 mod handlers;
+mod login_throttle;
+mod mailer;
+mod oidc;
+mod request_bodies;
 mod response_bodies;
+mod roster;
+mod session_store;
 mod states;
+mod terms;
+mod tokens;
+mod totp;
+mod webauthn;
 
 /// The main function for he backend
 #[tokio::main]
@@ -138,51 +159,428 @@ async fn main() {
         }
     };
 
+    // Get the session cleanup interval from the command line arguments
+    let session_cleanup_interval = match program_args.session_cleanup_interval {
+        Some(seconds) => {
+            event!(Level::INFO, "Setting session cleanup interval to {}s", seconds);
+            seconds
+        }
+        None => {
+            event!(
+                Level::INFO,
+                "No session cleanup interval provided, defaulting to 60s"
+            );
+            60
+        }
+    };
+
     // Create the session store and layer
-    let session_store = MemoryStore::default();
-    let session_layer = SessionManagerLayer::new(session_store)
-        .with_expiry(tower_sessions::Expiry::OnInactivity(Duration::days(1)));
+    let session_store = session_store::DatabaseStore::new(database_connection.clone());
+
+    // Periodically delete expired sessions
+    {
+        let session_store = session_store.clone();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(std::time::Duration::from_secs(session_cleanup_interval));
+            loop {
+                interval.tick().await;
+                if let Err(err) = session_store.delete_expired().await {
+                    event!(Level::ERROR, "Failed to delete expired sessions: {}", err);
+                }
+            }
+        });
+    }
+
+    // Get the login throttling settings from the command line arguments
+    let login_max_failures = match program_args.login_max_failures {
+        Some(failures) => {
+            event!(Level::INFO, "Locking out usernames after {} failed logins", failures);
+            failures
+        }
+        None => {
+            event!(
+                Level::INFO,
+                "No login failure limit provided, defaulting to 5"
+            );
+            5
+        }
+    };
+    let login_max_failures_per_ip = match program_args.login_max_failures_per_ip {
+        Some(failures) => {
+            event!(Level::INFO, "Locking out IP addresses after {} failed logins", failures);
+            failures
+        }
+        None => {
+            event!(
+                Level::INFO,
+                "No login failure limit per IP address provided, defaulting to 50"
+            );
+            50
+        }
+    };
+    let login_lockout_duration = match program_args.login_lockout_duration {
+        Some(seconds) => {
+            event!(Level::INFO, "Setting login lockout duration to {}s", seconds);
+            seconds
+        }
+        None => {
+            event!(
+                Level::INFO,
+                "No login lockout duration provided, defaulting to 900s"
+            );
+            900
+        }
+    };
+    let login_throttle = login_throttle::LoginThrottle::new(
+        database_connection.clone(),
+        login_max_failures,
+        login_max_failures_per_ip,
+        time::Duration::seconds(login_lockout_duration as i64),
+    );
+
+    // Periodically delete failed logins that no longer count
+    {
+        let login_throttle = login_throttle.clone();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(std::time::Duration::from_secs(session_cleanup_interval));
+            loop {
+                interval.tick().await;
+                if let Err(err) = login_throttle.delete_stale().await {
+                    event!(Level::ERROR, "Failed to delete stale failed logins: {}", err);
+                }
+            }
+        });
+    }
+
+    if program_args.trust_proxy_headers {
+        event!(Level::INFO, "Trusting client addresses from X-Forwarded-For");
+    }
+
+    // Logins end with the browser unless the user asks to be remembered, and the session has to
+    // survive the single sign-on provider redirecting back, which a strict cookie would not
+    let session_layer = SessionManagerLayer::new(session_store.clone())
+        .with_same_site(SameSite::Lax)
+        .with_expiry(tower_sessions::Expiry::OnSessionEnd);
+    let remember_me_days = match program_args.remember_me_days {
+        Some(days) => {
+            event!(Level::INFO, "Remembering logins for {} days", days);
+            days
+        }
+        None => {
+            event!(Level::INFO, "No remember me duration provided, defaulting to 30 days");
+            30
+        }
+    };
+
+    // Get the password hashing settings from the command line arguments
+    let argon2_algorithm = match program_args.argon2_algorithm {
+        Some(name) => match name.parse::<argon2::Algorithm>() {
+            Ok(algorithm) => {
+                event!(Level::INFO, "Hashing passwords with {}", algorithm);
+                algorithm
+            }
+            Err(err) => {
+                event!(Level::ERROR, "Failed to parse Argon2 algorithm: {}", err);
+                panic!("Failed to parse Argon2 algorithm: {}", err);
+            }
+        },
+        None => {
+            event!(
+                Level::INFO,
+                "No Argon2 algorithm provided, defaulting to {}",
+                argon2::Algorithm::default()
+            );
+            argon2::Algorithm::default()
+        }
+    };
+    let argon2_version = match program_args.argon2_version {
+        Some(version) => match argon2::Version::try_from(version) {
+            Ok(version) => {
+                event!(Level::INFO, "Hashing passwords with Argon2 version {}", u32::from(version));
+                version
+            }
+            Err(err) => {
+                event!(Level::ERROR, "Failed to parse Argon2 version: {}", err);
+                panic!("Failed to parse Argon2 version: {}", err);
+            }
+        },
+        None => {
+            event!(
+                Level::INFO,
+                "No Argon2 version provided, defaulting to {}",
+                u32::from(argon2::Version::default())
+            );
+            argon2::Version::default()
+        }
+    };
+    let argon2_params = match argon2::Params::new(
+        program_args
+            .argon2_memory_cost
+            .unwrap_or(argon2::Params::DEFAULT_M_COST),
+        program_args
+            .argon2_time_cost
+            .unwrap_or(argon2::Params::DEFAULT_T_COST),
+        program_args
+            .argon2_parallelism
+            .unwrap_or(argon2::Params::DEFAULT_P_COST),
+        None,
+    ) {
+        Ok(params) => {
+            event!(
+                Level::INFO,
+                "Hashing passwords with {} KiB of memory, {} passes and {} lanes",
+                params.m_cost(),
+                params.t_cost(),
+                params.p_cost()
+            );
+            params
+        }
+        Err(err) => {
+            event!(Level::ERROR, "Invalid Argon2 parameters: {}", err);
+            panic!("Invalid Argon2 parameters: {}", err);
+        }
+    };
 
     // Create the auth backend and layer
-    let auth_backend = auth::Backend::new(database_connection.clone());
+    let auth_backend = auth::Backend::new(
+        database_connection.clone(),
+        auth::PasswordSettings {
+            algorithm: argon2_algorithm,
+            version: argon2_version,
+            params: argon2_params,
+        },
+    );
     let auth_layer =
         AuthManagerLayerBuilder::new(auth_backend.clone(), session_layer.clone()).build();
 
-    // Create a super user if passed in
-    if let Some(input) = program_args.create_super_user {
-        let mut parts = input.split(":");
-        if let Some(username) = parts.next() {
-            if let Some(password) = parts.next() {
-                match auth_backend.create_user(username, password, true).await {
-                    Ok(_) => event!(Level::INFO, "Super user created"),
-                    Err(err) => {
-                        event!(Level::ERROR, "Failed to create super user: {}", err);
-                        panic!("Failed to create super user: {}", err);
-                    }
+    // Periodically delete registrations that were never verified
+    {
+        let auth_backend = auth_backend.clone();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(std::time::Duration::from_secs(session_cleanup_interval));
+            loop {
+                interval.tick().await;
+                if let Err(err) = auth_backend.delete_expired_registrations().await {
+                    event!(Level::ERROR, "Failed to delete expired registrations: {}", err);
                 }
             }
+        });
+    }
+
+    // Create a super user if passed in
+    if let Some((username, password)) = program_args
+        .create_super_user
+        .as_deref()
+        .and_then(|input| input.split_once(":"))
+    {
+        match auth_backend.create_user(username, password, auth::roles::ADMIN, None).await {
+            Ok(user) if user.require_two_factor => event!(
+                Level::INFO,
+                "Super user created, it has to set up two-factor authentication or a passkey in its settings before using admin permissions"
+            ),
+            Ok(_) => event!(Level::INFO, "Super user created"),
+            Err(auth::Error::UsernameTaken) => {
+                event!(Level::WARN, "Super user {} already exists, skipping", username)
+            }
+            Err(err) => {
+                event!(Level::ERROR, "Failed to create super user: {}", err);
+                panic!("Failed to create super user: {}", err);
+            }
+        }
+    }
+
+    // Get the public url from the command line arguments
+    let public_url = match program_args.public_url {
+        Some(url) => {
+            event!(Level::INFO, "Setting public URL to {}", url);
+            url.trim_end_matches('/').to_string()
         }
+        None => {
+            event!(
+                Level::INFO,
+                "No public URL provided, defaulting to http://localhost:{}",
+                port
+            );
+            format!("http://localhost:{}", port)
+        }
+    };
+
+    // Import a roster instead of serving if asked to
+    if let Some(args::Command::ImportRoster { file, apply }) = program_args.command {
+        import_roster(&auth_backend, &file, apply, &public_url).await;
+        return;
+    }
+
+    // Get the allowed email domains from the command line arguments
+    let allowed_email_domains = program_args
+        .allowed_email_domains
+        .iter()
+        .map(|domain| domain.trim().to_lowercase())
+        .filter(|domain| !domain.is_empty())
+        .collect::<Vec<_>>();
+    if allowed_email_domains.is_empty() {
+        event!(
+            Level::INFO,
+            "No allowed email domains provided, registration is disabled"
+        );
+    } else {
+        event!(
+            Level::INFO,
+            "Allowing registration from {}",
+            allowed_email_domains.join(", ")
+        );
     }
 
+    // Get the address to send emails from the command line arguments
+    let mail_from = match program_args.mail_from {
+        Some(address) => {
+            event!(Level::INFO, "Setting mail sender to {}", address);
+            address
+        }
+        None => {
+            event!(
+                Level::INFO,
+                "No mail sender provided, defaulting to ConnectIA <noreply@localhost>"
+            );
+            "ConnectIA <noreply@localhost>".to_string()
+        }
+    };
+
+    // Create the mailer
+    let mailer: Arc<dyn mailer::Mailer> = match (program_args.smtp_url, program_args.mail_dir) {
+        (Some(url), _) => match mailer::SmtpMailer::new(&url, &mail_from) {
+            Ok(mailer) => {
+                event!(Level::INFO, "Sending emails over SMTP");
+                Arc::new(mailer)
+            }
+            Err(err) => {
+                event!(Level::ERROR, "Failed to create SMTP mailer: {}", err);
+                panic!("Failed to create SMTP mailer: {}", err);
+            }
+        },
+        (None, Some(dir)) => match mailer::FileMailer::new(dir.clone(), &mail_from) {
+            Ok(mailer) => {
+                event!(Level::INFO, "Writing emails to {}", dir.display());
+                Arc::new(mailer)
+            }
+            Err(err) => {
+                event!(Level::ERROR, "Failed to create file mailer: {}", err);
+                panic!("Failed to create file mailer: {}", err);
+            }
+        },
+        (None, None) => {
+            event!(
+                Level::INFO,
+                "No SMTP URL or mail directory provided, logging emails instead"
+            );
+            Arc::new(mailer::LogMailer)
+        }
+    };
+
+    // Discover the single sign-on provider if one is configured
+    let oidc = match (program_args.oidc_issuer_url, program_args.oidc_client_id) {
+        (Some(issuer_url), Some(client_id)) => {
+            let provider_name = program_args
+                .oidc_provider_name
+                .unwrap_or_else(|| "your school account".to_string());
+            match oidc::Provider::discover(
+                &issuer_url,
+                &client_id,
+                program_args.oidc_client_secret.as_deref(),
+                &format!("{}/backend/oidc/callback", public_url),
+                &provider_name,
+            )
+            .await
+            {
+                Ok(provider) => {
+                    event!(Level::INFO, "Allowing single sign-on through {}", issuer_url);
+                    Some(Arc::new(provider))
+                }
+                Err(err) => {
+                    event!(Level::ERROR, "Failed to discover OpenID Connect provider: {}", err);
+                    panic!("Failed to discover OpenID Connect provider: {}", err);
+                }
+            }
+        }
+        (Some(_), None) | (None, Some(_)) => {
+            event!(
+                Level::ERROR,
+                "Single sign-on needs both an OpenID Connect issuer URL and client id"
+            );
+            panic!("Single sign-on needs both an OpenID Connect issuer URL and client id");
+        }
+        (None, None) => {
+            event!(
+                Level::INFO,
+                "No OpenID Connect provider configured, single sign-on is disabled"
+            );
+            None
+        }
+    };
+
+    // Bind passkeys to the site's public URL
+    let relying_party = match webauthn::RelyingParty::from_public_url(&public_url, "ConnectIA") {
+        Ok(relying_party) => {
+            event!(Level::INFO, "Registering passkeys for {}", relying_party.id);
+            Arc::new(relying_party)
+        }
+        Err(err) => {
+            event!(Level::ERROR, "Failed to use the public URL for passkeys: {}", err);
+            panic!("Failed to use the public URL for passkeys: {}", err);
+        }
+    };
+
+    // Get the terms of use from the command line arguments
+    let terms_text = match program_args.terms_file {
+        Some(file) => match std::fs::read_to_string(&file) {
+            Ok(text) => {
+                event!(Level::INFO, "Serving terms of use from {}", file.display());
+                text
+            }
+            Err(err) => {
+                event!(Level::ERROR, "Failed to read terms of use: {}", err);
+                panic!("Failed to read terms of use: {}", err);
+            }
+        },
+        None => {
+            event!(
+                Level::INFO,
+                "No terms of use file provided, defaulting to the built-in TERMS.md"
+            );
+            terms::DEFAULT_TERMS.to_string()
+        }
+    };
+    let terms = terms::Terms::new(database_connection.clone(), terms_text);
+    event!(Level::INFO, "Terms of use are at version {}", terms.version());
+
     // Create the backend state
     let backend_state = states::BackendState {
         db_connection: database_connection.clone(),
+        mailer,
+        allowed_email_domains: allowed_email_domains.into(),
+        public_url,
+        oidc,
+        relying_party,
+        login_throttle,
+        audit_log: audit_log::AuditLog::new(database_connection.clone()),
+        terms,
+        session_store,
+        remember_me_lifetime: Duration::days(remember_me_days.into()),
+        trust_proxy_headers: program_args.trust_proxy_headers,
     };
 
     // Create the backend router
-    let backend_router = Router::new()
-        .route("/ping", get(handlers::backend::get_ping))
-        .route("/login", post(handlers::backend::post_login))
-        .route("/logout", post(handlers::backend::post_logout))
-        .route("/current-user", get(handlers::backend::get_current_user))
-        .layer(auth_layer)
+    let backend_router = backend_router(&backend_state, auth_layer)
+        .layer(middleware::from_fn(csrf::protect))
         .fallback(get(handlers::backend::get_404))
         .with_state(backend_state);
 
     // Create the root state
     let root_state = states::RootState {
         static_dir: static_dir.clone(),
-        ..states::RootState::default()
     };
 
     // Create the root router
@@ -219,8 +617,335 @@ async fn main() {
     };
 
     // Serve the root router
-    match axum::serve(listener, ServiceExt::<Request>::into_make_service(app)).await {
+    match axum::serve(listener, ServiceExt::<Request>::into_make_service_with_connect_info::<SocketAddr>(app)).await {
         Ok(_) => event!(Level::INFO, "Finished serving on port {}", port),
         Err(err) => event!(Level::ERROR, "Failed to serve: {}", err),
     };
 }
+
+/// Build the backend routes along with the session and login layers they need
+fn backend_router(
+    backend_state: &states::BackendState,
+    auth_layer: AuthManagerLayer<auth::Backend, session_store::DatabaseStore>,
+) -> Router<states::BackendState> {
+    // Create the router for leaving the session, which stays open while viewing as another user
+    let session_router = Router::new()
+        .route("/logout", post(handlers::backend::post_logout))
+        .route(
+            "/impersonation/stop",
+            post(handlers::backend::post_stop_impersonation),
+        )
+        .route_layer(middleware::from_fn(auth::require_session));
+
+    // Create the router for managing the account itself, which API tokens can't use
+    let account_router = Router::new()
+        .route("/change-password", post(handlers::backend::post_change_password))
+        .route(
+            "/two-factor/setup",
+            post(handlers::backend::post_two_factor_setup),
+        )
+        .route(
+            "/two-factor/enable",
+            post(handlers::backend::post_two_factor_enable),
+        )
+        .route(
+            "/two-factor/disable",
+            post(handlers::backend::post_two_factor_disable),
+        )
+        .route(
+            "/two-factor/recovery-codes",
+            post(handlers::backend::post_recovery_codes),
+        )
+        .route(
+            "/api-tokens",
+            get(handlers::backend::get_api_tokens).post(handlers::backend::post_api_token),
+        )
+        .route(
+            "/api-tokens/revoke",
+            post(handlers::backend::post_revoke_api_token),
+        )
+        .route("/terms/accept", post(handlers::backend::post_accept_terms))
+        .route("/profile", patch(handlers::backend::patch_profile))
+        .route("/passkeys", get(handlers::backend::get_passkeys))
+        .route(
+            "/passkeys/register/start",
+            post(handlers::backend::post_passkey_register_start),
+        )
+        .route(
+            "/passkeys/register/finish",
+            post(handlers::backend::post_passkey_register_finish),
+        )
+        .route("/passkeys/remove", post(handlers::backend::post_remove_passkey))
+        .route("/sessions", get(handlers::backend::get_sessions))
+        .route("/sessions/revoke", post(handlers::backend::post_revoke_session))
+        .route(
+            "/sessions/revoke-others",
+            post(handlers::backend::post_revoke_other_sessions),
+        )
+        .route_layer(middleware::from_fn(auth::require_session));
+
+    // Create the backend router
+    Router::new()
+        .route("/ping", get(handlers::backend::get_ping))
+        .route("/login", post(handlers::backend::post_login))
+        .route(
+            "/login/two-factor",
+            get(handlers::backend::get_login_two_factor)
+                .post(handlers::backend::post_login_two_factor),
+        )
+        .route(
+            "/login/two-factor/passkey/start",
+            post(handlers::backend::post_login_two_factor_passkey_start),
+        )
+        .route(
+            "/login/two-factor/passkey/finish",
+            post(handlers::backend::post_login_two_factor_passkey_finish),
+        )
+        .route(
+            "/login/passkey/start",
+            post(handlers::backend::post_passkey_login_start),
+        )
+        .route(
+            "/login/passkey/finish",
+            post(handlers::backend::post_passkey_login_finish),
+        )
+        .route("/current-user", get(handlers::backend::get_current_user))
+        .route("/register", post(handlers::backend::post_register))
+        .route("/verify-email", post(handlers::backend::post_verify_email))
+        .route(
+            "/password-reset/request",
+            post(handlers::backend::post_password_reset_request),
+        )
+        .route("/password-reset", post(handlers::backend::post_password_reset))
+        .route("/terms", get(handlers::backend::get_terms))
+        .route("/profiles/{username}", get(handlers::backend::get_profile))
+        .route("/oidc", get(handlers::backend::get_oidc))
+        .route("/oidc/login", get(handlers::backend::get_oidc_login))
+        .route("/oidc/callback", get(handlers::backend::get_oidc_callback))
+        .route(
+            "/create_user",
+            post(handlers::backend::post_create_user).route_layer(middleware::from_fn_with_state(
+                auth::permissions::USERS_CREATE,
+                auth::require_permission,
+            )),
+        )
+        .route(
+            "/admin/users",
+            get(handlers::backend::get_users).route_layer(middleware::from_fn_with_state(
+                auth::permissions::USERS_MANAGE,
+                auth::require_permission,
+            )),
+        )
+        .route(
+            "/admin/users/import",
+            post(handlers::backend::post_import_roster).route_layer(
+                middleware::from_fn_with_state(
+                    auth::permissions::USERS_MANAGE,
+                    auth::require_permission,
+                ),
+            ),
+        )
+        .route(
+            "/admin/users/duplicates",
+            get(handlers::backend::get_duplicate_users).route_layer(
+                middleware::from_fn_with_state(
+                    auth::permissions::USERS_MANAGE,
+                    auth::require_permission,
+                ),
+            ),
+        )
+        .route(
+            "/admin/users/{id}",
+            get(handlers::backend::get_user)
+                .patch(handlers::backend::patch_user)
+                .delete(handlers::backend::delete_user)
+                .route_layer(middleware::from_fn_with_state(
+                    auth::permissions::USERS_MANAGE,
+                    auth::require_permission,
+                )),
+        )
+        .route(
+            "/admin/users/{id}/reset-password",
+            post(handlers::backend::post_reset_user_password).route_layer(
+                middleware::from_fn_with_state(
+                    auth::permissions::USERS_MANAGE,
+                    auth::require_permission,
+                ),
+            ),
+        )
+        .route(
+            "/admin/users/{id}/impersonate",
+            post(handlers::backend::post_impersonate)
+                .route_layer(middleware::from_fn_with_state(
+                    auth::permissions::USERS_IMPERSONATE,
+                    auth::require_permission,
+                ))
+                .route_layer(middleware::from_fn(auth::require_session)),
+        )
+        .route(
+            "/admin/impersonations",
+            get(handlers::backend::get_impersonations).route_layer(
+                middleware::from_fn_with_state(
+                    auth::permissions::USERS_IMPERSONATE,
+                    auth::require_permission,
+                ),
+            ),
+        )
+        .route(
+            "/admin/terms/pending",
+            get(handlers::backend::get_pending_terms).route_layer(
+                middleware::from_fn_with_state(
+                    auth::permissions::USERS_MANAGE,
+                    auth::require_permission,
+                ),
+            ),
+        )
+        .route(
+            "/admin/audit-events",
+            get(handlers::backend::get_audit_events).route_layer(
+                middleware::from_fn_with_state(
+                    auth::permissions::AUDIT_READ,
+                    auth::require_permission,
+                ),
+            ),
+        )
+        .route(
+            "/admin/audit-events/export",
+            get(handlers::backend::get_audit_events_export).route_layer(
+                middleware::from_fn_with_state(
+                    auth::permissions::AUDIT_READ,
+                    auth::require_permission,
+                ),
+            ),
+        )
+        .route(
+            "/admin/login-locks",
+            get(handlers::backend::get_login_locks).route_layer(middleware::from_fn_with_state(
+                auth::permissions::USERS_MANAGE,
+                auth::require_permission,
+            )),
+        )
+        .route(
+            "/admin/login-locks/unlock",
+            post(handlers::backend::post_unlock_login).route_layer(
+                middleware::from_fn_with_state(
+                    auth::permissions::USERS_MANAGE,
+                    auth::require_permission,
+                ),
+            ),
+        )
+        .route(
+            "/admin/suspensions",
+            get(handlers::backend::get_suspensions)
+                .post(handlers::backend::post_suspension)
+                .route_layer(middleware::from_fn_with_state(
+                    auth::permissions::USERS_MODERATE,
+                    auth::require_permission,
+                )),
+        )
+        .route(
+            "/admin/suspensions/lift",
+            post(handlers::backend::post_lift_suspension).route_layer(
+                middleware::from_fn_with_state(
+                    auth::permissions::USERS_MODERATE,
+                    auth::require_permission,
+                ),
+            ),
+        )
+        .route(
+            "/roles",
+            get(handlers::backend::get_roles).route_layer(middleware::from_fn_with_state(
+                auth::permissions::ADMIN_PANEL,
+                auth::require_permission,
+            )),
+        )
+        .merge(account_router)
+        .layer(middleware::from_fn(auth::forbid_while_impersonating))
+        .merge(session_router)
+        .layer(middleware::from_fn(auth::bearer_token))
+        .layer(middleware::from_fn_with_state(
+            backend_state.clone(),
+            session_store::track,
+        ))
+        .layer(middleware::from_fn(auth::keep_remembered))
+        .layer(auth_layer)
+}
+
+/// Import a CSV roster from the command line, printing what changes
+async fn import_roster(auth_backend: &auth::Backend, file: &Path, apply: bool, public_url: &str) {
+    // Read and check every row
+    let data = match std::fs::read(file) {
+        Ok(data) => data,
+        Err(err) => {
+            event!(Level::ERROR, "Failed to read roster: {}", err);
+            panic!("Failed to read roster: {}", err);
+        }
+    };
+    let rows = match roster::parse(&data) {
+        Ok(rows) => rows,
+        Err(err) => {
+            event!(Level::ERROR, "Failed to parse roster: {}", err);
+            panic!("Failed to parse roster: {}", err);
+        }
+    };
+    let planned = match auth_backend.plan_roster(None, rows).await {
+        Ok(planned) => planned,
+        Err(err) => {
+            event!(Level::ERROR, "Failed to check roster: {}", err);
+            panic!("Failed to check roster: {}", err);
+        }
+    };
+
+    // Show what would change
+    for planned_row in &planned {
+        let details = match &planned_row.action {
+            roster::Action::Update { changes, .. } => changes.join(", "),
+            roster::Action::Invalid(error) => error.clone(),
+            roster::Action::Create | roster::Action::Skip => String::new(),
+        };
+        println!(
+            "line {}: {} {} {}",
+            planned_row.row.line,
+            planned_row.action.name(),
+            planned_row.row.username,
+            details
+        );
+    }
+    let invalid = planned
+        .iter()
+        .filter(|planned_row| matches!(planned_row.action, roster::Action::Invalid(_)))
+        .count();
+    if invalid > 0 {
+        event!(Level::ERROR, "{} rows are invalid, nothing was imported", invalid);
+        std::process::exit(1);
+    }
+    if !apply {
+        event!(Level::INFO, "Nothing was imported, pass --apply to import the roster");
+        return;
+    }
+
+    // Import it and hand out the new users' passwords and invite links
+    match auth_backend.apply_roster(planned).await {
+        Ok(outcomes) => {
+            for outcome in &outcomes {
+                if let Some(password) = &outcome.temporary_password {
+                    println!(
+                        "{}: temporary password {}",
+                        outcome.planned.row.username, password
+                    );
+                }
+                if let Some(token) = &outcome.invite_token {
+                    println!(
+                        "{}: invite link {}/reset-password?token={}",
+                        outcome.planned.row.username, public_url, token
+                    );
+                }
+            }
+            event!(Level::INFO, "Imported a roster of {} rows", outcomes.len());
+        }
+        Err(err) => {
+            event!(Level::ERROR, "Failed to import roster: {}", err);
+            panic!("Failed to import roster: {}", err);
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use openidconnect::{
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet, EndpointNotSet,
+    EndpointSet, IssuerUrl, Nonce, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope,
+    TokenResponse as _,
+    core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata},
+    reqwest,
+};
+use serde::{Deserialize, Serialize};
+
+/// The session key holding a single sign-on login that is waiting for the provider
+pub const PENDING_SSO_KEY: &str = "oidc.pending_sso";
+
+/// The client type once it's built from the provider's discovery document
+type DiscoveredClient = CoreClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+/// An OpenID Connect identity provider
+#[derive(Debug, Clone)]
+pub struct Provider {
+    client: DiscoveredClient,
+    http_client: reqwest::Client,
+    pub issuer: String,
+    pub name: String,
+}
+
+/// What is remembered between sending the user to the provider and them coming back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingSso {
+    pub state: String,
+    nonce: String,
+    pkce_verifier: String,
+    pub next: String,
+}
+
+/// The identity the provider vouched for
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidUrl(String),
+    HttpClient(reqwest::Error),
+    Discovery(String),
+    Configuration(String),
+    TokenExchange(String),
+    MissingIdToken,
+    InvalidIdToken(String),
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::HttpClient(err)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidUrl(err) => write!(f, "Invalid URL: {}", err),
+            Error::HttpClient(err) => write!(f, "HTTP Client Error: {}", err),
+            Error::Discovery(err) => write!(f, "Discovery Error: {}", err),
+            Error::Configuration(err) => write!(f, "Configuration Error: {}", err),
+            Error::TokenExchange(err) => write!(f, "Token Exchange Error: {}", err),
+            Error::MissingIdToken => write!(f, "The provider did not return an ID token"),
+            Error::InvalidIdToken(err) => write!(f, "Invalid ID token: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::HttpClient(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl Provider {
+    /// Discover a provider from its issuer URL
+    pub async fn discover(
+        issuer_url: &str,
+        client_id: &str,
+        client_secret: Option<&str>,
+        redirect_url: &str,
+        name: &str,
+    ) -> Result<Self, Error> {
+        // Don't follow redirects so the provider can't point requests somewhere else
+        let http_client = reqwest::ClientBuilder::new()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+
+        // Fetch the discovery document and signing keys
+        let issuer_url =
+            IssuerUrl::new(issuer_url.to_string()).map_err(|err| Error::InvalidUrl(err.to_string()))?;
+        let provider_metadata = CoreProviderMetadata::discover_async(issuer_url, &http_client)
+            .await
+            .map_err(|err| Error::Discovery(err.to_string()))?;
+        let issuer = provider_metadata.issuer().to_string();
+
+        // Create the client
+        let redirect_url = RedirectUrl::new(redirect_url.to_string())
+            .map_err(|err| Error::InvalidUrl(err.to_string()))?;
+        let client = CoreClient::from_provider_metadata(
+            provider_metadata,
+            ClientId::new(client_id.to_string()),
+            client_secret.map(|secret| ClientSecret::new(secret.to_string())),
+        )
+        .set_redirect_uri(redirect_url);
+
+        Ok(Self {
+            client,
+            http_client,
+            issuer,
+            name: name.to_string(),
+        })
+    }
+
+    /// Build the URL to send the user to, along with what to remember until they come back
+    pub fn authorize(&self, next: String) -> (String, PendingSso) {
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let (url, state, nonce) = self
+            .client
+            .authorize_url(
+                CoreAuthenticationFlow::AuthorizationCode,
+                CsrfToken::new_random,
+                Nonce::new_random,
+            )
+            .add_scope(Scope::new("email".to_string()))
+            .add_scope(Scope::new("profile".to_string()))
+            .set_pkce_challenge(pkce_challenge)
+            .url();
+
+        (
+            url.to_string(),
+            PendingSso {
+                state: state.secret().clone(),
+                nonce: nonce.secret().clone(),
+                pkce_verifier: pkce_verifier.secret().clone(),
+                next,
+            },
+        )
+    }
+
+    /// Exchange the code the provider sent back for the verified identity of the user
+    pub async fn exchange(&self, code: String, pending: PendingSso) -> Result<Identity, Error> {
+        // Get the tokens
+        let token_response = self
+            .client
+            .exchange_code(AuthorizationCode::new(code))
+            .map_err(|err| Error::Configuration(err.to_string()))?
+            .set_pkce_verifier(PkceCodeVerifier::new(pending.pkce_verifier))
+            .request_async(&self.http_client)
+            .await
+            .map_err(|err| Error::TokenExchange(err.to_string()))?;
+
+        // Verify the ID token
+        let id_token = token_response.id_token().ok_or(Error::MissingIdToken)?;
+        let claims = id_token
+            .claims(&self.client.id_token_verifier(), &Nonce::new(pending.nonce))
+            .map_err(|err| Error::InvalidIdToken(err.to_string()))?;
+
+        Ok(Identity {
+            subject: claims.subject().to_string(),
+            email: claims.email().map(|email| email.to_string()),
+            email_verified: claims.email_verified().unwrap_or(false),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use argon2::password_hash::rand_core::OsRng;
+    use axum::{Form, Json, Router, extract::State, http::StatusCode, routing::{get, post}};
+    use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+    use openidconnect::url::Url;
+    use rsa::{
+        RsaPrivateKey, pkcs1v15::SigningKey, signature::{SignatureEncoding as _, Signer as _},
+        traits::PublicKeyParts as _,
+    };
+    use serde_json::json;
+    use sha2::{Digest as _, Sha256};
+
+    use super::*;
+
+    const CLIENT_ID: &str = "connectia";
+    const REDIRECT_URL: &str = "http://localhost:8080/backend/oidc/callback";
+    const CODE: &str = "the-code";
+
+    /// A local identity provider that hands out one signed ID token
+    struct MockIssuer {
+        url: String,
+        key: RsaPrivateKey,
+        /// What the login being answered sent to the authorization endpoint
+        nonce: Mutex<String>,
+        code_challenge: Mutex<String>,
+    }
+
+    async fn discovery(State(issuer): State<Arc<MockIssuer>>) -> Json<serde_json::Value> {
+        Json(json!({
+            "issuer": issuer.url,
+            "authorization_endpoint": format!("{}/authorize", issuer.url),
+            "token_endpoint": format!("{}/token", issuer.url),
+            "jwks_uri": format!("{}/jwks", issuer.url),
+            "response_types_supported": ["code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["RS256"],
+        }))
+    }
+
+    async fn jwks(State(issuer): State<Arc<MockIssuer>>) -> Json<serde_json::Value> {
+        Json(json!({
+            "keys": [{
+                "kty": "RSA",
+                "use": "sig",
+                "alg": "RS256",
+                "kid": "mock",
+                "n": URL_SAFE_NO_PAD.encode(issuer.key.n().to_bytes_be()),
+                "e": URL_SAFE_NO_PAD.encode(issuer.key.e().to_bytes_be()),
+            }]
+        }))
+    }
+
+    async fn token(
+        State(issuer): State<Arc<MockIssuer>>,
+        Form(form): Form<std::collections::HashMap<String, String>>,
+    ) -> Result<Json<serde_json::Value>, StatusCode> {
+        // Only answer the code handed out, and only to whoever holds the PKCE verifier
+        let verifier = form.get("code_verifier").ok_or(StatusCode::BAD_REQUEST)?;
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        if form.get("code").map(String::as_str) != Some(CODE)
+            || challenge != *issuer.code_challenge.lock().unwrap()
+        {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        // Sign an ID token for the login
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let header = json!({ "alg": "RS256", "typ": "JWT", "kid": "mock" });
+        let claims = json!({
+            "iss": issuer.url,
+            "aud": CLIENT_ID,
+            "sub": "student-1",
+            "email": "Student@School.Example",
+            "email_verified": true,
+            "nonce": *issuer.nonce.lock().unwrap(),
+            "iat": now,
+            "exp": now + 300,
+        });
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature = SigningKey::<Sha256>::new(issuer.key.clone()).sign(message.as_bytes());
+        Ok(Json(json!({
+            "access_token": "the-access-token",
+            "token_type": "Bearer",
+            "id_token": format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature.to_vec())),
+        })))
+    }
+
+    /// Start a mock issuer on a free local port
+    async fn start_mock_issuer() -> Arc<MockIssuer> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = Arc::new(MockIssuer {
+            url: format!("http://{}", listener.local_addr().unwrap()),
+            key: RsaPrivateKey::new(&mut OsRng, 2048).unwrap(),
+            nonce: Mutex::new(String::new()),
+            code_challenge: Mutex::new(String::new()),
+        });
+        let router = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(issuer.clone());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        issuer
+    }
+
+    /// Send the user to the mock issuer, which remembers what the authorization URL asked for
+    async fn authorize(issuer: &MockIssuer, provider: &Provider) -> PendingSso {
+        let (url, pending) = provider.authorize("/courses".to_string());
+        let url = Url::parse(&url).unwrap();
+        assert!(url.as_str().starts_with(&format!("{}/authorize", issuer.url)));
+        for (name, value) in url.query_pairs() {
+            match &*name {
+                "nonce" => *issuer.nonce.lock().unwrap() = value.to_string(),
+                "code_challenge" => *issuer.code_challenge.lock().unwrap() = value.to_string(),
+                "state" => assert_eq!(value, pending.state),
+                "code_challenge_method" => assert_eq!(value, "S256"),
+                _ => {}
+            }
+        }
+        pending
+    }
+
+    #[tokio::test]
+    async fn round_trip_against_mock_issuer() {
+        let issuer = start_mock_issuer().await;
+        let provider = Provider::discover(&issuer.url, CLIENT_ID, None, REDIRECT_URL, "School")
+            .await
+            .unwrap();
+        assert_eq!(provider.issuer, issuer.url);
+
+        let pending = authorize(&issuer, &provider).await;
+        assert_eq!(pending.next, "/courses");
+        let identity = provider.exchange(CODE.to_string(), pending).await.unwrap();
+        assert_eq!(identity.subject, "student-1");
+        assert_eq!(identity.email.as_deref(), Some("Student@School.Example"));
+        assert!(identity.email_verified);
+    }
+
+    #[tokio::test]
+    async fn rejects_id_token_for_another_login() {
+        let issuer = start_mock_issuer().await;
+        let provider = Provider::discover(&issuer.url, CLIENT_ID, None, REDIRECT_URL, "School")
+            .await
+            .unwrap();
+
+        // The mock signs the token for the first login, so the second one's nonce doesn't match
+        let _ = authorize(&issuer, &provider).await;
+        let nonce = issuer.nonce.lock().unwrap().clone();
+        let pending = authorize(&issuer, &provider).await;
+        *issuer.nonce.lock().unwrap() = nonce;
+        assert!(matches!(
+            provider.exchange(CODE.to_string(), pending).await,
+            Err(Error::InvalidIdToken(_))
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+use secrecy::SecretString;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateUserBody {
+    pub username: String,
+    pub password: SecretString,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub student_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChangePasswordBody {
+    pub current_password: SecretString,
+    pub new_password: SecretString,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegisterBody {
+    pub username: String,
+    pub email: String,
+    pub password: SecretString,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VerifyEmailBody {
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordResetRequestBody {
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordResetBody {
+    pub token: String,
+    pub new_password: SecretString,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TwoFactorCodeBody {
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TwoFactorDisableBody {
+    pub password: SecretString,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcLoginQuery {
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcCallbackQuery {
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UnlockLoginBody {
+    pub id: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateApiTokenBody {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_in_days: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RevokeApiTokenBody {
+    pub id: i64,
+}
+
+/// A new passkey as the browser returned it, binary fields in base64url
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasskeyRegistrationBody {
+    pub name: String,
+    pub id: String,
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+/// A passkey's answer to a login challenge as the browser returned it, binary fields in base64url
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasskeyAssertionBody {
+    pub id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(default)]
+    pub user_handle: Option<String>,
+    /// Keep the login after the browser closes, instead of only until then
+    #[serde(default)]
+    pub remember: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RemovePasskeyBody {
+    pub id: i64,
+    pub password: SecretString,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RevokeSessionBody {
+    /// The session's public id
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SuspendUserBody {
+    pub username: String,
+    pub kind: String,
+    pub reason: String,
+    #[serde(default)]
+    pub days: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LiftSuspensionBody {
+    pub id: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListUsersQuery {
+    #[serde(default)]
+    pub page: Option<u64>,
+    #[serde(default)]
+    pub per_page: Option<u64>,
+    #[serde(default)]
+    pub sort: Option<String>,
+    #[serde(default)]
+    pub order: Option<String>,
+    #[serde(default)]
+    pub search: Option<String>,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpdateUserBody {
+    #[serde(default)]
+    pub username: Option<String>,
+    /// A new email, or an empty one to remove it
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub active: Option<bool>,
+    /// A new student ID, or an empty one to remove it
+    #[serde(default)]
+    pub student_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpdateProfileBody {
+    /// A new display name, or an empty one to remove it
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// New pronouns, or empty ones to remove them
+    #[serde(default)]
+    pub pronouns: Option<String>,
+    /// A new graduation year, or 0 to remove it
+    #[serde(default)]
+    pub graduation_year: Option<i32>,
+    /// A new bio, or an empty one to remove it
+    #[serde(default)]
+    pub bio: Option<String>,
+    /// The new links, replacing all of the old ones
+    #[serde(default)]
+    pub links: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportRosterQuery {
+    #[serde(default)]
+    pub apply: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuditEventsQuery {
+    #[serde(default)]
+    pub page: Option<u64>,
+    #[serde(default)]
+    pub per_page: Option<u64>,
+    #[serde(default)]
+    pub action: Option<String>,
+    #[serde(default)]
+    pub actor: Option<String>,
+    #[serde(default)]
+    pub target: Option<String>,
+    /// The first day to include, as `YYYY-MM-DD`
+    #[serde(default)]
+    pub from: Option<String>,
+    /// The last day to include, as `YYYY-MM-DD`
+    #[serde(default)]
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AcceptTermsBody {
+    /// The version of the terms of use the user read
+    pub version: String,
+}
@@ -1,12 +1,400 @@
+use sea_orm::prelude::TimeDateTimeWithTimeZone;
 use serde::Serialize;
 
+use crate::{db, duplicates, session_store, terms::Terms};
+
 #[derive(Debug, Clone, Serialize)]
 pub struct LoginResponse {
     pub username: String,
+    pub two_factor_required: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct MeResponse {
     pub username: String,
-    pub admin: bool,
-}
\ No newline at end of file
+    pub role: String,
+    pub permissions: Vec<String>,
+    pub two_factor_enabled: bool,
+    pub two_factor_required: bool,
+    /// Whether the role's permissions are withheld until the user sets up a second factor
+    pub second_factor_missing: bool,
+    /// The admin viewing the site as this user, if any
+    pub impersonator: Option<String>,
+    /// Whether the user agreed to the current version of the terms of use
+    pub terms_accepted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UserResponse {
+    pub id: i64,
+    pub username: String,
+    pub role: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoleResponse {
+    pub name: String,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TwoFactorSetupResponse {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OidcResponse {
+    pub enabled: bool,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoginLockResponse {
+    pub id: i64,
+    pub scope: String,
+    pub identifier: String,
+    pub failures: i32,
+    pub remaining_seconds: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiTokenResponse {
+    pub id: i64,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_on: String,
+    pub expires_on: String,
+    pub last_used_on: Option<String>,
+}
+
+impl From<db::api_tokens::Model> for ApiTokenResponse {
+    fn from(entity: db::api_tokens::Model) -> Self {
+        Self {
+            id: entity.id,
+            name: entity.name,
+            scopes: entity.scopes.split(',').map(str::to_string).collect(),
+            created_on: entity.created_at.date().to_string(),
+            expires_on: entity.expires_at.date().to_string(),
+            last_used_on: entity.last_used_at.map(|used_at| used_at.date().to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NewApiTokenResponse {
+    pub token: String,
+    pub api_token: ApiTokenResponse,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PasskeyResponse {
+    pub id: i64,
+    pub name: String,
+    pub created_on: String,
+    pub last_used_on: Option<String>,
+}
+
+impl From<db::passkeys::Model> for PasskeyResponse {
+    fn from(entity: db::passkeys::Model) -> Self {
+        Self {
+            id: entity.id,
+            name: entity.name,
+            created_on: entity.created_at.date().to_string(),
+            last_used_on: entity.last_used_at.map(|used_at| used_at.date().to_string()),
+        }
+    }
+}
+
+/// The second factors a login waiting for one can use
+#[derive(Debug, Clone, Serialize)]
+pub struct TwoFactorMethodsResponse {
+    pub code: bool,
+    pub passkey: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SuspendedResponse {
+    pub kind: String,
+    pub reason: String,
+    pub ends_on: Option<String>,
+}
+
+impl From<db::suspensions::Model> for SuspendedResponse {
+    fn from(entity: db::suspensions::Model) -> Self {
+        Self {
+            kind: entity.kind,
+            reason: entity.reason,
+            ends_on: entity.ends_at.map(|ends_at| ends_at.date().to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SuspensionResponse {
+    pub id: i64,
+    pub username: String,
+    pub kind: String,
+    pub reason: String,
+    pub created_on: String,
+    pub ends_on: Option<String>,
+}
+
+impl From<(db::suspensions::Model, db::users::Model)> for SuspensionResponse {
+    fn from((entity, user): (db::suspensions::Model, db::users::Model)) -> Self {
+        Self {
+            id: entity.id,
+            username: user.username,
+            kind: entity.kind,
+            reason: entity.reason,
+            created_on: entity.created_at.date().to_string(),
+            ends_on: entity.ends_at.map(|ends_at| ends_at.date().to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminUserResponse {
+    pub id: i64,
+    pub username: String,
+    pub email: Option<String>,
+    pub role: String,
+    pub active: bool,
+    pub suspended: bool,
+    pub student_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UserPageResponse {
+    pub users: Vec<AdminUserResponse>,
+    pub page: u64,
+    pub per_page: u64,
+    pub total: u64,
+    pub pages: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TemporaryPasswordResponse {
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RosterRowResponse {
+    pub line: u64,
+    pub username: String,
+    pub action: String,
+    pub changes: Vec<String>,
+    pub error: Option<String>,
+    pub temporary_password: Option<String>,
+    pub invite_link: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RosterReportResponse {
+    pub applied: bool,
+    pub creates: usize,
+    pub updates: usize,
+    pub skips: usize,
+    pub invalid: usize,
+    pub rows: Vec<RosterRowResponse>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateUserResponse {
+    pub id: i64,
+    pub username: String,
+    pub email: Option<String>,
+    pub student_id: Option<String>,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateCandidateResponse {
+    pub reason: String,
+    pub users: Vec<DuplicateUserResponse>,
+}
+
+impl From<duplicates::Candidate> for DuplicateCandidateResponse {
+    fn from(candidate: duplicates::Candidate) -> Self {
+        Self {
+            reason: candidate.reason.to_string(),
+            users: candidate
+                .users
+                .into_iter()
+                .map(|user| DuplicateUserResponse {
+                    id: user.id,
+                    username: user.username,
+                    email: user.email,
+                    student_id: user.student_id,
+                    active: user.active,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImpersonationResponse {
+    pub id: i64,
+    pub admin: Option<String>,
+    pub user: Option<String>,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+}
+
+impl From<(db::impersonations::Model, Option<String>, Option<String>)> for ImpersonationResponse {
+    fn from(
+        (entity, admin, user): (db::impersonations::Model, Option<String>, Option<String>),
+    ) -> Self {
+        Self {
+            id: entity.id,
+            admin,
+            user,
+            started_at: format_timestamp(entity.started_at),
+            ended_at: entity.ended_at.map(format_timestamp),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TermsResponse {
+    pub version: String,
+    /// The terms of use as markdown
+    pub text: String,
+    /// When the logged in user agreed to this version, if they did
+    pub accepted_at: Option<String>,
+}
+
+impl TermsResponse {
+    pub fn new(terms: &Terms, accepted_at: Option<TimeDateTimeWithTimeZone>) -> Self {
+        Self {
+            version: terms.version().to_string(),
+            text: terms.text().to_string(),
+            accepted_at: accepted_at.map(format_timestamp),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingTermsResponse {
+    pub id: i64,
+    pub username: String,
+    pub email: Option<String>,
+    /// The last version the user agreed to, if any
+    pub accepted_version: Option<String>,
+    pub accepted_at: Option<String>,
+}
+
+impl From<(db::users::Model, Option<db::terms_acceptances::Model>)> for PendingTermsResponse {
+    fn from(
+        (user, acceptance): (db::users::Model, Option<db::terms_acceptances::Model>),
+    ) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            accepted_version: acceptance.as_ref().map(|acceptance| acceptance.version.clone()),
+            accepted_at: acceptance.map(|acceptance| format_timestamp(acceptance.accepted_at)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionResponse {
+    /// The session's public id, not the one in its cookie
+    pub id: String,
+    pub created_at: Option<String>,
+    pub last_seen_at: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// Whether this is the session the request came from
+    pub current: bool,
+}
+
+impl SessionResponse {
+    pub fn new(entity: db::sessions::Model, current_id: Option<&str>) -> Self {
+        Self {
+            id: session_store::public_id(&entity.id),
+            created_at: entity.created_at.map(format_timestamp),
+            last_seen_at: entity.last_seen_at.map(format_timestamp),
+            ip: entity.ip,
+            user_agent: entity.user_agent,
+            current: current_id == Some(entity.id.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEventResponse {
+    pub id: i64,
+    pub action: String,
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: Option<String>,
+    pub created_at: String,
+}
+
+impl From<db::audit_events::Model> for AuditEventResponse {
+    fn from(entity: db::audit_events::Model) -> Self {
+        Self {
+            id: entity.id,
+            action: entity.action,
+            actor: entity.actor_name,
+            target: entity.target_name,
+            ip: entity.ip,
+            user_agent: entity.user_agent,
+            details: entity.details,
+            created_at: format_timestamp(entity.created_at),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditPageResponse {
+    pub events: Vec<AuditEventResponse>,
+    pub actions: Vec<String>,
+    pub page: u64,
+    pub per_page: u64,
+    pub total: u64,
+    pub pages: u64,
+}
+
+/// Show a moment to the minute, in UTC
+fn format_timestamp(at: TimeDateTimeWithTimeZone) -> String {
+    let at = at.to_offset(time::UtcOffset::UTC);
+    format!("{} {:02}:{:02} UTC", at.date(), at.hour(), at.minute())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileResponse {
+    pub username: String,
+    pub display_name: Option<String>,
+    pub pronouns: Option<String>,
+    pub graduation_year: Option<i32>,
+    pub bio: Option<String>,
+    pub links: Vec<String>,
+}
+
+impl From<(db::users::Model, Option<db::profiles::Model>)> for ProfileResponse {
+    fn from((user, profile): (db::users::Model, Option<db::profiles::Model>)) -> Self {
+        let links = profile
+            .as_ref()
+            .map(db::profiles::Model::links)
+            .unwrap_or_default();
+        Self {
+            username: user.username,
+            display_name: profile.as_ref().and_then(|entity| entity.display_name.clone()),
+            pronouns: profile.as_ref().and_then(|entity| entity.pronouns.clone()),
+            graduation_year: profile.as_ref().and_then(|entity| entity.graduation_year),
+            bio: profile.and_then(|entity| entity.bio),
+            links,
+        }
+    }
+}
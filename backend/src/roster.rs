@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+
+use crate::auth::normalize_student_id;
+
+/// The columns a roster can have, of which only the username is required
+const COLUMNS: &[&str] = &["username", "email", "grade", "role", "student_id"];
+
+/// A user as listed in a roster, with blank cells left out
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub line: u64,
+    pub username: String,
+    pub email: Option<String>,
+    pub grade: Option<String>,
+    pub role: Option<String>,
+    pub student_id: Option<String>,
+}
+
+/// What importing a roster row will do
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Create a new user
+    Create,
+    /// Change an existing user, described by the changes
+    Update { user_id: i64, changes: Vec<String> },
+    /// Leave an existing user as they are
+    Skip,
+    /// Refuse the whole import because of the row
+    Invalid(String),
+}
+
+impl Action {
+    /// The name of the action shown in reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Create => "create",
+            Action::Update { .. } => "update",
+            Action::Skip => "skip",
+            Action::Invalid(_) => "invalid",
+        }
+    }
+}
+
+/// A roster row along with what importing it will do
+#[derive(Debug, Clone)]
+pub struct PlannedRow {
+    pub row: Row,
+    pub action: Action,
+}
+
+/// What importing a roster row did
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub planned: PlannedRow,
+    /// The only copy of a created user's temporary password, for users without an email
+    pub temporary_password: Option<String>,
+    /// The only copy of a created user's invite token, for users with an email
+    pub invite_token: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Csv(csv::Error),
+    MissingUsernameColumn,
+    UnknownColumn(String),
+}
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Self {
+        Error::Csv(err)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Csv(err) => write!(f, "{}", err),
+            Error::MissingUsernameColumn => write!(f, "The roster needs a username column"),
+            Error::UnknownColumn(column) => write!(
+                f,
+                "Unknown column {}, expected {}",
+                column,
+                COLUMNS.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Csv(err) => Some(err),
+            Error::MissingUsernameColumn => None,
+            Error::UnknownColumn(_) => None,
+        }
+    }
+}
+
+/// Read the rows of a CSV roster whose header names its columns in any order
+pub fn parse(data: &[u8]) -> Result<Vec<Row>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(data);
+
+    // Find where each column is
+    let mut columns = HashMap::new();
+    for (index, header) in reader.headers()?.iter().enumerate() {
+        let header = header.to_lowercase();
+        if !COLUMNS.contains(&header.as_str()) {
+            return Err(Error::UnknownColumn(header));
+        }
+        columns.insert(header, index);
+    }
+    if !columns.contains_key("username") {
+        return Err(Error::MissingUsernameColumn);
+    }
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let cell = |column: &str| {
+            columns
+                .get(column)
+                .and_then(|index| record.get(*index))
+                .filter(|cell| !cell.is_empty())
+                .map(str::to_string)
+        };
+        rows.push(Row {
+            line: record.position().map_or(0, |position| position.line()),
+            username: cell("username").unwrap_or_default(),
+            email: cell("email").map(|email| email.to_lowercase()),
+            grade: cell("grade"),
+            role: cell("role").map(|role| role.to_lowercase()),
+            student_id: cell("student_id").map(|student_id| normalize_student_id(&student_id)),
+        });
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::{Algorithm, Params, Version};
+    use sea_orm::{ActiveModelTrait as _, ActiveValue::Set, DatabaseConnection, EntityTrait as _};
+
+    use super::*;
+    use crate::{auth, db};
+
+    /// Get a backend over a fresh in-memory database, along with the database itself
+    async fn backend() -> (auth::Backend, DatabaseConnection) {
+        let db = db::test_db().await;
+        let backend = auth::Backend::new(
+            db.clone(),
+            auth::PasswordSettings {
+                algorithm: Algorithm::Argon2id,
+                version: Version::V0x13,
+                params: Params::new(Params::MIN_M_COST, 1, 1, None).unwrap(),
+            },
+        );
+        (backend, db)
+    }
+
+    /// Parse and plan a roster, returning each row's action
+    async fn plan(backend: &auth::Backend, roster: &str) -> Vec<Action> {
+        let rows = parse(roster.as_bytes()).unwrap();
+        backend
+            .plan_roster(None, rows)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|planned_row| planned_row.action)
+            .collect()
+    }
+
+    fn invalid_reason(action: &Action) -> &str {
+        match action {
+            Action::Invalid(reason) => reason,
+            other => panic!("Expected an invalid row, got {}", other.name()),
+        }
+    }
+
+    #[test]
+    fn normalizes_cells() {
+        let rows = parse(
+            b"Username, EMAIL ,Role,Student_ID,grade\n\
+              alice , Alice@School.Example ,STUDENT, s-001 ,\n",
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[0].username, "alice");
+        assert_eq!(rows[0].email.as_deref(), Some("alice@school.example"));
+        assert_eq!(rows[0].role.as_deref(), Some("student"));
+        assert_eq!(rows[0].student_id.as_deref(), Some("S-001"));
+        assert_eq!(rows[0].grade, None);
+    }
+
+    #[test]
+    fn rejects_unknown_and_missing_columns() {
+        assert!(matches!(
+            parse(b"username,nickname\nalice,al\n"),
+            Err(Error::UnknownColumn(column)) if column == "nickname"
+        ));
+        assert!(matches!(
+            parse(b"email\nalice@school.example\n"),
+            Err(Error::MissingUsernameColumn)
+        ));
+    }
+
+    #[tokio::test]
+    async fn refuses_duplicate_rows() {
+        let (backend, _) = backend().await;
+        let actions = plan(
+            &backend,
+            "username,email,student_id\n\
+             alice,alice@school.example,s-001\n\
+             ALICE ,,\n\
+             bob,Alice@School.Example,\n\
+             carol,, S-001\n\
+             dave,,\n",
+        )
+        .await;
+        assert!(matches!(actions[0], Action::Create));
+        assert_eq!(invalid_reason(&actions[1]), "Username appears more than once");
+        assert_eq!(invalid_reason(&actions[2]), "Email appears more than once");
+        assert_eq!(invalid_reason(&actions[3]), "Student ID appears more than once");
+        assert!(matches!(actions[4], Action::Create));
+    }
+
+    #[tokio::test]
+    async fn matches_existing_users_ignoring_case() {
+        let (backend, _) = backend().await;
+        let alice = backend.create_user("Alice", "pw", auth::roles::STUDENT, None).await.unwrap();
+        let actions = plan(&backend, "username,grade\n alice ,\nALICE,10\n").await;
+        assert!(matches!(actions[0], Action::Skip));
+        assert_eq!(invalid_reason(&actions[1]), "Username appears more than once");
+
+        let actions = plan(&backend, "username,grade\nALICE,10\n").await;
+        assert!(matches!(
+            &actions[0],
+            Action::Update { user_id, changes }
+                if *user_id == alice.id && changes == &["grade: none → 10"]
+        ));
+    }
+
+    #[tokio::test]
+    async fn refuses_emails_and_student_ids_of_other_users() {
+        let (backend, db) = backend().await;
+        let bob = backend
+            .create_user("bob", "pw", auth::roles::STUDENT, Some("s-002"))
+            .await
+            .unwrap();
+        let mut bob_entity: db::users::ActiveModel =
+            db::users::Entity::find_by_id(bob.id).one(&db).await.unwrap().unwrap().into();
+        bob_entity.email = Set(Some("bob@school.example".to_string()));
+        bob_entity.update(&db).await.unwrap();
+
+        let actions = plan(
+            &backend,
+            "username,email,student_id\n\
+             carol,BOB@school.example,\n\
+             dave,,s-002\n",
+        )
+        .await;
+        assert_eq!(invalid_reason(&actions[0]), "Email already belongs to bob");
+        assert_eq!(invalid_reason(&actions[1]), "Student ID already belongs to bob");
+
+        // Bob's own row may list them
+        let actions =
+            plan(&backend, "username,email,student_id\nbob,bob@school.example,S-002\n").await;
+        assert!(matches!(actions[0], Action::Skip));
+    }
+}
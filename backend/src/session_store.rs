@@ -0,0 +1,266 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use axum_login::AuthSession;
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait as _, Condition, DatabaseConnection, DbErr, EntityTrait as _,
+    QueryFilter as _, QueryOrder as _,
+    sea_query::{Expr, OnConflict},
+};
+use time::{Duration, OffsetDateTime};
+use tower_sessions::{
+    ExpiredDeletion, Session, SessionStore,
+    session::{Id, Record},
+    session_store,
+};
+use tracing::{Level, event};
+
+use crate::{audit_log::RequestOrigin, auth, db, states::BackendState, tokens};
+
+/// How often a session's last use is written down, so not every request writes to the database
+const LAST_SEEN_PRECISION: Duration = Duration::minutes(1);
+
+/// A session store persisting sessions in the database
+#[derive(Debug, Clone)]
+pub struct DatabaseStore {
+    db: DatabaseConnection,
+}
+
+impl DatabaseStore {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Convert a session record into an active model for the sessions table
+    fn active_model(record: &Record) -> session_store::Result<db::sessions::ActiveModel> {
+        let data = serde_json::to_string(&record.data)
+            .map_err(|err| session_store::Error::Encode(err.to_string()))?;
+        Ok(db::sessions::ActiveModel {
+            id: Set(record.id.to_string()),
+            data: Set(data),
+            expiry_date: Set(record.expiry_date),
+            ..Default::default()
+        })
+    }
+
+    /// Note who used a session and from where, at most once a minute unless either changed
+    pub async fn touch(
+        &self,
+        session_id: &Id,
+        user_id: i64,
+        origin: &RequestOrigin,
+    ) -> Result<(), DbErr> {
+        let now = OffsetDateTime::now_utc();
+        db::sessions::Entity::update_many()
+            .col_expr(db::sessions::Column::UserId, Expr::value(user_id))
+            .col_expr(db::sessions::Column::LastSeenAt, Expr::value(now))
+            .col_expr(db::sessions::Column::Ip, Expr::value(origin.ip.clone()))
+            .col_expr(
+                db::sessions::Column::UserAgent,
+                Expr::value(origin.user_agent.clone()),
+            )
+            .filter(db::sessions::Column::Id.eq(session_id.to_string()))
+            .filter(
+                Condition::any()
+                    .add(db::sessions::Column::LastSeenAt.is_null())
+                    .add(db::sessions::Column::LastSeenAt.lt(now - LAST_SEEN_PRECISION))
+                    .add(db::sessions::Column::UserId.ne(user_id))
+                    .add(db::sessions::Column::Ip.ne(origin.ip.as_str())),
+            )
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Get a user's sessions that haven't expired, most recently used first
+    pub async fn user_sessions(&self, user_id: i64) -> Result<Vec<db::sessions::Model>, DbErr> {
+        db::sessions::Entity::find()
+            .filter(db::sessions::Column::UserId.eq(user_id))
+            .filter(db::sessions::Column::ExpiryDate.gt(OffsetDateTime::now_utc()))
+            .order_by_desc(db::sessions::Column::LastSeenAt)
+            .all(&self.db)
+            .await
+    }
+
+    /// End one of a user's sessions by its public id, returning it if there was one
+    pub async fn delete_user_session(
+        &self,
+        user_id: i64,
+        public_session_id: &str,
+    ) -> Result<Option<db::sessions::Model>, DbErr> {
+        let Some(session_entity) = self
+            .user_sessions(user_id)
+            .await?
+            .into_iter()
+            .find(|session_entity| public_id(&session_entity.id) == public_session_id)
+        else {
+            return Ok(None);
+        };
+        db::sessions::Entity::delete_by_id(session_entity.id.clone())
+            .exec(&self.db)
+            .await?;
+        Ok(Some(session_entity))
+    }
+
+    /// End every session of a user except one, returning how many ended
+    pub async fn delete_other_user_sessions(
+        &self,
+        user_id: i64,
+        kept_session_id: &Id,
+    ) -> Result<u64, DbErr> {
+        let result = db::sessions::Entity::delete_many()
+            .filter(db::sessions::Column::UserId.eq(user_id))
+            .filter(db::sessions::Column::Id.ne(kept_session_id.to_string()))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    /// End every session of a user, returning how many ended
+    pub async fn delete_user_sessions(&self, user_id: i64) -> Result<u64, DbErr> {
+        let result = db::sessions::Entity::delete_many()
+            .filter(db::sessions::Column::UserId.eq(user_id))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+}
+
+/// The id a session is shown to its user with, which can't be used to take the session over
+pub fn public_id(session_id: &str) -> String {
+    tokens::hash(session_id)[..16].to_string()
+}
+
+/// Middleware noting who uses each session, from where and when
+///
+/// Attach inside the auth layer and outside `auth::bearer_token`. Sessions an admin uses to view
+/// the site as someone else are left alone, so they don't show up among that user's sessions.
+pub async fn track(
+    State(state): State<BackendState>,
+    auth_session: AuthSession<auth::Backend>,
+    session: Session,
+    origin: RequestOrigin,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let (Some(user), Some(session_id)) = (&auth_session.user, session.id()) {
+        let impersonating = session
+            .get::<auth::Impersonation>(auth::IMPERSONATION_KEY)
+            .await
+            .ok()
+            .flatten()
+            .is_some();
+        if !impersonating
+            && let Err(err) = state.session_store.touch(&session_id, user.id, &origin).await
+        {
+            event!(Level::ERROR, "Failed to note the use of a session: {}", err);
+        }
+    }
+    next.run(request).await
+}
+
+/// Note who logged in with a session and from where right away
+///
+/// Logging in gives the session a new id that isn't stored until the response is sent, so `track`
+/// wouldn't see it until its next request. This stores it early so it can be noted.
+pub async fn track_login(
+    state: &BackendState,
+    session: &Session,
+    user_id: i64,
+    origin: &RequestOrigin,
+) -> Result<(), tower_sessions::session::Error> {
+    session.save().await?;
+    let Some(session_id) = session.id() else {
+        return Ok(());
+    };
+    state
+        .session_store
+        .touch(&session_id, user_id, origin)
+        .await
+        .map_err(|err| tower_sessions::session::Error::Store(backend_error(err)))
+}
+
+/// Convert a database error into a session store error
+fn backend_error(err: sea_orm::DbErr) -> session_store::Error {
+    session_store::Error::Backend(err.to_string())
+}
+
+#[async_trait]
+impl SessionStore for DatabaseStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        // Pick a new id until it doesn't collide with an existing session
+        while db::sessions::Entity::find_by_id(record.id.to_string())
+            .one(&self.db)
+            .await
+            .map_err(backend_error)?
+            .is_some()
+        {
+            record.id = Id::default();
+        }
+
+        let mut session_entity = Self::active_model(record)?;
+        session_entity.created_at = Set(Some(OffsetDateTime::now_utc()));
+        db::sessions::Entity::insert(session_entity)
+            .exec(&self.db)
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        db::sessions::Entity::insert(Self::active_model(record)?)
+            .on_conflict(
+                OnConflict::column(db::sessions::Column::Id)
+                    .update_columns([
+                        db::sessions::Column::Data,
+                        db::sessions::Column::ExpiryDate,
+                    ])
+                    .to_owned(),
+            )
+            .exec(&self.db)
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        let session_entity = db::sessions::Entity::find_by_id(session_id.to_string())
+            .filter(db::sessions::Column::ExpiryDate.gt(OffsetDateTime::now_utc()))
+            .one(&self.db)
+            .await
+            .map_err(backend_error)?;
+
+        match session_entity {
+            Some(entity) => Ok(Some(Record {
+                id: *session_id,
+                data: serde_json::from_str(&entity.data)
+                    .map_err(|err| session_store::Error::Decode(err.to_string()))?,
+                expiry_date: entity.expiry_date,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        db::sessions::Entity::delete_by_id(session_id.to_string())
+            .exec(&self.db)
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for DatabaseStore {
+    async fn delete_expired(&self) -> session_store::Result<()> {
+        db::sessions::Entity::delete_many()
+            .filter(db::sessions::Column::ExpiryDate.lte(OffsetDateTime::now_utc()))
+            .exec(&self.db)
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+}
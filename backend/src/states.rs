@@ -1,13 +1,31 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 use sea_orm::DatabaseConnection;
 
+use crate::{
+    audit_log::AuditLog, login_throttle::LoginThrottle, mailer::Mailer, oidc,
+    session_store::DatabaseStore, terms::Terms, webauthn,
+};
+
 #[derive(Debug, Clone, Default)]
 pub struct RootState {
     pub static_dir: PathBuf,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct BackendState {
+    #[allow(dead_code)]
     pub db_connection: DatabaseConnection,
+    pub mailer: Arc<dyn Mailer>,
+    pub allowed_email_domains: Arc<[String]>,
+    pub public_url: String,
+    pub oidc: Option<Arc<oidc::Provider>>,
+    pub relying_party: Arc<webauthn::RelyingParty>,
+    pub login_throttle: LoginThrottle,
+    pub session_store: DatabaseStore,
+    /// How long a login the user asked to remember lasts
+    pub remember_me_lifetime: time::Duration,
+    pub audit_log: AuditLog,
+    pub terms: Terms,
+    pub trust_proxy_headers: bool,
 }
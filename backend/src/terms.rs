@@ -0,0 +1,113 @@
+use std::{collections::HashMap, sync::Arc};
+
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait as _, DatabaseConnection, DbErr, EntityTrait as _,
+    QueryFilter as _, QueryOrder as _,
+    sea_query::{OnConflict, Query},
+};
+use sha2::{Digest as _, Sha256};
+use time::OffsetDateTime;
+
+use crate::db;
+
+/// The terms of use served when no other file is given
+pub const DEFAULT_TERMS: &str = include_str!("../../TERMS.md");
+
+/// The terms of use users have to agree to, and who agreed to which version
+#[derive(Debug, Clone)]
+pub struct Terms {
+    db: DatabaseConnection,
+    text: Arc<str>,
+    version: Arc<str>,
+}
+
+impl Terms {
+    /// Serve terms of use, versioned by their contents so any edit asks users to agree again
+    pub fn new(db: DatabaseConnection, text: impl Into<Arc<str>>) -> Self {
+        let text = text.into();
+        let version = hex::encode(Sha256::digest(text.as_bytes()))[..12].into();
+        Self { db, text, version }
+    }
+
+    /// The terms of use as markdown
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The version of the terms of use users have to agree to
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Get when a user agreed to the current version, if they did
+    pub async fn accepted_at(&self, user_id: i64) -> Result<Option<OffsetDateTime>, DbErr> {
+        Ok(db::terms_acceptances::Entity::find()
+            .filter(db::terms_acceptances::Column::UserId.eq(user_id))
+            .filter(db::terms_acceptances::Column::Version.eq(self.version()))
+            .one(&self.db)
+            .await?
+            .map(|acceptance_entity| acceptance_entity.accepted_at))
+    }
+
+    /// Record a user agreeing to the current version, returning whether they hadn't already
+    pub async fn accept(&self, user_id: i64) -> Result<bool, DbErr> {
+        let inserted = db::terms_acceptances::Entity::insert(db::terms_acceptances::ActiveModel {
+            user_id: Set(user_id),
+            version: Set(self.version().to_string()),
+            accepted_at: Set(OffsetDateTime::now_utc()),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns([
+                db::terms_acceptances::Column::UserId,
+                db::terms_acceptances::Column::Version,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec_without_returning(&self.db)
+        .await?;
+        Ok(inserted > 0)
+    }
+
+    /// Get the active users who haven't agreed to the current version, by username,
+    /// along with the last version each of them did agree to
+    pub async fn pending(
+        &self,
+    ) -> Result<Vec<(db::users::Model, Option<db::terms_acceptances::Model>)>, DbErr> {
+        let user_entities = db::users::Entity::find()
+            .filter(db::users::Column::Active.eq(true))
+            .filter(
+                db::users::Column::Id.not_in_subquery(
+                    Query::select()
+                        .column(db::terms_acceptances::Column::UserId)
+                        .from(db::terms_acceptances::Entity)
+                        .and_where(db::terms_acceptances::Column::Version.eq(self.version()))
+                        .to_owned(),
+                ),
+            )
+            .order_by_asc(db::users::Column::Username)
+            .all(&self.db)
+            .await?;
+
+        // Keep only the newest earlier acceptance of each user
+        let user_ids = user_entities.iter().map(|user_entity| user_entity.id);
+        let mut previous = HashMap::new();
+        for acceptance_entity in db::terms_acceptances::Entity::find()
+            .filter(db::terms_acceptances::Column::UserId.is_in(user_ids))
+            .order_by_asc(db::terms_acceptances::Column::AcceptedAt)
+            .all(&self.db)
+            .await?
+        {
+            previous.insert(acceptance_entity.user_id, acceptance_entity);
+        }
+
+        Ok(user_entities
+            .into_iter()
+            .map(|user_entity| {
+                let acceptance_entity = previous.remove(&user_entity.id);
+                (user_entity, acceptance_entity)
+            })
+            .collect())
+    }
+}
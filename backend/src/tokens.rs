@@ -0,0 +1,30 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore as _};
+use sha2::{Digest as _, Sha256};
+
+/// Generate a random token, returning it along with the hash to store for it
+pub fn generate() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+    let token_hash = hash(&token);
+    (token, token_hash)
+}
+
+/// Generate a random temporary password that is easy to read out and type
+pub fn generate_password() -> String {
+    let mut bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut bytes);
+    let password = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}",
+        &password[..4],
+        &password[4..8],
+        &password[8..12],
+        &password[12..]
+    )
+}
+
+/// Hash a token for storage or lookup
+pub fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
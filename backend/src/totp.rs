@@ -0,0 +1,62 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore as _};
+use totp_rs::{Builder, Secret, Totp, TotpError};
+
+/// The issuer shown in authenticator apps
+const ISSUER: &str = "ConnectIA";
+
+/// How many recovery codes are issued at once
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Generate a new base32 encoded TOTP secret
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    Secret::from(bytes).to_base32()
+}
+
+/// Build the TOTP for a stored secret
+fn totp(secret: &str, username: &str) -> Result<Totp, TotpError> {
+    let secret = Secret::try_from_base32(secret).map_err(|_| TotpError::InvalidSecret)?;
+    Builder::new()
+        .with_secret(secret)
+        .with_issuer(Some(ISSUER))
+        // Colons separate the issuer from the account so they can't appear in it
+        .with_account_name(username.replace(':', "_"))
+        .build()
+}
+
+/// Get the otpauth:// URI authenticator apps read from a QR code
+pub fn provisioning_uri(secret: &str, username: &str) -> Result<String, TotpError> {
+    totp(secret, username)?.to_url()
+}
+
+/// Check a code against a secret, returning the time step it was valid for
+pub fn check(secret: &str, code: &str) -> Result<Option<u64>, TotpError> {
+    Ok(totp(secret, "")?.check_current(code.trim()))
+}
+
+/// Generate a fresh set of recovery codes
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            OsRng.fill_bytes(&mut bytes);
+            let code = hex::encode(bytes);
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Normalize a typed recovery code so it can be hashed for lookup
+pub fn normalize_recovery_code(code: &str) -> String {
+    let code = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_lowercase();
+    if code.len() == 10 {
+        format!("{}-{}", &code[..5], &code[5..])
+    } else {
+        code
+    }
+}
@@ -0,0 +1,824 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore as _};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use p256::ecdsa::signature::Verifier as _;
+use reqwest::Url;
+use rsa::{BigUint, RsaPublicKey, pkcs1v15};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use time::{Duration, OffsetDateTime};
+
+use crate::cbor;
+
+/// The session key holding a passkey registration that is waiting for the authenticator
+pub const PENDING_REGISTRATION_KEY: &str = "webauthn.pending_registration";
+
+/// The session key holding a passkey login that is waiting for the authenticator
+pub const PENDING_AUTHENTICATION_KEY: &str = "webauthn.pending_authentication";
+
+/// The longest credential id accepted, which is plenty for every authenticator in use
+pub const CREDENTIAL_ID_MAX_LENGTH: usize = 255;
+
+/// How long the user has to answer their authenticator
+const CEREMONY_LIFETIME: Duration = Duration::minutes(5);
+
+/// The COSE algorithms passkeys may sign with, in order of preference
+const ES256: i128 = -7;
+const RS256: i128 = -257;
+
+/// Bits of the authenticator data flags
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// The site passkeys are registered with, taken from its public URL
+#[derive(Debug, Clone)]
+pub struct RelyingParty {
+    pub id: String,
+    pub origin: String,
+    pub name: String,
+}
+
+/// A registration or login waiting for the authenticator, kept in the session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingCeremony {
+    challenge: String,
+    /// The user the passkey has to belong to, if it's known beforehand
+    pub user_id: Option<i64>,
+    user_verification_required: bool,
+    expires_at: i64,
+}
+
+/// The options `navigator.credentials.create()` is called with, binary fields in base64url
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreationOptions {
+    rp: RelyingPartyEntity,
+    user: UserEntity,
+    challenge: String,
+    pub_key_cred_params: Vec<CredentialParameters>,
+    timeout: i64,
+    exclude_credentials: Vec<CredentialDescriptor>,
+    authenticator_selection: AuthenticatorSelection,
+    attestation: &'static str,
+}
+
+/// The options `navigator.credentials.get()` is called with, binary fields in base64url
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+    challenge: String,
+    timeout: i64,
+    rp_id: String,
+    allow_credentials: Vec<CredentialDescriptor>,
+    user_verification: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RelyingPartyEntity {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UserEntity {
+    id: String,
+    name: String,
+    display_name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CredentialParameters {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    alg: i128,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuthenticatorSelection {
+    resident_key: &'static str,
+    user_verification: &'static str,
+}
+
+/// The browser's record of what it asked the authenticator
+#[derive(Debug, Clone, Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+    /// Whether the site was framed by another one, which browsers only say when it was
+    #[serde(default, rename = "crossOrigin")]
+    cross_origin: bool,
+    /// The site framing this one, if any
+    #[serde(rename = "topOrigin")]
+    top_origin: Option<String>,
+}
+
+/// A passkey the authenticator just made, ready to be stored
+#[derive(Debug, Clone)]
+pub struct NewCredential {
+    /// The credential id in base64url
+    pub id: String,
+    /// The COSE public key in base64url
+    pub public_key: String,
+    pub sign_count: u32,
+}
+
+/// What an authenticator answered a login challenge with, in base64url like the browser gives it
+#[derive(Debug, Clone, Copy)]
+pub struct Assertion<'a> {
+    pub credential_id: &'a str,
+    pub client_data_json: &'a str,
+    pub authenticator_data: &'a str,
+    pub signature: &'a str,
+    pub user_handle: Option<&'a str>,
+}
+
+/// The authenticator data both ceremonies sign
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    sign_count: u32,
+    /// The new credential's id and COSE public key, only sent when registering
+    attested_credential: Option<(&'a [u8], &'a [u8])>,
+}
+
+/// A public key a passkey signs with
+enum PublicKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    Rs256(pkcs1v15::VerifyingKey<Sha256>),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidUrl(String),
+    InvalidEncoding,
+    InvalidClientData,
+    InvalidAuthenticatorData,
+    Cbor(cbor::Error),
+    Expired,
+    WrongCeremony,
+    WrongChallenge,
+    WrongOrigin,
+    CrossOrigin,
+    WrongRelyingParty,
+    UserNotPresent,
+    UserNotVerified,
+    CredentialMismatch,
+    CredentialTooLong,
+    UnsupportedKey,
+    InvalidSignature,
+    SignCountWentBack,
+}
+
+impl From<cbor::Error> for Error {
+    fn from(err: cbor::Error) -> Self {
+        Error::Cbor(err)
+    }
+}
+
+impl From<base64::DecodeError> for Error {
+    fn from(_: base64::DecodeError) -> Self {
+        Error::InvalidEncoding
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidUrl(err) => write!(f, "Invalid URL: {}", err),
+            Error::InvalidEncoding => write!(f, "Passkey data is not valid base64url"),
+            Error::InvalidClientData => write!(f, "Invalid client data"),
+            Error::InvalidAuthenticatorData => write!(f, "Invalid authenticator data"),
+            Error::Cbor(err) => write!(f, "CBOR Error: {}", err),
+            Error::Expired => write!(f, "The passkey request expired, please try again"),
+            Error::WrongCeremony => write!(f, "The passkey answered a different request"),
+            Error::WrongChallenge => write!(f, "The passkey answered a different challenge"),
+            Error::WrongOrigin => write!(f, "The passkey was used on a different site"),
+            Error::CrossOrigin => write!(f, "The passkey was used inside a different site"),
+            Error::WrongRelyingParty => write!(f, "The passkey belongs to a different site"),
+            Error::UserNotPresent => write!(f, "The authenticator didn't check the user was there"),
+            Error::UserNotVerified => write!(f, "The authenticator didn't verify the user"),
+            Error::CredentialMismatch => write!(f, "The passkey's id doesn't match"),
+            Error::CredentialTooLong => write!(f, "The passkey's id is too long"),
+            Error::UnsupportedKey => write!(f, "The passkey uses an unsupported key type"),
+            Error::InvalidSignature => write!(f, "The passkey's signature is invalid"),
+            Error::SignCountWentBack => write!(f, "The passkey may have been cloned"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Cbor(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl PendingCeremony {
+    fn new(user_id: Option<i64>, user_verification_required: bool) -> Self {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        Self {
+            challenge: URL_SAFE_NO_PAD.encode(bytes),
+            user_id,
+            user_verification_required,
+            expires_at: (OffsetDateTime::now_utc() + CEREMONY_LIFETIME).unix_timestamp(),
+        }
+    }
+
+    /// Check whether the user waited too long to answer their authenticator
+    pub fn is_expired(&self) -> bool {
+        OffsetDateTime::now_utc().unix_timestamp() >= self.expires_at
+    }
+}
+
+impl RelyingParty {
+    /// Bind passkeys to the host of the site's public URL
+    pub fn from_public_url(public_url: &str, name: &str) -> Result<Self, Error> {
+        let url = Url::parse(public_url).map_err(|err| Error::InvalidUrl(err.to_string()))?;
+        let id = url
+            .host_str()
+            .ok_or_else(|| Error::InvalidUrl("The public URL has no host".to_string()))?
+            .to_string();
+        Ok(Self {
+            id,
+            origin: url.origin().ascii_serialization(),
+            name: name.to_string(),
+        })
+    }
+
+    /// Start registering a new passkey for a user, leaving out the authenticators they already
+    /// registered
+    pub fn start_registration(
+        &self,
+        user_id: i64,
+        username: &str,
+        existing_credential_ids: impl IntoIterator<Item = String>,
+    ) -> (CreationOptions, PendingCeremony) {
+        let pending = PendingCeremony::new(Some(user_id), false);
+        let options = CreationOptions {
+            rp: RelyingPartyEntity {
+                id: self.id.clone(),
+                name: self.name.clone(),
+            },
+            user: UserEntity {
+                id: user_handle(user_id),
+                name: username.to_string(),
+                display_name: username.to_string(),
+            },
+            challenge: pending.challenge.clone(),
+            pub_key_cred_params: [ES256, RS256]
+                .into_iter()
+                .map(|alg| CredentialParameters {
+                    kind: "public-key",
+                    alg,
+                })
+                .collect(),
+            timeout: CEREMONY_LIFETIME.whole_milliseconds() as i64,
+            exclude_credentials: credential_descriptors(existing_credential_ids),
+            // Discoverable passkeys can log in without a username, others only as a second factor
+            authenticator_selection: AuthenticatorSelection {
+                resident_key: "preferred",
+                user_verification: "preferred",
+            },
+            // Any authenticator is welcome, so there's no need to know its make
+            attestation: "none",
+        };
+        (options, pending)
+    }
+
+    /// Check the authenticator's answer to a registration and get the new passkey out of it
+    pub fn finish_registration(
+        &self,
+        pending: &PendingCeremony,
+        credential_id: &str,
+        client_data_json: &str,
+        attestation_object: &str,
+    ) -> Result<NewCredential, Error> {
+        if pending.is_expired() {
+            return Err(Error::Expired);
+        }
+        self.check_client_data(pending, "webauthn.create", client_data_json)?;
+
+        // Only the authenticator data matters, since attestation isn't asked for
+        let attestation_object = cbor::decode(&URL_SAFE_NO_PAD.decode(attestation_object)?)?;
+        let authenticator_data = attestation_object
+            .get(&cbor::Value::Text("authData".to_string()))
+            .and_then(cbor::Value::as_bytes)
+            .ok_or(Error::InvalidAuthenticatorData)?;
+        let authenticator_data = self.check_authenticator_data(pending, authenticator_data)?;
+
+        let (attested_id, public_key) = authenticator_data
+            .attested_credential
+            .ok_or(Error::InvalidAuthenticatorData)?;
+        if attested_id.len() > CREDENTIAL_ID_MAX_LENGTH {
+            return Err(Error::CredentialTooLong);
+        }
+        if URL_SAFE_NO_PAD.encode(attested_id) != credential_id {
+            return Err(Error::CredentialMismatch);
+        }
+        parse_public_key(public_key)?;
+
+        Ok(NewCredential {
+            id: credential_id.to_string(),
+            public_key: URL_SAFE_NO_PAD.encode(public_key),
+            sign_count: authenticator_data.sign_count,
+        })
+    }
+
+    /// Start a login with a passkey, either any of the ones on the device or one of a known user's
+    ///
+    /// Logging in with only a passkey needs the authenticator to verify the user with a PIN or
+    /// biometrics, while a passkey used after a password only has to be touched.
+    pub fn start_authentication(
+        &self,
+        user_id: Option<i64>,
+        allowed_credential_ids: impl IntoIterator<Item = String>,
+    ) -> (RequestOptions, PendingCeremony) {
+        let user_verification_required = user_id.is_none();
+        let pending = PendingCeremony::new(user_id, user_verification_required);
+        let options = RequestOptions {
+            challenge: pending.challenge.clone(),
+            timeout: CEREMONY_LIFETIME.whole_milliseconds() as i64,
+            rp_id: self.id.clone(),
+            allow_credentials: credential_descriptors(allowed_credential_ids),
+            user_verification: if user_verification_required {
+                "required"
+            } else {
+                "discouraged"
+            },
+        };
+        (options, pending)
+    }
+
+    /// Check the authenticator's answer to a login against the passkey's stored public key,
+    /// returning the passkey's new signature count
+    pub fn finish_authentication(
+        &self,
+        pending: &PendingCeremony,
+        assertion: Assertion<'_>,
+        public_key: &str,
+        stored_sign_count: u32,
+    ) -> Result<u32, Error> {
+        if pending.is_expired() {
+            return Err(Error::Expired);
+        }
+        let client_data_json =
+            self.check_client_data(pending, "webauthn.get", assertion.client_data_json)?;
+        let authenticator_data_bytes = URL_SAFE_NO_PAD.decode(assertion.authenticator_data)?;
+        let authenticator_data = self.check_authenticator_data(pending, &authenticator_data_bytes)?;
+
+        // The signature covers the authenticator data and a hash of the client data
+        let mut signed = authenticator_data_bytes.clone();
+        signed.extend_from_slice(&Sha256::digest(&client_data_json));
+        let signature = URL_SAFE_NO_PAD.decode(assertion.signature)?;
+        let public_key = parse_public_key(&URL_SAFE_NO_PAD.decode(public_key)?)?;
+        public_key.verify(&signed, &signature)?;
+
+        // Authenticators that count signatures never go back, unless the passkey was copied
+        let sign_count = authenticator_data.sign_count;
+        if (sign_count != 0 || stored_sign_count != 0) && sign_count <= stored_sign_count {
+            return Err(Error::SignCountWentBack);
+        }
+        Ok(sign_count)
+    }
+
+    /// Check the browser asked the authenticator what this site asked it to, returning the
+    /// decoded client data
+    fn check_client_data(
+        &self,
+        pending: &PendingCeremony,
+        kind: &str,
+        client_data_json: &str,
+    ) -> Result<Vec<u8>, Error> {
+        let client_data_json = URL_SAFE_NO_PAD.decode(client_data_json)?;
+        let client_data = serde_json::from_slice::<ClientData>(&client_data_json)
+            .map_err(|_| Error::InvalidClientData)?;
+        if client_data.kind != kind {
+            return Err(Error::WrongCeremony);
+        }
+        if client_data.challenge != pending.challenge {
+            return Err(Error::WrongChallenge);
+        }
+        if client_data.origin != self.origin {
+            return Err(Error::WrongOrigin);
+        }
+        if client_data.cross_origin || client_data.top_origin.is_some() {
+            return Err(Error::CrossOrigin);
+        }
+        Ok(client_data_json)
+    }
+
+    /// Check the authenticator data is for this site and the user did what was asked of them
+    fn check_authenticator_data<'a>(
+        &self,
+        pending: &PendingCeremony,
+        bytes: &'a [u8],
+    ) -> Result<AuthenticatorData<'a>, Error> {
+        let authenticator_data = parse_authenticator_data(bytes)?;
+        if authenticator_data.rp_id_hash != Sha256::digest(self.id.as_bytes()).as_slice() {
+            return Err(Error::WrongRelyingParty);
+        }
+        if authenticator_data.flags & FLAG_USER_PRESENT == 0 {
+            return Err(Error::UserNotPresent);
+        }
+        if pending.user_verification_required
+            && authenticator_data.flags & FLAG_USER_VERIFIED == 0
+        {
+            return Err(Error::UserNotVerified);
+        }
+        Ok(authenticator_data)
+    }
+}
+
+impl PublicKey {
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), Error> {
+        match self {
+            PublicKey::Es256(key) => {
+                let signature = p256::ecdsa::Signature::from_der(signature)
+                    .map_err(|_| Error::InvalidSignature)?;
+                key.verify(message, &signature)
+                    .map_err(|_| Error::InvalidSignature)
+            }
+            PublicKey::Rs256(key) => {
+                let signature = pkcs1v15::Signature::try_from(signature)
+                    .map_err(|_| Error::InvalidSignature)?;
+                key.verify(message, &signature)
+                    .map_err(|_| Error::InvalidSignature)
+            }
+        }
+    }
+}
+
+/// The id authenticators keep for a user, which is their account id rather than anything personal
+pub fn user_handle(user_id: i64) -> String {
+    URL_SAFE_NO_PAD.encode(user_id.to_be_bytes())
+}
+
+fn credential_descriptors(ids: impl IntoIterator<Item = String>) -> Vec<CredentialDescriptor> {
+    ids.into_iter()
+        .map(|id| CredentialDescriptor {
+            kind: "public-key",
+            id,
+        })
+        .collect()
+}
+
+/// Split authenticator data into its fields, along with the new credential if it has one
+fn parse_authenticator_data(bytes: &[u8]) -> Result<AuthenticatorData<'_>, Error> {
+    if bytes.len() < 37 {
+        return Err(Error::InvalidAuthenticatorData);
+    }
+    let flags = bytes[32];
+    let sign_count = u32::from_be_bytes([bytes[33], bytes[34], bytes[35], bytes[36]]);
+
+    // The new credential follows the authenticator's 16 byte model id
+    let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+        let id_length = bytes
+            .get(53..55)
+            .map(|length| usize::from(u16::from_be_bytes([length[0], length[1]])))
+            .ok_or(Error::InvalidAuthenticatorData)?;
+        let id = bytes
+            .get(55..55 + id_length)
+            .ok_or(Error::InvalidAuthenticatorData)?;
+        let rest = &bytes[55 + id_length..];
+        let (_, public_key_length) = cbor::decode_prefix(rest)?;
+        Some((id, &rest[..public_key_length]))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash: &bytes[..32],
+        flags,
+        sign_count,
+        attested_credential,
+    })
+}
+
+/// Read a COSE public key, refusing algorithms that weren't offered
+fn parse_public_key(bytes: &[u8]) -> Result<PublicKey, Error> {
+    let key = cbor::decode(bytes)?;
+    let field = |label: i128| key.get(&cbor::Value::Integer(label));
+    let bytes_field = |label: i128| {
+        field(label)
+            .and_then(cbor::Value::as_bytes)
+            .ok_or(Error::UnsupportedKey)
+    };
+    let key_type = field(1).and_then(cbor::Value::as_integer);
+    let algorithm = field(3).and_then(cbor::Value::as_integer);
+    match (key_type, algorithm) {
+        // An uncompressed P-256 point
+        (Some(2), Some(ES256)) => {
+            if field(-1).and_then(cbor::Value::as_integer) != Some(1) {
+                return Err(Error::UnsupportedKey);
+            }
+            let mut point = vec![0x04];
+            point.extend_from_slice(bytes_field(-2)?);
+            point.extend_from_slice(bytes_field(-3)?);
+            p256::ecdsa::VerifyingKey::from_sec1_bytes(&point)
+                .map(PublicKey::Es256)
+                .map_err(|_| Error::UnsupportedKey)
+        }
+        // An RSA modulus and exponent
+        (Some(3), Some(RS256)) => RsaPublicKey::new(
+            BigUint::from_bytes_be(bytes_field(-1)?),
+            BigUint::from_bytes_be(bytes_field(-2)?),
+        )
+        .map(|key| PublicKey::Rs256(pkcs1v15::VerifyingKey::new(key)))
+        .map_err(|_| Error::UnsupportedKey),
+        _ => Err(Error::UnsupportedKey),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::ecdsa::{SigningKey, signature::Signer as _};
+
+    use super::*;
+
+    const PUBLIC_URL: &str = "https://connectia.example";
+
+    /// A software authenticator holding one passkey
+    struct Authenticator {
+        key: SigningKey,
+        credential_id: Vec<u8>,
+        rp_id: String,
+        origin: String,
+        /// Extra client data, like what a browser adds inside a frame
+        framing: serde_json::Map<String, serde_json::Value>,
+    }
+
+    /// Ways to tamper with an answer before it's sent
+    #[derive(Default)]
+    struct Tampering {
+        challenge: Option<String>,
+        flags: Option<u8>,
+        flip_signature: bool,
+    }
+
+    impl Authenticator {
+        fn new() -> Self {
+            Self {
+                key: SigningKey::random(&mut OsRng),
+                credential_id: vec![7; 16],
+                rp_id: "connectia.example".to_string(),
+                origin: PUBLIC_URL.to_string(),
+                framing: serde_json::Map::new(),
+            }
+        }
+
+        fn client_data(&self, kind: &str, challenge: &str) -> String {
+            let mut client_data = serde_json::json!({
+                "type": kind,
+                "challenge": challenge,
+                "origin": self.origin,
+            });
+            client_data
+                .as_object_mut()
+                .unwrap()
+                .extend(self.framing.clone());
+            URL_SAFE_NO_PAD.encode(client_data.to_string())
+        }
+
+        fn authenticator_data(&self, flags: u8, sign_count: u32, attested: bool) -> Vec<u8> {
+            let mut data = Sha256::digest(self.rp_id.as_bytes()).to_vec();
+            data.push(flags);
+            data.extend_from_slice(&sign_count.to_be_bytes());
+            if attested {
+                let point = self.key.verifying_key().to_encoded_point(false);
+                data.extend_from_slice(&[0; 16]);
+                data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+                data.extend_from_slice(&self.credential_id);
+                // {1: 2, 3: -7, -1: 1, -2: x, -3: y}
+                data.extend_from_slice(&[0xa5, 0x01, 0x02, 0x03, 0x26, 0x20, 0x01]);
+                data.extend_from_slice(&[0x21, 0x58, 0x20]);
+                data.extend_from_slice(point.x().unwrap());
+                data.extend_from_slice(&[0x22, 0x58, 0x20]);
+                data.extend_from_slice(point.y().unwrap());
+            }
+            data
+        }
+
+        fn register(
+            &self,
+            relying_party: &RelyingParty,
+            pending: &PendingCeremony,
+            challenge: &str,
+        ) -> Result<NewCredential, Error> {
+            let authenticator_data = self.authenticator_data(
+                FLAG_USER_PRESENT | FLAG_USER_VERIFIED | FLAG_ATTESTED_CREDENTIAL,
+                0,
+                true,
+            );
+            // {"fmt": "none", "attStmt": {}, "authData": authenticator_data}
+            let mut attestation_object = vec![0xa3, 0x63];
+            attestation_object.extend_from_slice(b"fmt");
+            attestation_object.push(0x64);
+            attestation_object.extend_from_slice(b"none");
+            attestation_object.push(0x67);
+            attestation_object.extend_from_slice(b"attStmt");
+            attestation_object.extend_from_slice(&[0xa0, 0x68]);
+            attestation_object.extend_from_slice(b"authData");
+            attestation_object.extend_from_slice(&[0x58, authenticator_data.len() as u8]);
+            attestation_object.extend_from_slice(&authenticator_data);
+            relying_party.finish_registration(
+                pending,
+                &URL_SAFE_NO_PAD.encode(&self.credential_id),
+                &self.client_data("webauthn.create", challenge),
+                &URL_SAFE_NO_PAD.encode(attestation_object),
+            )
+        }
+
+        fn log_in(
+            &self,
+            relying_party: &RelyingParty,
+            pending: &PendingCeremony,
+            credential: &NewCredential,
+            sign_count: u32,
+            stored_sign_count: u32,
+            tampering: Tampering,
+        ) -> Result<u32, Error> {
+            let challenge = tampering.challenge.unwrap_or_else(|| pending.challenge.clone());
+            let client_data_json = self.client_data("webauthn.get", &challenge);
+            let authenticator_data = self.authenticator_data(
+                tampering.flags.unwrap_or(FLAG_USER_PRESENT | FLAG_USER_VERIFIED),
+                sign_count,
+                false,
+            );
+            let mut signed = authenticator_data.clone();
+            let client_data = URL_SAFE_NO_PAD.decode(&client_data_json).unwrap();
+            signed.extend_from_slice(&Sha256::digest(client_data));
+            let signature: p256::ecdsa::Signature = self.key.sign(&signed);
+            let mut signature = signature.to_der().as_bytes().to_vec();
+            if tampering.flip_signature {
+                *signature.last_mut().unwrap() ^= 0x01;
+            }
+            relying_party.finish_authentication(
+                pending,
+                Assertion {
+                    credential_id: &credential.id,
+                    client_data_json: &client_data_json,
+                    authenticator_data: &URL_SAFE_NO_PAD.encode(authenticator_data),
+                    signature: &URL_SAFE_NO_PAD.encode(signature),
+                    user_handle: Some(&user_handle(1)),
+                },
+                &credential.public_key,
+                stored_sign_count,
+            )
+        }
+    }
+
+    /// Register a passkey, then start a login without a username
+    fn registered() -> (RelyingParty, Authenticator, NewCredential, PendingCeremony) {
+        let relying_party = RelyingParty::from_public_url(PUBLIC_URL, "ConnectIA").unwrap();
+        let authenticator = Authenticator::new();
+        let (_, pending) = relying_party.start_registration(1, "alice", []);
+        let credential = authenticator
+            .register(&relying_party, &pending, &pending.challenge)
+            .unwrap();
+        let (_, pending) = relying_party.start_authentication(None, []);
+        (relying_party, authenticator, credential, pending)
+    }
+
+    #[test]
+    fn registers_then_logs_in() {
+        let (relying_party, authenticator, credential, pending) = registered();
+        assert_eq!(credential.id, URL_SAFE_NO_PAD.encode(&authenticator.credential_id));
+        assert_eq!(credential.sign_count, 0);
+
+        let result = authenticator.log_in(
+            &relying_party,
+            &pending,
+            &credential,
+            1,
+            credential.sign_count,
+            Tampering::default(),
+        );
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[test]
+    fn rejects_wrong_challenge() {
+        let (relying_party, authenticator, credential, pending) = registered();
+        let (_, other) = relying_party.start_authentication(None, []);
+        let tampering = Tampering {
+            challenge: Some(other.challenge),
+            ..Default::default()
+        };
+        let result = authenticator.log_in(&relying_party, &pending, &credential, 1, 0, tampering);
+        assert!(matches!(result, Err(Error::WrongChallenge)));
+
+        // Registering checks it too
+        let (_, pending) = relying_party.start_registration(1, "alice", []);
+        let (_, other) = relying_party.start_registration(1, "alice", []);
+        let result = authenticator.register(&relying_party, &pending, &other.challenge);
+        assert!(matches!(result, Err(Error::WrongChallenge)));
+    }
+
+    #[test]
+    fn rejects_wrong_origin() {
+        let (relying_party, mut authenticator, credential, pending) = registered();
+        authenticator.origin = "https://connectia.example.evil".to_string();
+        let result =
+            authenticator.log_in(&relying_party, &pending, &credential, 1, 0, Tampering::default());
+        assert!(matches!(result, Err(Error::WrongOrigin)));
+    }
+
+    #[test]
+    fn rejects_use_inside_another_site() {
+        for (key, value) in [
+            ("crossOrigin", serde_json::json!(true)),
+            ("topOrigin", serde_json::json!("https://evil.example")),
+        ] {
+            let (relying_party, mut authenticator, credential, pending) = registered();
+            authenticator.framing.insert(key.to_string(), value);
+            let result = authenticator.log_in(
+                &relying_party,
+                &pending,
+                &credential,
+                1,
+                0,
+                Tampering::default(),
+            );
+            assert!(matches!(result, Err(Error::CrossOrigin)));
+        }
+
+        // Browsers may say it wasn't framed
+        let (relying_party, mut authenticator, credential, pending) = registered();
+        authenticator
+            .framing
+            .insert("crossOrigin".to_string(), serde_json::json!(false));
+        let result =
+            authenticator.log_in(&relying_party, &pending, &credential, 1, 0, Tampering::default());
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn rejects_wrong_rp_id_hash() {
+        let (relying_party, mut authenticator, credential, pending) = registered();
+        authenticator.rp_id = "evil.example".to_string();
+        let result =
+            authenticator.log_in(&relying_party, &pending, &credential, 1, 0, Tampering::default());
+        assert!(matches!(result, Err(Error::WrongRelyingParty)));
+    }
+
+    #[test]
+    fn rejects_sign_count_regression() {
+        let (relying_party, authenticator, credential, pending) = registered();
+        for sign_count in [4, 5] {
+            let result = authenticator.log_in(
+                &relying_party,
+                &pending,
+                &credential,
+                sign_count,
+                5,
+                Tampering::default(),
+            );
+            assert!(matches!(result, Err(Error::SignCountWentBack)));
+        }
+    }
+
+    #[test]
+    fn rejects_flipped_signature_byte() {
+        let (relying_party, authenticator, credential, pending) = registered();
+        let tampering = Tampering {
+            flip_signature: true,
+            ..Default::default()
+        };
+        let result = authenticator.log_in(&relying_party, &pending, &credential, 1, 0, tampering);
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+    }
+
+    #[test]
+    fn requires_user_verification_without_a_username() {
+        let (relying_party, authenticator, credential, pending) = registered();
+        let tampering = Tampering {
+            flags: Some(FLAG_USER_PRESENT),
+            ..Default::default()
+        };
+        let result = authenticator.log_in(&relying_party, &pending, &credential, 1, 0, tampering);
+        assert!(matches!(result, Err(Error::UserNotVerified)));
+
+        // A second factor only has to be touched
+        let (_, pending) = relying_party.start_authentication(Some(1), [credential.id.clone()]);
+        let tampering = Tampering {
+            flags: Some(FLAG_USER_PRESENT),
+            ..Default::default()
+        };
+        let result = authenticator.log_in(&relying_party, &pending, &credential, 1, 0, tampering);
+        assert_eq!(result.unwrap(), 1);
+    }
+}
@@ -0,0 +1,92 @@
+use std::rc::Rc;
+
+use wasm_bindgen_futures::spawn_local;
+use yew::{Callback, Html, MouseEvent, classes, function_component, html, use_effect_with, use_state};
+use yew_hooks::use_async;
+use yew_router::hooks::{use_location, use_navigator};
+
+use crate::app::{
+    Route,
+    utils::{get_current_user, post},
+};
+
+/// A banner shown on every page while an admin is viewing the site as another user
+#[function_component]
+pub(in crate::app) fn ImpersonationBanner() -> Html {
+    // Use stuff
+    let error_state = use_state(|| None::<String>);
+    let user_fetch = use_async(async { get_current_user().await.map_err(Rc::new) });
+    let navigator = use_navigator().expect("Navigator not found");
+    let path = use_location().map(|location| location.path().to_string());
+
+    // Fetch the current user whenever the page changes, since starting or stopping switches it
+    {
+        let user_fetch = user_fetch.clone();
+        use_effect_with(path, move |_| {
+            user_fetch.run();
+            || ()
+        })
+    }
+
+    // Create the stop handler, which goes back to the admin page as the admin
+    let on_stop = {
+        let error_state = error_state.clone();
+        let user_fetch = user_fetch.clone();
+        Callback::from(move |_: MouseEvent| {
+            let error_state = error_state.clone();
+            let user_fetch = user_fetch.clone();
+            let navigator = navigator.clone();
+            spawn_local(async move {
+                match post("/backend/impersonation/stop").send().await {
+                    Ok(response) => match response.status() {
+                        200 => {
+                            error_state.set(None);
+                            navigator.push(&Route::Admin);
+                        }
+                        400 | 401 => {
+                            error_state.set(None);
+                            user_fetch.run();
+                            navigator.push(&Route::Login);
+                        }
+                        500 => {
+                            error_state.set(Some("Internal server error".to_string()));
+                        }
+                        _ => {
+                            error_state.set(Some("Internal frontend error".to_string()));
+                        }
+                    },
+                    Err(err) => {
+                        error_state.set(Some(err.to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Only show the banner while impersonating
+    let Some(Some(user)) = &user_fetch.data else {
+        return html! {};
+    };
+    let Some(impersonator) = &user.impersonator else {
+        return html! {};
+    };
+    html! {
+        <div class={ classes!("sticky", "top-0", "z-10", "flex", "items-center", "justify-center", "gap-3", "px-3", "py-2", "bg-red-200", "border-b-3", "border-red-400") }>
+            <p>
+                { format!("{}, you are viewing the site as {}. Changes are blocked until you stop.", impersonator, user.username) }
+            </p>
+            {
+                if let Some(err) = &*error_state {
+                    html! {
+                        <p class={ classes!("text-red-700") }>{ err }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            <button class={ classes!("px-3", "py-1", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") } onclick={ on_stop }>
+                { "Stop viewing" }
+            </button>
+        </div>
+    }
+}
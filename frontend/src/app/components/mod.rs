@@ -1,3 +1,9 @@
+pub(in crate::app) use impersonation_banner::ImpersonationBanner;
+pub(in crate::app) use terms_interstitial::TermsInterstitial;
 pub(in crate::app) use title::Title;
+pub(in crate::app) use two_factor_redirect::TwoFactorRedirect;
 
-mod title;
\ No newline at end of file
+mod impersonation_banner;
+mod terms_interstitial;
+mod title;
+mod two_factor_redirect;
\ No newline at end of file
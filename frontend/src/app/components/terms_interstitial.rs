@@ -0,0 +1,167 @@
+use std::rc::Rc;
+
+use gloo_net::http::Request;
+use wasm_bindgen_futures::spawn_local;
+use yew::{Callback, Html, MouseEvent, classes, function_component, html, use_effect_with, use_state};
+use yew_hooks::use_async;
+use yew_router::{
+    components::Link,
+    hooks::{use_location, use_navigator, use_route},
+};
+
+use crate::{
+    app::{
+        Route,
+        utils::{get_current_user, post_json},
+    },
+    net::{bodies, responses},
+};
+
+/// A page covering the site until the logged in user agrees to the current terms of use
+#[function_component]
+pub(in crate::app) fn TermsInterstitial() -> Html {
+    // Use stuff
+    let error_state = use_state(|| None::<String>);
+    let user_fetch = use_async(async { get_current_user().await.map_err(Rc::new) });
+    let terms_fetch = use_async(async {
+        let response = Request::get("/backend/terms")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.ok() {
+            return Err(format!("Unexpected status code: {}", response.status()));
+        }
+        response
+            .json::<responses::TermsResponse>()
+            .await
+            .map_err(|err| err.to_string())
+    });
+    let navigator = use_navigator().expect("Navigator not found");
+    let route = use_route::<Route>();
+    let path = use_location().map(|location| location.path().to_string());
+
+    // Fetch the current user whenever the page changes, since logging in switches it
+    {
+        let user_fetch = user_fetch.clone();
+        use_effect_with(path, move |_| {
+            user_fetch.run();
+            || ()
+        })
+    }
+
+    // Users can still log out without agreeing, and admins viewing as them can't agree for them
+    let needs_terms = match &user_fetch.data {
+        Some(Some(user)) => {
+            !user.terms_accepted && user.impersonator.is_none() && route != Some(Route::Logout)
+        }
+        _ => false,
+    };
+
+    // Fetch the terms once they have to be shown
+    {
+        let terms_fetch = terms_fetch.clone();
+        use_effect_with(needs_terms, move |needs_terms| {
+            if *needs_terms {
+                terms_fetch.run();
+            }
+            || ()
+        })
+    }
+
+    // Create the accept handler, which agrees to the version that was shown
+    let on_accept = {
+        let error_state = error_state.clone();
+        let user_fetch = user_fetch.clone();
+        let terms_fetch = terms_fetch.clone();
+        Callback::from(move |_: MouseEvent| {
+            let Some(terms) = &terms_fetch.data else {
+                return;
+            };
+            let body = bodies::AcceptTermsBody {
+                version: terms.version.clone(),
+            };
+            let error_state = error_state.clone();
+            let user_fetch = user_fetch.clone();
+            let terms_fetch = terms_fetch.clone();
+            let navigator = navigator.clone();
+            spawn_local(async move {
+                match post_json("/backend/terms/accept", &body).await {
+                    Ok(response) => match response.status() {
+                        200 => {
+                            error_state.set(None);
+                            user_fetch.run();
+                        }
+                        401 => {
+                            error_state.set(None);
+                            user_fetch.run();
+                            navigator.push(&Route::Login);
+                        }
+                        409 => {
+                            error_state.set(Some(response.text().await.unwrap_or_default()));
+                            terms_fetch.run();
+                        }
+                        500 => {
+                            error_state.set(Some("Internal server error".to_string()));
+                        }
+                        _ => {
+                            error_state.set(Some("Internal frontend error".to_string()));
+                        }
+                    },
+                    Err(err) => {
+                        error_state.set(Some(err));
+                    }
+                }
+            });
+        })
+    };
+
+    // Only cover the site while the terms need agreeing to
+    if !needs_terms {
+        return html! {};
+    }
+    html! {
+        <div class={ classes!("fixed", "inset-0", "z-20", "overflow-y-auto", "bg-white") }>
+            <div class={ classes!("w-1/2", "mx-auto", "my-10") }>
+                <h2 class={ classes!("text-3xl", "mb-5") }>{ "Terms of Use" }</h2>
+                <p class={ classes!("mb-5") }>
+                    { "Please read and agree to the current terms of use to keep using ConnectIA." }
+                </p>
+                {
+                    if let Some(err) = &terms_fetch.error {
+                        html! {
+                            <p class={ classes!("text-red-500") }>{ format!("Error fetching the terms of use: {}", err) }</p>
+                        }
+                    } else if let Some(terms) = &terms_fetch.data {
+                        html! {
+                            <>
+                                <pre class={ classes!("mb-5", "p-3", "whitespace-pre-wrap", "font-sans", "border-3", "border-gray-300", "rounded") }>
+                                    { &terms.text }
+                                </pre>
+                                <p class={ classes!("mb-5", "text-gray-500") }>{ format!("Version {}", terms.version) }</p>
+                                {
+                                    if let Some(err) = &*error_state {
+                                        html! {
+                                            <p class={ classes!("mb-5", "text-red-500") }>{ err }</p>
+                                        }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                <div class={ classes!("flex", "items-center", "gap-5") }>
+                                    <button class={ classes!("px-3", "py-1", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") } onclick={ on_accept }>
+                                        { "I agree" }
+                                    </button>
+                                    <Link<Route> to={ Route::Logout } classes={ classes!("underline") }>{ "Log out instead" }</Link<Route>>
+                                </div>
+                            </>
+                        }
+                    } else {
+                        html! {
+                            <p>{ "Loading the terms of use..." }</p>
+                        }
+                    }
+                }
+            </div>
+        </div>
+    }
+}
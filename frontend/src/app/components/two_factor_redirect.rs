@@ -0,0 +1,46 @@
+use std::rc::Rc;
+
+use yew::{Html, function_component, html, use_effect_with};
+use yew_hooks::use_async;
+use yew_router::hooks::{use_location, use_navigator, use_route};
+
+use crate::app::{Route, utils::get_current_user};
+
+/// Send users whose role is waiting on a second factor to their settings to set one up
+#[function_component]
+pub(in crate::app) fn TwoFactorRedirect() -> Html {
+    // Use stuff
+    let user_fetch = use_async(async { get_current_user().await.map_err(Rc::new) });
+    let navigator = use_navigator().expect("Navigator not found");
+    let route = use_route::<Route>();
+    let path = use_location().map(|location| location.path().to_string());
+
+    // Fetch the current user whenever the page changes, since logging in switches it
+    {
+        let user_fetch = user_fetch.clone();
+        use_effect_with(path, move |_| {
+            user_fetch.run();
+            || ()
+        })
+    }
+
+    // Users can still log out without one, and admins viewing as them can't set one up for them
+    let needs_two_factor = match &user_fetch.data {
+        Some(Some(user)) => {
+            user.needs_two_factor()
+                && user.impersonator.is_none()
+                && !matches!(route, Some(Route::Settings | Route::Logout))
+        }
+        _ => false,
+    };
+
+    // Go to the settings, where the two-factor section explains why
+    use_effect_with(needs_two_factor, move |needs_two_factor| {
+        if *needs_two_factor {
+            navigator.push(&Route::Settings);
+        }
+        || ()
+    });
+
+    html! {}
+}
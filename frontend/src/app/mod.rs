@@ -1,4 +1,9 @@
-use pages::{AdminPage, ErrorPage, LandingPage, LoginPage, LogoutPage};
+use components::{ImpersonationBanner, TermsInterstitial, TwoFactorRedirect};
+use pages::{
+    AdminPage, ApiTokensPage, EditProfilePage, ErrorPage, ForgotPasswordPage, LandingPage,
+    LoginPage, LogoutPage, ProfilePage, RegisterPage, ResetPasswordPage, SessionsPage,
+    SettingsPage, VerifyEmailPage,
+};
 use serde::{Deserialize, Serialize};
 use yew::{Html, function_component, html};
 use yew_router::{BrowserRouter, Routable, Switch};
@@ -16,8 +21,26 @@ pub(self) enum Route {
     Login,
     #[at("/logout")]
     Logout,
+    #[at("/register")]
+    Register,
+    #[at("/verify-email")]
+    VerifyEmail,
+    #[at("/forgot-password")]
+    ForgotPassword,
+    #[at("/reset-password")]
+    ResetPassword,
     #[at("/admin")]
     Admin,
+    #[at("/settings")]
+    Settings,
+    #[at("/settings/api-tokens")]
+    ApiTokens,
+    #[at("/settings/sessions")]
+    Sessions,
+    #[at("/u/:username")]
+    Profile { username: String },
+    #[at("/u/:username/edit")]
+    EditProfile { username: String },
     #[not_found]
     #[at("/404")]
     NotFound,
@@ -34,9 +57,36 @@ fn switch(route: Route) -> Html {
         Route::Logout => html! {
             <LogoutPage />
         },
+        Route::Register => html! {
+            <RegisterPage />
+        },
+        Route::VerifyEmail => html! {
+            <VerifyEmailPage />
+        },
+        Route::ForgotPassword => html! {
+            <ForgotPasswordPage />
+        },
+        Route::ResetPassword => html! {
+            <ResetPasswordPage />
+        },
         Route::Admin => html! {
             <AdminPage />
         },
+        Route::Settings => html! {
+            <SettingsPage />
+        },
+        Route::ApiTokens => html! {
+            <ApiTokensPage />
+        },
+        Route::Sessions => html! {
+            <SessionsPage />
+        },
+        Route::Profile { username } => html! {
+            <ProfilePage username={ username } />
+        },
+        Route::EditProfile { username } => html! {
+            <EditProfilePage username={ username } />
+        },
         Route::NotFound => html! {
             <ErrorPage error_num={ 404 } error_message={ "Page not found" } />
         },
@@ -47,6 +97,9 @@ fn switch(route: Route) -> Html {
 pub fn App() -> Html {
     html! {
         <BrowserRouter>
+            <ImpersonationBanner />
+            <TermsInterstitial />
+            <TwoFactorRedirect />
             <Switch<Route> render={switch} />
         </BrowserRouter>
     }
@@ -1,13 +1,14 @@
 use std::rc::Rc;
 
-use gloo_net::http::Request;
-use wasm_bindgen_futures::spawn_local;
-use web_sys::HtmlInputElement;
-use yew::{classes, function_component, html, use_effect_with, use_state, Callback, Html, InputEvent, SubmitEvent, TargetCast as _};
+use gloo_net::http::{Request, Response};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::{classes, function_component, html, use_effect_with, use_state, Callback, Event, Html, InputEvent, SubmitEvent, TargetCast as _};
+use yew_autoprops::autoprops;
 use yew_hooks::{use_async, use_effect_once};
-use yew_router::hooks::use_navigator;
+use yew_router::{components::Link, hooks::use_navigator};
 
-use crate::{app::{components::Title, utils::get_current_user, Route}, net::bodies};
+use crate::{app::{components::Title, utils::{delete, get_current_user, patch_json, post, post_json}, Route}, net::{bodies, responses}};
 
 use super::LoginQuery;
 
@@ -16,7 +17,32 @@ pub(super) fn CreateUserForm() -> Html {
     // Use stuff
     let username_state = use_state(String::new);
     let password_state = use_state(String::new);
+    let role_state = use_state(|| "student".to_string());
+    let student_id_state = use_state(String::new);
     let error_state = use_state(|| None::<String>);
+    let success_state = use_state(|| None::<String>);
+    let roles_fetch = use_async(async {
+        let response = Request::get("/backend/roles")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.ok() {
+            return Err(format!("Unexpected status code: {}", response.status()));
+        }
+        response
+            .json::<Vec<responses::RoleResponse>>()
+            .await
+            .map_err(|err| err.to_string())
+    });
+
+    // Fetch the available roles
+    {
+        let roles_fetch = roles_fetch.clone();
+        use_effect_once(move || {
+            roles_fetch.run();
+            || ()
+        })
+    }
 
     // Create the username input handler
     let handle_username_input = {
@@ -36,12 +62,33 @@ pub(super) fn CreateUserForm() -> Html {
         })
     };
 
+    // Create the student ID input handler
+    let handle_student_id_input = {
+        let student_id_state = student_id_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            student_id_state.set(input.value());
+        })
+    };
+
+    // Create the role select handler
+    let handle_role_change = {
+        let role_state = role_state.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_dyn_into().unwrap();
+            role_state.set(select.value());
+        })
+    };
+
     // Create the on submit handler
     let on_submit = {
         // Clone stuff
         let username = (*username_state).clone();
         let password = (*password_state).clone();
+        let role = (*role_state).clone();
+        let student_id = (*student_id_state).clone();
         let error_state = error_state.clone();
+        let success_state = success_state.clone();
 
         // Create the callback
         Callback::from(move |e: SubmitEvent| {
@@ -49,17 +96,20 @@ pub(super) fn CreateUserForm() -> Html {
             e.prevent_default();
 
             // Clone stuff
-            let credentials = bodies::LoginBody {
+            let body = bodies::CreateUserBody {
                 username: username.clone(),
                 password: password.clone(),
+                role: role.clone(),
+                student_id: Some(student_id.trim().to_string()).filter(|student_id| !student_id.is_empty()),
             };
             let error_state = error_state.clone();
+            let success_state = success_state.clone();
 
             // Spawn the task
             spawn_local(async move {
-                // Serialize the credentials to json
-                let credentials = match serde_json::to_string(&credentials) {
-                    Ok(credentials) => credentials,
+                // Serialize the body to json
+                let body = match serde_json::to_string(&body) {
+                    Ok(body) => body,
                     Err(error) => {
                         error_state.set(Some(error.to_string()));
                         return;
@@ -67,9 +117,9 @@ pub(super) fn CreateUserForm() -> Html {
                 };
 
                 // Create a new request
-                let request = match Request::post("/backend/create_user")
+                let request = match post("/backend/create_user")
                     .header("Content-Type", "application/json")
-                    .body(credentials)
+                    .body(body)
                 {
                     Ok(request) => request,
                     Err(_) => {
@@ -87,20 +137,37 @@ pub(super) fn CreateUserForm() -> Html {
                     }
                 };
 
-                // If the response isn't ok then error
-                if !response.ok() {
-                    match response.status() {
-                        401 => {
-                            error_state.set(Some("Invalid credentials".to_string()));
+                // Do an action based on the response status
+                match response.status() {
+                    201 => match response.json::<responses::UserResponse>().await {
+                        Ok(user) => {
+                            error_state.set(None);
+                            success_state.set(Some(format!("Created user {}", user.username)));
                         }
-                        500 => {
-                            error_state.set(Some("Internal server error".to_string()));
-                        }
-                        _ => {
+                        Err(_) => {
                             error_state.set(Some("Internal frontend error".to_string()));
                         }
+                    },
+                    400 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Invalid user".to_string())),
+                    },
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    403 => {
+                        error_state.set(Some("You are not allowed to create this user".to_string()));
+                    }
+                    409 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Username is already taken".to_string())),
+                    },
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
                     }
-                    return;
                 }
             });
         })
@@ -129,11 +196,39 @@ pub(super) fn CreateUserForm() -> Html {
                     oninput={ handle_password_input }
                 />
             </div>
+            <div class={ classes!("mb-5") }>
+                <label for="role">{ "Role:" }</label>
+                <select
+                    id="role"
+                    class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    onchange={ handle_role_change }
+                >
+                    {
+                        for roles_fetch.data.iter().flatten().map(|role| html! {
+                            <option value={ role.name.clone() } selected={ role.name == *role_state }>{ &role.name }</option>
+                        })
+                    }
+                </select>
+            </div>
+            <div class={ classes!("mb-5") }>
+                <label for="student-id">{ "Student ID (optional, as verified with the school):" }</label>
+                <input
+                    id="student-id"
+                    class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    type="text"
+                    value={ (*student_id_state).clone() }
+                    oninput={ handle_student_id_input }
+                />
+            </div>
             {
                 if let Some(error) = &*error_state {
                     html! {
                         <p class={ classes!("text-red-500") }>{ error }</p>
                     }
+                } else if let Some(success) = &*success_state {
+                    html! {
+                        <p class={ classes!("text-green-700") }>{ success }</p>
+                    }
                 } else {
                     html! {}
                 }
@@ -147,61 +242,1705 @@ pub(super) fn CreateUserForm() -> Html {
     }
 }
 
+/// How many users the admin user table shows per page
+const USERS_PER_PAGE: u64 = 20;
+
+/// An action on a user that waits for the admin to confirm it
+#[derive(Debug, Clone, PartialEq)]
+enum UserAction {
+    ResetPassword { id: i64, username: String },
+    SetRole { id: i64, username: String, role: String },
+    Delete { id: i64, username: String },
+    Impersonate { id: i64, username: String },
+}
+
+impl UserAction {
+    /// Ask the admin whether they really want to do this
+    fn question(&self) -> String {
+        match self {
+            Self::ResetPassword { username, .. } => format!(
+                "Reset the password of {}? They will be signed out everywhere.",
+                username
+            ),
+            Self::SetRole { username, role, .. } if role == "admin" => {
+                format!("Make {} an admin?", username)
+            }
+            Self::SetRole { username, role, .. } if role.is_empty() => {
+                format!("Remove admin from {}? Choose their new role.", username)
+            }
+            Self::SetRole { username, role, .. } => {
+                format!("Remove admin from {}, making them a {}?", username, role)
+            }
+            Self::Delete { username, .. } => {
+                format!("Delete {}? This can't be undone.", username)
+            }
+            Self::Impersonate { username, .. } => format!(
+                "View the site as {}? Changes are blocked until you stop, and this is logged.",
+                username
+            ),
+        }
+    }
+}
+
+#[autoprops]
 #[function_component]
-pub(in crate::app) fn AdminPage() -> Html {
+pub(super) fn UserTable(current_username: &String, can_impersonate: bool) -> Html {
     // Use stuff
-    let user_fetch = use_async(async { get_current_user().await.map_err(|err| Rc::new(err)) });
     let navigator = use_navigator().expect("Navigator not found");
+    let search_state = use_state(String::new);
+    let query_state = use_state(|| (String::new(), 1u64));
+    let pending_state = use_state(|| None::<UserAction>);
+    let notice_state = use_state(|| None::<String>);
+    let error_state = use_state(|| None::<String>);
+    let roles_fetch = use_async(async {
+        let response = Request::get("/backend/roles")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.ok() {
+            return Err(format!("Unexpected status code: {}", response.status()));
+        }
+        response
+            .json::<Vec<responses::RoleResponse>>()
+            .await
+            .map_err(|err| err.to_string())
+    });
+    let users_fetch = {
+        let (search, page) = (*query_state).clone();
+        use_async(async move {
+            let response = Request::get(&format!(
+                "/backend/admin/users?sort=username&per_page={}&page={}&search={}",
+                USERS_PER_PAGE,
+                page,
+                urlencoding::encode(&search)
+            ))
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+            if !response.ok() {
+                return Err(format!("Unexpected status code: {}", response.status()));
+            }
+            response
+                .json::<responses::UserPageResponse>()
+                .await
+                .map_err(|err| err.to_string())
+        })
+    };
 
-    // Fetch the current user
+    // Fetch the roles users can be moved to
     {
-        let user_fetch = user_fetch.clone();
+        let roles_fetch = roles_fetch.clone();
         use_effect_once(move || {
-            user_fetch.run();
+            roles_fetch.run();
             || ()
         })
     }
 
-    // Effect to redirect if user is not logged in
+    // Fetch the users whenever the search or page changes
     {
-        let user_fetch = user_fetch.clone();
-        let navigator = navigator.clone();
-        use_effect_with(user_fetch, move |user_fetch| {
-            if let Some(data) = &user_fetch.data {
-                if data.is_none() {
-                    let navigation_result = navigator.push_with_query(
-                        &Route::Login,
-                        &LoginQuery {
-                            next: Some(Route::Admin),
-                        },
-                    );
-                    if let Err(_err) = navigation_result {}
-                }
-            }
+        let users_fetch = users_fetch.clone();
+        use_effect_with((*query_state).clone(), move |_| {
+            users_fetch.run();
             || ()
         })
     }
 
-    // Return html for this page
+    // Create the search input handler
+    let handle_search_input = {
+        let search_state = search_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            search_state.set(input.value());
+        })
+    };
+
+    // Create the search handler, which starts again from the first page
+    let on_search = {
+        let search_state = search_state.clone();
+        let query_state = query_state.clone();
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            query_state.set(((*search_state).clone(), 1));
+        })
+    };
+
+    // Create the page handler
+    let on_page = {
+        let query_state = query_state.clone();
+        Callback::from(move |page: u64| {
+            query_state.set((query_state.0.clone(), page));
+        })
+    };
+
+    // Create the handler asking for confirmation
+    let on_action = {
+        let pending_state = pending_state.clone();
+        let notice_state = notice_state.clone();
+        Callback::from(move |action: UserAction| {
+            notice_state.set(None);
+            pending_state.set(Some(action));
+        })
+    };
+
+    // Create the handler picking which role a removed admin gets
+    let handle_demote_role_change = {
+        let pending_state = pending_state.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_dyn_into().unwrap();
+            if let Some(UserAction::SetRole { id, username, .. }) = (*pending_state).clone() {
+                pending_state.set(Some(UserAction::SetRole { id, username, role: select.value() }));
+            }
+        })
+    };
+
+    // Create the cancel handler
+    let on_cancel = {
+        let pending_state = pending_state.clone();
+        Callback::from(move |_| pending_state.set(None))
+    };
+
+    // Create the confirm handler
+    let on_confirm = {
+        let pending_state = pending_state.clone();
+        let notice_state = notice_state.clone();
+        let error_state = error_state.clone();
+        let users_fetch = users_fetch.clone();
+        Callback::from(move |_| {
+            let Some(action) = (*pending_state).clone() else {
+                return;
+            };
+            pending_state.set(None);
+            let notice_state = notice_state.clone();
+            let error_state = error_state.clone();
+            let users_fetch = users_fetch.clone();
+            let navigator = navigator.clone();
+            spawn_local(async move {
+                // Send the request for the action
+                let response = match &action {
+                    UserAction::ResetPassword { id, .. } => {
+                        post_json(&format!("/backend/admin/users/{}/reset-password", id), &()).await
+                    }
+                    UserAction::SetRole { id, role, .. } => {
+                        let body = bodies::UpdateUserRoleBody { role: role.clone() };
+                        patch_json(&format!("/backend/admin/users/{}", id), &body).await
+                    }
+                    UserAction::Delete { id, .. } => {
+                        delete(&format!("/backend/admin/users/{}", id)).await
+                    }
+                    UserAction::Impersonate { id, .. } => {
+                        post_json(&format!("/backend/admin/users/{}/impersonate", id), &()).await
+                    }
+                };
+                let response = match response {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 | 204 => {
+                        error_state.set(None);
+                        match &action {
+                            UserAction::ResetPassword { username, .. } => {
+                                match response.json::<responses::TemporaryPasswordResponse>().await {
+                                    Ok(reset) => notice_state.set(Some(format!(
+                                        "The temporary password of {} is {}",
+                                        username, reset.password
+                                    ))),
+                                    Err(_) => {
+                                        error_state.set(Some("Internal frontend error".to_string()));
+                                    }
+                                }
+                            }
+                            UserAction::SetRole { username, role, .. } => {
+                                notice_state.set(Some(format!("{} is now a {}", username, role)));
+                            }
+                            UserAction::Delete { username, .. } => {
+                                notice_state.set(Some(format!("Deleted {}", username)));
+                            }
+                            UserAction::Impersonate { .. } => {
+                                navigator.push(&Route::Landing);
+                                return;
+                            }
+                        }
+                        users_fetch.run();
+                    }
+                    400 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Invalid request".to_string())),
+                    },
+                    403 => {
+                        error_state.set(Some("You are not allowed to change this user".to_string()));
+                    }
+                    404 => {
+                        error_state.set(Some("User not found".to_string()));
+                        users_fetch.run();
+                    }
+                    409 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Conflict".to_string())),
+                    },
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Return html for the table
+    let button_classes = classes!("px-2", "mr-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer");
     html! {
-        <>
-            <Title>{ "Admin" }</Title>
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "Users" }</h2>
+            <form onsubmit={ on_search } class={ classes!("flex", "mb-5") }>
+                <input
+                    class={ classes!("grow", "mr-2", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    type="search"
+                    placeholder="Search by username or email"
+                    value={ (*search_state).clone() }
+                    oninput={ handle_search_input }
+                />
+                <input
+                    type="submit"
+                    value="Search"
+                    class={ classes!("px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                />
+            </form>
             {
-                if user_fetch.loading {
+                if let Some(notice) = &*notice_state {
                     html! {
-                        <p>{ "Loading admin panel..." }</p>
+                        <p class={ classes!("text-green-700", "mb-5") }>{ notice }</p>
                     }
-                } else if let Some(err) = &user_fetch.error {
+                } else {
+                    html! {}
+                }
+            }
+            {
+                if let Some(error) = &*error_state {
                     html! {
-                        <p>{ format!("Error fetching the current user: {}", err.to_string()) }</p>
+                        <p class={ classes!("text-red-500", "mb-5") }>{ error }</p>
                     }
-                } else if let Some(data) = &user_fetch.data {
-                    if let Some(user) = data {
-                        if user.admin {
-                            html! {
-                            <div class={ classes!("w-1/2", "mx-auto") }>
-                                <CreateUserForm />
+                } else {
+                    html! {}
+                }
+            }
+            {
+                if let Some(err) = &users_fetch.error {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ format!("Error fetching users: {}", err) }</p>
+                    }
+                } else if let Some(user_page) = &users_fetch.data {
+                    if user_page.users.is_empty() {
+                        html! {
+                            <p>{ "No users found." }</p>
+                        }
+                    } else {
+                        html! {
+                            <>
+                                <table class={ classes!("w-full", "mb-5", "text-left") }>
+                                    <thead>
+                                        <tr>
+                                            <th>{ "Username" }</th>
+                                            <th>{ "Email" }</th>
+                                            <th>{ "Student ID" }</th>
+                                            <th>{ "Role" }</th>
+                                            <th>{ "Status" }</th>
+                                            <th>{ "Actions" }</th>
+                                        </tr>
+                                    </thead>
+                                    <tbody>
+                                        {
+                                            for user_page.users.iter().map(|user| {
+                                                let status = if !user.active {
+                                                    "Inactive"
+                                                } else if user.suspended {
+                                                    "Suspended"
+                                                } else {
+                                                    "Active"
+                                                };
+                                                let reset_action = UserAction::ResetPassword { id: user.id, username: user.username.clone() };
+                                                // Removing admin leaves the role empty until the dialog asks which one they get
+                                                let role_action = UserAction::SetRole {
+                                                    id: user.id,
+                                                    username: user.username.clone(),
+                                                    role: if user.role == "admin" { "" } else { "admin" }.to_string(),
+                                                };
+                                                let delete_action = UserAction::Delete { id: user.id, username: user.username.clone() };
+                                                let impersonate_action = UserAction::Impersonate { id: user.id, username: user.username.clone() };
+                                                let on_reset = { let on_action = on_action.clone(); move |_| on_action.emit(reset_action.clone()) };
+                                                let on_role = { let on_action = on_action.clone(); move |_| on_action.emit(role_action.clone()) };
+                                                let on_delete = { let on_action = on_action.clone(); move |_| on_action.emit(delete_action.clone()) };
+                                                let on_impersonate = { let on_action = on_action.clone(); move |_| on_action.emit(impersonate_action.clone()) };
+                                                html! {
+                                                    <tr>
+                                                        <td>{ &user.username }</td>
+                                                        <td>{ user.email.clone().unwrap_or_default() }</td>
+                                                        <td>{ user.student_id.clone().unwrap_or_default() }</td>
+                                                        <td>{ &user.role }</td>
+                                                        <td>{ status }</td>
+                                                        <td class={ classes!("py-1") }>
+                                                            {
+                                                                if user.username == *current_username {
+                                                                    html! { { "You" } }
+                                                                } else {
+                                                                    html! {
+                                                                        <>
+                                                                            <button class={ button_classes.clone() } onclick={ on_reset }>{ "Reset password" }</button>
+                                                                            <button class={ button_classes.clone() } onclick={ on_role }>
+                                                                                { if user.role == "admin" { "Remove admin" } else { "Make admin" } }
+                                                                            </button>
+                                                                            <button class={ button_classes.clone() } onclick={ on_delete }>{ "Delete" }</button>
+                                                                            {
+                                                                                if can_impersonate && user.active && !user.suspended {
+                                                                                    html! {
+                                                                                        <button class={ button_classes.clone() } onclick={ on_impersonate }>{ "View as" }</button>
+                                                                                    }
+                                                                                } else {
+                                                                                    html! {}
+                                                                                }
+                                                                            }
+                                                                        </>
+                                                                    }
+                                                                }
+                                                            }
+                                                        </td>
+                                                    </tr>
+                                                }
+                                            })
+                                        }
+                                    </tbody>
+                                </table>
+                                <div class={ classes!("flex", "items-center") }>
+                                    {
+                                        if user_page.page > 1 {
+                                            let on_page = on_page.clone();
+                                            let page = user_page.page - 1;
+                                            html! {
+                                                <button class={ button_classes.clone() } onclick={ move |_| on_page.emit(page) }>{ "Previous" }</button>
+                                            }
+                                        } else {
+                                            html! {}
+                                        }
+                                    }
+                                    <span class={ classes!("mr-2") }>{ format!("Page {} of {} ({} users)", user_page.page, user_page.pages, user_page.total) }</span>
+                                    {
+                                        if user_page.page < user_page.pages {
+                                            let on_page = on_page.clone();
+                                            let page = user_page.page + 1;
+                                            html! {
+                                                <button class={ button_classes.clone() } onclick={ move |_| on_page.emit(page) }>{ "Next" }</button>
+                                            }
+                                        } else {
+                                            html! {}
+                                        }
+                                    }
+                                </div>
+                            </>
+                        }
+                    }
+                } else {
+                    html! {
+                        <p>{ "Loading users..." }</p>
+                    }
+                }
+            }
+            {
+                if let Some(action) = &*pending_state {
+                    html! {
+                        <div class={ classes!("fixed", "inset-0", "flex", "items-center", "justify-center", "bg-black/50") }>
+                            <div class={ classes!("p-5", "rounded", "border-3", "border-gray-300", "bg-white") } role="dialog">
+                                <p class={ classes!("mb-5") }>{ action.question() }</p>
+                                {
+                                    match action {
+                                        UserAction::SetRole { role, .. } if role != "admin" => html! {
+                                            <div class={ classes!("mb-5") }>
+                                                <label for="demote-role">{ "New role:" }</label>
+                                                <select
+                                                    id="demote-role"
+                                                    class={ classes!("w-full", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                                                    onchange={ handle_demote_role_change }
+                                                >
+                                                    <option value="" disabled=true selected={ role.is_empty() }>{ "Choose a role" }</option>
+                                                    {
+                                                        for roles_fetch.data.iter().flatten().filter(|option| option.name != "admin").map(|option| html! {
+                                                            <option value={ option.name.clone() } selected={ option.name == *role }>{ &option.name }</option>
+                                                        })
+                                                    }
+                                                </select>
+                                            </div>
+                                        },
+                                        _ => html! {},
+                                    }
+                                }
+                                <button
+                                    class={ button_classes.clone() }
+                                    disabled={ matches!(action, UserAction::SetRole { role, .. } if role.is_empty()) }
+                                    onclick={ on_confirm }
+                                >
+                                    { "Confirm" }
+                                </button>
+                                <button class={ button_classes.clone() } onclick={ on_cancel }>{ "Cancel" }</button>
                             </div>
+                        </div>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+        </div>
+    }
+}
+
+/// Send a CSV roster to the backend to preview or apply it
+async fn send_roster(roster: String, apply: bool) -> Result<Response, String> {
+    post(&format!("/backend/admin/users/import?apply={}", apply))
+        .header("Content-Type", "text/csv")
+        .body(roster)
+        .map_err(|_| "Internal frontend error".to_string())?
+        .send()
+        .await
+        .map_err(|_| "Internal frontend error".to_string())
+}
+
+#[function_component]
+pub(super) fn RosterImport() -> Html {
+    // Use stuff
+    let roster_state = use_state(|| None::<String>);
+    let report_state = use_state(|| None::<responses::RosterReportResponse>);
+    let error_state = use_state(|| None::<String>);
+
+    // Create the handler sending the roster, only showing what would change unless applying it
+    let on_send = {
+        let roster_state = roster_state.clone();
+        let report_state = report_state.clone();
+        let error_state = error_state.clone();
+        Callback::from(move |(roster, apply): (String, bool)| {
+            let roster_state = roster_state.clone();
+            let report_state = report_state.clone();
+            let error_state = error_state.clone();
+            spawn_local(async move {
+                let response = match send_roster(roster.clone(), apply).await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 | 422 => match response.json::<responses::RosterReportResponse>().await {
+                        Ok(report) => {
+                            error_state.set(None);
+                            roster_state.set((!report.applied).then_some(roster));
+                            report_state.set(Some(report));
+                        }
+                        Err(_) => {
+                            error_state.set(Some("Internal frontend error".to_string()));
+                        }
+                    },
+                    400 | 409 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Invalid roster".to_string())),
+                    },
+                    403 => {
+                        error_state.set(Some("You are not allowed to import rosters".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Create the file handler, which previews the roster as soon as it is read
+    let handle_file_change = {
+        let report_state = report_state.clone();
+        let error_state = error_state.clone();
+        let on_send = on_send.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            let Some(file) = input.files().and_then(|files| files.get(0)) else {
+                return;
+            };
+            let report_state = report_state.clone();
+            let error_state = error_state.clone();
+            let on_send = on_send.clone();
+            spawn_local(async move {
+                match JsFuture::from(file.text()).await.ok().and_then(|text| text.as_string()) {
+                    Some(roster) => {
+                        report_state.set(None);
+                        on_send.emit((roster, false));
+                    }
+                    None => error_state.set(Some("Could not read the file".to_string())),
+                }
+            });
+        })
+    };
+
+    // Create the apply handler
+    let on_apply = {
+        let roster_state = roster_state.clone();
+        let on_send = on_send.clone();
+        Callback::from(move |_| {
+            if let Some(roster) = &*roster_state {
+                on_send.emit((roster.clone(), true));
+            }
+        })
+    };
+
+    // Return html for the upload and report
+    html! {
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "Import Roster" }</h2>
+            <p class={ classes!("mb-5") }>
+                { "Upload a CSV file with a header naming its username, email, grade, role and student_id columns. Nothing changes until you apply the preview." }
+            </p>
+            <input
+                class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                type="file"
+                accept=".csv,text/csv"
+                onchange={ handle_file_change }
+            />
+            {
+                if let Some(error) = &*error_state {
+                    html! {
+                        <p class={ classes!("text-red-500", "mb-5") }>{ error }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            {
+                if let Some(report) = &*report_state {
+                    let summary = format!(
+                        "{} {} created, {} updated, {} unchanged and {} invalid.",
+                        if report.applied { "Imported:" } else { "Preview:" },
+                        report.creates,
+                        report.updates,
+                        report.skips,
+                        report.invalid
+                    );
+                    html! {
+                        <>
+                            <p class={ classes!("mb-5") }>{ summary }</p>
+                            {
+                                if report.invalid > 0 {
+                                    html! {
+                                        <p class={ classes!("text-red-500", "mb-5") }>{ "Fix the invalid rows and upload the roster again." }</p>
+                                    }
+                                } else if !report.applied && report.creates + report.updates > 0 {
+                                    html! {
+                                        <button
+                                            class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                                            onclick={ on_apply }
+                                        >
+                                            { "Apply" }
+                                        </button>
+                                    }
+                                } else {
+                                    html! {}
+                                }
+                            }
+                            {
+                                if report.applied {
+                                    html! {
+                                        <p class={ classes!("mb-5") }>{ "Hand out the temporary passwords and invite links below now, they won't be shown again." }</p>
+                                    }
+                                } else {
+                                    html! {}
+                                }
+                            }
+                            <table class={ classes!("w-full", "mb-5", "text-left") }>
+                                <thead>
+                                    <tr>
+                                        <th>{ "Line" }</th>
+                                        <th>{ "Username" }</th>
+                                        <th>{ "Action" }</th>
+                                        <th>{ "Details" }</th>
+                                    </tr>
+                                </thead>
+                                <tbody>
+                                    {
+                                        for report.rows.iter().map(|row| {
+                                            let details = if let Some(error) = &row.error {
+                                                html! { <span class={ classes!("text-red-500") }>{ error }</span> }
+                                            } else if let Some(password) = &row.temporary_password {
+                                                html! { { format!("Temporary password: {}", password) } }
+                                            } else if let Some(link) = &row.invite_link {
+                                                html! { <a href={ link.clone() } class={ classes!("underline") }>{ "Invite link" }</a> }
+                                            } else {
+                                                html! { { row.changes.join(", ") } }
+                                            };
+                                            html! {
+                                                <tr>
+                                                    <td>{ row.line }</td>
+                                                    <td>{ &row.username }</td>
+                                                    <td>{ &row.action }</td>
+                                                    <td>{ details }</td>
+                                                </tr>
+                                            }
+                                        })
+                                    }
+                                </tbody>
+                            </table>
+                        </>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+        </div>
+    }
+}
+
+#[function_component]
+pub(super) fn LoginLocks() -> Html {
+    // Use stuff
+    let error_state = use_state(|| None::<String>);
+    let locks_fetch = use_async(async {
+        let response = Request::get("/backend/admin/login-locks")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.ok() {
+            return Err(format!("Unexpected status code: {}", response.status()));
+        }
+        response
+            .json::<Vec<responses::LoginLockResponse>>()
+            .await
+            .map_err(|err| err.to_string())
+    });
+
+    // Fetch the locked out logins
+    {
+        let locks_fetch = locks_fetch.clone();
+        use_effect_once(move || {
+            locks_fetch.run();
+            || ()
+        })
+    }
+
+    // Create the unlock handler
+    let on_unlock = {
+        let error_state = error_state.clone();
+        let locks_fetch = locks_fetch.clone();
+        Callback::from(move |id: i64| {
+            let error_state = error_state.clone();
+            let locks_fetch = locks_fetch.clone();
+            spawn_local(async move {
+                // Serialize the body to json
+                let body = match serde_json::to_string(&bodies::UnlockLoginBody { id }) {
+                    Ok(body) => body,
+                    Err(error) => {
+                        error_state.set(Some(error.to_string()));
+                        return;
+                    }
+                };
+
+                // Create a new request
+                let request = match post("/backend/admin/login-locks/unlock")
+                    .header("Content-Type", "application/json")
+                    .body(body)
+                {
+                    Ok(request) => request,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Send the request and get a response
+                let response = match request.send().await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 | 404 => {
+                        error_state.set(None);
+                        locks_fetch.run();
+                    }
+                    403 => {
+                        error_state.set(Some("You are not allowed to unlock logins".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Return html for the list
+    html! {
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "Locked Out Logins" }</h2>
+            {
+                if let Some(err) = &locks_fetch.error {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ format!("Error fetching locked out logins: {}", err) }</p>
+                    }
+                } else if let Some(locks) = &locks_fetch.data {
+                    if locks.is_empty() {
+                        html! {
+                            <p>{ "Nobody is locked out." }</p>
+                        }
+                    } else {
+                        html! {
+                            <ul>
+                                {
+                                    for locks.iter().map(|lock| {
+                                        let on_unlock = on_unlock.clone();
+                                        let id = lock.id;
+                                        let kind = if lock.scope == "ip" { "Address" } else { "Username" };
+                                        html! {
+                                            <li class={ classes!("mb-2") }>
+                                                { format!("{} {} after {} failed logins, {} minutes left ", kind, lock.identifier, lock.failures, (lock.remaining_seconds + 59) / 60) }
+                                                <button
+                                                    class={ classes!("px-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                                                    onclick={ move |_| on_unlock.emit(id) }
+                                                >
+                                                    { "Unlock" }
+                                                </button>
+                                            </li>
+                                        }
+                                    })
+                                }
+                            </ul>
+                        }
+                    }
+                } else {
+                    html! {
+                        <p>{ "Loading locked out logins..." }</p>
+                    }
+                }
+            }
+            {
+                if let Some(error) = &*error_state {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ error }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+        </div>
+    }
+}
+
+/// How many events the audit log shows per page
+const AUDIT_EVENTS_PER_PAGE: u64 = 50;
+
+/// Which audit events to show, with blank fields matching everything
+#[derive(Debug, Clone, Default, PartialEq)]
+struct AuditFilter {
+    action: String,
+    actor: String,
+    target: String,
+    from: String,
+    to: String,
+}
+
+impl AuditFilter {
+    /// Write the filter as a query string for the audit log endpoints
+    fn query(&self) -> String {
+        [
+            ("action", &self.action),
+            ("actor", &self.actor),
+            ("target", &self.target),
+            ("from", &self.from),
+            ("to", &self.to),
+        ]
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| format!("{}={}", name, urlencoding::encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+    }
+}
+
+#[function_component]
+pub(super) fn AuditLog() -> Html {
+    // Use stuff
+    let filter_state = use_state(AuditFilter::default);
+    let query_state = use_state(|| (AuditFilter::default(), 1u64));
+    let events_fetch = {
+        let (filter, page) = (*query_state).clone();
+        use_async(async move {
+            let response = Request::get(&format!(
+                "/backend/admin/audit-events?per_page={}&page={}&{}",
+                AUDIT_EVENTS_PER_PAGE,
+                page,
+                filter.query()
+            ))
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+            match response.status() {
+                200 => response
+                    .json::<responses::AuditPageResponse>()
+                    .await
+                    .map_err(|err| err.to_string()),
+                400 => Err(response.text().await.map_err(|err| err.to_string())?),
+                status => Err(format!("Unexpected status code: {}", status)),
+            }
+        })
+    };
+
+    // Fetch the events whenever the filter or page changes
+    {
+        let events_fetch = events_fetch.clone();
+        use_effect_with((*query_state).clone(), move |_| {
+            events_fetch.run();
+            || ()
+        })
+    }
+
+    // Create the input handlers, which only change the filter being edited
+    let handle_input = |set: fn(&mut AuditFilter, String)| {
+        let filter_state = filter_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            let mut filter = (*filter_state).clone();
+            set(&mut filter, input.value());
+            filter_state.set(filter);
+        })
+    };
+    let handle_actor_input = handle_input(|filter, value| filter.actor = value);
+    let handle_target_input = handle_input(|filter, value| filter.target = value);
+    let handle_from_input = handle_input(|filter, value| filter.from = value);
+    let handle_to_input = handle_input(|filter, value| filter.to = value);
+    let handle_action_change = {
+        let filter_state = filter_state.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_dyn_into().unwrap();
+            let mut filter = (*filter_state).clone();
+            filter.action = select.value();
+            filter_state.set(filter);
+        })
+    };
+
+    // Create the filter handler, which starts again from the first page
+    let on_filter = {
+        let filter_state = filter_state.clone();
+        let query_state = query_state.clone();
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            query_state.set(((*filter_state).clone(), 1));
+        })
+    };
+
+    // Create the page handler
+    let on_page = {
+        let query_state = query_state.clone();
+        Callback::from(move |page: u64| {
+            query_state.set((query_state.0.clone(), page));
+        })
+    };
+
+    // Return html for the filters and events
+    let input_classes = classes!("mr-2", "mb-2", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200");
+    let button_classes = classes!("px-3", "py-2", "mr-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer");
+    let actions = events_fetch
+        .data
+        .as_ref()
+        .map(|event_page| event_page.actions.clone())
+        .unwrap_or_default();
+    html! {
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "Audit Log" }</h2>
+            <form onsubmit={ on_filter } class={ classes!("flex", "flex-wrap", "items-center", "mb-3") }>
+                <select class={ input_classes.clone() } onchange={ handle_action_change }>
+                    <option value="" selected={ filter_state.action.is_empty() }>{ "Any action" }</option>
+                    {
+                        for actions.iter().map(|action| html! {
+                            <option value={ action.clone() } selected={ filter_state.action == *action }>{ action }</option>
+                        })
+                    }
+                </select>
+                <input class={ input_classes.clone() } type="text" placeholder="Actor" value={ filter_state.actor.clone() } oninput={ handle_actor_input } />
+                <input class={ input_classes.clone() } type="text" placeholder="Target" value={ filter_state.target.clone() } oninput={ handle_target_input } />
+                <label class={ classes!("mr-2", "mb-2") }>{ "From" }</label>
+                <input class={ input_classes.clone() } type="date" value={ filter_state.from.clone() } oninput={ handle_from_input } />
+                <label class={ classes!("mr-2", "mb-2") }>{ "To" }</label>
+                <input class={ input_classes.clone() } type="date" value={ filter_state.to.clone() } oninput={ handle_to_input } />
+                <input type="submit" value="Filter" class={ button_classes.clone() } />
+                <a
+                    class={ button_classes.clone() }
+                    href={ format!("/backend/admin/audit-events/export?{}", query_state.0.query()) }
+                    download="audit-events.csv"
+                >
+                    { "Export CSV" }
+                </a>
+            </form>
+            {
+                if let Some(err) = &events_fetch.error {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ format!("Error fetching the audit log: {}", err) }</p>
+                    }
+                } else if let Some(event_page) = &events_fetch.data {
+                    if event_page.events.is_empty() {
+                        html! {
+                            <p>{ "No events match." }</p>
+                        }
+                    } else {
+                        html! {
+                            <>
+                                <table class={ classes!("w-full", "mb-3", "text-left") }>
+                                    <thead>
+                                        <tr>
+                                            <th>{ "Time" }</th>
+                                            <th>{ "Action" }</th>
+                                            <th>{ "Actor" }</th>
+                                            <th>{ "Target" }</th>
+                                            <th>{ "Address" }</th>
+                                            <th>{ "Details" }</th>
+                                        </tr>
+                                    </thead>
+                                    <tbody>
+                                        {
+                                            for event_page.events.iter().map(|event| html! {
+                                                <tr key={ event.id }>
+                                                    <td>{ &event.created_at }</td>
+                                                    <td>{ &event.action }</td>
+                                                    <td>{ event.actor.clone().unwrap_or_default() }</td>
+                                                    <td>{ event.target.clone().unwrap_or_default() }</td>
+                                                    <td title={ event.user_agent.clone().unwrap_or_default() }>{ event.ip.clone().unwrap_or_default() }</td>
+                                                    <td>{ event.details.clone().unwrap_or_default() }</td>
+                                                </tr>
+                                            })
+                                        }
+                                    </tbody>
+                                </table>
+                                <div class={ classes!("flex", "items-center") }>
+                                    {
+                                        if event_page.page > 1 {
+                                            let on_page = on_page.clone();
+                                            let page = event_page.page - 1;
+                                            html! {
+                                                <button class={ button_classes.clone() } onclick={ move |_| on_page.emit(page) }>{ "Previous" }</button>
+                                            }
+                                        } else {
+                                            html! {}
+                                        }
+                                    }
+                                    <span class={ classes!("mr-2") }>{ format!("Page {} of {} ({} events)", event_page.page, event_page.pages, event_page.total) }</span>
+                                    {
+                                        if event_page.page < event_page.pages {
+                                            let on_page = on_page.clone();
+                                            let page = event_page.page + 1;
+                                            html! {
+                                                <button class={ button_classes.clone() } onclick={ move |_| on_page.emit(page) }>{ "Next" }</button>
+                                            }
+                                        } else {
+                                            html! {}
+                                        }
+                                    }
+                                </div>
+                            </>
+                        }
+                    }
+                } else {
+                    html! {
+                        <p>{ "Loading the audit log..." }</p>
+                    }
+                }
+            }
+        </div>
+    }
+}
+
+#[function_component]
+pub(super) fn ImpersonationLog() -> Html {
+    // Use stuff
+    let impersonations_fetch = use_async(async {
+        let response = Request::get("/backend/admin/impersonations")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.ok() {
+            return Err(format!("Unexpected status code: {}", response.status()));
+        }
+        response
+            .json::<Vec<responses::ImpersonationResponse>>()
+            .await
+            .map_err(|err| err.to_string())
+    });
+
+    // Fetch the recent impersonations
+    {
+        let impersonations_fetch = impersonations_fetch.clone();
+        use_effect_once(move || {
+            impersonations_fetch.run();
+            || ()
+        })
+    }
+
+    // Return html for the log
+    html! {
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "Viewed As Users" }</h2>
+            {
+                if let Some(err) = &impersonations_fetch.error {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ format!("Error fetching impersonations: {}", err) }</p>
+                    }
+                } else if let Some(impersonations) = &impersonations_fetch.data {
+                    if impersonations.is_empty() {
+                        html! {
+                            <p>{ "Nobody has viewed the site as another user." }</p>
+                        }
+                    } else {
+                        html! {
+                            <ul>
+                                {
+                                    for impersonations.iter().map(|impersonation| {
+                                        let admin = impersonation.admin.as_deref().unwrap_or("A deleted admin");
+                                        let user = impersonation.user.as_deref().unwrap_or("a deleted user");
+                                        let ended = match &impersonation.ended_at {
+                                            Some(ended_at) => format!("until {}", ended_at),
+                                            None => "and hasn't stopped".to_string(),
+                                        };
+                                        html! {
+                                            <li class={ classes!("mb-2") } key={ impersonation.id }>
+                                                { format!("{} viewed the site as {} from {} {}", admin, user, impersonation.started_at, ended) }
+                                            </li>
+                                        }
+                                    })
+                                }
+                            </ul>
+                        }
+                    }
+                } else {
+                    html! {
+                        <p>{ "Loading impersonations..." }</p>
+                    }
+                }
+            }
+        </div>
+    }
+}
+
+#[function_component]
+pub(super) fn DuplicateReport() -> Html {
+    // Use stuff
+    let candidates_fetch = use_async(async {
+        let response = Request::get("/backend/admin/users/duplicates")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.ok() {
+            return Err(format!("Unexpected status code: {}", response.status()));
+        }
+        response
+            .json::<Vec<responses::DuplicateCandidateResponse>>()
+            .await
+            .map_err(|err| err.to_string())
+    });
+
+    // Fetch the accounts that might belong to the same person
+    {
+        let candidates_fetch = candidates_fetch.clone();
+        use_effect_once(move || {
+            candidates_fetch.run();
+            || ()
+        })
+    }
+
+    // Return html for the report
+    html! {
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "Possible Duplicate Accounts" }</h2>
+            <p class={ classes!("mb-2") }>
+                { "Each person may only have one account. These accounts look alike, but only a student ID shows they belong to the same person." }
+            </p>
+            {
+                if let Some(err) = &candidates_fetch.error {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ format!("Error fetching possible duplicates: {}", err) }</p>
+                    }
+                } else if let Some(candidates) = &candidates_fetch.data {
+                    if candidates.is_empty() {
+                        html! {
+                            <p>{ "No accounts look alike." }</p>
+                        }
+                    } else {
+                        html! {
+                            <ul>
+                                {
+                                    for candidates.iter().map(|candidate| html! {
+                                        <li class={ classes!("mb-3") }>
+                                            <p class={ classes!("font-bold") }>{ &candidate.reason }</p>
+                                            <ul class={ classes!("ml-5", "list-disc") }>
+                                                {
+                                                    for candidate.users.iter().map(|user| {
+                                                        let mut details = Vec::new();
+                                                        if let Some(email) = &user.email {
+                                                            details.push(email.clone());
+                                                        }
+                                                        if let Some(student_id) = &user.student_id {
+                                                            details.push(format!("student ID {}", student_id));
+                                                        }
+                                                        if !user.active {
+                                                            details.push("inactive".to_string());
+                                                        }
+                                                        html! {
+                                                            <li key={ user.id }>
+                                                                {
+                                                                    if details.is_empty() {
+                                                                        user.username.clone()
+                                                                    } else {
+                                                                        format!("{} ({})", user.username, details.join(", "))
+                                                                    }
+                                                                }
+                                                            </li>
+                                                        }
+                                                    })
+                                                }
+                                            </ul>
+                                        </li>
+                                    })
+                                }
+                            </ul>
+                        }
+                    }
+                } else {
+                    html! {
+                        <p>{ "Loading possible duplicates..." }</p>
+                    }
+                }
+            }
+        </div>
+    }
+}
+
+#[function_component]
+pub(super) fn PendingTerms() -> Html {
+    // Use stuff
+    let pending_fetch = use_async(async {
+        let response = Request::get("/backend/admin/terms/pending")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.ok() {
+            return Err(format!("Unexpected status code: {}", response.status()));
+        }
+        response
+            .json::<Vec<responses::PendingTermsResponse>>()
+            .await
+            .map_err(|err| err.to_string())
+    });
+
+    // Fetch the users who haven't agreed to the terms
+    {
+        let pending_fetch = pending_fetch.clone();
+        use_effect_once(move || {
+            pending_fetch.run();
+            || ()
+        })
+    }
+
+    // Return html for the list
+    html! {
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "Terms of Use Not Accepted" }</h2>
+            {
+                if let Some(err) = &pending_fetch.error {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ format!("Error fetching users: {}", err) }</p>
+                    }
+                } else if let Some(pending) = &pending_fetch.data {
+                    if pending.is_empty() {
+                        html! {
+                            <p>{ "Every active user has agreed to the current terms of use." }</p>
+                        }
+                    } else {
+                        html! {
+                            <>
+                                <p class={ classes!("mb-2") }>
+                                    { format!("{} active users haven't agreed to the current terms of use.", pending.len()) }
+                                </p>
+                                <table class={ classes!("w-full", "text-left") }>
+                                    <thead>
+                                        <tr>
+                                            <th>{ "Username" }</th>
+                                            <th>{ "Email" }</th>
+                                            <th>{ "Last agreed to" }</th>
+                                        </tr>
+                                    </thead>
+                                    <tbody>
+                                        {
+                                            for pending.iter().map(|user| {
+                                                let accepted = match (&user.accepted_version, &user.accepted_at) {
+                                                    (Some(version), Some(accepted_at)) => format!("Version {} on {}", version, accepted_at),
+                                                    _ => "Never".to_string(),
+                                                };
+                                                html! {
+                                                    <tr key={ user.id }>
+                                                        <td>{ &user.username }</td>
+                                                        <td>{ user.email.as_deref().unwrap_or("") }</td>
+                                                        <td>{ accepted }</td>
+                                                    </tr>
+                                                }
+                                            })
+                                        }
+                                    </tbody>
+                                </table>
+                            </>
+                        }
+                    }
+                } else {
+                    html! {
+                        <p>{ "Loading users..." }</p>
+                    }
+                }
+            }
+        </div>
+    }
+}
+
+#[function_component]
+pub(super) fn Suspensions() -> Html {
+    // Use stuff
+    let username_state = use_state(String::new);
+    let kind_state = use_state(|| "suspended".to_string());
+    let reason_state = use_state(String::new);
+    let days_state = use_state(String::new);
+    let error_state = use_state(|| None::<String>);
+    let suspensions_fetch = use_async(async {
+        let response = Request::get("/backend/admin/suspensions")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.ok() {
+            return Err(format!("Unexpected status code: {}", response.status()));
+        }
+        response
+            .json::<Vec<responses::SuspensionResponse>>()
+            .await
+            .map_err(|err| err.to_string())
+    });
+
+    // Fetch the suspensions in force
+    {
+        let suspensions_fetch = suspensions_fetch.clone();
+        use_effect_once(move || {
+            suspensions_fetch.run();
+            || ()
+        })
+    }
+
+    // Create the username input handler
+    let handle_username_input = {
+        let username_state = username_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            username_state.set(input.value());
+        })
+    };
+
+    // Create the kind select handler
+    let handle_kind_change = {
+        let kind_state = kind_state.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_dyn_into().unwrap();
+            kind_state.set(select.value());
+        })
+    };
+
+    // Create the reason input handler
+    let handle_reason_input = {
+        let reason_state = reason_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            reason_state.set(input.value());
+        })
+    };
+
+    // Create the days input handler
+    let handle_days_input = {
+        let days_state = days_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            days_state.set(input.value());
+        })
+    };
+
+    // Create the on submit handler
+    let on_submit = {
+        // Clone stuff
+        let username_state = username_state.clone();
+        let kind = (*kind_state).clone();
+        let reason_state = reason_state.clone();
+        let days_state = days_state.clone();
+        let error_state = error_state.clone();
+        let suspensions_fetch = suspensions_fetch.clone();
+
+        // Create the callback
+        Callback::from(move |e: SubmitEvent| {
+            // Prevent the browser default form submission
+            e.prevent_default();
+
+            // Leaving the days empty suspends until lifted
+            let days = match days_state.trim() {
+                "" => None,
+                days => match days.parse::<u32>() {
+                    Ok(days) => Some(days),
+                    Err(_) => {
+                        error_state.set(Some("Days must be a whole number".to_string()));
+                        return;
+                    }
+                },
+            };
+
+            // Clone stuff
+            let body = bodies::SuspendUserBody {
+                username: (*username_state).clone(),
+                kind: kind.clone(),
+                reason: (*reason_state).clone(),
+                days,
+            };
+            let username_state = username_state.clone();
+            let reason_state = reason_state.clone();
+            let days_state = days_state.clone();
+            let error_state = error_state.clone();
+            let suspensions_fetch = suspensions_fetch.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                let response = match post_json("/backend/admin/suspensions", &body).await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    201 => {
+                        error_state.set(None);
+                        username_state.set(String::new());
+                        reason_state.set(String::new());
+                        days_state.set(String::new());
+                        suspensions_fetch.run();
+                    }
+                    400 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Invalid suspension".to_string())),
+                    },
+                    403 => {
+                        error_state.set(Some("You are not allowed to suspend this user".to_string()));
+                    }
+                    404 => {
+                        error_state.set(Some("User not found".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Create the lift handler
+    let on_lift = {
+        let error_state = error_state.clone();
+        let suspensions_fetch = suspensions_fetch.clone();
+        Callback::from(move |id: i64| {
+            let error_state = error_state.clone();
+            let suspensions_fetch = suspensions_fetch.clone();
+            spawn_local(async move {
+                let body = bodies::LiftSuspensionBody { id };
+                let response = match post_json("/backend/admin/suspensions/lift", &body).await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 | 404 => {
+                        error_state.set(None);
+                        suspensions_fetch.run();
+                    }
+                    403 => {
+                        error_state.set(Some("You are not allowed to lift suspensions".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Return html for the form and list
+    html! {
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "Suspensions" }</h2>
+            <form onsubmit={ on_submit } novalidate=true>
+                <div class={ classes!("mb-5") }>
+                    <label for="suspend-username">{ "Username:" }</label>
+                    <input
+                        id="suspend-username"
+                        class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                        type="text"
+                        value={ (*username_state).clone() }
+                        oninput={ handle_username_input }
+                    />
+                </div>
+                <div class={ classes!("mb-5") }>
+                    <label for="suspend-kind">{ "Kind:" }</label>
+                    <select
+                        id="suspend-kind"
+                        class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                        onchange={ handle_kind_change }
+                    >
+                        <option value="suspended" selected={ *kind_state == "suspended" }>{ "Suspension" }</option>
+                        <option value="banned" selected={ *kind_state == "banned" }>{ "Ban" }</option>
+                    </select>
+                </div>
+                <div class={ classes!("mb-5") }>
+                    <label for="suspend-reason">{ "Reason:" }</label>
+                    <input
+                        id="suspend-reason"
+                        class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                        type="text"
+                        value={ (*reason_state).clone() }
+                        oninput={ handle_reason_input }
+                    />
+                </div>
+                <div class={ classes!("mb-5") }>
+                    <label for="suspend-days">{ "Days (leave empty until lifted):" }</label>
+                    <input
+                        id="suspend-days"
+                        class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                        type="number"
+                        min="1"
+                        value={ (*days_state).clone() }
+                        oninput={ handle_days_input }
+                    />
+                </div>
+                <input
+                    type="submit"
+                    value="Suspend"
+                    class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                />
+            </form>
+            {
+                if let Some(error) = &*error_state {
+                    html! {
+                        <p class={ classes!("text-red-500", "mb-5") }>{ error }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            {
+                if let Some(err) = &suspensions_fetch.error {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ format!("Error fetching suspensions: {}", err) }</p>
+                    }
+                } else if let Some(suspensions) = &suspensions_fetch.data {
+                    if suspensions.is_empty() {
+                        html! {
+                            <p>{ "Nobody is suspended." }</p>
+                        }
+                    } else {
+                        html! {
+                            <ul>
+                                {
+                                    for suspensions.iter().map(|suspension| {
+                                        let on_lift = on_lift.clone();
+                                        let id = suspension.id;
+                                        let until = suspension
+                                            .ends_on
+                                            .as_ref()
+                                            .map_or("until lifted".to_string(), |ends_on| format!("until {}", ends_on));
+                                        html! {
+                                            <li class={ classes!("mb-2") }>
+                                                { format!("{} {} on {} {}: {} ", suspension.username, suspension.kind, suspension.created_on, until, suspension.reason) }
+                                                <button
+                                                    class={ classes!("px-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                                                    onclick={ move |_| on_lift.emit(id) }
+                                                >
+                                                    { "Lift" }
+                                                </button>
+                                            </li>
+                                        }
+                                    })
+                                }
+                            </ul>
+                        }
+                    }
+                } else {
+                    html! {
+                        <p>{ "Loading suspensions..." }</p>
+                    }
+                }
+            }
+        </div>
+    }
+}
+
+#[function_component]
+pub(in crate::app) fn AdminPage() -> Html {
+    // Use stuff
+    let user_fetch = use_async(async { get_current_user().await.map_err(Rc::new) });
+    let navigator = use_navigator().expect("Navigator not found");
+
+    // Fetch the current user
+    {
+        let user_fetch = user_fetch.clone();
+        use_effect_once(move || {
+            user_fetch.run();
+            || ()
+        })
+    }
+
+    // Effect to redirect if user is not logged in
+    {
+        let user_fetch = user_fetch.clone();
+        let navigator = navigator.clone();
+        use_effect_with(user_fetch, move |user_fetch| {
+            if let Some(None) = &user_fetch.data {
+                let navigation_result = navigator.push_with_query(
+                    &Route::Login,
+                    &LoginQuery {
+                        next: Some(Route::Admin),
+                        ..Default::default()
+                    },
+                );
+                if let Err(_err) = navigation_result {}
+            }
+            || ()
+        })
+    }
+
+    // Return html for this page
+    html! {
+        <>
+            <Title>{ "Admin" }</Title>
+            {
+                if user_fetch.loading {
+                    html! {
+                        <p>{ "Loading admin panel..." }</p>
+                    }
+                } else if let Some(err) = &user_fetch.error {
+                    html! {
+                        <p>{ format!("Error fetching the current user: {}", err.to_string()) }</p>
+                    }
+                } else if let Some(data) = &user_fetch.data {
+                    if let Some(user) = data {
+                        if user.has_permission("admin.panel") {
+                            html! {
+                            <div class={ classes!("w-1/2", "mx-auto") }>
+                                <p class={ classes!("mb-5") }>{ format!("Signed in as {} ({})", user.username, user.role) }</p>
+                                {
+                                    if user.has_permission("users.create") {
+                                        html! { <CreateUserForm /> }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                {
+                                    if user.has_permission("users.manage") {
+                                        html! {
+                                            <UserTable
+                                                current_username={ user.username.clone() }
+                                                can_impersonate={ user.has_permission("users.impersonate") }
+                                            />
+                                        }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                {
+                                    if user.has_permission("users.manage") {
+                                        html! { <RosterImport /> }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                {
+                                    if user.has_permission("users.manage") {
+                                        html! { <DuplicateReport /> }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                {
+                                    if user.has_permission("users.manage") {
+                                        html! { <PendingTerms /> }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                {
+                                    if user.has_permission("users.manage") {
+                                        html! { <LoginLocks /> }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                {
+                                    if user.has_permission("users.moderate") {
+                                        html! { <Suspensions /> }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                {
+                                    if user.has_permission("users.impersonate") {
+                                        html! { <ImpersonationLog /> }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                {
+                                    if user.has_permission("audit.read") {
+                                        html! { <AuditLog /> }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                            </div>
+                            }
+                        } else if user.needs_two_factor() {
+                            html! {
+                                <p>
+                                    { "Your role requires two-factor authentication. " }
+                                    <Link<Route> to={ Route::Settings } classes={ classes!("underline") }>{ "Set it up in your settings" }</Link<Route>>
+                                    { " to use the admin panel." }
+                                </p>
                             }
                         } else {
                             html! {
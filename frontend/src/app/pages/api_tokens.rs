@@ -0,0 +1,395 @@
+use std::rc::Rc;
+
+use gloo_net::http::Request;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::{classes, function_component, html, use_effect_with, use_state, Callback, Event, Html, InputEvent, SubmitEvent, TargetCast as _};
+use yew_autoprops::autoprops;
+use yew_hooks::{use_async, use_effect_once};
+use yew_router::{components::Link, hooks::use_navigator};
+
+use crate::{app::{components::Title, utils::{get_current_user, post_json}, Route}, net::{bodies, responses}};
+
+use super::LoginQuery;
+
+/// How long new tokens can stay valid, in days
+const LIFETIMES: &[(u32, &str)] = &[(7, "7 days"), (30, "30 days"), (90, "90 days"), (365, "1 year")];
+
+#[autoprops]
+#[function_component]
+fn ApiTokens(can_admin: bool) -> Html {
+    // Use stuff
+    let name_state = use_state(String::new);
+    let read_profile_state = use_state(|| true);
+    let admin_state = use_state(|| false);
+    let lifetime_state = use_state(|| 30u32);
+    let new_token_state = use_state(|| None::<String>);
+    let error_state = use_state(|| None::<String>);
+    let tokens_fetch = use_async(async {
+        let response = Request::get("/backend/api-tokens")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.ok() {
+            return Err(format!("Unexpected status code: {}", response.status()));
+        }
+        response
+            .json::<Vec<responses::ApiTokenResponse>>()
+            .await
+            .map_err(|err| err.to_string())
+    });
+
+    // Fetch the existing tokens
+    {
+        let tokens_fetch = tokens_fetch.clone();
+        use_effect_once(move || {
+            tokens_fetch.run();
+            || ()
+        })
+    }
+
+    // Create the name input handler
+    let handle_name_input = {
+        let name_state = name_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            name_state.set(input.value());
+        })
+    };
+
+    // Create the scope checkbox handlers
+    let handle_read_profile_change = {
+        let read_profile_state = read_profile_state.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            read_profile_state.set(input.checked());
+        })
+    };
+    let handle_admin_change = {
+        let admin_state = admin_state.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            admin_state.set(input.checked());
+        })
+    };
+
+    // Create the lifetime select handler
+    let handle_lifetime_change = {
+        let lifetime_state = lifetime_state.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_dyn_into().unwrap();
+            if let Ok(days) = select.value().parse() {
+                lifetime_state.set(days);
+            }
+        })
+    };
+
+    // Create the on submit handler
+    let on_submit = {
+        // Clone stuff
+        let name = (*name_state).clone();
+        let mut scopes = Vec::new();
+        if *read_profile_state {
+            scopes.push("read-profile".to_string());
+        }
+        if *admin_state {
+            scopes.push("admin".to_string());
+        }
+        let expires_in_days = *lifetime_state;
+        let name_state = name_state.clone();
+        let new_token_state = new_token_state.clone();
+        let error_state = error_state.clone();
+        let tokens_fetch = tokens_fetch.clone();
+
+        // Create the callback
+        Callback::from(move |e: SubmitEvent| {
+            // Prevent the browser default form submission
+            e.prevent_default();
+
+            // Clone stuff
+            let body = bodies::CreateApiTokenBody {
+                name: name.clone(),
+                scopes: scopes.clone(),
+                expires_in_days,
+            };
+            let name_state = name_state.clone();
+            let new_token_state = new_token_state.clone();
+            let error_state = error_state.clone();
+            let tokens_fetch = tokens_fetch.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Send the request and get a response
+                let response = match post_json("/backend/api-tokens", &body).await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    201 => match response.json::<responses::NewApiTokenResponse>().await {
+                        Ok(new_token) => {
+                            error_state.set(None);
+                            name_state.set(String::new());
+                            new_token_state.set(Some(new_token.token));
+                            tokens_fetch.run();
+                        }
+                        Err(_) => {
+                            error_state.set(Some("Internal frontend error".to_string()));
+                        }
+                    },
+                    400 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Invalid token".to_string())),
+                    },
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Create the revoke handler
+    let on_revoke = {
+        let new_token_state = new_token_state.clone();
+        let error_state = error_state.clone();
+        let tokens_fetch = tokens_fetch.clone();
+        Callback::from(move |id: i64| {
+            let new_token_state = new_token_state.clone();
+            let error_state = error_state.clone();
+            let tokens_fetch = tokens_fetch.clone();
+            spawn_local(async move {
+                // Send the request and get a response
+                let body = bodies::RevokeApiTokenBody { id };
+                let response = match post_json("/backend/api-tokens/revoke", &body).await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 | 404 => {
+                        error_state.set(None);
+                        new_token_state.set(None);
+                        tokens_fetch.run();
+                    }
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Return html for the tokens
+    html! {
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "API Tokens" }</h2>
+            <p class={ classes!("mb-5") }>
+                { "Scripts can use a token by sending it in an " }
+                <code>{ "Authorization: Bearer <token>" }</code>
+                { " header." }
+            </p>
+            {
+                if let Some(err) = &tokens_fetch.error {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ format!("Error fetching your tokens: {}", err) }</p>
+                    }
+                } else if let Some(tokens) = &tokens_fetch.data {
+                    if tokens.is_empty() {
+                        html! {
+                            <p class={ classes!("mb-5") }>{ "You have no tokens." }</p>
+                        }
+                    } else {
+                        html! {
+                            <ul class={ classes!("mb-5") }>
+                                {
+                                    for tokens.iter().map(|token| {
+                                        let on_revoke = on_revoke.clone();
+                                        let id = token.id;
+                                        let last_used = token.last_used_on.clone().unwrap_or_else(|| "never".to_string());
+                                        html! {
+                                            <li class={ classes!("mb-2") }>
+                                                { format!("{} ({}), created {}, expires {}, last used {} ", token.name, token.scopes.join(", "), token.created_on, token.expires_on, last_used) }
+                                                <button
+                                                    class={ classes!("px-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                                                    onclick={ move |_| on_revoke.emit(id) }
+                                                >
+                                                    { "Revoke" }
+                                                </button>
+                                            </li>
+                                        }
+                                    })
+                                }
+                            </ul>
+                        }
+                    }
+                } else {
+                    html! {
+                        <p class={ classes!("mb-5") }>{ "Loading your tokens..." }</p>
+                    }
+                }
+            }
+            {
+                if let Some(token) = &*new_token_state {
+                    html! {
+                        <div class={ classes!("mb-5") }>
+                            <p>{ "Copy your new token now, it won't be shown again:" }</p>
+                            <code class={ classes!("break-all") }>{ token }</code>
+                        </div>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            <form onsubmit={ on_submit } novalidate=true>
+                <div class={ classes!("mb-5") }>
+                    <label for="token-name">{ "Name:" }</label>
+                    <input
+                        id="token-name"
+                        class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                        type="text"
+                        value={ (*name_state).clone() }
+                        oninput={ handle_name_input }
+                    />
+                </div>
+                <div class={ classes!("mb-5") }>
+                    <p>{ "Scopes:" }</p>
+                    <label>
+                        <input type="checkbox" checked={ *read_profile_state } onchange={ handle_read_profile_change } />
+                        { " Read your profile" }
+                    </label>
+                    {
+                        if can_admin {
+                            html! {
+                                <label class={ classes!("block") }>
+                                    <input type="checkbox" checked={ *admin_state } onchange={ handle_admin_change } />
+                                    { " Admin, everything your role allows" }
+                                </label>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                </div>
+                <div class={ classes!("mb-5") }>
+                    <label for="token-lifetime">{ "Expires after:" }</label>
+                    <select
+                        id="token-lifetime"
+                        class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                        onchange={ handle_lifetime_change }
+                    >
+                        {
+                            for LIFETIMES.iter().map(|(days, label)| html! {
+                                <option value={ days.to_string() } selected={ *days == *lifetime_state }>{ *label }</option>
+                            })
+                        }
+                    </select>
+                </div>
+                {
+                    if let Some(error) = &*error_state {
+                        html! {
+                            <p class={ classes!("text-red-500") }>{ error }</p>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                <input
+                    type="submit"
+                    value="Create Token"
+                    class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                />
+            </form>
+        </div>
+    }
+}
+
+#[function_component]
+pub(in crate::app) fn ApiTokensPage() -> Html {
+    // Use stuff
+    let user_fetch = use_async(async { get_current_user().await.map_err(Rc::new) });
+    let navigator = use_navigator().expect("Navigator not found");
+
+    // Fetch the current user
+    {
+        let user_fetch = user_fetch.clone();
+        use_effect_once(move || {
+            user_fetch.run();
+            || ()
+        })
+    }
+
+    // Effect to redirect if user is not logged in
+    {
+        let user_fetch = user_fetch.clone();
+        let navigator = navigator.clone();
+        use_effect_with(user_fetch, move |user_fetch| {
+            if let Some(None) = &user_fetch.data {
+                let navigation_result = navigator.push_with_query(
+                    &Route::Login,
+                    &LoginQuery {
+                        next: Some(Route::ApiTokens),
+                        ..Default::default()
+                    },
+                );
+                if let Err(_err) = navigation_result {}
+            }
+            || ()
+        })
+    }
+
+    // Return html for this page
+    html! {
+        <>
+            <Title>{ "API Tokens" }</Title>
+            {
+                if user_fetch.loading {
+                    html! {
+                        <p>{ "Loading your tokens..." }</p>
+                    }
+                } else if let Some(err) = &user_fetch.error {
+                    html! {
+                        <p>{ format!("Error fetching the current user: {}", err) }</p>
+                    }
+                } else if let Some(data) = &user_fetch.data {
+                    if let Some(user) = data {
+                        html! {
+                            <div class={ classes!("w-1/2", "mx-auto") }>
+                                <Link<Route> to={ Route::Settings } classes={ classes!("underline", "block", "mb-5") }>{ "Back to settings" }</Link<Route>>
+                                <ApiTokens can_admin={ user.has_permission("admin.panel") } />
+                            </div>
+                        }
+                    } else {
+                        html! {
+                            <p>{ "You are not logged in!" }</p>
+                        }
+                    }
+                } else {
+                    html! {
+                        <p>{ "Initializing..." }</p>
+                    }
+                }
+            }
+        </>
+    }
+}
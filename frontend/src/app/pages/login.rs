@@ -4,26 +4,327 @@ use gloo_net::http::Request;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen_futures::spawn_local;
 use web_sys::HtmlInputElement;
-use yew::{classes, function_component, html, use_state, Callback, Html, InputEvent, SubmitEvent, TargetCast as _};
+use yew::{classes, function_component, html, use_effect_with, use_state, Callback, Event, Html, InputEvent, MouseEvent, SubmitEvent, TargetCast as _};
 use yew_autoprops::autoprops;
 use yew_hooks::{use_async, use_effect_once};
-use yew_router::hooks::{use_location, use_navigator};
+use yew_router::{components::Link, hooks::{use_location, use_navigator}, Routable as _};
 
-use crate::{app::{components::Title, utils::get_current_user, Route}, net::bodies};
+use crate::{app::{components::Title, utils::{get_current_user, log_in_with_passkey, post}, Route}, net::{bodies, responses}};
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub(super)struct LoginQuery {
     #[serde(default)]
     pub next: Option<Route>,
+    #[serde(default)]
+    pub two_factor: bool,
+    #[serde(default)]
+    pub sso_error: Option<String>,
+}
+
+#[autoprops]
+#[function_component]
+fn TwoFactorForm(#[prop_or_default] next: &Option<Route>, on_expired: &Callback<()>) -> Html {
+    // Use stuff
+    let code_state = use_state(String::new);
+    let error_state = use_state(|| None::<String>);
+    let navigator = use_navigator().expect("Navigator not found");
+    let methods_fetch = use_async(async {
+        let response = Request::get("/backend/login/two-factor")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        match response.status() {
+            200 => response
+                .json::<responses::TwoFactorMethodsResponse>()
+                .await
+                .map(Some)
+                .map_err(|err| err.to_string()),
+            // The password step expired
+            400 => Ok(None),
+            code => Err(format!("Unexpected status code: {}", code)),
+        }
+    });
+
+    // Fetch which second factors the user has
+    {
+        let methods_fetch = methods_fetch.clone();
+        use_effect_once(move || {
+            methods_fetch.run();
+            || ()
+        })
+    }
+
+    // Effect to start over if the password step expired
+    {
+        let on_expired = on_expired.clone();
+        use_effect_with(methods_fetch.data.clone(), move |methods| {
+            if let Some(None) = methods {
+                on_expired.emit(());
+            }
+            || ()
+        })
+    }
+
+    // Create the code input handler
+    let handle_code_input = {
+        let code_state = code_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            code_state.set(input.value());
+        })
+    };
+
+    // Create the onsubmit handler
+    let on_submit = {
+        // Clone stuff
+        let code = (*code_state).clone();
+        let error_state = error_state.clone();
+        let navigator = navigator.clone();
+        let next = next.clone();
+        let on_expired = on_expired.clone();
+
+        // Create the callback
+        Callback::from(move |e: SubmitEvent| {
+            // Prevent browser default form submission
+            e.prevent_default();
+
+            // Clone stuff
+            let body = bodies::TwoFactorCodeBody { code: code.clone() };
+            let error_state = error_state.clone();
+            let navigator = navigator.clone();
+            let next = next.clone();
+            let on_expired = on_expired.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Serialize the body to json
+                let body = match serde_json::to_string(&body) {
+                    Ok(body) => body,
+                    Err(error) => {
+                        error_state.set(Some(error.to_string()));
+                        return;
+                    }
+                };
+
+                // Create a new request
+                let request = match post("/backend/login/two-factor")
+                    .header("Content-Type", "application/json")
+                    .body(body)
+                {
+                    Ok(request) => request,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Send the request and get a response
+                let response = match request.send().await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 => {
+                        error_state.set(None);
+                        if let Some(route) = next {
+                            navigator.push(&route);
+                        } else {
+                            navigator.push(&Route::Landing);
+                        }
+                    }
+                    400 => {
+                        // The password step expired, so start over
+                        on_expired.emit(());
+                    }
+                    401 => {
+                        error_state.set(Some("Invalid code".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Create the passkey handler
+    let on_passkey = {
+        // Clone stuff
+        let error_state = error_state.clone();
+        let navigator = navigator.clone();
+        let next = next.clone();
+        let on_expired = on_expired.clone();
+
+        // Create the callback
+        Callback::from(move |_: MouseEvent| {
+            // Clone stuff
+            let error_state = error_state.clone();
+            let navigator = navigator.clone();
+            let next = next.clone();
+            let on_expired = on_expired.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Answer a challenge with one of the user's passkeys
+                let response = match log_in_with_passkey(
+                    "/backend/login/two-factor/passkey/start",
+                    "/backend/login/two-factor/passkey/finish",
+                    false,
+                )
+                .await
+                {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 => {
+                        error_state.set(None);
+                        if let Some(route) = next {
+                            navigator.push(&route);
+                        } else {
+                            navigator.push(&Route::Landing);
+                        }
+                    }
+                    400 => {
+                        // The password step expired, so start over
+                        on_expired.emit(());
+                    }
+                    401 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Invalid passkey".to_string())),
+                    },
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Offer the second factors the user has, falling back to a code if that's unknown
+    let methods = methods_fetch.data.clone().flatten();
+    let show_code = methods.as_ref().is_none_or(|methods| methods.code);
+    let show_passkey = methods.as_ref().is_some_and(|methods| methods.passkey);
+
+    // Wait to know which second factors the user has
+    if methods_fetch.loading {
+        return html! {
+            <p>{ "Loading..." }</p>
+        };
+    }
+
+    // Return html for the form
+    html! {
+        <>
+            {
+                if show_passkey {
+                    html! {
+                        <button
+                            onclick={ on_passkey }
+                            class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                        >
+                            { "Use a passkey" }
+                        </button>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            {
+                if show_code {
+                    html! {
+                        <form onsubmit={ on_submit } novalidate=true>
+                            <div class={ classes!("mb-5") }>
+                                <label for="code">{ "Code from your authenticator app or a recovery code:" }</label>
+                                <input
+                                    id="code"
+                                    class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                                    type="text"
+                                    autocomplete="one-time-code"
+                                    value={ (*code_state).clone() }
+                                    oninput={ handle_code_input }
+                                />
+                            </div>
+                            <input
+                                type="submit"
+                                value="Verify"
+                                class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                            />
+                        </form>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            {
+                if let Some(error) = &*error_state {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ error }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+        </>
+    }
+}
+
+/// Describe how long a locked out login has to wait
+fn locked_message(retry_after: Option<u64>) -> String {
+    match retry_after {
+        Some(seconds) if seconds >= 120 => format!(
+            "Too many failed logins. Try again in {} minutes.",
+            seconds.div_ceil(60)
+        ),
+        Some(seconds) if seconds > 1 => {
+            format!("Too many failed logins. Try again in {} seconds.", seconds)
+        }
+        Some(_) => "Too many failed logins. Try again in a second.".to_string(),
+        None => "Too many failed logins. Try again later.".to_string(),
+    }
+}
+
+/// Explain to a suspended user why they can't log in and until when
+fn suspended_message(suspension: &responses::SuspendedResponse) -> String {
+    let state = if suspension.kind == "banned" {
+        "banned"
+    } else {
+        "suspended"
+    };
+    match &suspension.ends_on {
+        Some(ends_on) => format!(
+            "Your account is {} until {}: {}",
+            state, ends_on, suspension.reason
+        ),
+        None => format!("Your account is {}: {}", state, suspension.reason),
+    }
 }
 
 #[autoprops]
 #[function_component]
-fn LoginForm(#[prop_or_default] next: &Option<Route>) -> Html {
+fn LoginForm(#[prop_or_default] next: &Option<Route>, #[prop_or_default] two_factor: bool, #[prop_or_default] error: &Option<String>) -> Html {
     // Use stuff
     let username_state = use_state(String::new);
     let password_state = use_state(String::new);
-    let error_state = use_state(|| None::<String>);
+    let remember_state = use_state(|| false);
+    let error_state = use_state(|| error.clone());
+    let two_factor_state = use_state(|| two_factor);
     let navigator = use_navigator().expect("Navigator not found");
 
     // Create the username input handler
@@ -44,12 +345,23 @@ fn LoginForm(#[prop_or_default] next: &Option<Route>) -> Html {
         })
     };
 
+    // Create the remember me checkbox handler
+    let handle_remember_change = {
+        let remember_state = remember_state.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            remember_state.set(input.checked());
+        })
+    };
+
     // Create the onsubmit handler
     let on_submit = {
         // Clone stuff
         let username = (*username_state).clone();
         let password = (*password_state).clone();
+        let remember = *remember_state;
         let error_state = error_state.clone();
+        let two_factor_state = two_factor_state.clone();
         let navigator = navigator.clone();
         let next = next.clone();
 
@@ -62,8 +374,10 @@ fn LoginForm(#[prop_or_default] next: &Option<Route>) -> Html {
             let credentials = bodies::LoginBody {
                 username: username.clone(),
                 password: password.clone(),
+                remember,
             };
             let error_state = error_state.clone();
+            let two_factor_state = two_factor_state.clone();
             let navigator = navigator.clone();
             let next = next.clone();
 
@@ -79,7 +393,7 @@ fn LoginForm(#[prop_or_default] next: &Option<Route>) -> Html {
                 };
 
                 // Create a new request
-                let request = match Request::post("/backend/login")
+                let request = match post("/backend/login")
                     .header("Content-Type", "application/json")
                     .body(credentials)
                 {
@@ -109,9 +423,88 @@ fn LoginForm(#[prop_or_default] next: &Option<Route>) -> Html {
                             navigator.push(&Route::Landing);
                         }
                     }
+                    202 => {
+                        error_state.set(None);
+                        two_factor_state.set(true);
+                    }
                     401 => {
                         error_state.set(Some("Invalid credentials".to_string()));
                     }
+                    403 => match response.json::<responses::SuspendedResponse>().await {
+                        Ok(suspension) => error_state.set(Some(suspended_message(&suspension))),
+                        Err(_) => error_state.set(Some("Internal frontend error".to_string())),
+                    },
+                    429 => {
+                        let retry_after = response
+                            .headers()
+                            .get("Retry-After")
+                            .and_then(|seconds| seconds.parse::<u64>().ok());
+                        error_state.set(Some(locked_message(retry_after)));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Create the passkey login handler
+    let on_passkey = {
+        // Clone stuff
+        let remember = *remember_state;
+        let error_state = error_state.clone();
+        let navigator = navigator.clone();
+        let next = next.clone();
+
+        // Create the callback
+        Callback::from(move |_: MouseEvent| {
+            // Clone stuff
+            let error_state = error_state.clone();
+            let navigator = navigator.clone();
+            let next = next.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Answer a challenge with any passkey on this device
+                let response = match log_in_with_passkey(
+                    "/backend/login/passkey/start",
+                    "/backend/login/passkey/finish",
+                    remember,
+                )
+                .await
+                {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 => {
+                        error_state.set(None);
+                        if let Some(route) = next {
+                            navigator.push(&route);
+                        } else {
+                            navigator.push(&Route::Landing);
+                        }
+                    }
+                    400 => {
+                        error_state.set(Some("Your passkey login expired, please try again".to_string()));
+                    }
+                    401 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Invalid passkey".to_string())),
+                    },
+                    403 => match response.json::<responses::SuspendedResponse>().await {
+                        Ok(suspension) => error_state.set(Some(suspended_message(&suspension))),
+                        Err(_) => error_state.set(Some("Internal frontend error".to_string())),
+                    },
                     500 => {
                         error_state.set(Some("Internal server error".to_string()));
                     }
@@ -123,6 +516,21 @@ fn LoginForm(#[prop_or_default] next: &Option<Route>) -> Html {
         })
     };
 
+    // Ask for the second factor once the password was accepted
+    if *two_factor_state {
+        let on_expired = {
+            let error_state = error_state.clone();
+            let two_factor_state = two_factor_state.clone();
+            Callback::from(move |_| {
+                error_state.set(Some("Your login expired, please log in again".to_string()));
+                two_factor_state.set(false);
+            })
+        };
+        return html! {
+            <TwoFactorForm next={ next.clone() } on_expired={ on_expired } />
+        };
+    }
+
     // Return html for the form
     html! {
         <form onsubmit={ on_submit } novalidate=true>
@@ -146,6 +554,13 @@ fn LoginForm(#[prop_or_default] next: &Option<Route>) -> Html {
                     oninput={ handle_password_input }
                 />
             </div>
+            <div class={ classes!("mb-5") }>
+                <label>
+                    <input type="checkbox" checked={ *remember_state } onchange={ handle_remember_change } />
+                    { " Remember me on this device" }
+                </label>
+                <p class={ classes!("text-gray-500") }>{ "Leave this unchecked on shared computers, so closing the browser logs you out." }</p>
+            </div>
             {
                 if let Some(error) = &*error_state {
                     html! {
@@ -160,6 +575,13 @@ fn LoginForm(#[prop_or_default] next: &Option<Route>) -> Html {
                 value="Login"
                 class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
             />
+            <button
+                type="button"
+                onclick={ on_passkey }
+                class={ classes!("mb-5", "ml-2", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+            >
+                { "Login with a passkey" }
+            </button>
         </form>
     }
 }
@@ -169,25 +591,44 @@ fn LoginForm(#[prop_or_default] next: &Option<Route>) -> Html {
 pub(in crate::app) fn LoginPage() -> Html {
     // Use stuff
     let location = use_location();
-    let user_fetch = use_async(async { get_current_user().await.map_err(|err| Rc::new(err)) });
-
-    // Attempt to parse the next url
-    let next = location.and_then(|location| {
-        location
-            .query::<LoginQuery>()
-            .ok()
-            .and_then(|query| query.next)
+    let user_fetch = use_async(async { get_current_user().await.map_err(Rc::new) });
+    let oidc_fetch = use_async(async {
+        let response = Request::get("/backend/oidc")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.ok() {
+            return Err(format!("Unexpected status code: {}", response.status()));
+        }
+        response
+            .json::<responses::OidcResponse>()
+            .await
+            .map_err(|err| err.to_string())
     });
 
-    // Fetch the current user
+    // Attempt to parse the login query
+    let query = location
+        .and_then(|location| location.query::<LoginQuery>().ok())
+        .unwrap_or_default();
+    let next = query.next.clone();
+
+    // Fetch the current user and whether single sign-on is available
     {
         let user_fetch = user_fetch.clone();
+        let oidc_fetch = oidc_fetch.clone();
         use_effect_once(move || {
             user_fetch.run();
+            oidc_fetch.run();
             || ()
         })
     }
 
+    // Create the link that starts single sign-on and comes back to the next page
+    let sso_href = match &next {
+        Some(route) => format!("/backend/oidc/login?next={}", urlencoding::encode(&route.to_path())),
+        None => "/backend/oidc/login".to_string(),
+    };
+
     html! {
         <>
             <Title>{ "Login" }</Title>
@@ -207,7 +648,28 @@ pub(in crate::app) fn LoginPage() -> Html {
                         }
                     } else {
                         html! {
-                            <LoginForm next={ next } />
+                            <div class={ classes!("w-1/2", "mx-auto") }>
+                                <LoginForm next={ next } two_factor={ query.two_factor } error={ query.sso_error.clone() } />
+                                {
+                                    match oidc_fetch.data.as_ref().and_then(|oidc| oidc.name.clone().filter(|_| oidc.enabled)) {
+                                        Some(name) => html! {
+                                            <p class={ classes!("mb-5") }>
+                                                <a href={ sso_href } class={ classes!("inline-block", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300") }>
+                                                    { format!("Sign in with {}", name) }
+                                                </a>
+                                            </p>
+                                        },
+                                        None => html! {},
+                                    }
+                                }
+                                <p class={ classes!("mb-2") }>
+                                    <Link<Route> to={ Route::ForgotPassword } classes={ classes!("underline") }>{ "Forgot your password?" }</Link<Route>>
+                                </p>
+                                <p>
+                                    { "New to ConnectIA? " }
+                                    <Link<Route> to={ Route::Register } classes={ classes!("underline") }>{ "Register with your school email" }</Link<Route>>
+                                </p>
+                            </div>
                         }
                     }
                 } else {
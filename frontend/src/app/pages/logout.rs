@@ -1,18 +1,21 @@
 use std::rc::Rc;
 
-use gloo_net::http::Request;
 use wasm_bindgen_futures::spawn_local;
 use yew::{Callback, Html, MouseEvent, classes, function_component, html, use_state};
 use yew_hooks::{use_async, use_effect_once};
 use yew_router::hooks::use_navigator;
 
-use crate::app::{Route, components::Title, utils::get_current_user};
+use crate::app::{
+    Route,
+    components::Title,
+    utils::{get_current_user, post},
+};
 
 #[function_component]
 pub(in crate::app) fn LogoutPage() -> Html {
     // Use stuff
     let error_state = use_state(|| None::<String>);
-    let user_fetch = use_async(async { get_current_user().await.map_err(|err| Rc::new(err)) });
+    let user_fetch = use_async(async { get_current_user().await.map_err(Rc::new) });
     let navigator = use_navigator().expect("Navigator not found");
 
     // Fetch the current user
@@ -42,7 +45,7 @@ pub(in crate::app) fn LogoutPage() -> Html {
             // Spawn the task
             spawn_local(async move {
                 // Create the logout request
-                let request = Request::post("/backend/logout");
+                let request = post("/backend/logout");
 
                 // Send the logout request
                 let response = request.send().await;
@@ -1,12 +1,29 @@
 pub(in crate::app) use admin::AdminPage;
+pub(in crate::app) use api_tokens::ApiTokensPage;
 pub(in crate::app) use error::ErrorPage;
 pub(in crate::app) use landing::LandingPage;
 pub(in crate::app) use login::LoginPage;
 pub(self) use login::LoginQuery;
 pub(in crate::app) use logout::LogoutPage;
+pub(in crate::app) use password_reset::{ForgotPasswordPage, ResetPasswordPage};
+pub(in crate::app) use profile::{EditProfilePage, ProfilePage};
+pub(in crate::app) use register::RegisterPage;
+pub(in crate::app) use sessions::SessionsPage;
+pub(in crate::app) use settings::SettingsPage;
+pub(in crate::app) use verify_email::VerifyEmailPage;
+use verify_email::TokenQuery;
 
 mod admin;
+mod api_tokens;
 mod error;
 mod landing;
 mod login;
 mod logout;
+mod passkeys;
+mod password_reset;
+mod profile;
+mod register;
+mod sessions;
+mod settings;
+mod two_factor;
+mod verify_email;
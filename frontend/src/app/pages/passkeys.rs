@@ -0,0 +1,304 @@
+use gloo_net::http::Request;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
+use yew::{classes, function_component, html, use_state, Callback, Html, InputEvent, SubmitEvent, TargetCast as _};
+use yew_hooks::{use_async, use_effect_once};
+
+use crate::{app::utils::{create_passkey, post, post_json}, net::{bodies, responses}};
+
+#[function_component]
+pub(super) fn PasskeySettings() -> Html {
+    // Use stuff
+    let name_state = use_state(String::new);
+    let password_state = use_state(String::new);
+    let message_state = use_state(|| None::<String>);
+    let error_state = use_state(|| None::<String>);
+    let passkeys_fetch = use_async(async {
+        let response = Request::get("/backend/passkeys")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.ok() {
+            return Err(format!("Unexpected status code: {}", response.status()));
+        }
+        response
+            .json::<Vec<responses::PasskeyResponse>>()
+            .await
+            .map_err(|err| err.to_string())
+    });
+
+    // Fetch the existing passkeys
+    {
+        let passkeys_fetch = passkeys_fetch.clone();
+        use_effect_once(move || {
+            passkeys_fetch.run();
+            || ()
+        })
+    }
+
+    // Create the name input handler
+    let handle_name_input = {
+        let name_state = name_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            name_state.set(input.value());
+        })
+    };
+
+    // Create the password input handler
+    let handle_password_input = {
+        let password_state = password_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            password_state.set(input.value());
+        })
+    };
+
+    // Create the add handler
+    let on_add = {
+        // Clone stuff
+        let name = (*name_state).clone();
+        let name_state = name_state.clone();
+        let message_state = message_state.clone();
+        let error_state = error_state.clone();
+        let passkeys_fetch = passkeys_fetch.clone();
+
+        // Create the callback
+        Callback::from(move |e: SubmitEvent| {
+            // Prevent browser default form submission
+            e.prevent_default();
+
+            // Clone stuff
+            let name = name.clone();
+            let name_state = name_state.clone();
+            let message_state = message_state.clone();
+            let error_state = error_state.clone();
+            let passkeys_fetch = passkeys_fetch.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Get the options for a new passkey from the backend
+                let response = match post("/backend/passkeys/register/start").send().await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+                let options = match response.status() {
+                    200 => match response.text().await {
+                        Ok(options) => options,
+                        Err(_) => {
+                            error_state.set(Some("Internal frontend error".to_string()));
+                            return;
+                        }
+                    },
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                        return;
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                        return;
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Let the browser make the passkey and send it back
+                let body = match create_passkey(&options, name).await {
+                    Ok(body) => body,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+                let response = match post_json("/backend/passkeys/register/finish", &body).await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    201 => {
+                        error_state.set(None);
+                        name_state.set(String::new());
+                        message_state.set(Some("Passkey added".to_string()));
+                        passkeys_fetch.run();
+                    }
+                    400 | 409 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Invalid passkey".to_string())),
+                    },
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Create the remove handler
+    let on_remove = {
+        let password = (*password_state).clone();
+        let password_state = password_state.clone();
+        let message_state = message_state.clone();
+        let error_state = error_state.clone();
+        let passkeys_fetch = passkeys_fetch.clone();
+        Callback::from(move |id: i64| {
+            let body = bodies::RemovePasskeyBody {
+                id,
+                password: password.clone(),
+            };
+            let password_state = password_state.clone();
+            let message_state = message_state.clone();
+            let error_state = error_state.clone();
+            let passkeys_fetch = passkeys_fetch.clone();
+            spawn_local(async move {
+                // Send the request and get a response
+                let response = match post_json("/backend/passkeys/remove", &body).await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 | 404 => {
+                        error_state.set(None);
+                        password_state.set(String::new());
+                        message_state.set(Some("Passkey removed".to_string()));
+                        passkeys_fetch.run();
+                    }
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    403 => {
+                        error_state.set(Some("Type your password to remove a passkey".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Return html for the settings
+    html! {
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "Passkeys" }</h2>
+            <p class={ classes!("mb-5") }>
+                { "Passkeys let you log in with your device's fingerprint, face or screen lock instead of your password, or use it as a second factor after your password." }
+            </p>
+            {
+                if let Some(err) = &passkeys_fetch.error {
+                    html! {
+                        <p class={ classes!("mb-5", "text-red-500") }>{ format!("Error fetching your passkeys: {}", err) }</p>
+                    }
+                } else if let Some(passkeys) = &passkeys_fetch.data {
+                    if passkeys.is_empty() {
+                        html! {
+                            <p class={ classes!("mb-5") }>{ "You have no passkeys." }</p>
+                        }
+                    } else {
+                        html! {
+                            <>
+                                <ul class={ classes!("mb-5") }>
+                                    {
+                                        for passkeys.iter().map(|passkey| {
+                                            let on_remove = on_remove.clone();
+                                            let id = passkey.id;
+                                            let last_used = match &passkey.last_used_on {
+                                                Some(last_used_on) => format!("last used {}", last_used_on),
+                                                None => "never used".to_string(),
+                                            };
+                                            html! {
+                                                <li class={ classes!("mb-2") }>
+                                                    <p>{ &passkey.name }</p>
+                                                    <p class={ classes!("text-gray-500") }>{ format!("Added {}, {} ", passkey.created_on, last_used) }</p>
+                                                    <button
+                                                        class={ classes!("px-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                                                        onclick={ move |_| on_remove.emit(id) }
+                                                    >
+                                                        { "Remove" }
+                                                    </button>
+                                                </li>
+                                            }
+                                        })
+                                    }
+                                </ul>
+                                <div class={ classes!("mb-5") }>
+                                    <label for="passkey-password">{ "Password, to remove a passkey:" }</label>
+                                    <input
+                                        id="passkey-password"
+                                        class={ classes!("w-full", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                                        type="password"
+                                        value={ (*password_state).clone() }
+                                        oninput={ handle_password_input }
+                                    />
+                                </div>
+                            </>
+                        }
+                    }
+                } else {
+                    html! {
+                        <p class={ classes!("mb-5") }>{ "Loading your passkeys..." }</p>
+                    }
+                }
+            }
+            <form onsubmit={ on_add } novalidate=true>
+                <div class={ classes!("mb-5") }>
+                    <label for="passkey-name">{ "Name for a new passkey:" }</label>
+                    <input
+                        id="passkey-name"
+                        class={ classes!("w-full", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                        type="text"
+                        placeholder="My laptop"
+                        value={ (*name_state).clone() }
+                        oninput={ handle_name_input }
+                    />
+                </div>
+                <input
+                    type="submit"
+                    value="Add Passkey"
+                    class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                />
+            </form>
+            {
+                if let Some(message) = &*message_state {
+                    html! {
+                        <p class={ classes!("mb-5") }>{ message }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            {
+                if let Some(error) = &*error_state {
+                    html! {
+                        <p class={ classes!("mb-5", "text-red-500") }>{ error }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+        </div>
+    }
+}
@@ -0,0 +1,319 @@
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
+use yew::{classes, function_component, html, use_state, Callback, Html, InputEvent, SubmitEvent, TargetCast as _};
+use yew_router::{components::Link, hooks::use_location};
+
+use crate::{app::{components::Title, utils::post, Route}, net::bodies};
+
+use super::TokenQuery;
+
+#[function_component]
+pub(in crate::app) fn ForgotPasswordPage() -> Html {
+    // Use stuff
+    let email_state = use_state(String::new);
+    let error_state = use_state(|| None::<String>);
+    let sent_state = use_state(|| false);
+
+    // Create the email input handler
+    let handle_email_input = {
+        let email_state = email_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            email_state.set(input.value());
+        })
+    };
+
+    // Create the on submit handler
+    let on_submit = {
+        // Clone stuff
+        let email = (*email_state).clone();
+        let error_state = error_state.clone();
+        let sent_state = sent_state.clone();
+
+        // Create the callback
+        Callback::from(move |e: SubmitEvent| {
+            // Prevent the browser default form submission
+            e.prevent_default();
+
+            // Clone stuff
+            let body = bodies::PasswordResetRequestBody {
+                email: email.clone(),
+            };
+            let error_state = error_state.clone();
+            let sent_state = sent_state.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Serialize the body to json
+                let body = match serde_json::to_string(&body) {
+                    Ok(body) => body,
+                    Err(error) => {
+                        error_state.set(Some(error.to_string()));
+                        return;
+                    }
+                };
+
+                // Create a new request
+                let request = match post("/backend/password-reset/request")
+                    .header("Content-Type", "application/json")
+                    .body(body)
+                {
+                    Ok(request) => request,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Send the request and get a response
+                let response = match request.send().await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 => {
+                        error_state.set(None);
+                        sent_state.set(true);
+                    }
+                    429 => {
+                        error_state.set(Some("Too many requests, try again later".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    html! {
+        <>
+            <Title>{ "Forgot Password" }</Title>
+            <div class={ classes!("w-1/2", "mx-auto") }>
+                {
+                    if *sent_state {
+                        html! {
+                            <p>{ "If an account uses that email, a link to reset its password is on the way." }</p>
+                        }
+                    } else {
+                        html! {
+                            <form onsubmit={ on_submit } novalidate=true>
+                                <div class={ classes!("mb-5") }>
+                                    <label for="email">{ "School email:" }</label>
+                                    <input
+                                        id="email"
+                                        class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                                        type="email"
+                                        value={ (*email_state).clone() }
+                                        oninput={ handle_email_input }
+                                    />
+                                </div>
+                                {
+                                    if let Some(error) = &*error_state {
+                                        html! {
+                                            <p class={ classes!("text-red-500") }>{ error }</p>
+                                        }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                <input
+                                    type="submit"
+                                    value="Send Reset Link"
+                                    class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                                />
+                            </form>
+                        }
+                    }
+                }
+            </div>
+        </>
+    }
+}
+
+#[function_component]
+pub(in crate::app) fn ResetPasswordPage() -> Html {
+    // Use stuff
+    let location = use_location();
+    let new_password_state = use_state(String::new);
+    let confirm_password_state = use_state(String::new);
+    let error_state = use_state(|| None::<String>);
+    let reset_state = use_state(|| false);
+
+    // Get the token from the url
+    let token = location.and_then(|location| {
+        location
+            .query::<TokenQuery>()
+            .ok()
+            .and_then(|query| query.token)
+    });
+
+    // Create the new password input handler
+    let handle_new_password_input = {
+        let new_password_state = new_password_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            new_password_state.set(input.value());
+        })
+    };
+
+    // Create the confirm password input handler
+    let handle_confirm_password_input = {
+        let confirm_password_state = confirm_password_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            confirm_password_state.set(input.value());
+        })
+    };
+
+    // Create the on submit handler
+    let on_submit = {
+        // Clone stuff
+        let token = token.clone();
+        let new_password = (*new_password_state).clone();
+        let confirm_password = (*confirm_password_state).clone();
+        let error_state = error_state.clone();
+        let reset_state = reset_state.clone();
+
+        // Create the callback
+        Callback::from(move |e: SubmitEvent| {
+            // Prevent the browser default form submission
+            e.prevent_default();
+
+            // Make sure the password was typed the same twice
+            if new_password != confirm_password {
+                error_state.set(Some("Passwords do not match".to_string()));
+                return;
+            }
+
+            // Clone stuff
+            let Some(token) = token.clone() else {
+                error_state.set(Some("No reset token was provided".to_string()));
+                return;
+            };
+            let body = bodies::PasswordResetBody {
+                token,
+                new_password: new_password.clone(),
+            };
+            let error_state = error_state.clone();
+            let reset_state = reset_state.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Serialize the body to json
+                let body = match serde_json::to_string(&body) {
+                    Ok(body) => body,
+                    Err(error) => {
+                        error_state.set(Some(error.to_string()));
+                        return;
+                    }
+                };
+
+                // Create a new request
+                let request = match post("/backend/password-reset")
+                    .header("Content-Type", "application/json")
+                    .body(body)
+                {
+                    Ok(request) => request,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Send the request and get a response
+                let response = match request.send().await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 => {
+                        error_state.set(None);
+                        reset_state.set(true);
+                    }
+                    400 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Invalid or expired token".to_string())),
+                    },
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    html! {
+        <>
+            <Title>{ "Reset Password" }</Title>
+            <div class={ classes!("w-1/2", "mx-auto") }>
+                {
+                    if *reset_state {
+                        html! {
+                            <p>
+                                { "Your password has been reset. You can now " }
+                                <Link<Route> to={ Route::Login } classes={ classes!("underline") }>{ "log in" }</Link<Route>>
+                                { "." }
+                            </p>
+                        }
+                    } else {
+                        html! {
+                            <form onsubmit={ on_submit } novalidate=true>
+                                <div class={ classes!("mb-5") }>
+                                    <label for="new-password">{ "New password:" }</label>
+                                    <input
+                                        id="new-password"
+                                        class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                                        type="password"
+                                        value={ (*new_password_state).clone() }
+                                        oninput={ handle_new_password_input }
+                                    />
+                                </div>
+                                <div class={ classes!("mb-5") }>
+                                    <label for="confirm-password">{ "Confirm new password:" }</label>
+                                    <input
+                                        id="confirm-password"
+                                        class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                                        type="password"
+                                        value={ (*confirm_password_state).clone() }
+                                        oninput={ handle_confirm_password_input }
+                                    />
+                                </div>
+                                {
+                                    if let Some(error) = &*error_state {
+                                        html! {
+                                            <p class={ classes!("text-red-500") }>{ error }</p>
+                                        }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                <input
+                                    type="submit"
+                                    value="Reset Password"
+                                    class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                                />
+                            </form>
+                        }
+                    }
+                }
+            </div>
+        </>
+    }
+}
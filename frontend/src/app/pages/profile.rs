@@ -0,0 +1,446 @@
+use std::rc::Rc;
+
+use gloo_net::http::Request;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{HtmlInputElement, HtmlTextAreaElement};
+use yew::{classes, function_component, html, use_effect_with, use_state, AttrValue, Callback, Html, InputEvent, SubmitEvent, TargetCast as _};
+use yew_autoprops::autoprops;
+use yew_hooks::{use_async, use_effect_once};
+use yew_router::{components::Link, hooks::use_navigator};
+
+use crate::{app::{components::Title, utils::{get_current_user, patch_json}, Route}, net::{bodies, responses}};
+
+use super::LoginQuery;
+
+/// Get a user's profile from the backend
+async fn get_profile(username: &str) -> Result<responses::ProfileResponse, String> {
+    let response = Request::get(&format!("/backend/profiles/{}", urlencoding::encode(username)))
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    match response.status() {
+        200 => response
+            .json::<responses::ProfileResponse>()
+            .await
+            .map_err(|err| err.to_string()),
+        404 => Err("No student has that username".to_string()),
+        code => Err(format!("Unexpected status code: {}", code)),
+    }
+}
+
+#[autoprops]
+#[function_component]
+fn Profile(profile: &responses::ProfileResponse, own: bool) -> Html {
+    html! {
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl") }>{ profile.display_name.clone().unwrap_or_else(|| profile.username.clone()) }</h2>
+            <p class={ classes!("mb-5", "text-gray-500") }>
+                {
+                    [Some(format!("@{}", profile.username)), profile.pronouns.clone(), profile.graduation_year.map(|year| format!("Class of {}", year))]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>()
+                        .join(" · ")
+                }
+            </p>
+            {
+                match &profile.bio {
+                    Some(bio) => html! {
+                        <p class={ classes!("mb-5", "whitespace-pre-line") }>{ bio }</p>
+                    },
+                    None => html! {},
+                }
+            }
+            <ul class={ classes!("mb-5") }>
+                {
+                    for profile.links.iter().map(|link| html! {
+                        <li>
+                            <a href={ link.clone() } target="_blank" rel="noopener noreferrer nofollow" class={ classes!("underline", "break-all") }>{ link }</a>
+                        </li>
+                    })
+                }
+            </ul>
+            {
+                if own {
+                    html! {
+                        <Link<Route> to={ Route::EditProfile { username: profile.username.clone() } } classes={ classes!("underline") }>{ "Edit your profile" }</Link<Route>>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+        </div>
+    }
+}
+
+#[autoprops]
+#[function_component]
+pub(in crate::app) fn ProfilePage(username: &AttrValue) -> Html {
+    // Use stuff
+    let user_fetch = use_async(async { get_current_user().await.map_err(Rc::new) });
+    let navigator = use_navigator().expect("Navigator not found");
+    let profile_fetch = {
+        let username = username.clone();
+        use_async(async move { get_profile(&username).await })
+    };
+
+    // Fetch the current user and the profile, again when another profile is opened
+    {
+        let user_fetch = user_fetch.clone();
+        let profile_fetch = profile_fetch.clone();
+        use_effect_with(username.clone(), move |_| {
+            user_fetch.run();
+            profile_fetch.run();
+            || ()
+        })
+    }
+
+    // Effect to redirect if user is not logged in
+    {
+        let user_fetch = user_fetch.clone();
+        let navigator = navigator.clone();
+        let username = username.clone();
+        use_effect_with(user_fetch, move |user_fetch| {
+            if let Some(None) = &user_fetch.data {
+                let navigation_result = navigator.push_with_query(
+                    &Route::Login,
+                    &LoginQuery {
+                        next: Some(Route::Profile { username: username.to_string() }),
+                        ..Default::default()
+                    },
+                );
+                if let Err(_err) = navigation_result {}
+            }
+            || ()
+        })
+    }
+
+    // Return html for this page
+    html! {
+        <>
+            <Title>{ "Profile" }</Title>
+            <div class={ classes!("w-1/2", "mx-auto") }>
+                {
+                    if let Some(err) = &user_fetch.error {
+                        html! {
+                            <p>{ format!("Error fetching the current user: {}", err) }</p>
+                        }
+                    } else if let Some(Some(user)) = &user_fetch.data {
+                        if let Some(err) = &profile_fetch.error {
+                            html! {
+                                <p>{ format!("Error fetching the profile: {}", err) }</p>
+                            }
+                        } else if let Some(profile) = &profile_fetch.data {
+                            html! {
+                                <Profile profile={ profile.clone() } own={ profile.username == user.username } />
+                            }
+                        } else {
+                            html! {
+                                <p>{ "Loading the profile..." }</p>
+                            }
+                        }
+                    } else if let Some(None) = &user_fetch.data {
+                        html! {
+                            <p>{ "You are not logged in!" }</p>
+                        }
+                    } else {
+                        html! {
+                            <p>{ "Loading the profile..." }</p>
+                        }
+                    }
+                }
+            </div>
+        </>
+    }
+}
+
+#[autoprops]
+#[function_component]
+fn EditProfileForm(profile: &responses::ProfileResponse) -> Html {
+    // Use stuff
+    let display_name_state = use_state(|| profile.display_name.clone().unwrap_or_default());
+    let pronouns_state = use_state(|| profile.pronouns.clone().unwrap_or_default());
+    let graduation_year_state = use_state(|| profile.graduation_year.map(|year| year.to_string()).unwrap_or_default());
+    let bio_state = use_state(|| profile.bio.clone().unwrap_or_default());
+    let links_state = use_state(|| profile.links.join("\n"));
+    let error_state = use_state(|| None::<String>);
+    let navigator = use_navigator().expect("Navigator not found");
+
+    // Create the input handlers
+    let handle_display_name_input = {
+        let display_name_state = display_name_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            display_name_state.set(input.value());
+        })
+    };
+    let handle_pronouns_input = {
+        let pronouns_state = pronouns_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            pronouns_state.set(input.value());
+        })
+    };
+    let handle_graduation_year_input = {
+        let graduation_year_state = graduation_year_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            graduation_year_state.set(input.value());
+        })
+    };
+    let handle_bio_input = {
+        let bio_state = bio_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlTextAreaElement = e.target_dyn_into().unwrap();
+            bio_state.set(input.value());
+        })
+    };
+    let handle_links_input = {
+        let links_state = links_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlTextAreaElement = e.target_dyn_into().unwrap();
+            links_state.set(input.value());
+        })
+    };
+
+    // Create the on submit handler
+    let on_submit = {
+        // Clone stuff
+        let display_name = (*display_name_state).clone();
+        let pronouns = (*pronouns_state).clone();
+        let graduation_year = (*graduation_year_state).clone();
+        let bio = (*bio_state).clone();
+        let links = (*links_state).clone();
+        let error_state = error_state.clone();
+        let navigator = navigator.clone();
+        let username = profile.username.clone();
+
+        // Create the callback
+        Callback::from(move |e: SubmitEvent| {
+            // Prevent the browser default form submission
+            e.prevent_default();
+
+            // An empty graduation year removes it
+            let graduation_year = match graduation_year.trim() {
+                "" => 0,
+                year => match year.parse::<i32>() {
+                    Ok(year) => year,
+                    Err(_) => {
+                        error_state.set(Some("Graduation year must be a number".to_string()));
+                        return;
+                    }
+                },
+            };
+
+            // Clone stuff
+            let body = bodies::UpdateProfileBody {
+                display_name: Some(display_name.clone()),
+                pronouns: Some(pronouns.clone()),
+                graduation_year: Some(graduation_year),
+                bio: Some(bio.clone()),
+                links: Some(links.lines().map(str::to_string).collect()),
+            };
+            let error_state = error_state.clone();
+            let navigator = navigator.clone();
+            let username = username.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Send the request and get a response
+                let response = match patch_json("/backend/profile", &body).await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 => {
+                        error_state.set(None);
+                        navigator.push(&Route::Profile { username });
+                    }
+                    400 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Invalid profile".to_string())),
+                    },
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    403 => {
+                        error_state.set(Some("You can't edit a profile while viewing the site as someone else".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Return html for the form
+    html! {
+        <form onsubmit={ on_submit } novalidate=true>
+            <div class={ classes!("mb-5") }>
+                <label for="display-name">{ "Display name:" }</label>
+                <input
+                    id="display-name"
+                    class={ classes!("w-full", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    type="text"
+                    value={ (*display_name_state).clone() }
+                    oninput={ handle_display_name_input }
+                />
+            </div>
+            <div class={ classes!("mb-5") }>
+                <label for="pronouns">{ "Pronouns:" }</label>
+                <input
+                    id="pronouns"
+                    class={ classes!("w-full", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    type="text"
+                    placeholder="they/them"
+                    value={ (*pronouns_state).clone() }
+                    oninput={ handle_pronouns_input }
+                />
+            </div>
+            <div class={ classes!("mb-5") }>
+                <label for="graduation-year">{ "Graduation year:" }</label>
+                <input
+                    id="graduation-year"
+                    class={ classes!("w-full", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    type="number"
+                    value={ (*graduation_year_state).clone() }
+                    oninput={ handle_graduation_year_input }
+                />
+            </div>
+            <div class={ classes!("mb-5") }>
+                <label for="bio">{ "Bio:" }</label>
+                <textarea
+                    id="bio"
+                    class={ classes!("w-full", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    rows="5"
+                    value={ (*bio_state).clone() }
+                    oninput={ handle_bio_input }
+                />
+            </div>
+            <div class={ classes!("mb-5") }>
+                <label for="links">{ "Links, one per line:" }</label>
+                <textarea
+                    id="links"
+                    class={ classes!("w-full", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    rows="3"
+                    placeholder="https://"
+                    value={ (*links_state).clone() }
+                    oninput={ handle_links_input }
+                />
+            </div>
+            {
+                if let Some(error) = &*error_state {
+                    html! {
+                        <p class={ classes!("mb-5", "text-red-500") }>{ error }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            <input
+                type="submit"
+                value="Save Profile"
+                class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+            />
+        </form>
+    }
+}
+
+#[autoprops]
+#[function_component]
+pub(in crate::app) fn EditProfilePage(username: &AttrValue) -> Html {
+    // Use stuff
+    let user_fetch = use_async(async { get_current_user().await.map_err(Rc::new) });
+    let navigator = use_navigator().expect("Navigator not found");
+    let profile_fetch = {
+        let username = username.clone();
+        use_async(async move { get_profile(&username).await })
+    };
+
+    // Fetch the current user and the profile
+    {
+        let user_fetch = user_fetch.clone();
+        let profile_fetch = profile_fetch.clone();
+        use_effect_once(move || {
+            user_fetch.run();
+            profile_fetch.run();
+            || ()
+        })
+    }
+
+    // Effect to redirect if user is not logged in
+    {
+        let user_fetch = user_fetch.clone();
+        let navigator = navigator.clone();
+        let username = username.clone();
+        use_effect_with(user_fetch, move |user_fetch| {
+            if let Some(None) = &user_fetch.data {
+                let navigation_result = navigator.push_with_query(
+                    &Route::Login,
+                    &LoginQuery {
+                        next: Some(Route::EditProfile { username: username.to_string() }),
+                        ..Default::default()
+                    },
+                );
+                if let Err(_err) = navigation_result {}
+            }
+            || ()
+        })
+    }
+
+    // Return html for this page
+    html! {
+        <>
+            <Title>{ "Edit Profile" }</Title>
+            <div class={ classes!("w-1/2", "mx-auto") }>
+                {
+                    if let Some(err) = &user_fetch.error {
+                        html! {
+                            <p>{ format!("Error fetching the current user: {}", err) }</p>
+                        }
+                    } else if let Some(Some(user)) = &user_fetch.data {
+                        if let Some(err) = &profile_fetch.error {
+                            html! {
+                                <p>{ format!("Error fetching the profile: {}", err) }</p>
+                            }
+                        } else if let Some(profile) = &profile_fetch.data {
+                            if profile.username == user.username {
+                                html! {
+                                    <>
+                                        <Link<Route> to={ Route::Profile { username: profile.username.clone() } } classes={ classes!("underline", "block", "mb-5") }>{ "Back to your profile" }</Link<Route>>
+                                        <EditProfileForm profile={ profile.clone() } />
+                                    </>
+                                }
+                            } else {
+                                html! {
+                                    <p>{ "You can only edit your own profile." }</p>
+                                }
+                            }
+                        } else {
+                            html! {
+                                <p>{ "Loading your profile..." }</p>
+                            }
+                        }
+                    } else if let Some(None) = &user_fetch.data {
+                        html! {
+                            <p>{ "You are not logged in!" }</p>
+                        }
+                    } else {
+                        html! {
+                            <p>{ "Loading your profile..." }</p>
+                        }
+                    }
+                }
+            </div>
+        </>
+    }
+}
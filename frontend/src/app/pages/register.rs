@@ -0,0 +1,219 @@
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
+use yew::{classes, function_component, html, use_state, Callback, Html, InputEvent, SubmitEvent, TargetCast as _};
+use yew_router::components::Link;
+
+use crate::{app::{components::Title, utils::post, Route}, net::bodies};
+
+#[function_component]
+fn RegisterForm() -> Html {
+    // Use stuff
+    let username_state = use_state(String::new);
+    let email_state = use_state(String::new);
+    let password_state = use_state(String::new);
+    let confirm_password_state = use_state(String::new);
+    let error_state = use_state(|| None::<String>);
+    let registered_state = use_state(|| false);
+
+    // Create the username input handler
+    let handle_username_input = {
+        let username_state = username_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            username_state.set(input.value());
+        })
+    };
+
+    // Create the email input handler
+    let handle_email_input = {
+        let email_state = email_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            email_state.set(input.value());
+        })
+    };
+
+    // Create the password input handler
+    let handle_password_input = {
+        let password_state = password_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            password_state.set(input.value());
+        })
+    };
+
+    // Create the confirm password input handler
+    let handle_confirm_password_input = {
+        let confirm_password_state = confirm_password_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            confirm_password_state.set(input.value());
+        })
+    };
+
+    // Create the on submit handler
+    let on_submit = {
+        // Clone stuff
+        let username = (*username_state).clone();
+        let email = (*email_state).clone();
+        let password = (*password_state).clone();
+        let confirm_password = (*confirm_password_state).clone();
+        let error_state = error_state.clone();
+        let registered_state = registered_state.clone();
+
+        // Create the callback
+        Callback::from(move |e: SubmitEvent| {
+            // Prevent the browser default form submission
+            e.prevent_default();
+
+            // Make sure the password was typed the same twice
+            if password != confirm_password {
+                error_state.set(Some("Passwords do not match".to_string()));
+                return;
+            }
+
+            // Clone stuff
+            let body = bodies::RegisterBody {
+                username: username.clone(),
+                email: email.clone(),
+                password: password.clone(),
+            };
+            let error_state = error_state.clone();
+            let registered_state = registered_state.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Serialize the body to json
+                let body = match serde_json::to_string(&body) {
+                    Ok(body) => body,
+                    Err(error) => {
+                        error_state.set(Some(error.to_string()));
+                        return;
+                    }
+                };
+
+                // Create a new request
+                let request = match post("/backend/register")
+                    .header("Content-Type", "application/json")
+                    .body(body)
+                {
+                    Ok(request) => request,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Send the request and get a response
+                let response = match request.send().await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    201 => {
+                        error_state.set(None);
+                        registered_state.set(true);
+                    }
+                    400 | 403 | 409 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Registration failed".to_string())),
+                    },
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Once registered, only tell the user to check their email
+    if *registered_state {
+        return html! {
+            <p>{ "Check your school email for a link to activate your account." }</p>
+        };
+    }
+
+    // Return html for the form
+    html! {
+        <form onsubmit={ on_submit } novalidate=true>
+            <div class={ classes!("mb-5") }>
+                <label for="username">{ "Username:" }</label>
+                <input
+                    id="username"
+                    class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    type="text"
+                    value={ (*username_state).clone() }
+                    oninput={ handle_username_input }
+                />
+            </div>
+            <div class={ classes!("mb-5") }>
+                <label for="email">{ "School email:" }</label>
+                <input
+                    id="email"
+                    class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    type="email"
+                    value={ (*email_state).clone() }
+                    oninput={ handle_email_input }
+                />
+            </div>
+            <div class={ classes!("mb-5") }>
+                <label for="password">{ "Password:" }</label>
+                <input
+                    id="password"
+                    class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    type="password"
+                    value={ (*password_state).clone() }
+                    oninput={ handle_password_input }
+                />
+            </div>
+            <div class={ classes!("mb-5") }>
+                <label for="confirm-password">{ "Confirm password:" }</label>
+                <input
+                    id="confirm-password"
+                    class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    type="password"
+                    value={ (*confirm_password_state).clone() }
+                    oninput={ handle_confirm_password_input }
+                />
+            </div>
+            {
+                if let Some(error) = &*error_state {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ error }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            <input
+                type="submit"
+                value="Register"
+                class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+            />
+        </form>
+    }
+}
+
+#[function_component]
+pub(in crate::app) fn RegisterPage() -> Html {
+    html! {
+        <>
+            <Title>{ "Register" }</Title>
+            <div class={ classes!("w-1/2", "mx-auto") }>
+                <RegisterForm />
+                <p>
+                    { "Already have an account? " }
+                    <Link<Route> to={ Route::Login } classes={ classes!("underline") }>{ "Log in" }</Link<Route>>
+                </p>
+            </div>
+        </>
+    }
+}
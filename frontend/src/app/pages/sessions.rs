@@ -0,0 +1,281 @@
+use std::rc::Rc;
+
+use gloo_net::http::Request;
+use wasm_bindgen_futures::spawn_local;
+use yew::{classes, function_component, html, use_effect_with, use_state, Callback, Html, MouseEvent};
+use yew_hooks::{use_async, use_effect_once};
+use yew_router::{components::Link, hooks::use_navigator};
+
+use crate::{app::{components::Title, utils::{get_current_user, post, post_json}, Route}, net::{bodies, responses}};
+
+use super::LoginQuery;
+
+#[function_component]
+fn Sessions() -> Html {
+    // Use stuff
+    let message_state = use_state(|| None::<String>);
+    let error_state = use_state(|| None::<String>);
+    let sessions_fetch = use_async(async {
+        let response = Request::get("/backend/sessions")
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.ok() {
+            return Err(format!("Unexpected status code: {}", response.status()));
+        }
+        response
+            .json::<Vec<responses::SessionResponse>>()
+            .await
+            .map_err(|err| err.to_string())
+    });
+
+    // Fetch the sessions
+    {
+        let sessions_fetch = sessions_fetch.clone();
+        use_effect_once(move || {
+            sessions_fetch.run();
+            || ()
+        })
+    }
+
+    // Create the revoke handler
+    let on_revoke = {
+        let message_state = message_state.clone();
+        let error_state = error_state.clone();
+        let sessions_fetch = sessions_fetch.clone();
+        Callback::from(move |id: String| {
+            let message_state = message_state.clone();
+            let error_state = error_state.clone();
+            let sessions_fetch = sessions_fetch.clone();
+            spawn_local(async move {
+                // Send the request and get a response
+                let body = bodies::RevokeSessionBody { id };
+                let response = match post_json("/backend/sessions/revoke", &body).await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 | 404 => {
+                        error_state.set(None);
+                        message_state.set(Some("Signed out that device".to_string()));
+                        sessions_fetch.run();
+                    }
+                    400 => match response.text().await {
+                        Ok(message) => error_state.set(Some(message)),
+                        Err(_) => error_state.set(Some("Invalid session".to_string())),
+                    },
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Create the revoke others handler
+    let on_revoke_others = {
+        let message_state = message_state.clone();
+        let error_state = error_state.clone();
+        let sessions_fetch = sessions_fetch.clone();
+        Callback::from(move |_: MouseEvent| {
+            let message_state = message_state.clone();
+            let error_state = error_state.clone();
+            let sessions_fetch = sessions_fetch.clone();
+            spawn_local(async move {
+                // Send the request and get a response
+                let response = match post("/backend/sessions/revoke-others").send().await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error.to_string()));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 => match response.json::<u64>().await {
+                        Ok(revoked) => {
+                            error_state.set(None);
+                            message_state.set(Some(format!("Signed out {} other devices", revoked)));
+                            sessions_fetch.run();
+                        }
+                        Err(_) => {
+                            error_state.set(Some("Internal frontend error".to_string()));
+                        }
+                    },
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Return html for the sessions
+    html! {
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "Signed In Devices" }</h2>
+            <p class={ classes!("mb-5") }>
+                { "These are the browsers signed in to your account. Sign out any you don't recognize, then change your password." }
+            </p>
+            {
+                if let Some(err) = &sessions_fetch.error {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ format!("Error fetching your sessions: {}", err) }</p>
+                    }
+                } else if let Some(sessions) = &sessions_fetch.data {
+                    html! {
+                        <ul class={ classes!("mb-5") }>
+                            {
+                                for sessions.iter().map(|session| {
+                                    let on_revoke = on_revoke.clone();
+                                    let id = session.id.clone();
+                                    let unknown = || "unknown".to_string();
+                                    html! {
+                                        <li class={ classes!("mb-2") }>
+                                            <p class={ classes!("break-all") }>{ session.user_agent.clone().unwrap_or_else(|| "Unknown browser".to_string()) }</p>
+                                            <p class={ classes!("text-gray-500") }>
+                                                { format!("From {}, signed in {}, last active {} ", session.ip.clone().unwrap_or_else(unknown), session.created_at.clone().unwrap_or_else(unknown), session.last_seen_at.clone().unwrap_or_else(unknown)) }
+                                            </p>
+                                            {
+                                                if session.current {
+                                                    html! {
+                                                        <p class={ classes!("font-bold") }>{ "This device" }</p>
+                                                    }
+                                                } else {
+                                                    html! {
+                                                        <button
+                                                            class={ classes!("px-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                                                            onclick={ move |_| on_revoke.emit(id.clone()) }
+                                                        >
+                                                            { "Sign out" }
+                                                        </button>
+                                                    }
+                                                }
+                                            }
+                                        </li>
+                                    }
+                                })
+                            }
+                        </ul>
+                    }
+                } else {
+                    html! {
+                        <p class={ classes!("mb-5") }>{ "Loading your sessions..." }</p>
+                    }
+                }
+            }
+            {
+                if let Some(message) = &*message_state {
+                    html! {
+                        <p class={ classes!("mb-5") }>{ message }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            {
+                if let Some(error) = &*error_state {
+                    html! {
+                        <p class={ classes!("mb-5", "text-red-500") }>{ error }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            <button
+                class={ classes!("px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                onclick={ on_revoke_others }
+            >
+                { "Sign out everywhere else" }
+            </button>
+        </div>
+    }
+}
+
+#[function_component]
+pub(in crate::app) fn SessionsPage() -> Html {
+    // Use stuff
+    let user_fetch = use_async(async { get_current_user().await.map_err(Rc::new) });
+    let navigator = use_navigator().expect("Navigator not found");
+
+    // Fetch the current user
+    {
+        let user_fetch = user_fetch.clone();
+        use_effect_once(move || {
+            user_fetch.run();
+            || ()
+        })
+    }
+
+    // Effect to redirect if user is not logged in
+    {
+        let user_fetch = user_fetch.clone();
+        let navigator = navigator.clone();
+        use_effect_with(user_fetch, move |user_fetch| {
+            if let Some(None) = &user_fetch.data {
+                let navigation_result = navigator.push_with_query(
+                    &Route::Login,
+                    &LoginQuery {
+                        next: Some(Route::Sessions),
+                        ..Default::default()
+                    },
+                );
+                if let Err(_err) = navigation_result {}
+            }
+            || ()
+        })
+    }
+
+    // Return html for this page
+    html! {
+        <>
+            <Title>{ "Signed In Devices" }</Title>
+            {
+                if user_fetch.loading {
+                    html! {
+                        <p>{ "Loading your sessions..." }</p>
+                    }
+                } else if let Some(err) = &user_fetch.error {
+                    html! {
+                        <p>{ format!("Error fetching the current user: {}", err) }</p>
+                    }
+                } else if let Some(data) = &user_fetch.data {
+                    if data.is_some() {
+                        html! {
+                            <div class={ classes!("w-1/2", "mx-auto") }>
+                                <Link<Route> to={ Route::Settings } classes={ classes!("underline", "block", "mb-5") }>{ "Back to settings" }</Link<Route>>
+                                <Sessions />
+                            </div>
+                        }
+                    } else {
+                        html! {
+                            <p>{ "You are not logged in!" }</p>
+                        }
+                    }
+                } else {
+                    html! {
+                        <p>{ "Initializing..." }</p>
+                    }
+                }
+            }
+        </>
+    }
+}
@@ -0,0 +1,269 @@
+use std::rc::Rc;
+
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
+use yew::{classes, function_component, html, use_effect_with, use_state, Callback, Html, InputEvent, SubmitEvent, TargetCast as _};
+use yew_hooks::{use_async, use_effect_once};
+use yew_router::{components::Link, hooks::use_navigator};
+
+use crate::{app::{components::Title, utils::{get_current_user, post}, Route}, net::bodies};
+
+use super::{passkeys::PasskeySettings, two_factor::TwoFactorSettings, LoginQuery};
+
+#[function_component]
+fn ChangePasswordForm() -> Html {
+    // Use stuff
+    let current_password_state = use_state(String::new);
+    let new_password_state = use_state(String::new);
+    let confirm_password_state = use_state(String::new);
+    let error_state = use_state(|| None::<String>);
+    let success_state = use_state(|| None::<String>);
+
+    // Create the current password input handler
+    let handle_current_password_input = {
+        let current_password_state = current_password_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            current_password_state.set(input.value());
+        })
+    };
+
+    // Create the new password input handler
+    let handle_new_password_input = {
+        let new_password_state = new_password_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            new_password_state.set(input.value());
+        })
+    };
+
+    // Create the confirm password input handler
+    let handle_confirm_password_input = {
+        let confirm_password_state = confirm_password_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            confirm_password_state.set(input.value());
+        })
+    };
+
+    // Create the on submit handler
+    let on_submit = {
+        // Clone stuff
+        let current_password = (*current_password_state).clone();
+        let new_password = (*new_password_state).clone();
+        let confirm_password = (*confirm_password_state).clone();
+        let error_state = error_state.clone();
+        let success_state = success_state.clone();
+
+        // Create the callback
+        Callback::from(move |e: SubmitEvent| {
+            // Prevent the browser default form submission
+            e.prevent_default();
+
+            // Make sure the new password was typed the same twice
+            if new_password != confirm_password {
+                success_state.set(None);
+                error_state.set(Some("Passwords do not match".to_string()));
+                return;
+            }
+
+            // Clone stuff
+            let body = bodies::ChangePasswordBody {
+                current_password: current_password.clone(),
+                new_password: new_password.clone(),
+            };
+            let error_state = error_state.clone();
+            let success_state = success_state.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Serialize the body to json
+                let body = match serde_json::to_string(&body) {
+                    Ok(body) => body,
+                    Err(error) => {
+                        error_state.set(Some(error.to_string()));
+                        return;
+                    }
+                };
+
+                // Create a new request
+                let request = match post("/backend/change-password")
+                    .header("Content-Type", "application/json")
+                    .body(body)
+                {
+                    Ok(request) => request,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Send the request and get a response
+                let response = match request.send().await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 => {
+                        error_state.set(None);
+                        success_state.set(Some("Password changed, other sessions have been signed out".to_string()));
+                    }
+                    400 => {
+                        success_state.set(None);
+                        error_state.set(Some("Password must not be empty".to_string()));
+                    }
+                    401 => {
+                        success_state.set(None);
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    403 => {
+                        success_state.set(None);
+                        error_state.set(Some("Current password is incorrect".to_string()));
+                    }
+                    500 => {
+                        success_state.set(None);
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        success_state.set(None);
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Return html for the form
+    html! {
+        <form onsubmit={ on_submit } novalidate=true>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "Change Password" }</h2>
+            <div class={ classes!("mb-5") }>
+                <label for="current-password">{ "Current password:" }</label>
+                <input
+                    id="current-password"
+                    class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    type="password"
+                    value={ (*current_password_state).clone() }
+                    oninput={ handle_current_password_input }
+                />
+            </div>
+            <div class={ classes!("mb-5") }>
+                <label for="new-password">{ "New password:" }</label>
+                <input
+                    id="new-password"
+                    class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    type="password"
+                    value={ (*new_password_state).clone() }
+                    oninput={ handle_new_password_input }
+                />
+            </div>
+            <div class={ classes!("mb-5") }>
+                <label for="confirm-password">{ "Confirm new password:" }</label>
+                <input
+                    id="confirm-password"
+                    class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                    type="password"
+                    value={ (*confirm_password_state).clone() }
+                    oninput={ handle_confirm_password_input }
+                />
+            </div>
+            {
+                if let Some(error) = &*error_state {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ error }</p>
+                    }
+                } else if let Some(success) = &*success_state {
+                    html! {
+                        <p class={ classes!("text-green-700") }>{ success }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            <input
+                type="submit"
+                value="Change Password"
+                class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+            />
+        </form>
+    }
+}
+
+#[function_component]
+pub(in crate::app) fn SettingsPage() -> Html {
+    // Use stuff
+    let user_fetch = use_async(async { get_current_user().await.map_err(Rc::new) });
+    let navigator = use_navigator().expect("Navigator not found");
+
+    // Fetch the current user
+    {
+        let user_fetch = user_fetch.clone();
+        use_effect_once(move || {
+            user_fetch.run();
+            || ()
+        })
+    }
+
+    // Effect to redirect if user is not logged in
+    {
+        let user_fetch = user_fetch.clone();
+        let navigator = navigator.clone();
+        use_effect_with(user_fetch, move |user_fetch| {
+            if let Some(None) = &user_fetch.data {
+                let navigation_result = navigator.push_with_query(
+                    &Route::Login,
+                    &LoginQuery {
+                        next: Some(Route::Settings),
+                        ..Default::default()
+                    },
+                );
+                if let Err(_err) = navigation_result {}
+            }
+            || ()
+        })
+    }
+
+    // Return html for this page
+    html! {
+        <>
+            <Title>{ "Settings" }</Title>
+            {
+                if user_fetch.loading {
+                    html! {
+                        <p>{ "Loading settings..." }</p>
+                    }
+                } else if let Some(err) = &user_fetch.error {
+                    html! {
+                        <p>{ format!("Error fetching the current user: {}", err) }</p>
+                    }
+                } else if let Some(data) = &user_fetch.data {
+                    if let Some(user) = data {
+                        html! {
+                            <div class={ classes!("w-1/2", "mx-auto") }>
+                                <ChangePasswordForm />
+                                <TwoFactorSettings enabled={ user.two_factor_enabled } required={ user.needs_two_factor() } />
+                                <PasskeySettings />
+                                <Link<Route> to={ Route::Profile { username: user.username.clone() } } classes={ classes!("underline", "block", "mb-2") }>{ "View your profile" }</Link<Route>>
+                                <Link<Route> to={ Route::Sessions } classes={ classes!("underline", "block", "mb-2") }>{ "Manage signed in devices" }</Link<Route>>
+                                <Link<Route> to={ Route::ApiTokens } classes={ classes!("underline") }>{ "Manage API tokens" }</Link<Route>>
+                            </div>
+                        }
+                    } else {
+                        html! {
+                            <p>{ "You are not logged in!" }</p>
+                        }
+                    }
+                } else {
+                    html! {
+                        <p>{ "Initializing..." }</p>
+                    }
+                }
+            }
+        </>
+    }
+}
@@ -0,0 +1,411 @@
+use qrcode::{render::svg, QrCode};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
+use yew::{classes, function_component, html, use_state, AttrValue, Callback, Html, InputEvent, MouseEvent, SubmitEvent, TargetCast as _};
+use yew_autoprops::autoprops;
+
+use crate::{app::utils::{post, post_json}, net::{bodies, responses}};
+
+/// Render a provisioning URI as a QR code for authenticator apps to scan
+fn qr_code(provisioning_uri: &str) -> Html {
+    match QrCode::new(provisioning_uri.as_bytes()) {
+        Ok(code) => {
+            let image = code
+                .render::<svg::Color>()
+                .min_dimensions(200, 200)
+                .build();
+            Html::from_html_unchecked(AttrValue::from(image))
+        }
+        Err(_) => html! {},
+    }
+}
+
+#[autoprops]
+#[function_component]
+pub(super) fn TwoFactorSettings(enabled: bool, required: bool) -> Html {
+    // Use stuff
+    let enabled_state = use_state(|| enabled);
+    let setup_state = use_state(|| None::<responses::TwoFactorSetupResponse>);
+    let recovery_codes_state = use_state(|| None::<Vec<String>>);
+    let code_state = use_state(String::new);
+    let password_state = use_state(String::new);
+    let error_state = use_state(|| None::<String>);
+
+    // Create the code input handler
+    let handle_code_input = {
+        let code_state = code_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            code_state.set(input.value());
+        })
+    };
+
+    // Create the password input handler
+    let handle_password_input = {
+        let password_state = password_state.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_dyn_into().unwrap();
+            password_state.set(input.value());
+        })
+    };
+
+    // Create the set up handler
+    let on_set_up = {
+        // Clone stuff
+        let setup_state = setup_state.clone();
+        let recovery_codes_state = recovery_codes_state.clone();
+        let error_state = error_state.clone();
+
+        // Create the callback
+        Callback::from(move |_: MouseEvent| {
+            // Clone stuff
+            let setup_state = setup_state.clone();
+            let recovery_codes_state = recovery_codes_state.clone();
+            let error_state = error_state.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Send the request and get a response
+                let response = match post("/backend/two-factor/setup").send().await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 => match response.json::<responses::TwoFactorSetupResponse>().await {
+                        Ok(setup) => {
+                            error_state.set(None);
+                            recovery_codes_state.set(None);
+                            setup_state.set(Some(setup));
+                        }
+                        Err(_) => {
+                            error_state.set(Some("Internal frontend error".to_string()));
+                        }
+                    },
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    409 => {
+                        error_state.set(Some("Two-factor authentication is already on".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Create the enable handler
+    let on_enable = {
+        // Clone stuff
+        let code = (*code_state).clone();
+        let enabled_state = enabled_state.clone();
+        let setup_state = setup_state.clone();
+        let recovery_codes_state = recovery_codes_state.clone();
+        let code_state = code_state.clone();
+        let error_state = error_state.clone();
+
+        // Create the callback
+        Callback::from(move |e: SubmitEvent| {
+            // Prevent the browser default form submission
+            e.prevent_default();
+
+            // Clone stuff
+            let body = bodies::TwoFactorCodeBody { code: code.clone() };
+            let enabled_state = enabled_state.clone();
+            let setup_state = setup_state.clone();
+            let recovery_codes_state = recovery_codes_state.clone();
+            let code_state = code_state.clone();
+            let error_state = error_state.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Send the request and get a response
+                let response = match post_json("/backend/two-factor/enable", &body).await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 => match response.json::<responses::RecoveryCodesResponse>().await {
+                        Ok(response) => {
+                            error_state.set(None);
+                            code_state.set(String::new());
+                            setup_state.set(None);
+                            enabled_state.set(true);
+                            recovery_codes_state.set(Some(response.recovery_codes));
+                        }
+                        Err(_) => {
+                            error_state.set(Some("Internal frontend error".to_string()));
+                        }
+                    },
+                    400 => {
+                        setup_state.set(None);
+                        error_state.set(Some("Setup expired, please start again".to_string()));
+                    }
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    403 => {
+                        error_state.set(Some("Invalid code".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Create the regenerate recovery codes handler
+    let on_regenerate = {
+        // Clone stuff
+        let code = (*code_state).clone();
+        let recovery_codes_state = recovery_codes_state.clone();
+        let code_state = code_state.clone();
+        let error_state = error_state.clone();
+
+        // Create the callback
+        Callback::from(move |e: SubmitEvent| {
+            // Prevent the browser default form submission
+            e.prevent_default();
+
+            // Clone stuff
+            let body = bodies::TwoFactorCodeBody { code: code.clone() };
+            let recovery_codes_state = recovery_codes_state.clone();
+            let code_state = code_state.clone();
+            let error_state = error_state.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Send the request and get a response
+                let response = match post_json("/backend/two-factor/recovery-codes", &body).await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 => match response.json::<responses::RecoveryCodesResponse>().await {
+                        Ok(response) => {
+                            error_state.set(None);
+                            code_state.set(String::new());
+                            recovery_codes_state.set(Some(response.recovery_codes));
+                        }
+                        Err(_) => {
+                            error_state.set(Some("Internal frontend error".to_string()));
+                        }
+                    },
+                    400 => {
+                        error_state.set(Some("Two-factor authentication is off".to_string()));
+                    }
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    403 => {
+                        error_state.set(Some("Invalid code".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Create the disable handler
+    let on_disable = {
+        // Clone stuff
+        let password = (*password_state).clone();
+        let enabled_state = enabled_state.clone();
+        let recovery_codes_state = recovery_codes_state.clone();
+        let password_state = password_state.clone();
+        let error_state = error_state.clone();
+
+        // Create the callback
+        Callback::from(move |e: SubmitEvent| {
+            // Prevent the browser default form submission
+            e.prevent_default();
+
+            // Clone stuff
+            let body = bodies::TwoFactorDisableBody {
+                password: password.clone(),
+            };
+            let enabled_state = enabled_state.clone();
+            let recovery_codes_state = recovery_codes_state.clone();
+            let password_state = password_state.clone();
+            let error_state = error_state.clone();
+
+            // Spawn the task
+            spawn_local(async move {
+                // Send the request and get a response
+                let response = match post_json("/backend/two-factor/disable", &body).await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error_state.set(Some(error));
+                        return;
+                    }
+                };
+
+                // Do an action based on the response status
+                match response.status() {
+                    200 => {
+                        error_state.set(None);
+                        password_state.set(String::new());
+                        recovery_codes_state.set(None);
+                        enabled_state.set(false);
+                    }
+                    401 => {
+                        error_state.set(Some("You are not logged in!".to_string()));
+                    }
+                    403 => {
+                        error_state.set(Some("Password is incorrect".to_string()));
+                    }
+                    500 => {
+                        error_state.set(Some("Internal server error".to_string()));
+                    }
+                    _ => {
+                        error_state.set(Some("Internal frontend error".to_string()));
+                    }
+                }
+            });
+        })
+    };
+
+    // Create the code input shared by the enable and regenerate forms
+    let code_input = html! {
+        <div class={ classes!("mb-5") }>
+            <label for="two-factor-code">{ "Code from your authenticator app:" }</label>
+            <input
+                id="two-factor-code"
+                class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                type="text"
+                autocomplete="one-time-code"
+                value={ (*code_state).clone() }
+                oninput={ handle_code_input }
+            />
+        </div>
+    };
+
+    // Return html for the settings
+    html! {
+        <div class={ classes!("mb-5") }>
+            <h2 class={ classes!("text-3xl", "mb-5") }>{ "Two-Factor Authentication" }</h2>
+            {
+                if let Some(recovery_codes) = &*recovery_codes_state {
+                    html! {
+                        <div class={ classes!("mb-5") }>
+                            <p class={ classes!("mb-2") }>{ "Save these recovery codes somewhere safe. Each one can be used once instead of a code from your authenticator app, and they won't be shown again." }</p>
+                            <ul class={ classes!("font-mono") }>
+                                { for recovery_codes.iter().map(|code| html! { <li>{ code }</li> }) }
+                            </ul>
+                        </div>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+            {
+                if *enabled_state {
+                    html! {
+                        <>
+                            <p class={ classes!("mb-5") }>{ "Two-factor authentication is on." }</p>
+                            <form onsubmit={ on_regenerate } novalidate=true>
+                                { code_input }
+                                <input
+                                    type="submit"
+                                    value="New Recovery Codes"
+                                    class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                                />
+                            </form>
+                            <form onsubmit={ on_disable } novalidate=true>
+                                <div class={ classes!("mb-5") }>
+                                    <label for="two-factor-password">{ "Password:" }</label>
+                                    <input
+                                        id="two-factor-password"
+                                        class={ classes!("w-full", "mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200") }
+                                        type="password"
+                                        value={ (*password_state).clone() }
+                                        oninput={ handle_password_input }
+                                    />
+                                </div>
+                                <input
+                                    type="submit"
+                                    value="Turn Off"
+                                    class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                                />
+                            </form>
+                        </>
+                    }
+                } else if let Some(setup) = &*setup_state {
+                    html! {
+                        <form onsubmit={ on_enable } novalidate=true>
+                            <p class={ classes!("mb-2") }>{ "Scan this QR code with your authenticator app:" }</p>
+                            <div class={ classes!("mb-2", "w-52", "bg-white") }>{ qr_code(&setup.provisioning_uri) }</div>
+                            <p class={ classes!("mb-5") }>
+                                { "Or enter this key by hand: " }
+                                <span class={ classes!("font-mono") }>{ &setup.secret }</span>
+                            </p>
+                            { code_input }
+                            <input
+                                type="submit"
+                                value="Turn On"
+                                class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                            />
+                        </form>
+                    }
+                } else {
+                    html! {
+                        <>
+                            <p class={ classes!("mb-5") }>
+                                {
+                                    if required {
+                                        "Two-factor authentication is off. Your role requires it or a passkey, so you won't be able to use its permissions until you set one up."
+                                    } else {
+                                        "Two-factor authentication is off."
+                                    }
+                                }
+                            </p>
+                            <button
+                                onclick={ on_set_up }
+                                class={ classes!("mb-5", "px-3", "py-2", "rounded", "border-3", "border-gray-300", "bg-amber-200", "active:bg-amber-300", "cursor-pointer") }
+                            >
+                                { "Set Up" }
+                            </button>
+                        </>
+                    }
+                }
+            }
+            {
+                if let Some(error) = &*error_state {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ error }</p>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+        </div>
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use yew::{classes, function_component, html, Html};
+use yew_hooks::{use_async, use_effect_once};
+use yew_router::{components::Link, hooks::use_location};
+
+use crate::{app::{components::Title, utils::post, Route}, net::bodies};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct TokenQuery {
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[function_component]
+pub(in crate::app) fn VerifyEmailPage() -> Html {
+    // Use stuff
+    let location = use_location();
+
+    // Get the token from the url
+    let token = location.and_then(|location| {
+        location
+            .query::<TokenQuery>()
+            .ok()
+            .and_then(|query| query.token)
+    });
+
+    // Create the verification request
+    let verify_fetch = use_async(async move {
+        let Some(token) = token else {
+            return Err("No verification token was provided".to_string());
+        };
+        let body = serde_json::to_string(&bodies::TokenBody { token })
+            .map_err(|err| err.to_string())?;
+        let response = post("/backend/verify-email")
+            .header("Content-Type", "application/json")
+            .body(body)
+            .map_err(|_| "Internal frontend error".to_string())?
+            .send()
+            .await
+            .map_err(|_| "Internal frontend error".to_string())?;
+        match response.status() {
+            200 => Ok(()),
+            400 => Err("This verification link is invalid or has expired".to_string()),
+            500 => Err("Internal server error".to_string()),
+            _ => Err("Internal frontend error".to_string()),
+        }
+    });
+
+    // Verify the email
+    {
+        let verify_fetch = verify_fetch.clone();
+        use_effect_once(move || {
+            verify_fetch.run();
+            || ()
+        })
+    }
+
+    html! {
+        <>
+            <Title>{ "Verify Email" }</Title>
+            {
+                if verify_fetch.loading {
+                    html! {
+                        <p>{ "Verifying your email..." }</p>
+                    }
+                } else if let Some(err) = &verify_fetch.error {
+                    html! {
+                        <p class={ classes!("text-red-500") }>{ err }</p>
+                    }
+                } else if verify_fetch.data.is_some() {
+                    html! {
+                        <p>
+                            { "Your account is active! You can now " }
+                            <Link<Route> to={ Route::Login } classes={ classes!("underline") }>{ "log in" }</Link<Route>>
+                            { "." }
+                        </p>
+                    }
+                } else {
+                    html! {
+                        <p>{ "Initializing..." }</p>
+                    }
+                }
+            }
+        </>
+    }
+}
@@ -3,7 +3,27 @@ use yewdux::Store;
 #[derive(Debug, Clone, Eq)]
 pub(super) struct User {
     pub username: String,
-    pub admin: bool,
+    pub role: String,
+    pub permissions: Vec<String>,
+    pub two_factor_enabled: bool,
+    /// Whether the role's permissions are withheld until the user sets up a second factor
+    pub second_factor_missing: bool,
+    /// The admin viewing the site as this user, if any
+    pub impersonator: Option<String>,
+    /// Whether the user agreed to the current version of the terms of use
+    pub terms_accepted: bool,
+}
+
+impl User {
+    /// Check whether the user holds a permission
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|held| held == permission)
+    }
+
+    /// Check whether the user's role requires a second factor they haven't set up
+    pub fn needs_two_factor(&self) -> bool {
+        self.second_factor_missing
+    }
 }
 
 impl PartialEq for User {
@@ -12,6 +32,7 @@ impl PartialEq for User {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq, Store)]
 pub(super) struct State {
     pub current_user: Option<User>,
@@ -21,4 +42,4 @@ impl Default for State {
     fn default() -> Self {
         Self { current_user: None }
     }
-}
\ No newline at end of file
+}
@@ -1,9 +1,24 @@
-use gloo_net::http::Request;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use gloo_net::http::{Request, RequestBuilder, Response};
+use js_sys::{Array, ArrayBuffer, Reflect, Uint8Array, JSON};
+use serde::Serialize;
+use wasm_bindgen::{JsCast as _, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    AuthenticatorAssertionResponse, AuthenticatorAttestationResponse, CredentialCreationOptions,
+    CredentialRequestOptions, CredentialsContainer, HtmlDocument, PublicKeyCredential,
+};
 
-use crate::net::responses;
+use crate::net::{bodies, responses};
 
 use super::state;
 
+/// The cookie the backend hands out the CSRF token in
+const CSRF_COOKIE: &str = "connectia_csrf";
+
+/// The header requests that change something repeat the CSRF token in
+const CSRF_HEADER: &str = "X-CSRF-Token";
+
 /// An error in getting the current logged in user
 #[derive(Debug)]
 pub(super) enum GetCurrentUserError {
@@ -58,7 +73,12 @@ pub(super) async fn get_current_user() -> Result<Option<state::User>, GetCurrent
             let response: responses::CurrentUserResponse = response.json().await?;
             Ok(Some(state::User {
                 username: response.username,
-                admin: response.admin,
+                role: response.role,
+                permissions: response.permissions,
+                two_factor_enabled: response.two_factor_enabled,
+                second_factor_missing: response.second_factor_missing,
+                impersonator: response.impersonator,
+                terms_accepted: response.terms_accepted,
             }))
         }
         401 => Ok(None),
@@ -69,3 +89,190 @@ pub(super) async fn get_current_user() -> Result<Option<state::User>, GetCurrent
         ))),
     }
 }
+
+/// Send a json body to a backend endpoint
+pub(super) async fn post_json(url: &str, body: &impl Serialize) -> Result<Response, String> {
+    // Serialize the body to json
+    let body = serde_json::to_string(body).map_err(|error| error.to_string())?;
+
+    // Create a new request and send it
+    post(url)
+        .header("Content-Type", "application/json")
+        .body(body)
+        .map_err(|_| "Internal frontend error".to_string())?
+        .send()
+        .await
+        .map_err(|_| "Internal frontend error".to_string())
+}
+
+/// Send a json body to a backend endpoint to change something
+pub(super) async fn patch_json(url: &str, body: &impl Serialize) -> Result<Response, String> {
+    // Serialize the body to json
+    let body = serde_json::to_string(body).map_err(|error| error.to_string())?;
+
+    // Create a new request and send it
+    with_csrf_token(Request::patch(url))
+        .header("Content-Type", "application/json")
+        .body(body)
+        .map_err(|_| "Internal frontend error".to_string())?
+        .send()
+        .await
+        .map_err(|_| "Internal frontend error".to_string())
+}
+
+/// Ask a backend endpoint to delete something
+pub(super) async fn delete(url: &str) -> Result<Response, String> {
+    with_csrf_token(Request::delete(url))
+        .send()
+        .await
+        .map_err(|_| "Internal frontend error".to_string())
+}
+
+/// Start a POST request to a backend endpoint, carrying the CSRF token it checks
+pub(super) fn post(url: &str) -> RequestBuilder {
+    with_csrf_token(Request::post(url))
+}
+
+/// Attach the CSRF token the backend handed out, which it checks on requests that change something
+fn with_csrf_token(request: RequestBuilder) -> RequestBuilder {
+    match csrf_token() {
+        Some(token) => request.header(CSRF_HEADER, &token),
+        None => request,
+    }
+}
+
+/// Read the CSRF token from its cookie, if the backend handed one out yet
+fn csrf_token() -> Option<String> {
+    let document = web_sys::window()?.document()?.dyn_into::<HtmlDocument>().ok()?;
+    document.cookie().ok()?.split(';').find_map(|cookie| {
+        let (name, value) = cookie.trim().split_once('=')?;
+        (name == CSRF_COOKIE).then(|| value.to_string())
+    })
+}
+
+/// Ask the browser to make a passkey with the options the backend started its registration with
+pub(super) async fn create_passkey(options: &str, name: String) -> Result<bodies::PasskeyRegistrationBody, String> {
+    // Turn the binary fields the backend sent as base64url into buffers
+    let options = JSON::parse(options).map_err(|_| "Invalid passkey options".to_string())?;
+    decode_field(&options, "challenge")?;
+    decode_field(&Reflect::get(&options, &"user".into()).map_err(js_error)?, "id")?;
+    decode_credential_ids(&options, "excludeCredentials")?;
+    let creation_options = CredentialCreationOptions::new();
+    creation_options.set_public_key(options.unchecked_ref());
+
+    // Let the authenticator make the passkey
+    let promise = credentials()?
+        .create_with_options(&creation_options)
+        .map_err(js_error)?;
+    let credential = JsFuture::from(promise)
+        .await
+        .map_err(js_error)?
+        .dyn_into::<PublicKeyCredential>()
+        .map_err(|_| "The browser didn't make a passkey".to_string())?;
+    let response = credential
+        .response()
+        .unchecked_into::<AuthenticatorAttestationResponse>();
+    Ok(bodies::PasskeyRegistrationBody {
+        name,
+        id: encode_buffer(&credential.raw_id()),
+        client_data_json: encode_buffer(&response.client_data_json()),
+        attestation_object: encode_buffer(&response.attestation_object()),
+    })
+}
+
+/// Ask the browser to answer a passkey login challenge the backend started
+async fn get_passkey(options: &str, remember: bool) -> Result<bodies::PasskeyAssertionBody, String> {
+    // Turn the binary fields the backend sent as base64url into buffers
+    let options = JSON::parse(options).map_err(|_| "Invalid passkey options".to_string())?;
+    decode_field(&options, "challenge")?;
+    decode_credential_ids(&options, "allowCredentials")?;
+    let request_options = CredentialRequestOptions::new();
+    request_options.set_public_key(options.unchecked_ref());
+
+    // Let the authenticator sign the challenge
+    let promise = credentials()?
+        .get_with_options(&request_options)
+        .map_err(js_error)?;
+    let credential = JsFuture::from(promise)
+        .await
+        .map_err(js_error)?
+        .dyn_into::<PublicKeyCredential>()
+        .map_err(|_| "The browser didn't use a passkey".to_string())?;
+    let response = credential
+        .response()
+        .unchecked_into::<AuthenticatorAssertionResponse>();
+    Ok(bodies::PasskeyAssertionBody {
+        id: encode_buffer(&credential.raw_id()),
+        client_data_json: encode_buffer(&response.client_data_json()),
+        authenticator_data: encode_buffer(&response.authenticator_data()),
+        signature: encode_buffer(&response.signature()),
+        user_handle: response.user_handle().map(|handle| encode_buffer(&handle)),
+        remember,
+    })
+}
+
+/// Run a whole passkey login against a pair of backend endpoints, returning the finishing response
+pub(super) async fn log_in_with_passkey(start_url: &str, finish_url: &str, remember: bool) -> Result<Response, String> {
+    // Get a challenge from the backend
+    let response = post(start_url)
+        .send()
+        .await
+        .map_err(|_| "Internal frontend error".to_string())?;
+    if !response.ok() {
+        return Err(response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Internal frontend error".to_string()));
+    }
+    let options = response
+        .text()
+        .await
+        .map_err(|_| "Internal frontend error".to_string())?;
+
+    // Answer it with a passkey and send the answer back
+    let body = get_passkey(&options, remember).await?;
+    post_json(finish_url, &body).await
+}
+
+/// Get the browser's store of credentials
+fn credentials() -> Result<CredentialsContainer, String> {
+    web_sys::window()
+        .map(|window| window.navigator().credentials())
+        .ok_or_else(|| "Passkeys need a browser window".to_string())
+}
+
+/// Replace a base64url text field of a javascript object with the bytes it holds
+fn decode_field(object: &JsValue, field: &str) -> Result<(), String> {
+    let key = JsValue::from_str(field);
+    let bytes = Reflect::get(object, &key)
+        .ok()
+        .and_then(|value| value.as_string())
+        .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+        .ok_or_else(|| format!("Invalid passkey options field {}", field))?;
+    Reflect::set(object, &key, &Uint8Array::from(bytes.as_slice())).map_err(js_error)?;
+    Ok(())
+}
+
+/// Decode the ids of a list of credentials in the options
+fn decode_credential_ids(options: &JsValue, field: &str) -> Result<(), String> {
+    let credentials = Reflect::get(options, &field.into()).map_err(js_error)?;
+    if let Some(credentials) = credentials.dyn_ref::<Array>() {
+        for credential in credentials.iter() {
+            decode_field(&credential, "id")?;
+        }
+    }
+    Ok(())
+}
+
+/// Encode a buffer the browser returned as base64url, which the backend expects
+fn encode_buffer(buffer: &ArrayBuffer) -> String {
+    URL_SAFE_NO_PAD.encode(Uint8Array::new(buffer).to_vec())
+}
+
+/// Describe an error thrown by the browser, such as the user cancelling a passkey prompt
+fn js_error(error: JsValue) -> String {
+    Reflect::get(&error, &"message".into())
+        .ok()
+        .and_then(|message| message.as_string())
+        .unwrap_or_else(|| "The passkey request failed".to_string())
+}
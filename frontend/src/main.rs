@@ -1,3 +1,11 @@
+// The app's style spells out private items with `pub(self)`, names error variants after their
+// source and writes some `Default` impls by hand
+#![allow(
+    clippy::needless_pub_self,
+    clippy::enum_variant_names,
+    clippy::derivable_impls
+)]
+
 use app::App;
 
 mod app;
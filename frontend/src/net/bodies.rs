@@ -4,4 +4,130 @@ use serde::Serialize;
 pub struct LoginBody {
     pub username: String,
     pub password: String,
+    pub remember: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateUserBody {
+    pub username: String,
+    pub password: String,
+    pub role: String,
+    pub student_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangePasswordBody {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RegisterBody {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenBody {
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PasswordResetRequestBody {
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PasswordResetBody {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TwoFactorCodeBody {
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TwoFactorDisableBody {
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnlockLoginBody {
+    pub id: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateApiTokenBody {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_in_days: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RevokeApiTokenBody {
+    pub id: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RevokeSessionBody {
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SuspendUserBody {
+    pub username: String,
+    pub kind: String,
+    pub reason: String,
+    pub days: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LiftSuspensionBody {
+    pub id: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateUserRoleBody {
+    pub role: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AcceptTermsBody {
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PasskeyRegistrationBody {
+    pub name: String,
+    pub id: String,
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PasskeyAssertionBody {
+    pub id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+    pub remember: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RemovePasskeyBody {
+    pub id: i64,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateProfileBody {
+    pub display_name: Option<String>,
+    pub pronouns: Option<String>,
+    pub graduation_year: Option<i32>,
+    pub bio: Option<String>,
+    pub links: Option<Vec<String>>,
 }
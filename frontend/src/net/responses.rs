@@ -3,5 +3,217 @@ use serde::Deserialize;
 #[derive(Debug, Clone, Deserialize)]
 pub struct CurrentUserResponse {
     pub username: String,
-    pub admin: bool,
-}
\ No newline at end of file
+    pub role: String,
+    pub permissions: Vec<String>,
+    pub two_factor_enabled: bool,
+    pub second_factor_missing: bool,
+    pub impersonator: Option<String>,
+    pub terms_accepted: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserResponse {
+    pub username: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoleResponse {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TwoFactorSetupResponse {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcResponse {
+    pub enabled: bool,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoginLockResponse {
+    pub id: i64,
+    pub scope: String,
+    pub identifier: String,
+    pub failures: i32,
+    pub remaining_seconds: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiTokenResponse {
+    pub id: i64,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_on: String,
+    pub expires_on: String,
+    pub last_used_on: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewApiTokenResponse {
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionResponse {
+    pub id: String,
+    pub created_at: Option<String>,
+    pub last_seen_at: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub current: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SuspendedResponse {
+    pub kind: String,
+    pub reason: String,
+    pub ends_on: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SuspensionResponse {
+    pub id: i64,
+    pub username: String,
+    pub kind: String,
+    pub reason: String,
+    pub created_on: String,
+    pub ends_on: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdminUserResponse {
+    pub id: i64,
+    pub username: String,
+    pub email: Option<String>,
+    pub role: String,
+    pub active: bool,
+    pub suspended: bool,
+    pub student_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserPageResponse {
+    pub users: Vec<AdminUserResponse>,
+    pub page: u64,
+    pub total: u64,
+    pub pages: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TemporaryPasswordResponse {
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RosterRowResponse {
+    pub line: u64,
+    pub username: String,
+    pub action: String,
+    pub changes: Vec<String>,
+    pub error: Option<String>,
+    pub temporary_password: Option<String>,
+    pub invite_link: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RosterReportResponse {
+    pub applied: bool,
+    pub creates: usize,
+    pub updates: usize,
+    pub skips: usize,
+    pub invalid: usize,
+    pub rows: Vec<RosterRowResponse>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ImpersonationResponse {
+    pub id: i64,
+    pub admin: Option<String>,
+    pub user: Option<String>,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AuditEventResponse {
+    pub id: i64,
+    pub action: String,
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuditPageResponse {
+    pub events: Vec<AuditEventResponse>,
+    pub actions: Vec<String>,
+    pub page: u64,
+    pub total: u64,
+    pub pages: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TermsResponse {
+    pub version: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PendingTermsResponse {
+    pub id: i64,
+    pub username: String,
+    pub email: Option<String>,
+    pub accepted_version: Option<String>,
+    pub accepted_at: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DuplicateUserResponse {
+    pub id: i64,
+    pub username: String,
+    pub email: Option<String>,
+    pub student_id: Option<String>,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DuplicateCandidateResponse {
+    pub reason: String,
+    pub users: Vec<DuplicateUserResponse>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PasskeyResponse {
+    pub id: i64,
+    pub name: String,
+    pub created_on: String,
+    pub last_used_on: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TwoFactorMethodsResponse {
+    pub code: bool,
+    pub passkey: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ProfileResponse {
+    pub username: String,
+    pub display_name: Option<String>,
+    pub pronouns: Option<String>,
+    pub graduation_year: Option<i32>,
+    pub bio: Option<String>,
+    pub links: Vec<String>,
+}